@@ -0,0 +1,111 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: ICMP encoding/decoding benchmarks
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+// Run as:
+// cargo bench --no-default-features
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use gufo_ping::IcmpPacket;
+use internet_checksum::checksum;
+use std::convert::TryFrom;
+use std::mem::MaybeUninit;
+
+const SIGNATURE: u64 = 0xdeadbeefdeadbeef;
+const TS: u64 = 0x01020304;
+const SIZES: [usize; 3] = [64 - 20, 1500 - 20, 4096 - 20];
+
+fn new_buf() -> [MaybeUninit<u8>; 4096] {
+    unsafe { MaybeUninit::uninit().assume_init() }
+}
+
+fn encode(pkt: &IcmpPacket, buf: &mut [MaybeUninit<u8>; 4096]) -> Vec<u8> {
+    let n = pkt.write(buf);
+    unsafe { &*(&buf[..n] as *const [MaybeUninit<u8>] as *const [u8]) }.to_vec()
+}
+
+fn bench_encode_request(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_request");
+    let mut buf = new_buf();
+    for size in SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(size.to_string(), |b| {
+            b.iter(|| {
+                let pkt = IcmpPacket::new(8, 0x0102, 1, SIGNATURE, TS, black_box(size));
+                pkt.write(&mut buf)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_rewrite_request(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rewrite_request");
+    let mut buf = new_buf();
+    for size in SIZES {
+        let mut data = encode(
+            &IcmpPacket::new(8, 0x0102, 1, SIGNATURE, TS, size),
+            &mut buf,
+        );
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(size.to_string(), |b| {
+            let mut seq = 0u16;
+            b.iter(|| {
+                seq = seq.wrapping_add(1);
+                let pkt = IcmpPacket::new(8, 0x0102, seq, SIGNATURE, TS, black_box(size));
+                pkt.rewrite(&mut data)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_decode_reply(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_reply");
+    let mut buf = new_buf();
+    for size in SIZES {
+        let data = encode(
+            &IcmpPacket::new(0, 0x0102, 1, SIGNATURE, TS, size),
+            &mut buf,
+        );
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(size.to_string(), |b| {
+            b.iter(|| IcmpPacket::try_from(black_box(data.as_slice())))
+        });
+    }
+    group.finish();
+}
+
+fn bench_checksum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("checksum");
+    let mut buf = new_buf();
+    for size in SIZES {
+        let data = encode(
+            &IcmpPacket::new(8, 0x0102, 1, SIGNATURE, TS, size),
+            &mut buf,
+        );
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(size.to_string(), |b| {
+            b.iter(|| checksum(black_box(data.as_slice())))
+        });
+    }
+    group.finish();
+}
+
+fn bench_sid(c: &mut Criterion) {
+    let pkt = IcmpPacket::new(0, 0x0102, 1, SIGNATURE, TS, SIZES[0]);
+    c.bench_function("get_sid", |b| {
+        b.iter(|| pkt.get_sid(black_box("192.0.2.1".to_string())))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_encode_request,
+    bench_rewrite_request,
+    bench_decode_reply,
+    bench_checksum,
+    bench_sid
+);
+criterion_main!(benches);
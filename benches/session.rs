@@ -0,0 +1,104 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Session table benchmarks
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+// Run as:
+// cargo bench --no-default-features --bench session
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use gufo_ping::{Session, SessionKey, SessionTable};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, Ipv4Addr};
+
+const SESSIONS: u32 = 100_000;
+
+fn keys() -> Vec<(SessionKey, u64)> {
+    (0..SESSIONS)
+        .map(|n| {
+            let addr = IpAddr::V4(Ipv4Addr::from(0x0a000000 + n));
+            (SessionKey::new(addr, 1, n as u16), n as u64)
+        })
+        .collect()
+}
+
+// Former implementation: sessions ordered by (deadline, sid)
+fn btree(keys: &[(SessionKey, u64)]) -> BTreeSet<(u64, String)> {
+    keys.iter()
+        .map(|(key, deadline)| (*deadline, key.get_sid()))
+        .collect()
+}
+
+fn table(keys: &[(SessionKey, u64)]) -> SessionTable {
+    let mut table = SessionTable::new();
+    for (key, deadline) in keys {
+        table.insert(Session::new(*key, *deadline));
+    }
+    table
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let keys = keys();
+    let mut group = c.benchmark_group("session_insert");
+    group.bench_function("btree", |b| b.iter(|| btree(black_box(&keys))));
+    group.bench_function("table", |b| b.iter(|| table(black_box(&keys))));
+    group.finish();
+}
+
+fn bench_remove(c: &mut Criterion) {
+    let keys = keys();
+    let mut group = c.benchmark_group("session_remove");
+    group.bench_function("btree", |b| {
+        b.iter_batched(
+            || btree(&keys),
+            |mut set| {
+                // Reply carries sid and the timestamp to restore deadline
+                for (key, deadline) in keys.iter() {
+                    set.remove(&(*deadline, key.get_sid()));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("table", |b| {
+        b.iter_batched(
+            || table(&keys),
+            |mut table| {
+                for (key, _) in keys.iter() {
+                    table.remove(key);
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_expire(c: &mut Criterion) {
+    let keys = keys();
+    let mut group = c.benchmark_group("session_expire");
+    group.bench_function("btree", |b| {
+        b.iter_batched(
+            || btree(&keys),
+            |mut set| {
+                let expired: Vec<(u64, String)> = set.iter().cloned().collect();
+                for item in expired.iter() {
+                    set.remove(item);
+                }
+                expired
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("table", |b| {
+        b.iter_batched(
+            || table(&keys),
+            |mut table| table.expire(u64::MAX),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_insert, bench_remove, bench_expire);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: systemd integration
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::systemd;
+use pyo3::{exceptions::PyOSError, prelude::*};
+use std::collections::HashMap;
+use std::env;
+use std::os::unix::io::RawFd;
+
+/// Get raw sockets, passed by systemd socket activation.
+/// Returns dict of address family (4 or 6) to file descriptor.
+/// Sockets of other families are ignored.
+#[pyfunction]
+pub(crate) fn listen_fds() -> PyResult<HashMap<u8, RawFd>> {
+    let fds = systemd::parse_listen_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    let mut r = HashMap::new();
+    for fd in fds {
+        let afi = match systemd::get_domain(fd) {
+            Ok(libc::AF_INET) => 4,
+            Ok(libc::AF_INET6) => 6,
+            _ => continue,
+        };
+        if r.contains_key(&afi) {
+            continue;
+        }
+        systemd::set_cloexec(fd).map_err(|e| PyOSError::new_err(e.to_string()))?;
+        r.insert(afi, fd);
+    }
+    Ok(r)
+}
+
+/// Send state to the service manager, i.e. `READY=1`.
+/// Returns false, if not running under the service manager.
+#[pyfunction]
+pub(crate) fn sd_notify(state: &str) -> PyResult<bool> {
+    match env::var("NOTIFY_SOCKET") {
+        Ok(path) if !path.is_empty() => {
+            systemd::notify(&path, state).map_err(|e| PyOSError::new_err(e.to_string()))?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Get watchdog timeout, requested by the service manager,
+/// in microseconds. None, if the watchdog is disabled.
+#[pyfunction]
+pub(crate) fn get_watchdog_usec() -> Option<u64> {
+    systemd::parse_watchdog(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
@@ -0,0 +1,224 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: RTT baseline and anomaly detection
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 1 + 4;
+const ITEM_SIZE: usize = 8 + 8 + 8 + 2;
+/// Number of samples to learn the baseline before flagging anomalies
+pub const WARMUP: u64 = 10;
+/// Lower bound of the deviation, as the share of the baseline,
+/// preventing the flagging of the small jitter on the stable paths
+const MIN_MAD_RATIO: f64 = 0.01;
+
+/// RTT baseline of the target: exponentially weighted moving average
+/// and mean absolute deviation, in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Baseline {
+    pub ewma: f64,
+    pub mad: f64,
+    pub count: u64,
+}
+
+impl Baseline {
+    /// Check if the sample deviates from the baseline more than
+    /// `threshold` deviations, then update the baseline with the sample
+    pub fn register(&mut self, rtt: f64, alpha: f64, threshold: f64) -> bool {
+        if self.count == 0 {
+            self.ewma = rtt;
+            self.count = 1;
+            return false;
+        }
+        let dev = (rtt - self.ewma).abs();
+        let anomalous =
+            self.count >= WARMUP && dev > threshold * self.mad.max(self.ewma * MIN_MAD_RATIO);
+        self.ewma += alpha * (rtt - self.ewma);
+        self.mad += alpha * (dev - self.mad);
+        self.count = self.count.saturating_add(1);
+        anomalous
+    }
+}
+
+/// Baselines of the targets
+#[derive(Debug, Clone, PartialEq)]
+pub struct Baselines {
+    alpha: f64,
+    threshold: f64,
+    targets: HashMap<String, Baseline>,
+}
+
+impl Baselines {
+    /// Create empty baselines with smoothing factor `alpha`
+    /// and anomaly `threshold`, in mean absolute deviations
+    pub fn new(alpha: f64, threshold: f64) -> Self {
+        Baselines {
+            alpha,
+            threshold,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Account target's RTT, in nanoseconds.
+    /// Returns true, if the sample is anomalous
+    pub fn register(&mut self, target: &str, rtt: u64) -> bool {
+        let (alpha, threshold) = (self.alpha, self.threshold);
+        match self.targets.get_mut(target) {
+            Some(baseline) => baseline.register(rtt as f64, alpha, threshold),
+            None => self
+                .targets
+                .entry(target.into())
+                .or_default()
+                .register(rtt as f64, alpha, threshold),
+        }
+    }
+
+    /// Get target's baseline
+    pub fn get(&self, target: &str) -> Option<&Baseline> {
+        self.targets.get(target)
+    }
+
+    /// Forget target's baseline
+    pub fn remove(&mut self, target: &str) {
+        self.targets.remove(target);
+    }
+
+    /// Serialize baselines, to be restored after restart:
+    /// ```text
+    /// version(1) targets(4)
+    /// [ewma(8) mad(8) count(8) target_len(2) target(target_len)] * targets
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_SIZE];
+        buf[0] = VERSION;
+        BigEndian::write_u32(&mut buf[1..], self.targets.len() as u32);
+        let mut item = [0u8; ITEM_SIZE];
+        for (target, baseline) in self.targets.iter() {
+            BigEndian::write_f64(&mut item, baseline.ewma);
+            BigEndian::write_f64(&mut item[8..], baseline.mad);
+            BigEndian::write_u64(&mut item[16..], baseline.count);
+            BigEndian::write_u16(&mut item[24..], target.len() as u16);
+            buf.extend_from_slice(&item);
+            buf.extend_from_slice(target.as_bytes());
+        }
+        buf
+    }
+
+    /// Restore baselines, serialized by `encode`
+    pub fn decode(&mut self, buf: &[u8]) -> Result<(), &'static str> {
+        if buf.len() < HEADER_SIZE {
+            return Err("too short");
+        }
+        if buf[0] != VERSION {
+            return Err("unsupported version");
+        }
+        let count = BigEndian::read_u32(&buf[1..]) as usize;
+        let mut targets = HashMap::with_capacity(count.min(buf.len() / ITEM_SIZE));
+        let mut rest = &buf[HEADER_SIZE..];
+        for _ in 0..count {
+            if rest.len() < ITEM_SIZE {
+                return Err("too short");
+            }
+            let baseline = Baseline {
+                ewma: BigEndian::read_f64(rest),
+                mad: BigEndian::read_f64(&rest[8..]),
+                count: BigEndian::read_u64(&rest[16..]),
+            };
+            let target_len = BigEndian::read_u16(&rest[24..]) as usize;
+            rest = &rest[ITEM_SIZE..];
+            if rest.len() < target_len {
+                return Err("too short");
+            }
+            let target = std::str::from_utf8(&rest[..target_len]).map_err(|_| "invalid target")?;
+            targets.insert(target.to_string(), baseline);
+            rest = &rest[target_len..];
+        }
+        self.targets.extend(targets);
+        Ok(())
+    }
+}
+
+// Parse baselines with default settings
+impl TryFrom<&[u8]> for Baselines {
+    type Error = &'static str;
+
+    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+        let mut r = Baselines::new(0.1, 3.0);
+        r.decode(buf)?;
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn test_warmup() {
+        let mut b = Baselines::new(0.1, 3.0);
+        for _ in 0..WARMUP - 1 {
+            assert!(!b.register("127.0.0.1", 10 * MS));
+        }
+        // Not learned yet
+        assert!(!b.register("127.0.0.1", 1_000 * MS));
+        assert_eq!(b.get("127.0.0.1").unwrap().count, WARMUP);
+    }
+
+    #[test]
+    fn test_anomaly() {
+        let mut b = Baselines::new(0.1, 3.0);
+        for i in 0..100 {
+            // 10ms ± 1ms
+            assert!(!b.register("127.0.0.1", (9 + 2 * (i % 2)) * MS), "{}", i);
+        }
+        let baseline = *b.get("127.0.0.1").unwrap();
+        assert!((baseline.ewma - (10 * MS) as f64).abs() < (MS / 2) as f64);
+        assert!((baseline.mad - MS as f64).abs() < (MS / 2) as f64);
+        assert!(!b.register("127.0.0.1", 12 * MS));
+        assert!(b.register("127.0.0.1", 20 * MS));
+        assert!(b.register("127.0.0.1", MS));
+    }
+
+    #[test]
+    fn test_stable() {
+        let mut b = Baselines::new(0.1, 3.0);
+        for _ in 0..100 {
+            b.register("127.0.0.1", 10 * MS);
+        }
+        // Within MIN_MAD_RATIO
+        assert!(!b.register("127.0.0.1", 10 * MS + 250_000));
+        assert!(b.register("127.0.0.1", 11 * MS));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut b = Baselines::new(0.1, 3.0);
+        b.register("127.0.0.1", 10 * MS);
+        b.register("127.0.0.1", 12 * MS);
+        b.register("::1", MS);
+        let buf = b.encode();
+        assert_eq!(Baselines::try_from(buf.as_slice()), Ok(b));
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let mut b = Baselines::new(0.1, 3.0);
+        b.register("127.0.0.1", 10 * MS);
+        let buf = b.encode();
+        assert_eq!(Baselines::try_from(&buf[..3]), Err("too short"));
+        assert_eq!(Baselines::try_from(&buf[..buf.len() - 1]), Err("too short"));
+        let mut bad = buf.clone();
+        bad[0] = 0;
+        assert_eq!(
+            Baselines::try_from(bad.as_slice()),
+            Err("unsupported version")
+        );
+    }
+}
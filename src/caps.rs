@@ -0,0 +1,242 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Capabilities inspection
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+#[cfg(target_os = "linux")]
+use std::io::Error;
+use std::io::Result;
+use std::path::Path;
+
+/// CAP_NET_RAW capability number
+pub const CAP_NET_RAW: u32 = 13;
+
+/// _LINUX_CAPABILITY_VERSION_3
+#[cfg(target_os = "linux")]
+pub(crate) const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+pub(crate) struct CapHeader {
+    pub(crate) version: u32,
+    pub(crate) pid: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub(crate) struct CapData {
+    pub(crate) effective: u32,
+    pub(crate) permitted: u32,
+    pub(crate) inheritable: u32,
+}
+
+const VFS_CAP_REVISION_MASK: u32 = 0xff00_0000;
+const VFS_CAP_REVISION_1: u32 = 0x0100_0000;
+const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+const VFS_CAP_REVISION_3: u32 = 0x0300_0000;
+const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x0000_0001;
+
+/// File capabilities, stored in `security.capability` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileCaps {
+    pub permitted: u64,
+    pub inheritable: u64,
+    /// Raise permitted capabilities to the effective set on `execve`
+    pub effective: bool,
+}
+
+impl FileCaps {
+    /// Check if the capability is permitted
+    pub fn is_permitted(&self, cap: u32) -> bool {
+        self.permitted & (1 << cap) != 0
+    }
+
+    /// Parse `vfs_cap_data` structure
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let read = |offset: usize| -> Option<u64> {
+            buf.get(offset..offset + 4)
+                .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]) as u64)
+        };
+        let magic = read(0)? as u32;
+        let words = match magic & VFS_CAP_REVISION_MASK {
+            VFS_CAP_REVISION_1 => 1,
+            VFS_CAP_REVISION_2 | VFS_CAP_REVISION_3 => 2,
+            _ => return None,
+        };
+        let mut r = FileCaps {
+            effective: magic & VFS_CAP_FLAGS_EFFECTIVE != 0,
+            ..Default::default()
+        };
+        for i in 0..words {
+            r.permitted |= read(4 + 8 * i)? << (32 * i);
+            r.inheritable |= read(8 + 8 * i)? << (32 * i);
+        }
+        Some(r)
+    }
+}
+
+/// Get effective capabilities of the current thread (Linux)
+#[cfg(target_os = "linux")]
+pub fn get_effective() -> Result<u64> {
+    let mut header = CapHeader {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(data[0].effective as u64 | (data[1].effective as u64) << 32)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_effective() -> Result<u64> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Get file capabilities, None if not set (Linux)
+#[cfg(target_os = "linux")]
+pub fn get_file_caps(path: &Path) -> Result<Option<FileCaps>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut buf = [0u8; 24];
+    let n = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            c"security.capability".as_ptr(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+        )
+    };
+    if n < 0 {
+        let e = Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::ENODATA) | Some(libc::ENOTSUP) => Ok(None),
+            _ => Err(e),
+        };
+    }
+    Ok(FileCaps::parse(&buf[..n as usize]))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_file_caps(_path: &Path) -> Result<Option<FileCaps>> {
+    Ok(None)
+}
+
+/// Explain the failure to open the raw socket, given the effective
+/// capabilities of the process and the file capabilities
+/// of the executable
+pub fn explain_raw_denied(
+    effective: Option<u64>,
+    file_caps: Option<FileCaps>,
+    exe: &str,
+) -> String {
+    match effective {
+        None => "raw socket requires root privileges".into(),
+        Some(x) if x & (1 << CAP_NET_RAW) != 0 => {
+            "raw socket is denied by the security policy (SELinux, AppArmor, or seccomp), \
+             while CAP_NET_RAW is effective"
+                .into()
+        }
+        _ if file_caps
+            .map(|x| x.is_permitted(CAP_NET_RAW))
+            .unwrap_or(false) =>
+        {
+            format!(
+                "{} has cap_net_raw file capability, but it is not effective: \
+                 check the capability bounding set and nosuid mounts",
+                exe
+            )
+        }
+        _ => format!(
+            "raw socket requires CAP_NET_RAW: run as root \
+             or grant the capability with `setcap cap_net_raw+ep {}`",
+            exe
+        ),
+    }
+}
+
+/// Explain the failure to open the raw socket for the current process
+pub fn raw_denied_hint() -> String {
+    let exe = std::env::current_exe().ok();
+    let file_caps = exe.as_deref().and_then(|x| get_file_caps(x).ok()).flatten();
+    let exe = exe
+        .as_deref()
+        .map(|x| x.display().to_string())
+        .unwrap_or_else(|| "<interpreter>".into());
+    explain_raw_denied(get_effective().ok(), file_caps, &exe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NET_RAW: u64 = 1 << CAP_NET_RAW;
+
+    #[test]
+    fn test_parse() {
+        // setcap cap_net_raw+ep
+        let buf = [
+            0x01, 0x00, 0x00, 0x02, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            FileCaps::parse(&buf),
+            Some(FileCaps {
+                permitted: NET_RAW,
+                inheritable: 0,
+                effective: true
+            })
+        );
+        // Revision 1, no effective flag
+        let buf = [
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x20, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00,
+        ];
+        let caps = FileCaps::parse(&buf).unwrap();
+        assert!(caps.is_permitted(CAP_NET_RAW));
+        assert_eq!(caps.inheritable, NET_RAW);
+        assert!(!caps.effective);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(FileCaps::parse(&[]), None);
+        assert_eq!(
+            FileCaps::parse(&[0, 0, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0]),
+            None
+        );
+        assert_eq!(FileCaps::parse(&[0, 0, 0, 0x02, 0, 0x20, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_explain() {
+        let exe = "/usr/bin/python3";
+        assert!(explain_raw_denied(Some(0), None, exe)
+            .contains("setcap cap_net_raw+ep /usr/bin/python3"));
+        assert!(explain_raw_denied(Some(NET_RAW), None, exe).contains("security policy"));
+        let file_caps = FileCaps {
+            permitted: NET_RAW,
+            ..Default::default()
+        };
+        assert!(explain_raw_denied(Some(0), Some(file_caps), exe).contains("not effective"));
+        assert!(explain_raw_denied(None, None, exe).contains("root"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_get_effective() {
+        let caps = get_effective().unwrap();
+        if unsafe { libc::geteuid() } != 0 {
+            assert_eq!(caps & NET_RAW, 0);
+        }
+    }
+
+    #[test]
+    fn test_get_file_caps_missed() {
+        assert_eq!(get_file_caps(Path::new("/")).unwrap_or(None), None);
+    }
+}
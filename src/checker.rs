@@ -0,0 +1,39 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: HealthChecker implementation
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::health::{HealthCheck, Policy};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+/// Python wrapper for the target's health check
+#[pyclass]
+pub(crate) struct HealthChecker {
+    check: HealthCheck,
+}
+
+#[pymethods]
+impl HealthChecker {
+    /// Parse policy
+    #[new]
+    fn new(policy: &str) -> PyResult<Self> {
+        let policy: Policy = policy
+            .parse()
+            .map_err(|e: &str| PyValueError::new_err(format!("invalid policy: {}", e)))?;
+        Ok(HealthChecker {
+            check: HealthCheck::new(policy),
+        })
+    }
+
+    /// Account the result. RTT is in nanoseconds, None for lost request.
+    /// Returns the new verdict, if changed
+    fn register(&mut self, rtt: Option<u64>) -> Option<bool> {
+        self.check.register(rtt)
+    }
+
+    /// Current verdict, None before the first result
+    fn is_down(&self) -> Option<bool> {
+        self.check.is_down()
+    }
+}
@@ -0,0 +1,477 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Configuration file
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::health::Policy;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Option value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+/// Expected type of the option
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Str,
+    Int,
+    Float,
+    Bool,
+}
+
+/// Settings of the `Ping` engine
+const PING_OPTIONS: &[(&str, Kind)] = &[
+    ("size", Kind::Int),
+    ("ttl", Kind::Int),
+    ("tos", Kind::Int),
+    ("timeout", Kind::Float),
+    ("send_buffer_size", Kind::Int),
+    ("recv_buffer_size", Kind::Int),
+    ("coarse", Kind::Bool),
+    ("accelerated", Kind::Bool),
+    ("max_in_flight", Kind::Int),
+    ("batch_size", Kind::Int),
+    ("batch_delay", Kind::Float),
+    ("jitter_percent", Kind::Float),
+    ("late_grace", Kind::Float),
+    ("rate_limit_backoff", Kind::Bool),
+    ("link_monitor", Kind::Bool),
+    ("socket_activation", Kind::Bool),
+    ("anomaly_threshold", Kind::Float),
+    ("anomaly_alpha", Kind::Float),
+];
+
+/// Settings of the `ProbeProfile`, along with the health check
+const PROFILE_OPTIONS: &[(&str, Kind)] = &[
+    ("size", Kind::Int),
+    ("interval", Kind::Float),
+    ("count", Kind::Int),
+    ("timeout", Kind::Float),
+    ("dscp", Kind::Int),
+    ("ttl", Kind::Int),
+    ("window", Kind::Int),
+    ("health_check", Kind::Str),
+];
+
+/// Settings of the target
+const TARGET_OPTIONS: &[(&str, Kind)] = &[
+    ("address", Kind::Str),
+    ("profile", Kind::Str),
+    ("tag", Kind::Str),
+    ("health_check", Kind::Str),
+];
+
+/// Minimal packet size, IPv4 and ICMP headers
+const MIN_SIZE: f64 = 28.0;
+
+/// Allowed ranges of the numeric options, inclusive.
+/// Upper bound is not checked when None.
+const RANGES: &[(&str, f64, Option<f64>)] = &[
+    ("size", MIN_SIZE, Some(65_535.0)),
+    ("ttl", 1.0, Some(255.0)),
+    ("tos", 0.0, Some(255.0)),
+    ("dscp", 0.0, Some(63.0)),
+    ("window", 1.0, None),
+    ("interval", 0.0, None),
+    ("timeout", 0.0, None),
+];
+
+/// Options of the section, in order of appearance
+pub type Options = Vec<(String, Value)>;
+
+/// Declarative engine configuration, the subset of TOML:
+///
+/// ``` toml
+/// [ping]
+/// timeout = 1.0
+///
+/// [profile.core]
+/// interval = 0.5
+/// dscp = 46
+/// health_check = "lost >= 3 of 5 or p95 > 200ms"
+///
+/// [[target]]
+/// address = "192.0.2.1"
+/// profile = "core"
+/// tag = "router"
+/// ```
+///
+/// Values are strings, integers, floats, and booleans.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+    /// `Ping` settings
+    pub ping: Options,
+    /// Profiles' names and settings
+    pub profiles: Vec<(String, Options)>,
+    /// Targets' settings, `address` is always set
+    pub targets: Vec<Options>,
+}
+
+/// Current section
+enum Section {
+    Ping,
+    Profile,
+    Target,
+}
+
+/// Strip comment, outside of the strings
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+/// Parse basic string, without quotes
+fn parse_basic_string(s: &str) -> Result<String, &'static str> {
+    let mut r = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            return Err("unescaped quote");
+        }
+        if c != '\\' {
+            r.push(c);
+            continue;
+        }
+        r.push(match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('t') => '\t',
+            _ => return Err("invalid escape"),
+        });
+    }
+    Ok(r)
+}
+
+/// Parse value
+fn parse_value(s: &str) -> Result<Value, &'static str> {
+    if let Some(x) = s.strip_prefix('"') {
+        let x = x.strip_suffix('"').ok_or("unterminated string")?;
+        return Ok(Value::Str(parse_basic_string(x)?));
+    }
+    if let Some(x) = s.strip_prefix('\'') {
+        let x = x.strip_suffix('\'').ok_or("unterminated string")?;
+        if x.contains('\'') {
+            return Err("unescaped quote");
+        }
+        return Ok(Value::Str(x.into()));
+    }
+    match s {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    let digits = s.replace('_', "");
+    if let Ok(x) = digits.parse::<i64>() {
+        return Ok(Value::Int(x));
+    }
+    match digits.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(Value::Float(x)),
+        _ => Err("invalid value"),
+    }
+}
+
+/// Check the option against the schema, converting integers to floats
+fn check_option(schema: &[(&str, Kind)], key: &str, value: Value) -> Result<Value, String> {
+    let kind = schema
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, kind)| *kind)
+        .ok_or_else(|| format!("unknown option `{}`", key))?;
+    match (kind, value) {
+        (Kind::Float, Value::Int(x)) => Ok(Value::Float(x as f64)),
+        (Kind::Str, x @ Value::Str(_))
+        | (Kind::Int, x @ Value::Int(_))
+        | (Kind::Float, x @ Value::Float(_))
+        | (Kind::Bool, x @ Value::Bool(_)) => Ok(x),
+        _ => Err(format!("invalid type of `{}`", key)),
+    }
+}
+
+/// Check the numeric option against the allowed range
+fn check_range(key: &str, value: &Value) -> Result<(), String> {
+    let x = match value {
+        Value::Int(x) => *x as f64,
+        Value::Float(x) => *x,
+        _ => return Ok(()),
+    };
+    match RANGES.iter().find(|(name, _, _)| *name == key) {
+        Some((_, min, Some(max))) if x < *min || x > *max => {
+            Err(format!("`{}` must be in {}..{} range", key, min, max))
+        }
+        Some((_, min, None)) if x < *min => Err(format!("`{}` must be at least {}", key, min)),
+        _ => Ok(()),
+    }
+}
+
+/// Get string option
+fn get_str<'a>(options: &'a Options, key: &str) -> Option<&'a str> {
+    options.iter().find_map(|(k, v)| match v {
+        Value::Str(x) if k == key => Some(x.as_str()),
+        _ => None,
+    })
+}
+
+impl Config {
+    /// Check cross-references and values
+    fn validate(&self) -> Result<(), String> {
+        let mut profiles = HashSet::new();
+        for (name, options) in self.profiles.iter() {
+            if !profiles.insert(name.as_str()) {
+                return Err(format!("duplicated profile `{}`", name));
+            }
+            if let Some(policy) = get_str(options, "health_check") {
+                policy
+                    .parse::<Policy>()
+                    .map_err(|e| format!("profile `{}`: invalid health_check: {}", name, e))?;
+            }
+        }
+        let mut addresses = HashSet::new();
+        for (i, options) in self.targets.iter().enumerate() {
+            let address = get_str(options, "address")
+                .ok_or_else(|| format!("target #{}: address is not set", i + 1))?;
+            let ip: IpAddr = address
+                .parse()
+                .map_err(|_| format!("target #{}: invalid address `{}`", i + 1, address))?;
+            if !addresses.insert(ip) {
+                return Err(format!("duplicated target `{}`", address));
+            }
+            if let Some(profile) = get_str(options, "profile") {
+                if !profiles.contains(profile) {
+                    return Err(format!(
+                        "target `{}`: unknown profile `{}`",
+                        address, profile
+                    ));
+                }
+            }
+            if let Some(policy) = get_str(options, "health_check") {
+                policy
+                    .parse::<Policy>()
+                    .map_err(|e| format!("target `{}`: invalid health_check: {}", address, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        let mut section = None;
+        for (n, line) in text.lines().enumerate() {
+            let err = |e: &str| format!("line {}: {}", n + 1, e);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                section = Some(match line {
+                    "[ping]" => Section::Ping,
+                    "[[target]]" => {
+                        config.targets.push(Options::new());
+                        Section::Target
+                    }
+                    _ => {
+                        let name = line
+                            .strip_prefix("[profile.")
+                            .and_then(|x| x.strip_suffix(']'))
+                            .map(|x| x.trim())
+                            .filter(|x| !x.is_empty())
+                            .ok_or_else(|| err("unknown section"))?;
+                        config.profiles.push((name.into(), Options::new()));
+                        Section::Profile
+                    }
+                });
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| err("`=` expected"))?;
+            let key = key.trim();
+            let value = parse_value(value.trim()).map_err(err)?;
+            let (schema, options) = match section {
+                Some(Section::Ping) => (PING_OPTIONS, &mut config.ping),
+                Some(Section::Profile) => {
+                    (PROFILE_OPTIONS, &mut config.profiles.last_mut().unwrap().1)
+                }
+                Some(Section::Target) => (TARGET_OPTIONS, config.targets.last_mut().unwrap()),
+                None => return Err(err("option outside of section")),
+            };
+            let value = check_option(schema, key, value).map_err(|e| err(&e))?;
+            check_range(key, &value).map_err(|e| err(&e))?;
+            if options.iter().any(|(k, _)| k == key) {
+                return Err(err(&format!("duplicated option `{}`", key)));
+            }
+            options.push((key.into(), value));
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+# Engine
+[ping]
+timeout = 2  # Converted to float
+link_monitor = true
+
+[profile.core]
+interval = 0.5
+dscp = 46
+health_check = "lost >= 3 of 5 or p95 > 200ms"
+
+[[target]]
+address = "192.0.2.1"
+profile = "core"
+tag = 'router #1'
+
+[[target]]
+address = "2001:db8::1"
+"#;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = CONFIG.parse().unwrap();
+        assert_eq!(
+            config.ping,
+            vec![
+                ("timeout".into(), Value::Float(2.0)),
+                ("link_monitor".into(), Value::Bool(true))
+            ]
+        );
+        assert_eq!(
+            config.profiles,
+            vec![(
+                "core".into(),
+                vec![
+                    ("interval".into(), Value::Float(0.5)),
+                    ("dscp".into(), Value::Int(46)),
+                    (
+                        "health_check".into(),
+                        Value::Str("lost >= 3 of 5 or p95 > 200ms".into())
+                    ),
+                ]
+            )]
+        );
+        assert_eq!(
+            config.targets,
+            vec![
+                vec![
+                    ("address".into(), Value::Str("192.0.2.1".into())),
+                    ("profile".into(), Value::Str("core".into())),
+                    ("tag".into(), Value::Str("router #1".into())),
+                ],
+                vec![("address".into(), Value::Str("2001:db8::1".into()))],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("1_000"), Ok(Value::Int(1_000)));
+        assert_eq!(parse_value("-1"), Ok(Value::Int(-1)));
+        assert_eq!(parse_value("1e3"), Ok(Value::Float(1_000.0)));
+        assert_eq!(parse_value("false"), Ok(Value::Bool(false)));
+        assert_eq!(
+            parse_value(r#""a\"b\\c""#),
+            Ok(Value::Str("a\"b\\c".into()))
+        );
+        assert_eq!(parse_value(r#"'a\b'"#), Ok(Value::Str("a\\b".into())));
+        assert!(parse_value("\"a").is_err());
+        assert!(parse_value("\"a\"b\"").is_err());
+        assert!(parse_value("nan").is_err());
+        assert!(parse_value("yes").is_err());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        for (text, err) in [
+            ("timeout = 1", "line 1: option outside of section"),
+            ("[pinger]", "line 1: unknown section"),
+            ("[profile.]", "line 1: unknown section"),
+            ("[ping]\ntimeout", "line 2: `=` expected"),
+            ("[ping]\nfoo = 1", "line 2: unknown option `foo`"),
+            (
+                "[ping]\ntimeout = \"1\"",
+                "line 2: invalid type of `timeout`",
+            ),
+            ("[ping]\nsize = 1.5", "line 2: invalid type of `size`"),
+            (
+                "[ping]\nsize = 64\nsize = 128",
+                "line 3: duplicated option `size`",
+            ),
+            (
+                "[[target]]\nprofile = \"x\"",
+                "target #1: address is not set",
+            ),
+            (
+                "[[target]]\naddress = \"x\"",
+                "target #1: invalid address `x`",
+            ),
+            (
+                "[[target]]\naddress = \"::1\"\n[[target]]\naddress = \"::1\"",
+                "duplicated target `::1`",
+            ),
+            (
+                "[[target]]\naddress = \"::1\"\nprofile = \"x\"",
+                "target `::1`: unknown profile `x`",
+            ),
+            (
+                "[ping]\nsize = 27",
+                "line 2: `size` must be in 28..65535 range",
+            ),
+            ("[ping]\ntos = 256", "line 2: `tos` must be in 0..255 range"),
+            (
+                "[ping]\ntimeout = -1",
+                "line 2: `timeout` must be at least 0",
+            ),
+            (
+                "[profile.x]\ndscp = 64",
+                "line 2: `dscp` must be in 0..63 range",
+            ),
+            (
+                "[profile.x]\nttl = 0",
+                "line 2: `ttl` must be in 1..255 range",
+            ),
+            (
+                "[profile.x]\nwindow = 0",
+                "line 2: `window` must be at least 1",
+            ),
+            (
+                "[profile.x]\ninterval = -0.5",
+                "line 2: `interval` must be at least 0",
+            ),
+            ("[profile.x]\n[profile.x]", "duplicated profile `x`"),
+            (
+                "[profile.x]\nhealth_check = \"lost\"",
+                "profile `x`: invalid health_check: operator expected",
+            ),
+        ] {
+            assert_eq!(text.parse::<Config>(), Err(err.into()), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: AnomalyDetector implementation
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::baseline::Baselines;
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes};
+
+/// Python wrapper for the targets' RTT baselines
+#[pyclass]
+pub(crate) struct AnomalyDetector {
+    baselines: Baselines,
+}
+
+#[pymethods]
+impl AnomalyDetector {
+    /// Create detector with smoothing factor `alpha`
+    /// and `threshold` in mean absolute deviations
+    #[new]
+    #[args(alpha = "0.1", threshold = "3.0")]
+    fn new(alpha: f64, threshold: f64) -> PyResult<Self> {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            return Err(PyValueError::new_err("alpha must be in (0, 1]"));
+        }
+        if threshold <= 0.0 {
+            return Err(PyValueError::new_err("threshold must be positive"));
+        }
+        Ok(AnomalyDetector {
+            baselines: Baselines::new(alpha, threshold),
+        })
+    }
+
+    /// Account target's RTT, in nanoseconds.
+    /// Returns true, if the sample is anomalous
+    fn register(&mut self, target: &str, rtt: u64) -> bool {
+        self.baselines.register(target, rtt)
+    }
+
+    /// Get target's baseline as (ewma, mad, count), in nanoseconds
+    fn get(&self, target: &str) -> Option<(f64, f64, u64)> {
+        self.baselines.get(target).map(|b| (b.ewma, b.mad, b.count))
+    }
+
+    /// Forget target's baseline
+    fn remove(&mut self, target: &str) {
+        self.baselines.remove(target);
+    }
+
+    /// Serialize baselines
+    fn export_state(&self, py: Python) -> PyObject {
+        PyBytes::new(py, &self.baselines.encode()).into()
+    }
+
+    /// Restore baselines, serialized by `export_state`
+    fn import_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.baselines.decode(state).map_err(PyValueError::new_err)
+    }
+}
@@ -11,6 +11,115 @@ Attributes:
 
 # Gufo Labs modules
 from .ping import Ping  # noqa
+from .stats import PingStats  # noqa
+from .calibration import Calibration  # noqa
+from .burst import BurstResult  # noqa
+from .profile import ProbeProfile  # noqa
+from .options import ProtoOptions  # noqa
+from .schedule import Schedule, ScheduledProbe  # noqa
+from .middlebox import MiddleboxResult, probe_middlebox  # noqa
+from .host import HostCheckResult, check_host  # noqa
+from .matrix import MatrixResult, probe_matrix  # noqa
+from .emitter import (  # noqa
+    BaseEmitter,
+    InfluxEmitter,
+    JsonLinesEmitter,
+    ProbeResult,
+    VerdictChange,
+)
+from ._fast import (  # noqa
+    DEBUG_RX,
+    DEBUG_TX,
+    ECN_CE,
+    ECN_ECT0,
+    ECN_ECT1,
+    ECN_NOT_ECT,
+    INVALID_RTT_CLAMP,
+    INVALID_RTT_ERROR,
+    INVALID_RTT_RAW,
+    JITTER_EXPONENTIAL,
+    JITTER_NONE,
+    JITTER_UNIFORM,
+    MIMIC_LINUX,
+    MIMIC_NONE,
+    MIMIC_WINDOWS,
+    OVERLOAD_EVICT,
+    OVERLOAD_REJECT,
+    PADDING_FIXED,
+    PADDING_RANDOM,
+    RECORD_INVALID_RTT,
+    RECORD_PROHIBITED,
+    RECORD_REPLY,
+    RECORD_TIME_EXCEEDED,
+    RECORD_TIMEOUT,
+    RECORD_UNREACHABLE,
+    REQUEST_ID_CALLER,
+    REQUEST_ID_RANDOM,
+    REQUEST_ID_SIGNATURE,
+    RTT_MS,
+    RTT_NS,
+    RTT_S,
+    RTT_US,
+    TS_NONCE,
+    TS_OBFUSCATED,
+    TS_PLAIN,
+)
 
 __version__: str = "0.2.2"
-__all__ = ["Ping", "__version__"]
+__all__ = [
+    "BaseEmitter",
+    "BurstResult",
+    "Calibration",
+    "DEBUG_RX",
+    "DEBUG_TX",
+    "ECN_CE",
+    "ECN_ECT0",
+    "ECN_ECT1",
+    "ECN_NOT_ECT",
+    "HostCheckResult",
+    "InfluxEmitter",
+    "INVALID_RTT_CLAMP",
+    "INVALID_RTT_ERROR",
+    "INVALID_RTT_RAW",
+    "JITTER_EXPONENTIAL",
+    "JITTER_NONE",
+    "JITTER_UNIFORM",
+    "JsonLinesEmitter",
+    "MatrixResult",
+    "MiddleboxResult",
+    "MIMIC_LINUX",
+    "MIMIC_NONE",
+    "MIMIC_WINDOWS",
+    "OVERLOAD_EVICT",
+    "OVERLOAD_REJECT",
+    "PADDING_FIXED",
+    "PADDING_RANDOM",
+    "Ping",
+    "PingStats",
+    "ProbeProfile",
+    "ProbeResult",
+    "ProtoOptions",
+    "RECORD_INVALID_RTT",
+    "RECORD_PROHIBITED",
+    "RECORD_REPLY",
+    "RECORD_TIME_EXCEEDED",
+    "RECORD_TIMEOUT",
+    "RECORD_UNREACHABLE",
+    "REQUEST_ID_CALLER",
+    "REQUEST_ID_RANDOM",
+    "REQUEST_ID_SIGNATURE",
+    "RTT_MS",
+    "RTT_NS",
+    "RTT_S",
+    "RTT_US",
+    "Schedule",
+    "ScheduledProbe",
+    "TS_NONCE",
+    "TS_OBFUSCATED",
+    "TS_PLAIN",
+    "VerdictChange",
+    "__version__",
+    "check_host",
+    "probe_matrix",
+    "probe_middlebox",
+]
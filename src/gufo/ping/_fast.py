@@ -6,7 +6,190 @@
 # Copyright (C) 2022, Gufo Labs
 # ---------------------------------------------------------------------
 
+# Python modules
+from typing import Any, Dict, List, Optional, Tuple
+
+REQUEST_ID_CALLER: int = 0
+REQUEST_ID_RANDOM: int = 1
+REQUEST_ID_SIGNATURE: int = 2
+OVERLOAD_REJECT: int = 0
+OVERLOAD_EVICT: int = 1
+ECN_NOT_ECT: int = 0
+ECN_ECT1: int = 1
+ECN_ECT0: int = 2
+ECN_CE: int = 3
+DEBUG_TX: int = 0
+DEBUG_RX: int = 1
+PADDING_FIXED: int = 0
+PADDING_RANDOM: int = 1
+MIMIC_NONE: int = 0
+MIMIC_LINUX: int = 1
+MIMIC_WINDOWS: int = 2
+INVALID_RTT_CLAMP: int = 0
+INVALID_RTT_ERROR: int = 1
+INVALID_RTT_RAW: int = 2
+JITTER_NONE: int = 0
+JITTER_UNIFORM: int = 1
+JITTER_EXPONENTIAL: int = 2
+RTT_NS: int = 0
+RTT_US: int = 1
+RTT_MS: int = 2
+RTT_S: int = 3
+TS_PLAIN: int = 0
+TS_OBFUSCATED: int = 1
+TS_NONCE: int = 2
+RECORD_REPLY: int = 0
+RECORD_TIMEOUT: int = 1
+RECORD_UNREACHABLE: int = 2
+RECORD_PROHIBITED: int = 3
+RECORD_TIME_EXCEEDED: int = 4
+RECORD_INVALID_RTT: int = 5
+
 
 class SocketWrapper(object):
-    def __init__(self, afi: int) -> None:
+    def __init__(self, afi: int, request_id_policy: int = 0) -> None:
+        ...
+
+
+class LinkMonitor(object):
+    def __init__(self) -> None:
+        ...
+
+    def get_fd(self) -> int:
+        ...
+
+    def get_links(self) -> Dict[int, bool]:
+        ...
+
+    def recv(self) -> List[Tuple[int, bool]]:
+        ...
+
+    def close(self) -> None:
+        ...
+
+
+class HealthChecker(object):
+    def __init__(self, policy: str) -> None:
+        ...
+
+    def register(self, rtt: Optional[int]) -> Optional[bool]:
+        ...
+
+    def is_down(self) -> Optional[bool]:
+        ...
+
+
+class Suppressor(object):
+    def __init__(self) -> None:
+        ...
+
+    def add(self, target: str, start: float, end: float) -> None:
+        ...
+
+    def clear(self, target: str) -> None:
+        ...
+
+    def get(self, target: str) -> List[Tuple[float, float]]:
+        ...
+
+    def is_suppressed(self, target: str, ts: float) -> bool:
+        ...
+
+
+class AnomalyDetector(object):
+    def __init__(self, alpha: float = 0.1, threshold: float = 3.0) -> None:
+        ...
+
+    def register(self, target: str, rtt: int) -> bool:
+        ...
+
+    def get(self, target: str) -> Optional[Tuple[float, float, int]]:
         ...
+
+    def remove(self, target: str) -> None:
+        ...
+
+    def export_state(self) -> bytes:
+        ...
+
+    def import_state(self, state: bytes) -> None:
+        ...
+
+
+StatsTuple = Tuple[
+    int, int, Optional[float], Optional[float], float, int, int, int, int
+]
+
+
+def encode_stats(
+    stats: List[Tuple[Any, StatsTuple]], baselines: Optional[bytes]
+) -> bytes:
+    ...
+
+
+def decode_stats(
+    state: bytes,
+) -> Tuple[List[Tuple[Any, StatsTuple]], Optional[bytes]]:
+    ...
+
+
+def parse_config(text: str) -> Dict[str, Any]:
+    ...
+
+
+class FileWatcher(object):
+    def __init__(self, path: str) -> None:
+        ...
+
+    def get_fd(self) -> int:
+        ...
+
+    def recv(self) -> bool:
+        ...
+
+    def close(self) -> None:
+        ...
+
+
+def listen_fds() -> Dict[int, int]:
+    ...
+
+
+def sd_notify(state: str) -> bool:
+    ...
+
+
+def get_watchdog_usec() -> Optional[int]:
+    ...
+
+
+def drop_privileges(
+    uid: Optional[int] = None, gid: Optional[int] = None
+) -> None:
+    ...
+
+
+def enable_seccomp(kill: bool) -> None:
+    ...
+
+
+def restrict_sockets(kill: bool, all_threads: bool) -> None:
+    ...
+
+
+class Reply(object):
+    sid: str
+    rtt: int
+    status: int
+    tos: Optional[int]
+    dscp: Optional[int]
+    ecn: Optional[int]
+    local_addr: Optional[str]
+    ifindex: Optional[int]
+    size: int
+    size_mismatch: Optional[bool]
+    icmp_type: Optional[int]
+    icmp_code: Optional[int]
+    reason: Optional[str]
+    truncated: bool
+    late: bool
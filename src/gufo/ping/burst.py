@@ -0,0 +1,77 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: BurstResult implementation
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+from typing import List, Optional
+
+
+class BurstResult(object):
+    """
+    Result of the burst of requests, sent back-to-back.
+
+    Attributes:
+        rtts: Round-trip times in seconds, in order of sending.
+            None for lost requests.
+        arrival: Indexes of the answered requests, in order of
+            replies' arrival.
+    """
+
+    __slots__ = ("rtts", "arrival")
+
+    def __init__(
+        self, rtts: List[Optional[float]], arrival: List[int]
+    ) -> None:
+        self.rtts = rtts
+        self.arrival = arrival
+
+    def __repr__(self) -> str:
+        return (
+            f"<BurstResult sent={self.sent} received={self.received}"
+            f" reordered={self.reordered}>"
+        )
+
+    @property
+    def sent(self) -> int:
+        """
+        Number of sent requests.
+        """
+        return len(self.rtts)
+
+    @property
+    def received(self) -> int:
+        """
+        Number of received replies.
+        """
+        return len(self.arrival)
+
+    @property
+    def lost(self) -> int:
+        """
+        Number of lost requests.
+        """
+        return self.sent - self.received
+
+    @property
+    def all_lost(self) -> bool:
+        """
+        All requests of the burst are lost, indicating outage.
+        """
+        return not self.received
+
+    @property
+    def partial(self) -> bool:
+        """
+        Some, but not all, requests of the burst are lost,
+        indicating random loss.
+        """
+        return 0 < self.received < self.sent
+
+    @property
+    def reordered(self) -> bool:
+        """
+        Replies arrived not in order of sending.
+        """
+        return any(a > b for a, b in zip(self.arrival, self.arrival[1:]))
@@ -0,0 +1,107 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Calibration implementation
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+from typing import Dict, List, Optional
+
+# Loopback address per address family
+LOOPBACK = {4: "127.0.0.1", 6: "::1"}
+
+
+class Calibration(object):
+    """
+    Host-side overhead floor, measured by pinging the loopback.
+    Loopback RTT covers the host's part of every measured RTT:
+    the packet's encoding, the system calls, the kernel's
+    processing, and the event loop's wakeup.
+
+    Attributes:
+        count: Number of requests per address family.
+        floor: Address family -> minimal loopback RTT, in seconds.
+            None if the family is not answered.
+        median: Address family -> median loopback RTT, in seconds.
+            None if the family is not answered.
+        resolution: Resolution of the RTT clock, in seconds.
+            Coarse clock quantizes RTTs by its resolution.
+    """
+
+    __slots__ = ("count", "floor", "median", "resolution")
+
+    def __init__(
+        self,
+        count: int,
+        floor: Dict[int, Optional[float]],
+        median: Dict[int, Optional[float]],
+        resolution: float,
+    ) -> None:
+        self.count = count
+        self.floor = floor
+        self.median = median
+        self.resolution = resolution
+
+    def __repr__(self) -> str:
+        return (
+            f"<Calibration count={self.count} floor={self.floor}"
+            f" resolution={self.resolution}>"
+        )
+
+    @classmethod
+    def from_rtts(
+        cls,
+        count: int,
+        rtts: Dict[int, List[Optional[float]]],
+        resolution: float,
+    ) -> "Calibration":
+        """
+        Build calibration from the loopback RTTs.
+
+        Args:
+            count: Number of requests per address family.
+            rtts: Address family -> RTTs, None for timed out requests.
+            resolution: Resolution of the RTT clock, in seconds.
+
+        Returns:
+            Calibration instance.
+        """
+        floor: Dict[int, Optional[float]] = {}
+        median: Dict[int, Optional[float]] = {}
+        for afi, values in rtts.items():
+            answered = sorted(x for x in values if x is not None)
+            if answered:
+                floor[afi] = answered[0]
+                median[afi] = answered[len(answered) // 2]
+            else:
+                floor[afi] = median[afi] = None
+        return cls(count, floor, median, resolution)
+
+    def get_floor(self, addr: str) -> Optional[float]:
+        """
+        Get overhead floor for the address' family.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Floor, in seconds. None if not calibrated.
+        """
+        return self.floor.get(6 if ":" in addr else 4)
+
+    def subtract(self, addr: str, rtt: float) -> float:
+        """
+        Subtract the overhead floor from the RTT,
+        never going below zero.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            rtt: Measured RTT, in seconds.
+
+        Returns:
+            Network part of the RTT, in seconds.
+        """
+        floor = self.get_floor(addr)
+        if floor is None:
+            return rtt
+        return max(rtt - floor, 0.0)
@@ -0,0 +1,393 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Result emitters
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+from typing import Any, Dict, Hashable, List, Optional, Tuple, Union
+from uuid import UUID
+import json
+import os
+import socket
+import time
+
+# Gufo Labs modules
+from .stats import PingStats
+
+# Opaque id, joining the results with the external systems
+CorrelationId = Union[bytes, UUID]
+
+
+def format_correlation_id(cid: CorrelationId) -> str:
+    """
+    Format the correlation id as string: bytes as hex,
+    UUID in canonical form.
+
+    Args:
+        cid: Correlation id.
+
+    Returns:
+        Formatted correlation id.
+    """
+    if isinstance(cid, UUID):
+        return str(cid)
+    return cid.hex()
+
+
+class ProbeResult(object):
+    """
+    Completed probe's result, passed to the emitters.
+    New attributes are added with defaults, so the emitters
+    are not broken by the new fields.
+
+    Attributes:
+        target: Target address.
+        seq: ICMP sequence number.
+        rtt: Round-trip time in seconds, None if timed out.
+        ttl: Outgoing packet's TTL, None for OS default.
+        tag: Target's tag.
+        suppressed: Target is under maintenance.
+            See `Ping.add_maintenance`.
+        anomalous: RTT deviates from the target's baseline.
+            See `Ping.get_baseline`.
+        correlation_id: Probe's or target's correlation id.
+            See `Ping.set_correlation_id`.
+    """
+
+    __slots__ = (
+        "target",
+        "seq",
+        "rtt",
+        "ttl",
+        "tag",
+        "suppressed",
+        "anomalous",
+        "correlation_id",
+    )
+
+    def __init__(
+        self,
+        target: str,
+        seq: int,
+        rtt: Optional[float],
+        ttl: Optional[int] = None,
+        tag: Optional[Hashable] = None,
+        suppressed: bool = False,
+        anomalous: bool = False,
+        correlation_id: Optional[CorrelationId] = None,
+    ) -> None:
+        self.target = target
+        self.seq = seq
+        self.rtt = rtt
+        self.ttl = ttl
+        self.tag = tag
+        self.suppressed = suppressed
+        self.anomalous = anomalous
+        self.correlation_id = correlation_id
+
+    def __repr__(self) -> str:
+        return (
+            f"<ProbeResult target={self.target} seq={self.seq}"
+            f" rtt={self.rtt}>"
+        )
+
+
+class VerdictChange(object):
+    """
+    Change of the target's health check verdict, passed
+    to the emitters. New attributes are added with defaults,
+    like ones of `ProbeResult`.
+
+    Attributes:
+        target: Target address.
+        down: Target is down.
+        tag: Target's tag.
+        suppressed: Target is under maintenance.
+            See `Ping.add_maintenance`.
+    """
+
+    __slots__ = ("target", "down", "tag", "suppressed")
+
+    def __init__(
+        self,
+        target: str,
+        down: bool,
+        tag: Optional[Hashable] = None,
+        suppressed: bool = False,
+    ) -> None:
+        self.target = target
+        self.down = down
+        self.tag = tag
+        self.suppressed = suppressed
+
+    def __repr__(self) -> str:
+        return f"<VerdictChange target={self.target} down={self.down}>"
+
+
+class BaseEmitter(object):
+    """
+    Base class for result emitters. Emitter is called by `Ping`
+    for every completed probe.
+    """
+
+    def emit(self, result: ProbeResult) -> None:
+        """
+        Emit the probe's result.
+
+        Args:
+            result: Probe's result.
+        """
+        raise NotImplementedError
+
+    def emit_verdict(self, verdict: VerdictChange) -> None:
+        """
+        Emit the change of the target's health check verdict.
+        See `Ping.set_health_check`. Ignored by default.
+
+        Args:
+            verdict: Verdict change.
+        """
+
+    def close(self) -> None:
+        """
+        Flush pending output and release resources.
+        """
+
+
+class JsonLinesEmitter(BaseEmitter):
+    """
+    Write one JSON object per result to the file descriptor:
+
+    ``` json
+    {"target": "127.0.0.1", "seq": 1, "rtt_ns": 37000,
+     "status": "ok", "ttl": null, "ts": 1660000000.0}
+    ```
+
+    Where `status` is either `ok` or `timeout`, `rtt_ns` is null
+    on timeout, and `ts` is the UNIX time of the result.
+    `tag` is added for the tagged targets, `"suppressed": true`
+    is added within the target's maintenance window,
+    `"anomalous": true` is added for the RTT, deviating
+    from the target's baseline, and `correlation_id` is added
+    for the probes with correlation id: bytes as hex,
+    UUID in canonical form.
+
+    Changes of the health check verdict are written as:
+
+    ``` json
+    {"target": "127.0.0.1", "verdict": "down", "ts": 1660000000.0}
+    ```
+
+    Args:
+        fd: File descriptor, i.e. `sys.stdout.fileno()`.
+    """
+
+    def __init__(self, fd: int) -> None:
+        self.__fd = fd
+
+    def emit(self, result: ProbeResult) -> None:
+        rtt = result.rtt
+        r: Dict[str, Any] = {
+            "target": result.target,
+            "seq": result.seq,
+            "rtt_ns": None if rtt is None else int(rtt * 1_000_000_000),
+            "status": "timeout" if rtt is None else "ok",
+            "ttl": result.ttl,
+            "ts": time.time(),
+        }
+        if result.anomalous:
+            r["anomalous"] = True
+        if result.correlation_id is not None:
+            r["correlation_id"] = format_correlation_id(
+                result.correlation_id
+            )
+        self.__write(r, result.tag, result.suppressed)
+
+    def emit_verdict(self, verdict: VerdictChange) -> None:
+        r: Dict[str, Any] = {
+            "target": verdict.target,
+            "verdict": "down" if verdict.down else "up",
+            "ts": time.time(),
+        }
+        self.__write(r, verdict.tag, verdict.suppressed)
+
+    def __write(
+        self, r: Dict[str, Any], tag: Optional[Hashable], suppressed: bool
+    ) -> None:
+        """
+        Write the object as JSON line.
+
+        Args:
+            r: Object.
+            tag: Target's tag, added when set.
+            suppressed: Add `suppressed` flag, when set.
+        """
+        if tag is not None:
+            r["tag"] = tag
+        if suppressed:
+            r["suppressed"] = True
+        data = (json.dumps(r, default=str) + "\n").encode()
+        while data:
+            n = os.write(self.__fd, data)
+            data = data[n:]
+
+
+class InfluxEmitter(BaseEmitter):
+    """
+    Format results as InfluxDB line protocol:
+
+    ```
+    ping,target=127.0.0.1,tag=t1 seq=1i,status="ok",rtt=0.000037,ttl=10i
+    ```
+
+    Lines are buffered and written either to the file descriptor,
+    or to UDP endpoint of InfluxDB or Telegraf's `socket_listener`.
+    `rtt` is omitted on timeout, `tag` is omitted for
+    the untagged targets. `suppressed=true` is added within
+    the target's maintenance window, `anomalous=true` is added
+    for the RTT, deviating from the target's baseline.
+    `correlation_id` string field is added for the probes
+    with correlation id.
+
+    Args:
+        fd: File descriptor.
+        addr: UDP endpoint as (`host`, `port`).
+        measurement: Name of the results measurement.
+        batch_size: Write after accumulating `batch_size` lines.
+    """
+
+    def __init__(
+        self,
+        fd: Optional[int] = None,
+        addr: Optional[Tuple[str, int]] = None,
+        measurement: str = "ping",
+        batch_size: int = 100,
+    ) -> None:
+        if (fd is None) == (addr is None):
+            raise ValueError("Either fd or addr must be set")
+        self.__fd = fd
+        self.__addr = addr
+        self.__sock: Optional[socket.socket] = None
+        if addr is not None:
+            self.__sock = socket.socket(
+                socket.AF_INET6 if ":" in addr[0] else socket.AF_INET,
+                socket.SOCK_DGRAM,
+            )
+        self.__measurement = self.__escape(measurement)
+        self.__batch_size = batch_size
+        self.__lines: List[str] = []
+
+    @staticmethod
+    def __escape(value: Any) -> str:
+        """
+        Escape measurement name or tag value.
+
+        Args:
+            value: Value.
+
+        Returns:
+            Escaped value.
+        """
+        return (
+            str(value)
+            .replace("\\", "\\\\")
+            .replace(",", "\\,")
+            .replace(" ", "\\ ")
+            .replace("=", "\\=")
+        )
+
+    def emit(self, result: ProbeResult) -> None:
+        tags = f"target={self.__escape(result.target)}"
+        if result.tag is not None:
+            tags += f",tag={self.__escape(result.tag)}"
+        fields = [f"seq={result.seq}i"]
+        if result.rtt is None:
+            fields.append('status="timeout"')
+        else:
+            fields += ['status="ok"', f"rtt={result.rtt}"]
+        if result.ttl is not None:
+            fields.append(f"ttl={result.ttl}i")
+        if result.suppressed:
+            fields.append("suppressed=true")
+        if result.anomalous:
+            fields.append("anomalous=true")
+        if result.correlation_id is not None:
+            cid = format_correlation_id(result.correlation_id)
+            fields.append(f'correlation_id="{cid}"')
+        self.__add(f"{self.__measurement},{tags} {','.join(fields)}")
+
+    def emit_verdict(self, verdict: VerdictChange) -> None:
+        """
+        Emit the change of the health check verdict to
+        `<measurement>_verdict` measurement.
+
+        Args:
+            verdict: Verdict change.
+        """
+        tags = f"target={self.__escape(verdict.target)}"
+        if verdict.tag is not None:
+            tags += f",tag={self.__escape(verdict.tag)}"
+        fields = [f'verdict="{"down" if verdict.down else "up"}"']
+        if verdict.suppressed:
+            fields.append("suppressed=true")
+        self.__add(
+            f"{self.__measurement}_verdict,{tags} {','.join(fields)}"
+        )
+
+    def emit_stats(self, stats: Dict[Optional[Hashable], PingStats]) -> None:
+        """
+        Emit the aggregated statistics to `<measurement>_stats`
+        measurement, as returned by `Ping.get_stats`.
+
+        Args:
+            stats: Dict of tag -> `PingStats`.
+        """
+        for tag, st in stats.items():
+            name = f"{self.__measurement}_stats"
+            if tag is not None:
+                name += f",tag={self.__escape(tag)}"
+            fields = [
+                f"sent={st.sent}i",
+                f"received={st.received}i",
+                f"lost={st.lost}i",
+            ]
+            if st.received:
+                fields += [
+                    f"rtt_min={st.rtt_min}",
+                    f"rtt_avg={st.rtt_avg}",
+                    f"rtt_max={st.rtt_max}",
+                ]
+            self.__add(f"{name} {','.join(fields)}")
+
+    def __add(self, line: str) -> None:
+        """
+        Timestamp and buffer the line, write when batch is full.
+
+        Args:
+            line: Line without timestamp.
+        """
+        self.__lines.append(f"{line} {time.time_ns()}")
+        if len(self.__lines) >= self.__batch_size:
+            self.flush()
+
+    def flush(self) -> None:
+        """
+        Write buffered lines.
+        """
+        if not self.__lines:
+            return
+        data = ("\n".join(self.__lines) + "\n").encode()
+        self.__lines = []
+        if self.__sock and self.__addr:
+            self.__sock.sendto(data, self.__addr)
+        elif self.__fd is not None:
+            while data:
+                n = os.write(self.__fd, data)
+                data = data[n:]
+
+    def close(self) -> None:
+        self.flush()
+        if self.__sock:
+            self.__sock.close()
+            self.__sock = None
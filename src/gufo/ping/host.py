@@ -0,0 +1,167 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Resolve and ping combined check
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import asyncio
+import socket
+from time import perf_counter
+from typing import Dict, List, Optional
+
+# Gufo Labs modules
+from .socket import PingSocket
+from ._fast import REQUEST_ID_RANDOM
+
+FAMILIES = {None: socket.AF_UNSPEC, 4: socket.AF_INET, 6: socket.AF_INET6}
+
+
+class HostCheckResult(object):
+    """
+    Result of the end-to-end check of the host name:
+    resolution followed by ping of the resolved addresses.
+
+    Attributes:
+        host: Checked host name.
+        resolve_time: Resolution time, in seconds.
+        addresses: Resolved addresses, in resolver's order.
+        rtts: Address -> RTT in seconds, None for lost requests.
+        error: Resolution or probe error, None on success.
+            Addresses, which cannot be probed, are reported
+            as lost.
+    """
+
+    __slots__ = ("host", "resolve_time", "addresses", "rtts", "error")
+
+    def __init__(
+        self,
+        host: str,
+        resolve_time: float,
+        addresses: List[str],
+        rtts: Dict[str, Optional[float]],
+        error: Optional[str] = None,
+    ) -> None:
+        self.host = host
+        self.resolve_time = resolve_time
+        self.addresses = addresses
+        self.rtts = rtts
+        self.error = error
+
+    def __repr__(self) -> str:
+        return (
+            f"<HostCheckResult host={self.host} addr={self.addr}"
+            f" resolve_time={self.resolve_time} rtt={self.rtt}>"
+        )
+
+    @property
+    def resolved(self) -> bool:
+        """
+        Host name is resolved to at least one address.
+        """
+        return bool(self.addresses)
+
+    @property
+    def addr(self) -> Optional[str]:
+        """
+        Chosen address: the first answered one, in resolver's order.
+        None, when no address is answered.
+        """
+        for addr in self.addresses:
+            if self.rtts.get(addr) is not None:
+                return addr
+        return None
+
+    @property
+    def rtt(self) -> Optional[float]:
+        """
+        RTT of the chosen address, in seconds.
+        None, when no address is answered.
+        """
+        addr = self.addr
+        return None if addr is None else self.rtts[addr]
+
+    @property
+    def reachable(self) -> bool:
+        """
+        Any of the resolved addresses is answered.
+        """
+        return self.addr is not None
+
+
+async def check_host(
+    host: str,
+    *,
+    afi: Optional[int] = None,
+    timeout: float = 1.0,
+    max_addresses: int = 4,
+) -> HostCheckResult:
+    """
+    Resolve the host name and ping the resolved addresses
+    concurrently, reporting resolution time, the chosen address,
+    and its RTT as the single result.
+
+    Requests are sent over the dedicated sockets and are not
+    accounted in `Ping` statistics.
+
+    Args:
+        host: Host name or address.
+        afi: Resolve addresses of the family only: 4 or 6.
+            Resolve both when empty.
+        timeout: Timeout of requests, in seconds.
+        max_addresses: Ping up to `max_addresses` first
+            resolved addresses.
+
+    Returns:
+        HostCheckResult instance. Resolution and probe errors
+        are reported by `error`, rather than raised.
+    """
+    if afi not in FAMILIES:
+        raise ValueError("afi must be 4 or 6")
+    if max_addresses < 1:
+        raise ValueError("max_addresses must be positive")
+    t0 = perf_counter()
+    try:
+        info = await asyncio.get_running_loop().getaddrinfo(
+            host, None, family=FAMILIES[afi], type=socket.SOCK_DGRAM
+        )
+    except socket.gaierror as e:
+        return HostCheckResult(host, perf_counter() - t0, [], {}, str(e))
+    resolve_time = perf_counter() - t0
+    # Drop duplicates, keeping resolver's order
+    addresses = list(dict.fromkeys(str(x[4][0]) for x in info))
+    addresses = addresses[:max_addresses]
+
+    errors: List[str] = []
+
+    async def ping(sock: PingSocket, addr: str) -> Optional[float]:
+        try:
+            return await sock.ping(addr)
+        except (OSError, ValueError) as e:
+            # i.e. scoped IPv6 address
+            errors.append(f"{addr}: {e}")
+            return None
+
+    async def probe(afi: int, addrs: List[str]) -> List[Optional[float]]:
+        try:
+            async with PingSocket(
+                afi=afi, timeout=timeout, request_id_policy=REQUEST_ID_RANDOM
+            ) as sock:
+                return await asyncio.gather(*(ping(sock, a) for a in addrs))
+        except (OSError, ValueError) as e:
+            # i.e. permission denied or no IPv6 support
+            errors.append(f"IPv{afi}: {e}")
+            return [None] * len(addrs)
+
+    by_afi: Dict[int, List[str]] = {}
+    for addr in addresses:
+        by_afi.setdefault(6 if ":" in addr else 4, []).append(addr)
+    rtts: Dict[str, Optional[float]] = {}
+    for addrs, r in zip(
+        by_afi.values(),
+        await asyncio.gather(*(probe(a, x) for a, x in by_afi.items())),
+    ):
+        rtts.update(zip(addrs, r))
+    return HostCheckResult(
+        host, resolve_time, addresses, rtts, "; ".join(errors) or None
+    )
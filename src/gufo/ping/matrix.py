@@ -0,0 +1,159 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Matrix probe
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import asyncio
+import itertools
+from typing import Dict, Iterable, List, Optional, Tuple
+
+# Gufo Labs modules
+from .socket import PingSocket
+from ._fast import REQUEST_ID_RANDOM
+
+# size, DSCP, TTL
+Cell = Tuple[int, int, Optional[int]]
+
+
+class MatrixResult(object):
+    """
+    Result of the matrix probe: round-trip times of the requests
+    for every combination of size, DSCP, and TTL.
+
+    Attributes:
+        rtts: (size, dscp, ttl) -> list of RTTs in seconds,
+            None for lost requests. `ttl` is None for OS default.
+    """
+
+    __slots__ = ("rtts",)
+
+    def __init__(self, rtts: Dict[Cell, List[Optional[float]]]) -> None:
+        self.rtts = rtts
+
+    def __repr__(self) -> str:
+        return (
+            f"<MatrixResult cells={len(self.rtts)}"
+            f" lost={len(self.lost)}>"
+        )
+
+    def get_received(self, size: int, dscp: int, ttl: Optional[int]) -> int:
+        """
+        Get number of replies to the cell's requests.
+
+        Args:
+            size: Request size.
+            dscp: DSCP of requests.
+            ttl: TTL of requests.
+
+        Returns:
+            Number of replies.
+        """
+        return sum(1 for rtt in self.rtts[size, dscp, ttl] if rtt is not None)
+
+    @property
+    def reachable(self) -> bool:
+        """
+        Any request is answered.
+        """
+        return any(
+            rtt is not None for rtts in self.rtts.values() for rtt in rtts
+        )
+
+    @property
+    def lost(self) -> List[Cell]:
+        """
+        Cells, left without any reply.
+        """
+        return [
+            cell
+            for cell, rtts in self.rtts.items()
+            if all(rtt is None for rtt in rtts)
+        ]
+
+    @property
+    def max_size(self) -> Optional[int]:
+        """
+        Maximal answered size, None when unreachable.
+        Smaller than the maximal probed size indicates
+        "works small, fails big" case, i.e. MTU blackhole.
+        """
+        sizes = [
+            size
+            for (size, _, _), rtts in self.rtts.items()
+            if any(rtt is not None for rtt in rtts)
+        ]
+        return max(sizes) if sizes else None
+
+
+async def probe_matrix(
+    addr: str,
+    *,
+    sizes: Iterable[int] = (64, 1500),
+    dscps: Iterable[int] = (0,),
+    ttls: Iterable[Optional[int]] = (None,),
+    count: int = 1,
+    timeout: float = 1.0,
+    src_addr: Optional[str] = None,
+) -> MatrixResult:
+    """
+    Probe the target with the grid of sizes, DSCPs, and TTLs
+    for the quick triage of size and QoS-dependent loss.
+
+    Requests are sent over the dedicated sockets and are not
+    accounted in `Ping` statistics. The grid is probed at once,
+    so keep it small.
+
+    Args:
+        addr: Address to probe.
+        sizes: Sizes of requests, including IP header.
+        dscps: DSCPs of requests, 0-63.
+        ttls: TTLs of requests. None for OS default.
+        count: Number of requests of each cell.
+        timeout: Timeout of requests, in seconds.
+        src_addr: Send requests from the local address.
+
+    Returns:
+        MatrixResult instance.
+    """
+    sizes = list(sizes)
+    dscps = list(dscps)
+    ttls = list(ttls)
+    if count < 1:
+        raise ValueError("count must be positive")
+    if not sizes or not dscps or not ttls:
+        raise ValueError("sizes, dscps, and ttls must not be empty")
+    if any(not 0 <= dscp < 64 for dscp in dscps):
+        raise ValueError("dscp must be in range 0-63")
+    afi = 6 if ":" in addr else 4
+
+    async def probe(
+        dscp: int, ttl: Optional[int]
+    ) -> List[List[Optional[float]]]:
+        async with PingSocket(
+            afi=afi,
+            tos=dscp << 2,
+            ttl=ttl,
+            timeout=timeout,
+            request_id_policy=REQUEST_ID_RANDOM,
+            src_addr=src_addr,
+        ) as sock:
+            rtts = await asyncio.gather(
+                *(
+                    sock.ping(addr, size=size, seq=i * count + n)
+                    for i, size in enumerate(sizes)
+                    for n in range(count)
+                )
+            )
+        return [rtts[i * count : (i + 1) * count] for i in range(len(sizes))]
+
+    combos = list(itertools.product(dscps, ttls))
+    results = await asyncio.gather(*(probe(d, t) for d, t in combos))
+    return MatrixResult(
+        {
+            (size, dscp, ttl): rtts
+            for (dscp, ttl), row in zip(combos, results)
+            for size, rtts in zip(sizes, row)
+        }
+    )
@@ -0,0 +1,132 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Middlebox fingerprinting
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import asyncio
+from typing import Any, Dict, List, Optional, Tuple
+
+# Gufo Labs modules
+from .options import ProtoOptions
+from .socket import PingSocket
+from ._fast import REQUEST_ID_RANDOM
+
+# Name, PingSocket settings, and size of the probe.
+# Unusual probes are expected to be answered along with the baseline
+# ones, unless interfered on the path.
+PROBES: List[Tuple[str, Dict[str, Any], int]] = [
+    ("baseline", {}, 64),
+    ("code", {"proto_options": ProtoOptions(code=1)}, 64),
+    ("odd_size", {}, 65),
+    ("min_size", {}, 44),
+    ("large", {}, 1500),
+    ("fragmented", {}, 4000),
+    ("dscp_ef", {"tos": 0xB8}, 64),
+    ("ecn_ect1", {"tos": 0x01}, 64),
+    ("ecn_ce", {"tos": 0x03}, 64),
+]
+
+
+class MiddleboxResult(object):
+    """
+    Result of middlebox fingerprinting: number of replies
+    to the series of intentionally unusual probes.
+
+    Probes:
+
+    * `baseline` - ordinary 64-octet echo request.
+    * `code` - echo request with non-zero ICMP code.
+    * `odd_size` - odd-sized request.
+    * `min_size` - request without padding.
+    * `large` - 1500-octet request.
+    * `fragmented` - request, exceeding the common MTU.
+    * `dscp_ef` - request, marked with Expedited Forwarding DSCP.
+    * `ecn_ect1` - request, marked with ECT(1), former must-be-zero
+      bit of ToS.
+    * `ecn_ce` - request, marked with Congestion Experienced.
+
+    Attributes:
+        sent: Number of requests of each probe.
+        received: Number of replies to each probe.
+    """
+
+    __slots__ = ("sent", "received")
+
+    def __init__(self, sent: int, received: Dict[str, int]) -> None:
+        self.sent = sent
+        self.received = received
+
+    def __repr__(self) -> str:
+        return (
+            f"<MiddleboxResult sent={self.sent}"
+            f" reachable={self.reachable} dropped={self.dropped}>"
+        )
+
+    @property
+    def reachable(self) -> bool:
+        """
+        Baseline probe is answered.
+        """
+        return self.received.get("baseline", 0) > 0
+
+    @property
+    def dropped(self) -> List[str]:
+        """
+        Probes, left unanswered while the baseline probe is answered,
+        indicating the interference on the path.
+        """
+        if not self.reachable:
+            return []
+        return [name for name, n in self.received.items() if n == 0]
+
+
+async def probe_middlebox(
+    addr: str,
+    *,
+    count: int = 3,
+    timeout: float = 1.0,
+    src_addr: Optional[str] = None,
+) -> MiddleboxResult:
+    """
+    Send the series of intentionally unusual probes to the target
+    and record which ones get answered, to fingerprint middleboxes,
+    dropping or mangling the unusual packets.
+
+    Probes are sent over the dedicated sockets and are not accounted
+    in `Ping` statistics. Use sparingly: unusual packets may trigger
+    IDS alerts.
+
+    Args:
+        addr: Address to probe.
+        count: Number of requests of each probe.
+        timeout: Timeout of requests, in seconds.
+        src_addr: Send requests from the local address.
+
+    Returns:
+        MiddleboxResult instance.
+    """
+    if count < 1:
+        raise ValueError("count must be positive")
+    afi = 6 if ":" in addr else 4
+
+    async def probe(settings: Dict[str, Any], size: int) -> int:
+        async with PingSocket(
+            afi=afi,
+            timeout=timeout,
+            request_id_policy=REQUEST_ID_RANDOM,
+            src_addr=src_addr,
+            **settings,
+        ) as sock:
+            rtts = await asyncio.gather(
+                *(sock.ping(addr, size=size, seq=i) for i in range(count))
+            )
+        return sum(1 for rtt in rtts if rtt is not None)
+
+    received = await asyncio.gather(
+        *(probe(settings, size) for _, settings, size in PROBES)
+    )
+    return MiddleboxResult(
+        count, {name: n for (name, _, _), n in zip(PROBES, received)}
+    )
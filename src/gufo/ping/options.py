@@ -0,0 +1,58 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: ProtoOptions implementation
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+from typing import Optional
+
+
+class ProtoOptions(object):
+    """
+    Overrides of the ICMP protocol parameters of the socket,
+    for the exotic stacks and test harnesses.
+    Empty settings fall back to the protocol defaults.
+
+    Args:
+        request_type: ICMP type of requests. Echo Request
+            (8 for IPv4, 128 for IPv6) when empty.
+        reply_type: ICMP type of replies. Echo Reply
+            (0 for IPv4, 129 for IPv6) when empty.
+        code: ICMP code of requests.
+        verify_checksum: Verify checksum of ICMPv4 replies.
+            ICMPv6 checksum is always verified by kernel.
+    """
+
+    __slots__ = (
+        "request_type",
+        "reply_type",
+        "code",
+        "verify_checksum",
+    )
+
+    def __init__(
+        self,
+        request_type: Optional[int] = None,
+        reply_type: Optional[int] = None,
+        code: int = 0,
+        verify_checksum: bool = True,
+    ) -> None:
+        for name, value in (
+            ("request_type", request_type),
+            ("reply_type", reply_type),
+            ("code", code),
+        ):
+            if value is not None and (value < 0 or value > 255):
+                raise ValueError(f"{name} must be in 0..255 range")
+        self.request_type = request_type
+        self.reply_type = reply_type
+        self.code = code
+        self.verify_checksum = verify_checksum
+
+    def __repr__(self) -> str:
+        return (
+            f"<ProtoOptions request_type={self.request_type}"
+            f" reply_type={self.reply_type} code={self.code}"
+            f" verify_checksum={self.verify_checksum}>"
+        )
@@ -0,0 +1,76 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: OpenTelemetry emitter
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+"""
+OpenTelemetry metrics support. Requires `opentelemetry-api`,
+installed as `gufo_ping[otel]` extra.
+"""
+
+# Python modules
+from typing import Dict, Optional
+
+# Third-party modules
+from opentelemetry import metrics
+
+# Gufo Labs modules
+from .emitter import BaseEmitter, ProbeResult
+
+
+class OtelEmitter(BaseEmitter):
+    """
+    Record results as OpenTelemetry metrics:
+
+    * `ping.rtt` - histogram of round-trip times, in seconds.
+    * `ping.sent` - counter of sent requests.
+    * `ping.lost` - counter of lost requests.
+    * `ping.anomalous` - counter of RTTs, deviating from
+      the target's baseline.
+
+    Metrics are attributed with `target`, `tag` (for the tagged
+    targets), and `suppressed` (within the target's maintenance
+    window), and exported by the configured meter provider,
+    i.e. with OTLP exporter.
+
+    Args:
+        meter_provider: Meter provider. Use global one when empty.
+        per_target: Attribute metrics with `target`. Disable
+            to reduce cardinality, leaving `tag` only.
+    """
+
+    def __init__(
+        self,
+        meter_provider: Optional[metrics.MeterProvider] = None,
+        per_target: bool = True,
+    ) -> None:
+        meter = metrics.get_meter("gufo.ping", meter_provider=meter_provider)
+        self.__rtt = meter.create_histogram(
+            "ping.rtt", unit="s", description="Round-trip time"
+        )
+        self.__sent = meter.create_counter(
+            "ping.sent", description="Sent requests"
+        )
+        self.__lost = meter.create_counter(
+            "ping.lost", description="Lost requests"
+        )
+        self.__anomalous = meter.create_counter(
+            "ping.anomalous", description="Anomalous round-trip times"
+        )
+        self.__per_target = per_target
+
+    def emit(self, result: ProbeResult) -> None:
+        attrs: Dict[str, str] = {}
+        if self.__per_target:
+            attrs["target"] = result.target
+        if result.tag is not None:
+            attrs["tag"] = str(result.tag)
+        if result.suppressed:
+            attrs["suppressed"] = "true"
+        self.__sent.add(1, attrs)
+        if result.rtt is None:
+            self.__lost.add(1, attrs)
+        else:
+            self.__rtt.record(result.rtt, attrs)
+        if result.anomalous:
+            self.__anomalous.add(1, attrs)
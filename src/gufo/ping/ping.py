@@ -5,14 +5,79 @@
 # ---------------------------------------------------------------------
 
 # Python modules
-from typing import Optional, Dict, Tuple, AsyncIterable
+from typing import (
+    Any,
+    Optional,
+    Dict,
+    Tuple,
+    AsyncIterable,
+    Type,
+    Hashable,
+    Iterable,
+    Set,
+    Deque,
+    List,
+    Union,
+)
+from types import TracebackType
+from collections import deque
 import asyncio
+import grp
 import itertools
+import logging
+import math
+import pwd
+import signal
 import random
-from time import perf_counter
+from time import clock_gettime_ns, perf_counter, time
 
 # Gufo Labs modules
-from .socket import PingSocket
+from .socket import PingSocket, NS
+from .profile import ProbeProfile
+from .stats import PingStats
+from .burst import BurstResult
+from .schedule import Schedule, ScheduledProbe
+from .calibration import Calibration, LOOPBACK
+from .ratelimit import RateLimitDetector
+from .emitter import (
+    BaseEmitter,
+    CorrelationId,
+    ProbeResult,
+    VerdictChange,
+)
+from ._fast import (
+    REQUEST_ID_CALLER,
+    REQUEST_ID_RANDOM,
+    OVERLOAD_REJECT,
+    TS_PLAIN,
+    PADDING_FIXED,
+    MIMIC_NONE,
+    JITTER_NONE,
+    INVALID_RTT_CLAMP,
+    LinkMonitor,
+    HealthChecker,
+    Suppressor,
+    AnomalyDetector,
+    encode_stats,
+    decode_stats,
+    parse_config,
+    FileWatcher,
+    listen_fds,
+    sd_notify,
+    get_watchdog_usec,
+    drop_privileges,
+    enable_seccomp,
+    restrict_sockets,
+)
+
+logger = logging.getLogger(__name__)
+
+# Scheduled requests are passed to kernel in advance, in nanoseconds
+TXTIME_LEAD = 5_000_000
+# Interval multiplier on detected rate limiting
+BACKOFF_FACTOR = 2.0
+# Maximal backed off interval, in requested intervals
+MAX_BACKOFF = 16.0
 
 
 class Ping(object):
@@ -34,6 +99,79 @@ class Ping(object):
             fall back to CLOCK_MONOTONIC otherwise.
         accelerated: Enable platform-dependend accelerated
             socket processing.
+        busy_poll: Busy polling timeout, in microseconds (Linux).
+            Do not use busy polling when empty.
+        incoming_cpu: Process incoming packets on the given CPU
+            (Linux). Use OS defaults when empty.
+        max_in_flight: Limit number of in-flight requests.
+            Unlimited when empty.
+        overload_policy: Action on reaching `max_in_flight` limit:
+
+            * `OVERLOAD_REJECT` - `ping` raises `BlockingIOError`.
+            * `OVERLOAD_EVICT` - the request with the closest deadline
+              is considered timed out.
+        request_id_policy: ICMP request id allocation policy.
+            See `PingSocket` for details.
+        batch_size: Deliver replies in batches of up to `batch_size`
+            replies. Deliver immediately when empty.
+        batch_delay: Maximal delay of the batch delivery, in seconds.
+        ts_mode: Mode of timestamps, embedded into requests:
+            `TS_PLAIN`, `TS_OBFUSCATED`, or `TS_NONCE`.
+            See `SocketProto.set_ts_mode` for details.
+        padding: Padding of requests: `PADDING_FIXED` or
+            `PADDING_RANDOM`. See `SocketProto.set_padding` for details.
+        mimic: Reproduce payload of OS ping utility: `MIMIC_NONE`,
+            `MIMIC_LINUX`, or `MIMIC_WINDOWS`.
+            See `SocketProto.set_mimic` for details.
+        txtime: Release requests at exact times by kernel
+            (SO_TXTIME, Linux), according to the clock, i.e.
+            `time.CLOCK_MONOTONIC` for `fq` qdisc, or `time.CLOCK_TAI`
+            for `etf` qdisc. See `SocketProto.set_txtime` for details.
+            `iter_rtt` schedules requests on the exact interval grid.
+        tx_timestamps: Measure RTT from the time the requests
+            left the host, excluding local queuing delay (Linux).
+            See `SocketProto.set_tx_timestamps` for details.
+        jitter: Randomize `iter_rtt` intervals: `JITTER_NONE`,
+            `JITTER_UNIFORM`, or `JITTER_EXPONENTIAL`, preventing
+            probes of many targets from synchronizing into bursts.
+            See `SocketProto.set_jitter` for details.
+        jitter_percent: Randomized share of interval, in percents.
+        late_grace: Count replies, arrived up to `late_grace` seconds
+            after the timeout, as late rather than unknown.
+            See `SocketProto.set_late_grace` for details.
+        latency_budget: Account host-side latency of the pipeline
+            stages. See `get_latency_budget` for details.
+        recv_errors: Resolve requests, failed by ICMP errors
+            (unreachable, prohibited, time exceeded), as lost
+            without waiting for the timeout.
+        invalid_rtt: Handling of zero or negative RTT:
+            `INVALID_RTT_CLAMP`, `INVALID_RTT_ERROR`, or
+            `INVALID_RTT_RAW`. See `SocketProto.set_invalid_rtt`
+            for details.
+        rebuild: Re-create sockets on `ENETDOWN` and `EBADF` errors,
+            handing pending requests over, instead of failing
+            the requests. Rebuilds are logged as warnings.
+        rate_limit_backoff: Increase `iter_rtt` interval for the target
+            when its ICMP rate limiting is detected by the loss
+            pattern. See `get_rate_limit` for details.
+        link_monitor: Listen to netlink link events (Linux).
+            Probes to the targets behind the downed interface
+            are not sent and resolved as lost immediately, pending
+            ones are cancelled. See `is_link_down` for details.
+        anomaly_threshold: Flag RTTs, deviating from the target's
+            baseline more than `anomaly_threshold` mean absolute
+            deviations, as anomalous. See `get_baseline` for details.
+            Disabled when empty.
+        anomaly_alpha: Smoothing factor of the baseline, in (0, 1].
+        socket_activation: Use raw sockets, passed by systemd
+            socket activation, allowing to run unprivileged.
+            Inherited socket is used by the first socket of its
+            address family, the other ones are opened as usual.
+            See `notify_ready` for readiness notification.
+        schedule: Record the exact schedule of the probes,
+            to be replayed later by `replay`.
+        emitter: Pass every result to the emitter, i.e.
+            `JsonLinesEmitter`. Emitter is not closed by `close`.
 
     Note:
         Opening the Raw Socket may require super-user priveleges
@@ -52,6 +190,17 @@ class Ping(object):
             print(rtt)
         ```
 
+        Close sockets on exit.
+
+        ``` py
+        from gufo.ping import Ping
+
+        async def ping(address):
+            async with Ping() as p:
+                rtt = await p.ping(address)
+                print(rtt)
+        ```
+
         Ping multiple packets.
 
         ``` py
@@ -62,6 +211,30 @@ class Ping(object):
             async for rtt in p.iter_rtt(address):
                 print(rtt)
         ```
+
+        Group statistics by tenant.
+
+        ``` py
+        from gufo.ping import Ping
+
+        async def ping(address):
+            p = Ping()
+            p.set_tag(address, "customer1")
+            await p.ping(address)
+            print(p.get_stats()["customer1"])
+        ```
+
+        Probe the group of targets with the shared profile.
+
+        ``` py
+        from gufo.ping import Ping, ProbeProfile
+
+        async def ping(addresses):
+            p = Ping()
+            p.set_profile(addresses, ProbeProfile(interval=5.0, ttl=16))
+            async for rtt in p.iter_target(addresses[0]):
+                print(rtt)
+        ```
     """
 
     request_id = itertools.count(random.randint(0, 0xFFFF))
@@ -76,6 +249,32 @@ class Ping(object):
         recv_buffer_size: Optional[int] = None,
         coarse: bool = False,
         accelerated: bool = True,
+        request_id_policy: int = REQUEST_ID_CALLER,
+        busy_poll: Optional[int] = None,
+        incoming_cpu: Optional[int] = None,
+        max_in_flight: Optional[int] = None,
+        overload_policy: int = OVERLOAD_REJECT,
+        batch_size: Optional[int] = None,
+        batch_delay: float = 0.05,
+        ts_mode: int = TS_PLAIN,
+        padding: int = PADDING_FIXED,
+        mimic: int = MIMIC_NONE,
+        txtime: Optional[int] = None,
+        tx_timestamps: bool = False,
+        jitter: int = JITTER_NONE,
+        jitter_percent: float = 10.0,
+        late_grace: Optional[float] = None,
+        latency_budget: bool = False,
+        recv_errors: bool = False,
+        invalid_rtt: int = INVALID_RTT_CLAMP,
+        rebuild: bool = False,
+        rate_limit_backoff: bool = False,
+        link_monitor: bool = False,
+        anomaly_threshold: Optional[float] = None,
+        anomaly_alpha: float = 0.1,
+        socket_activation: bool = False,
+        schedule: Optional[Schedule] = None,
+        emitter: Optional[BaseEmitter] = None,
     ) -> None:
         self.__size = size
         self.__ttl = ttl
@@ -85,7 +284,1272 @@ class Ping(object):
         self.__recv_buffer_size = recv_buffer_size
         self.__coarse = coarse
         self.__accelerated = accelerated
-        self.__sockets: Dict[int, PingSocket] = {}
+        self.__request_id_policy = request_id_policy
+        self.__busy_poll = busy_poll
+        self.__incoming_cpu = incoming_cpu
+        self.__max_in_flight = max_in_flight
+        self.__overload_policy = overload_policy
+        self.__batch_size = batch_size
+        self.__batch_delay = batch_delay
+        self.__ts_mode = ts_mode
+        self.__padding = padding
+        self.__mimic = mimic
+        self.__txtime = txtime
+        self.__tx_timestamps = tx_timestamps
+        self.__jitter = jitter
+        self.__jitter_percent = jitter_percent
+        self.__late_grace = late_grace
+        self.__latency_budget = latency_budget
+        self.__recv_errors = recv_errors
+        self.__invalid_rtt = invalid_rtt
+        self.__rebuild = rebuild
+        self.__rate_limit_backoff = rate_limit_backoff
+        self.__link_monitor = link_monitor
+        self.__emitter = emitter
+        self.__sockets: Dict[
+            Tuple[int, Optional[ProbeProfile]], PingSocket
+        ] = {}
+        self.__profiles: Dict[str, ProbeProfile] = {}
+        self.__targets: Set[str] = set()
+        self.__tags: Dict[str, Hashable] = {}
+        self.__correlation_ids: Dict[str, CorrelationId] = {}
+        self.__stats: Dict[Optional[Hashable], PingStats] = {}
+        self.__rate_limits: Dict[str, float] = {}
+        self.__monitor: Optional[LinkMonitor] = None
+        self.__links: Dict[int, bool] = {}
+        self.__ifindexes: Dict[str, Optional[int]] = {}
+        self.__checks: Dict[str, HealthChecker] = {}
+        self.__suppressor = Suppressor()
+        self.__detector = (
+            AnomalyDetector(anomaly_alpha, anomaly_threshold)
+            if anomaly_threshold is not None
+            else None
+        )
+        self.__anomalous: Set[str] = set()
+        # Profile name -> (settings, profile)
+        self.__config_profiles: Dict[
+            str, Tuple[Dict[str, Any], ProbeProfile]
+        ] = {}
+        # Address -> effective settings
+        self.__config_targets: Dict[str, Tuple[Any, ...]] = {}
+        self.__watcher: Optional[FileWatcher] = None
+        self.__watched: Optional[str] = None
+        # Address family -> inherited socket's file descriptor
+        self.__inherited: Dict[int, int] = (
+            listen_fds() if socket_activation else {}
+        )
+        self.__notified = False
+        self.__watchdog: Optional[asyncio.TimerHandle] = None
+        self.__schedule = schedule
+        self.__calibration: Optional[Calibration] = None
+        self.__subtract_floor = False
+
+    @classmethod
+    def from_config(cls, path: str, **kwargs: Any) -> "Ping":
+        """
+        Create the engine from the configuration file,
+        the subset of TOML, parsed and validated in Rust:
+
+        ``` toml
+        # Ping settings
+        [ping]
+        timeout = 1.0
+        link_monitor = true
+
+        # ProbeProfile settings, along with the health check
+        [profile.core]
+        interval = 0.5
+        size = 128
+        dscp = 46
+        health_check = "lost >= 3 of 5 or p95 > 200ms"
+
+        # Registered targets
+        [[target]]
+        address = "192.0.2.1"
+        profile = "core"
+        tag = "router"
+        # Overrides profile's one
+        health_check = "lost >= 1 of 1"
+        ```
+
+        Settings of `[ping]` section are the numeric and boolean
+        `Ping` options.
+
+        Args:
+            path: Path to the configuration file.
+            kwargs: Additional `Ping` options, i.e. `emitter`.
+                Override ones of the configuration.
+
+        Returns:
+            Ping instance with registered targets.
+
+        Raises:
+            ValueError: On invalid configuration.
+        """
+        with open(path) as f:
+            config = parse_config(f.read())
+        ping = cls(**{**config["ping"], **kwargs})
+        ping.__apply_config(config)
+        return ping
+
+    def reload_config(
+        self, path: str
+    ) -> Tuple[List[str], List[str], List[str]]:
+        """
+        Reload the configuration file, created by `from_config`,
+        and apply the difference. Removed targets are unregistered,
+        new targets are registered, and changed profiles, tags,
+        and health checks are applied in place. In-flight requests
+        of the unaffected targets are not interrupted.
+        Changes of `[ping]` section require restart and are ignored.
+
+        Args:
+            path: Path to the configuration file.
+
+        Returns:
+            Tuple of lists of (`added`, `removed`, `changed`)
+            targets' addresses.
+
+        Raises:
+            ValueError: On invalid configuration. Current
+                configuration is left intact.
+        """
+        with open(path) as f:
+            config = parse_config(f.read())
+        return self.__apply_config(config)
+
+    def __apply_config(
+        self, config: Dict[str, Any]
+    ) -> Tuple[List[str], List[str], List[str]]:
+        """
+        Apply the difference between the parsed configuration
+        and the current one. Profiles are built before any change,
+        so the invalid configuration is not applied partially.
+
+        Args:
+            config: Configuration, as returned by `parse_config`.
+
+        Returns:
+            Tuple of lists of (`added`, `removed`, `changed`)
+            targets' addresses.
+
+        Raises:
+            ValueError: On invalid profile.
+        """
+        profiles: Dict[str, Tuple[Dict[str, Any], ProbeProfile]] = {}
+        checks: Dict[str, Optional[str]] = {}
+        # (current, new) profiles
+        replaced: List[Tuple[ProbeProfile, ProbeProfile]] = []
+        for name, options in config["profiles"].items():
+            checks[name] = options.pop("health_check", None)
+            current = self.__config_profiles.get(name)
+            if current and current[0] == options:
+                profiles[name] = current
+                continue
+            profile = ProbeProfile(**options)
+            if current:
+                replaced.append((current[1], profile))
+            profiles[name] = (options, profile)
+        targets: Dict[str, Tuple[Any, ...]] = {}
+        for target in config["targets"]:
+            name = target.get("profile")
+            options = profiles[name][0] if name else None
+            targets[target["address"]] = (
+                name,
+                options,
+                target.get("tag"),
+                target.get("health_check", checks.get(name)),
+            )
+        current_targets = self.__config_targets
+        removed = [addr for addr in current_targets if addr not in targets]
+        added = [addr for addr in targets if addr not in current_targets]
+        changed = [
+            addr
+            for addr, settings in targets.items()
+            if addr in current_targets and current_targets[addr] != settings
+        ]
+        for old, new in replaced:
+            # Reconfigure sockets in place
+            self.replace_profile(old, new)
+        for addr in removed:
+            self.unregister_target(addr)
+        for addr in added + changed:
+            name, _, tag, check = targets[addr]
+            profile = profiles[name][1] if name else None
+            if addr in current_targets:
+                self.set_profile([addr], profile)
+                self.set_tag(addr, tag)
+                if current_targets[addr][3] == check:
+                    continue
+            else:
+                self.register_target(addr, profile=profile, tag=tag)
+            self.set_health_check([addr], check)
+        self.__config_profiles = profiles
+        self.__config_targets = targets
+        return added, removed, changed
+
+    def notify_ready(self, status: Optional[str] = None) -> bool:
+        """
+        Notify systemd the service is ready (`Type=notify`) and
+        start sending keep-alive pings, when the watchdog is enabled
+        by `WatchdogSec`. `STOPPING=1` is sent on `close`.
+        Call within the running event loop.
+
+        Args:
+            status: Optional free-form status, shown by
+                `systemctl status`.
+
+        Returns:
+            True, if running under systemd.
+        """
+        state = "READY=1"
+        if status:
+            state += f"\nSTATUS={status}"
+        if not sd_notify(state):
+            return False
+        self.__notified = True
+        usec = get_watchdog_usec()
+        if usec and not self.__watchdog:
+            self.__on_watchdog(usec / 2_000_000)
+        return True
+
+    def drop_privileges(
+        self,
+        user: Optional[Union[str, int]] = None,
+        group: Optional[Union[str, int]] = None,
+        afi: Iterable[int] = (4, 6),
+    ) -> None:
+        """
+        Open the sockets for the `afi` address families and for
+        the registered targets and profiles, then switch to the user
+        and group, and clear the capabilities. The measurement
+        continues unprivileged over the opened sockets. Sockets,
+        required later, i.e. for the new profiles, cannot be opened.
+
+        Capabilities are per-thread on Linux, so the other threads
+        would keep them. The call is refused, when the process has
+        more than one thread, i.e. after the event loop's default
+        executor is used by `getaddrinfo`. Drop privileges before
+        starting the threads.
+
+        Args:
+            user: User name or uid. Keep the current user when empty.
+            group: Group name or gid. Defaults to the user's
+                primary group.
+            afi: Address families to open sockets for.
+
+        Raises:
+            OSError: On failure to open sockets or to drop privileges,
+                or when the process has more than one thread.
+            ValueError: When `rebuild` is set.
+        """
+        self.__check_no_rebuild()
+        self.__open_sockets(afi)
+        uid: Optional[int] = None
+        gid: Optional[int] = None
+        if user is not None:
+            pw = (
+                pwd.getpwuid(user)
+                if isinstance(user, int)
+                else pwd.getpwnam(user)
+            )
+            uid, gid = pw.pw_uid, pw.pw_gid
+        if group is not None:
+            gid = (
+                group if isinstance(group, int) else grp.getgrnam(group).gr_gid
+            )
+        drop_privileges(uid, gid)
+
+    def enable_sandbox(
+        self, kill: bool = False, afi: Iterable[int] = (4, 6)
+    ) -> None:
+        """
+        Open the sockets for the `afi` address families and for
+        the registered targets and profiles, then install the strict
+        seccomp filter for the calling thread (Linux). Only the probing
+        over the opened sockets, event polling, clocks, and the basic
+        runtime syscalls remain allowed. Opening files and sockets,
+        and spawning processes are denied. The filter cannot be
+        removed, so set up emitters, link monitor, and configuration
+        watcher in advance. Call within the running event loop.
+
+        Args:
+            kill: Kill the process on the denied syscall,
+                instead of failing it with `PermissionError`.
+            afi: Address families to open sockets for.
+
+        Raises:
+            OSError: On failure to open sockets or to install
+                the filter.
+            ValueError: When `rebuild` is set.
+        """
+        self.__check_no_rebuild()
+        self.__open_sockets(afi)
+        enable_seccomp(kill)
+
+    def restrict_sockets(
+        self,
+        kill: bool = False,
+        all_threads: bool = True,
+        afi: Iterable[int] = (4, 6),
+    ) -> None:
+        """
+        Open the sockets for the `afi` address families and for
+        the registered targets and profiles, then forbid creating
+        the raw, packet, and other sockets, beyond unix, TCP, UDP,
+        and routing netlink ones, by the seccomp filter (Linux).
+        Defense-in-depth for the process, holding `CAP_NET_RAW`,
+        leaving the rest of the application intact. Sockets,
+        required later, i.e. for the new profiles, cannot be opened.
+
+        Args:
+            kill: Kill the process on the denied syscall,
+                instead of failing it with `PermissionError`.
+            all_threads: Restrict all threads of the process,
+                or the calling thread only.
+            afi: Address families to open sockets for.
+
+        Raises:
+            OSError: On failure to open sockets or to install
+                the filter.
+            ValueError: When `rebuild` is set.
+        """
+        self.__check_no_rebuild()
+        self.__open_sockets(afi)
+        restrict_sockets(kill, all_threads)
+
+    def __check_no_rebuild(self) -> None:
+        """
+        Refuse to restrict the process, when `rebuild` is set:
+        rebuilt sockets cannot be opened, or the process is killed
+        on the attempt.
+
+        Raises:
+            ValueError: When `rebuild` is set.
+        """
+        if self.__rebuild:
+            raise ValueError("rebuild cannot reopen sockets when restricted")
+
+    def __open_sockets(self, afi: Iterable[int]) -> None:
+        """
+        Open sockets in advance, for the address families
+        and for the registered targets and profiles.
+
+        Args:
+            afi: Address families to open sockets for.
+        """
+        for x in afi:
+            self.__open_socket(x, None)
+        for addr in self.__targets | set(self.__profiles):
+            self.__get_socket(addr)
+
+    def __on_watchdog(self, interval: float) -> None:
+        """
+        Send watchdog keep-alive ping and schedule the next one.
+
+        Args:
+            interval: Keep-alive interval, in seconds.
+        """
+        sd_notify("WATCHDOG=1")
+        self.__watchdog = asyncio.get_running_loop().call_later(
+            interval, self.__on_watchdog, interval
+        )
+
+    def watch_config(self, path: str) -> None:
+        """
+        Reload the configuration file by `reload_config`
+        on SIGHUP, or when the file is written or replaced (Linux).
+        Reload errors are logged, leaving the current configuration
+        intact. Call within the running event loop.
+
+        Args:
+            path: Path to the configuration file.
+        """
+        loop = asyncio.get_running_loop()
+        self.__unwatch_config()
+        self.__watched = path
+        loop.add_signal_handler(signal.SIGHUP, self.__on_config_change)
+        try:
+            self.__watcher = FileWatcher(path)
+        except OSError as e:
+            logger.info("Cannot watch %s, reload on SIGHUP only: %s", path, e)
+            return
+        loop.add_reader(self.__watcher.get_fd(), self.__on_watch)
+
+    def __unwatch_config(self) -> None:
+        """
+        Stop watching the configuration file.
+        """
+        if not self.__watched:
+            return
+        loop = asyncio.get_running_loop()
+        loop.remove_signal_handler(signal.SIGHUP)
+        if self.__watcher:
+            loop.remove_reader(self.__watcher.get_fd())
+            self.__watcher.close()
+            self.__watcher = None
+        self.__watched = None
+
+    def __on_watch(self) -> None:
+        """
+        Handle configuration file events.
+        """
+        if self.__watcher and self.__watcher.recv():
+            self.__on_config_change()
+
+    def __on_config_change(self) -> None:
+        """
+        Reload the watched configuration file.
+        """
+        if not self.__watched:
+            return
+        try:
+            added, removed, changed = self.reload_config(self.__watched)
+        except (OSError, ValueError) as e:
+            logger.error("Failed to reload %s: %s", self.__watched, e)
+            return
+        logger.info(
+            "Reloaded %s: %d added, %d removed, %d changed",
+            self.__watched,
+            len(added),
+            len(removed),
+            len(changed),
+        )
+
+    async def __aenter__(self) -> "Ping":
+        return self
+
+    async def __aexit__(
+        self,
+        exc_type: Optional[Type[BaseException]],
+        exc_val: Optional[BaseException],
+        exc_tb: Optional[TracebackType],
+    ) -> None:
+        await self.close()
+
+    async def close(self, drain: bool = False) -> None:
+        """
+        Close all opened sockets.
+
+        Args:
+            drain: Wait until all pending requests are either
+                answered or expired before closing.
+        """
+        self.__unwatch_config()
+        if self.__watchdog:
+            self.__watchdog.cancel()
+            self.__watchdog = None
+        if self.__notified:
+            sd_notify("STOPPING=1")
+            self.__notified = False
+        for sock in self.__sockets.values():
+            await sock.close(drain=drain)
+        self.__sockets = {}
+        if self.__monitor:
+            asyncio.get_running_loop().remove_reader(
+                self.__monitor.get_fd()
+            )
+            self.__monitor.close()
+            self.__monitor = None
+            self.__links = {}
+            self.__ifindexes = {}
+
+    def register_target(
+        self,
+        addr: str,
+        profile: Optional[ProbeProfile] = None,
+        tag: Optional[Hashable] = None,
+    ) -> None:
+        """
+        Register the target. May be called while probing is running.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            profile: Target's `ProbeProfile`.
+            tag: Target's tag. See `set_tag` for details.
+        """
+        self.__targets.add(addr)
+        self.set_profile([addr], profile)
+        self.set_tag(addr, tag)
+
+    def unregister_target(self, addr: str) -> int:
+        """
+        Unregister the target with immediate effect. Pending
+        requests to the target are cancelled and resolved
+        as timed out, not affecting statistics. Running
+        `iter_target` loops are stopped.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Number of cancelled requests.
+        """
+        self.__targets.discard(addr)
+        self.__profiles.pop(addr, None)
+        self.__tags.pop(addr, None)
+        self.__correlation_ids.pop(addr, None)
+        self.__rate_limits.pop(addr, None)
+        self.__ifindexes.pop(addr, None)
+        self.__checks.pop(addr, None)
+        self.__suppressor.clear(addr)
+        if self.__detector:
+            self.__detector.remove(addr)
+        self.__anomalous.discard(addr)
+        return self.__cancel(addr)
+
+    def __cancel(self, addr: str) -> int:
+        """
+        Cancel all pending requests to the target.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Number of cancelled requests.
+        """
+        afi = self.__get_afi(addr)
+        return sum(
+            sock.cancel(addr)
+            for (sock_afi, _), sock in self.__sockets.items()
+            if sock_afi == afi
+        )
+
+    def get_route(self, addr: str) -> Dict[str, Any]:
+        """
+        Look up the route, the probes to the target take:
+        next-hop, egress interface, and source address (Linux).
+        Call on demand, i.e. when the target's RTT changes,
+        to attach the route to the results.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Dict of route details. See `SocketProto.get_route`
+            for details.
+
+        Raises:
+            OSError: When no route or not supported.
+        """
+        return self.__get_socket(addr).get_route(addr)
+
+    def is_link_down(self, addr: str) -> bool:
+        """
+        Check if the egress interface of the target is down,
+        according to netlink link events (Linux). Egress
+        interfaces are looked up on demand and cached until the next
+        link event. Call within the running event loop.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            * True, if the egress interface is down.
+            * False, if the interface is up, the target has no route,
+              or `link_monitor` is not set.
+        """
+        if not self.__link_monitor:
+            return False
+        if not self.__monitor:
+            self.__start_monitor()
+        if addr in self.__ifindexes:
+            ifindex = self.__ifindexes[addr]
+        else:
+            try:
+                ifindex = self.get_route(addr)["ifindex"]
+            except OSError:
+                ifindex = None
+            self.__ifindexes[addr] = ifindex
+        if ifindex is None:
+            return False
+        return not self.__links.get(ifindex, True)
+
+    def __start_monitor(self) -> None:
+        """
+        Subscribe to link events and get the current links' state.
+        """
+        self.__monitor = LinkMonitor()
+        self.__links = self.__monitor.get_links()
+        asyncio.get_running_loop().add_reader(
+            self.__monitor.get_fd(), self.__on_link
+        )
+
+    def __on_link(self) -> None:
+        """
+        Handle link events.
+        """
+        if not self.__monitor:
+            return
+        events = self.__monitor.recv()
+        if not events:
+            return
+        for ifindex, up in events:
+            self.__links[ifindex] = up
+        # Targets behind the downed interfaces
+        down = [
+            addr
+            for addr, ifindex in self.__ifindexes.items()
+            if ifindex is not None and not self.__links.get(ifindex, True)
+        ]
+        # Routes may be changed
+        self.__ifindexes = {}
+        for addr in down:
+            self.__cancel(addr)
+
+    def is_registered(self, addr: str) -> bool:
+        """
+        Check if the target is registered by `register_target`.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            True, if the target is registered.
+        """
+        return addr in self.__targets
+
+    def set_tag(self, addr: str, tag: Optional[Hashable]) -> None:
+        """
+        Assign an opaque tag (i.e. tenant) to the target.
+        Results of the target are accounted in the tag's statistics.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            tag: Any hashable value. Remove the tag when None.
+        """
+        if tag is None:
+            self.__tags.pop(addr, None)
+        else:
+            self.__tags[addr] = tag
+
+    def get_tag(self, addr: str) -> Optional[Hashable]:
+        """
+        Get the target's tag.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Tag, assigned by `set_tag`, or None.
+        """
+        return self.__tags.get(addr)
+
+    def set_correlation_id(
+        self, addr: str, cid: Optional[CorrelationId]
+    ) -> None:
+        """
+        Assign an opaque correlation id to the target's results,
+        passed to the emitter to join the measurements with
+        the external systems. The id is never sent over the wire.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            cid: Correlation id as bytes or UUID.
+                Remove the id when None.
+        """
+        if cid is None:
+            self.__correlation_ids.pop(addr, None)
+        else:
+            self.__correlation_ids[addr] = cid
+
+    def get_correlation_id(self, addr: str) -> Optional[CorrelationId]:
+        """
+        Get the target's correlation id.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Correlation id, assigned by `set_correlation_id`, or None.
+        """
+        return self.__correlation_ids.get(addr)
+
+    def set_profile(
+        self, addrs: Iterable[str], profile: Optional[ProbeProfile]
+    ) -> None:
+        """
+        Assign the probing profile to the group of targets.
+
+        Args:
+            addrs: Iterable of IPv4/IPv6 addresses.
+            profile: `ProbeProfile` instance. Remove the profile
+                when None.
+        """
+        if profile is None:
+            for addr in addrs:
+                self.__profiles.pop(addr, None)
+        else:
+            self.__profiles.update((addr, profile) for addr in addrs)
+
+    def get_profile(self, addr: str) -> Optional[ProbeProfile]:
+        """
+        Get the target's profile.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Profile, assigned by `set_profile`, or None.
+        """
+        return self.__profiles.get(addr)
+
+    def replace_profile(self, old: ProbeProfile, new: ProbeProfile) -> None:
+        """
+        Replace the profile for all targets at once. Sockets
+        of the old profile are reconfigured in place. Empty
+        `timeout`, `dscp`, and `ttl` of the new profile fall back
+        to `Ping` defaults, or leave the current values intact
+        if no defaults are set.
+
+        Args:
+            old: Profile in use.
+            new: Replacing profile.
+
+        Raises:
+            ValueError: if `new` profile is already in use.
+        """
+        if old is new:
+            return
+        if new in self.__profiles.values():
+            raise ValueError("profile is already in use")
+        for afi in (4, 6):
+            sock = self.__sockets.pop((afi, old), None)
+            if sock is None:
+                continue
+            sock.reconfigure(
+                timeout=self.__timeout if new.timeout is None else new.timeout,
+                ttl=self.__ttl if new.ttl is None else new.ttl,
+                tos=self.__tos if new.tos is None else new.tos,
+            )
+            self.__sockets[afi, new] = sock
+        for addr, profile in self.__profiles.items():
+            if profile is old:
+                self.__profiles[addr] = new
+
+    def set_health_check(
+        self, addrs: Iterable[str], policy: Optional[str]
+    ) -> None:
+        """
+        Attach the health check policy to the group of targets.
+        Policy is evaluated on every result of the target, and
+        the changes of the verdict are passed to the emitter's
+        `emit_verdict`.
+
+        Policy is the expression, marking the target down when true.
+        Expression is `or` of `and`-joined conditions:
+
+        * `lost >= 3 of 5`: number of lost requests among the latest
+          ones.
+        * `p95 > 200ms`, `avg`, `min`, `max`: aggregate of RTTs
+          of the replies, received within the window. Units are
+          `ns`, `us`, `ms` (default), and `s`.
+
+        Window is the largest `of` of `lost` conditions, or 10.
+        Operators are `>`, `>=`, `<`, `<=`.
+
+        Args:
+            addrs: Iterable of IPv4/IPv6 addresses.
+            policy: Policy expression, i.e.
+                `lost >= 3 of 5 or p95 > 200ms`. Remove the health
+                check when None.
+
+        Raises:
+            ValueError: On invalid policy.
+        """
+        if policy is None:
+            for addr in addrs:
+                self.__checks.pop(addr, None)
+            return
+        HealthChecker(policy)  # Validate
+        self.__checks.update((addr, HealthChecker(policy)) for addr in addrs)
+
+    def is_down(self, addr: str) -> Optional[bool]:
+        """
+        Get the health check verdict of the target.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            * True, if the target is down.
+            * False, if the target is up.
+            * None, if the target has no health check,
+              or no results yet.
+        """
+        check = self.__checks.get(addr)
+        if check is None:
+            return None
+        return check.is_down()
+
+    def add_maintenance(
+        self, addrs: Iterable[str], start: float, end: float
+    ) -> None:
+        """
+        Add the maintenance window to the group of targets.
+        Probes still run within the window, but results
+        are passed to the emitter with `suppressed` flag,
+        so the alerting may ignore the planned work.
+        Ended windows are removed automatically.
+
+        Args:
+            addrs: Iterable of IPv4/IPv6 addresses.
+            start: Start of the window, UNIX timestamp.
+            end: End of the window, UNIX timestamp.
+
+        Raises:
+            ValueError: If `end` is not after `start`.
+        """
+        if end <= start:
+            raise ValueError("end must be after start")
+        for addr in addrs:
+            self.__suppressor.add(addr, start, end)
+
+    def clear_maintenance(self, addrs: Iterable[str]) -> None:
+        """
+        Remove all maintenance windows of the group of targets.
+
+        Args:
+            addrs: Iterable of IPv4/IPv6 addresses.
+        """
+        for addr in addrs:
+            self.__suppressor.clear(addr)
+
+    def get_maintenance(self, addr: str) -> List[Tuple[float, float]]:
+        """
+        Get the maintenance windows of the target.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            List of (`start`, `end`) UNIX timestamps.
+        """
+        return self.__suppressor.get(addr)
+
+    def is_suppressed(self, addr: str, ts: Optional[float] = None) -> bool:
+        """
+        Check if the target's results are suppressed
+        by the maintenance window.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            ts: UNIX timestamp. Use current time, when empty.
+
+        Returns:
+            True, if the target is under maintenance.
+        """
+        return self.__suppressor.is_suppressed(
+            addr, time() if ts is None else ts
+        )
+
+    def get_baseline(self, addr: str) -> Optional[Tuple[float, float]]:
+        """
+        Get the RTT baseline of the target, learned when
+        `anomaly_threshold` is set.
+
+        Baseline is the exponentially weighted moving average of RTT
+        and mean absolute deviation (MAD) from it. RTT is flagged as
+        anomalous, when it deviates from the average more than
+        `anomaly_threshold` MADs. Anomalies are not flagged during the
+        first 10 replies, while the baseline is learned.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            (`average`, `mad`) in seconds, or None, if there are
+            no replies from the target yet.
+        """
+        if not self.__detector:
+            return None
+        r = self.__detector.get(addr)
+        if r is None:
+            return None
+        return r[0] / NS, r[1] / NS
+
+    def is_anomalous(self, addr: str) -> bool:
+        """
+        Check if the latest RTT of the target is anomalous.
+        See `get_baseline` for details.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            True, if the latest RTT deviates from the baseline.
+        """
+        return addr in self.__anomalous
+
+    def export_baselines(self) -> bytes:
+        """
+        Export RTT baselines of all targets, to be restored
+        by `import_baselines` after the restart.
+
+        Returns:
+            Serialized baselines.
+
+        Raises:
+            RuntimeError: If `anomaly_threshold` is not set.
+        """
+        if not self.__detector:
+            raise RuntimeError("Anomaly detection is disabled")
+        return self.__detector.export_state()
+
+    def import_baselines(self, state: bytes) -> None:
+        """
+        Import RTT baselines, exported by `export_baselines`.
+        Baselines of the known targets are replaced.
+
+        Args:
+            state: Serialized baselines.
+
+        Raises:
+            RuntimeError: If `anomaly_threshold` is not set.
+            ValueError: On invalid state.
+        """
+        if not self.__detector:
+            raise RuntimeError("Anomaly detection is disabled")
+        self.__detector.import_state(state)
+
+    def get_rate_limit(self, addr: str) -> Optional[float]:
+        """
+        Get the probe rate of `iter_rtt` for the target, applied
+        after ICMP rate limiting is detected. Rate limiting
+        is detected by the periodic loss at the constant
+        probe rate. The interval is doubled on every detection,
+        up to 16 times of the requested one,
+        when `rate_limit_backoff` is set, and left intact otherwise.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            * Probe rate, in requests per second.
+            * None - if rate limiting is not detected.
+        """
+        return self.__rate_limits.get(addr)
+
+    def __on_rate_limit(
+        self, addr: str, interval: float, base: float
+    ) -> float:
+        """
+        Back off the target's interval on detected rate limiting.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            interval: Current interval, in seconds.
+            base: Requested interval, in seconds.
+
+        Returns:
+            Interval to apply, in seconds.
+        """
+        if self.__rate_limit_backoff:
+            interval = min(interval * BACKOFF_FACTOR, base * MAX_BACKOFF)
+        self.__rate_limits[addr] = 1.0 / interval
+        return interval
+
+    def get_latency_budget(self) -> Optional[Dict[str, Dict[str, Any]]]:
+        """
+        Get histograms of the host-side latency of the pipeline
+        stages, merged over the opened sockets. Compare with RTTs
+        to tell whether RTT inflation is caused by the network
+        or by the host. See `SocketProto.get_latency_budget`
+        for the stages and the histogram format.
+
+        Returns:
+            Dict of stage -> histogram, None unless `latency_budget`
+            is set.
+        """
+        if not self.__latency_budget:
+            return None
+        r: Dict[str, Dict[str, Any]] = {}
+        for sock in self.__sockets.values():
+            for stage, h in (sock.get_latency_budget() or {}).items():
+                m = r.get(stage)
+                if m is None:
+                    r[stage] = dict(h)
+                    continue
+                m["count"] += h["count"]
+                m["sum"] += h["sum"]
+                for key, fn in (("min", min), ("max", max)):
+                    if h[key] is not None:
+                        m[key] = (
+                            h[key] if m[key] is None else fn(m[key], h[key])
+                        )
+                buckets = dict(m["buckets"])
+                for upper, n in h["buckets"]:
+                    buckets[upper] = buckets.get(upper, 0) + n
+                m["buckets"] = sorted(buckets.items())
+        for m in r.values():
+            m["p50"] = self.__get_quantile(m, 0.5)
+            m["p99"] = self.__get_quantile(m, 0.99)
+        return r
+
+    @staticmethod
+    def __get_quantile(h: Dict[str, Any], q: float) -> Optional[int]:
+        """
+        Estimate quantile of the histogram as the upper bound
+        of the bucket, clamped by the minimal and maximal values.
+
+        Args:
+            h: Histogram, as returned by `get_latency_budget`.
+            q: Quantile, in [0, 1] range.
+
+        Returns:
+            Quantile in nanoseconds, None if empty.
+        """
+        if not h["count"]:
+            return None
+        rank = max(math.ceil(q * h["count"]), 1)
+        seen = 0
+        for upper, n in h["buckets"]:
+            seen += n
+            if seen >= rank:
+                return int(max(min(upper - 1, h["max"]), h["min"]))
+        return int(h["max"])
+
+    async def calibrate(
+        self,
+        count: int = 10,
+        afi: Iterable[int] = (4, 6),
+        subtract: bool = False,
+    ) -> Calibration:
+        """
+        Ping the loopback to measure the host-side overhead floor
+        of RTTs and the clock's quantization. Call at startup,
+        before the measurements. Loopback requests are sent
+        one by one over the dedicated sockets with the engine's
+        settings, and are not accounted in the statistics.
+
+        Address families, unavailable on the host, are left
+        with empty floor.
+
+        Args:
+            count: Number of requests per address family.
+            afi: Address families to calibrate.
+            subtract: Subtract the floor from RTTs, accounted
+                in the statistics. RTTs, returned by the probing
+                methods, are left intact.
+
+        Returns:
+            Calibration instance, also available via
+            `get_calibration`.
+
+        Raises:
+            ValueError: On invalid count or address family.
+        """
+        if count < 1:
+            raise ValueError("count must be positive")
+        rtts: Dict[int, List[Optional[float]]] = {}
+        resolution = 0.0
+        for family in afi:
+            addr = LOOPBACK.get(family)
+            if addr is None:
+                raise ValueError(f"invalid address family: {family}")
+            try:
+                async with PingSocket(
+                    afi=family,
+                    size=self.__size,
+                    timeout=self.__timeout,
+                    coarse=self.__coarse,
+                    accelerated=self.__accelerated,
+                    request_id_policy=REQUEST_ID_RANDOM,
+                    busy_poll=self.__busy_poll,
+                    ts_mode=self.__ts_mode,
+                    tx_timestamps=self.__tx_timestamps,
+                ) as sock:
+                    resolution = sock.get_clock_resolution()
+                    rtts[family] = [
+                        await sock.ping(addr, seq=seq)
+                        for seq in range(count)
+                    ]
+            except PermissionError:
+                raise
+            except OSError as e:
+                logger.info("Cannot calibrate IPv%d: %s", family, e)
+                rtts[family] = []
+        self.__calibration = Calibration.from_rtts(count, rtts, resolution)
+        self.__subtract_floor = subtract
+        return self.__calibration
+
+    def get_calibration(self) -> Optional[Calibration]:
+        """
+        Get the result of the last `calibrate` call.
+
+        Returns:
+            Calibration instance, None if not calibrated.
+        """
+        return self.__calibration
+
+    def get_stats(self) -> Dict[Optional[Hashable], PingStats]:
+        """
+        Get statistics, grouped by tag. Results of untagged
+        targets are accounted under the None key.
+
+        Returns:
+            Dict of tag -> `PingStats`.
+        """
+        return dict(self.__stats)
+
+    def reset_stats(self) -> None:
+        """
+        Reset collected statistics.
+        """
+        self.__stats = {}
+
+    def save_stats(self) -> bytes:
+        """
+        Export collected statistics, along with RTT baselines
+        when `anomaly_threshold` is set, to be restored by `load_stats`
+        after the restart. Format is versioned binary.
+
+        Returns:
+            Serialized statistics.
+
+        Raises:
+            ValueError: If tag is not str, int, or None.
+        """
+        return encode_stats(
+            [
+                (
+                    tag,
+                    (
+                        st.sent,
+                        st.received,
+                        st.rtt_min,
+                        st.rtt_max,
+                        st.rtt_sum,
+                        st.bursts,
+                        st.bursts_all_lost,
+                        st.bursts_partial,
+                        st.bursts_reordered,
+                    ),
+                )
+                for tag, st in self.__stats.items()
+            ],
+            self.__detector.export_state() if self.__detector else None,
+        )
+
+    def load_stats(self, state: bytes) -> None:
+        """
+        Import statistics, exported by `save_stats`.
+        Statistics of the saved tags are replaced.
+        Baselines are imported when `anomaly_threshold` is set,
+        and ignored otherwise.
+
+        Args:
+            state: Serialized statistics.
+
+        Raises:
+            ValueError: On invalid state.
+        """
+        stats, baselines = decode_stats(state)
+        for tag, x in stats:
+            st = PingStats()
+            (
+                st.sent,
+                st.received,
+                st.rtt_min,
+                st.rtt_max,
+                st.rtt_sum,
+                st.bursts,
+                st.bursts_all_lost,
+                st.bursts_partial,
+                st.bursts_reordered,
+            ) = x
+            self.__stats[tag] = st
+        if baselines and self.__detector:
+            self.__detector.import_state(baselines)
+
+    def __register(
+        self,
+        addr: str,
+        seq: int,
+        rtt: Optional[float],
+        correlation_id: Optional[CorrelationId] = None,
+    ) -> None:
+        """
+        Account the result in the target's tag statistics,
+        health check, and baseline, and pass it to the emitter.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            seq: ICMP sequence number.
+            rtt: Round-trip time in seconds, None if timed out.
+            correlation_id: Probe's correlation id. Use the target's
+                one, when empty.
+        """
+        tag = self.__tags.get(addr)
+        if rtt is not None and self.__subtract_floor and self.__calibration:
+            self.__get_stats(tag).register(
+                self.__calibration.subtract(addr, rtt)
+            )
+        else:
+            self.__get_stats(tag).register(rtt)
+        check = self.__checks.get(addr)
+        down = (
+            check.register(None if rtt is None else int(rtt * NS))
+            if check
+            else None
+        )
+        anomalous = False
+        if self.__detector and rtt is not None:
+            anomalous = self.__detector.register(addr, int(rtt * NS))
+            if anomalous:
+                self.__anomalous.add(addr)
+            else:
+                self.__anomalous.discard(addr)
+        if self.__emitter:
+            profile = self.__profiles.get(addr)
+            ttl = self.__ttl
+            if profile and profile.ttl is not None:
+                ttl = profile.ttl
+            suppressed = self.is_suppressed(addr)
+            if correlation_id is None:
+                correlation_id = self.__correlation_ids.get(addr)
+            self.__emitter.emit(
+                ProbeResult(
+                    addr,
+                    seq,
+                    rtt,
+                    ttl=ttl,
+                    tag=tag,
+                    suppressed=suppressed,
+                    anomalous=anomalous,
+                    correlation_id=correlation_id,
+                )
+            )
+            if down is not None:
+                self.__emitter.emit_verdict(
+                    VerdictChange(addr, down, tag=tag, suppressed=suppressed)
+                )
+
+    def __register_burst(self, addr: str, result: BurstResult) -> None:
+        """
+        Account the burst's result in the target's tag statistics.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            result: Burst result.
+        """
+        self.__get_stats(self.__tags.get(addr)).register_burst(result)
+
+    def __get_stats(self, tag: Optional[Hashable]) -> PingStats:
+        """
+        Get or create the tag's statistics.
+
+        Args:
+            tag: Target's tag.
+
+        Returns:
+            PingStats instance.
+        """
+        stats = self.__stats.get(tag)
+        if stats is None:
+            stats = PingStats()
+            self.__stats[tag] = stats
+        return stats
 
     @staticmethod
     def __get_afi(address: str) -> int:
@@ -114,23 +1578,98 @@ class Ping(object):
         Returns:
             Initialized socket instance
         """
-        afi = self.__get_afi(address)
-        sock = self.__sockets.get(afi)
+        return self.__open_socket(
+            self.__get_afi(address), self.__profiles.get(address)
+        )
+
+    def __open_socket(
+        self, afi: int, profile: Optional[ProbeProfile]
+    ) -> PingSocket:
+        """
+        Get ping socket instance for the address family and profile.
+        Initialize when necessary.
+
+        Args:
+            afi: Address family, either 4 or 6.
+            profile: Target's `ProbeProfile`, if any.
+
+        Returns:
+            Initialized socket instance
+        """
+        sock = self.__sockets.get((afi, profile))
         if not sock:
+            ttl, tos, timeout = self.__ttl, self.__tos, self.__timeout
+            if profile:
+                if profile.ttl is not None:
+                    ttl = profile.ttl
+                if profile.tos is not None:
+                    tos = profile.tos
+                if profile.timeout is not None:
+                    timeout = profile.timeout
             sock = PingSocket(
                 afi=afi,
                 size=self.__size,
-                ttl=self.__ttl,
-                tos=self.__tos,
-                timeout=self.__timeout,
+                ttl=ttl,
+                tos=tos,
+                timeout=timeout,
                 send_buffer_size=self.__send_buffer_size,
                 recv_buffer_size=self.__recv_buffer_size,
                 coarse=self.__coarse,
                 accelerated=self.__accelerated,
+                request_id_policy=self.__request_id_policy,
+                busy_poll=self.__busy_poll,
+                incoming_cpu=self.__incoming_cpu,
+                max_in_flight=self.__max_in_flight,
+                overload_policy=self.__overload_policy,
+                batch_size=self.__batch_size,
+                batch_delay=self.__batch_delay,
+                ts_mode=self.__ts_mode,
+                padding=self.__padding,
+                mimic=self.__mimic,
+                txtime=self.__txtime,
+                tx_timestamps=self.__tx_timestamps,
+                jitter=self.__jitter,
+                jitter_percent=self.__jitter_percent,
+                late_grace=self.__late_grace,
+                latency_budget=self.__latency_budget,
+                recv_errors=self.__recv_errors,
+                invalid_rtt=self.__invalid_rtt,
+                rebuild=self.__rebuild,
+                on_rebuild=self.__on_rebuild,
+                fd=self.__inherited.pop(afi, None),
+                schedule=self.__schedule,
             )
-            self.__sockets[afi] = sock
+            self.__sockets[afi, profile] = sock
         return sock
 
+    @staticmethod
+    def __on_rebuild(e: OSError) -> None:
+        """
+        Log the socket's rebuild.
+
+        Args:
+            e: Error, caused the rebuild.
+        """
+        logger.warning("Socket is re-created after error: %s", e)
+
+    def __get_size(self, addr: str, size: Optional[int]) -> Optional[int]:
+        """
+        Get packet's size, falling back to the target's profile.
+
+        Args:
+            addr: IPv4/IPv6 address.
+            size: Requested size.
+
+        Returns:
+            Packet size or None to use the socket's default.
+        """
+        if size:
+            return size
+        profile = self.__profiles.get(addr)
+        if profile:
+            return profile.size
+        return None
+
     def __get_request_id(self) -> Tuple[int, int]:
         """
         Generate ICMP request id and starting
@@ -147,6 +1686,8 @@ class Ping(object):
         self,
         addr: str,
         size: Optional[int] = None,
+        src_addr: Optional[str] = None,
+        correlation_id: Optional[CorrelationId] = None,
     ) -> Optional[float]:
         """
         Send ICMP echo request to the given address and await
@@ -154,16 +1695,35 @@ class Ping(object):
 
         Args:
             addr: IPv4/IPv6 address to ping.
-            size: Packet's size, including IP headers. Use PingSocket
-                intialized defaults, when empty.
+            size: Packet's size, including IP headers. Use the target's
+                profile or PingSocket intialized defaults, when empty.
+            src_addr: Send request from the local address.
+                Use OS defaults when empty.
+            correlation_id: Pass the correlation id with the result
+                to the emitter, overriding the target's one.
+                See `set_correlation_id`.
 
         Returns:
             * Round-trip time in seconds (as float) if success.
             * None - if failed or timed out.
         """
         sock = self.__get_socket(addr)
+        size = self.__get_size(addr, size)
         request_id, seq = self.__get_request_id()
-        return await sock.ping(addr, size=size, request_id=request_id, seq=seq)
+        registered = addr in self.__targets
+        if self.is_link_down(addr):
+            rtt = None
+        else:
+            rtt = await sock.ping(
+                addr,
+                size=size,
+                request_id=request_id,
+                seq=seq,
+                src_addr=src_addr,
+            )
+        if not registered or addr in self.__targets:
+            self.__register(addr, seq, rtt, correlation_id)
+        return rtt
 
     async def iter_rtt(
         self,
@@ -172,18 +1732,29 @@ class Ping(object):
         size: Optional[int] = None,
         interval: Optional[float] = 1.0,
         count: Optional[int] = None,
+        window: int = 1,
     ) -> AsyncIterable[Optional[float]]:
         """
         Send echo request every `interval` seconds,
         await and yield the result.
 
+        Stops when the target is unregistered by `unregister_target`.
+
+        When `window` is greater than 1, up to `window` requests
+        may be outstanding at once, each with its own sequence number.
+        Next request is sent every `interval` without waiting
+        for the previous reply, so probing rate is not bound
+        by round-trip time. Results are still yielded in order
+        of sending.
+
         Args:
             addr: Address to ping.
-            size: Packets' size, including IP headers. Use PingSocket
-                intialized defaults, when empty.
+            size: Packets' size, including IP headers. Use the target's
+                profile or PingSocket intialized defaults, when empty.
             interval: Interval between requests, in seconds.
             count: Stop after `count` requests, if set. Do not stop
                 otherwise.
+            window: Maximal number of outstanding requests.
 
         Returns:
             Yields for each attempt:
@@ -192,21 +1763,319 @@ class Ping(object):
             * None - if failed or timed out.
 
         """
+        if window < 1:
+            raise ValueError("window must be positive")
         sock = self.__get_socket(addr)
+        size = self.__get_size(addr, size)
         request_id, seq = self.__get_request_id()
+        registered = addr in self.__targets
+        if window > 1:
+            async for rtt in self.__iter_window(
+                addr,
+                sock,
+                size=size,
+                request_id=request_id,
+                seq=seq,
+                interval=interval,
+                count=count,
+                window=window,
+                registered=registered,
+            ):
+                yield rtt
+            return
+        base = interval
+        detector = RateLimitDetector() if interval else None
+        # Schedule requests by kernel
+        txtime = self.__txtime if interval else None
+        at: Optional[int] = None
         t0 = 0.0
         n = 0
-        while True:
-            if interval:
+        while not registered or addr in self.__targets:
+            if txtime is not None:
+                # Keep the grid unless falling behind
+                at = max(at or 0, clock_gettime_ns(txtime) + TXTIME_LEAD)
+            elif interval:
                 t0 = perf_counter()
-            yield await sock.ping(
-                addr, size=size, request_id=request_id, seq=seq
-            )
+            if self.is_link_down(addr):
+                # Do not probe, resolve as lost
+                rtt = None
+                if not interval:
+                    await asyncio.sleep(self.__timeout)
+            else:
+                rtt = await sock.ping(
+                    addr, size=size, request_id=request_id, seq=seq, at=at
+                )
+            if registered and addr not in self.__targets:
+                break  # Unregistered
+            self.__register(addr, seq, rtt)
+            if detector and interval and base:
+                if detector.register(rtt is not None):
+                    interval = self.__on_rate_limit(addr, interval, base)
+            yield rtt
             seq = (seq + 1) & 0xFFFF
-            if interval:
-                dt = perf_counter() - t0
-                if dt < interval:
-                    await asyncio.sleep(interval - dt)
+            if txtime is not None and interval and at is not None:
+                at += int(sock.next_interval(interval) * NS)
+                # Wake up ahead of the release time
+                dt = at - TXTIME_LEAD - clock_gettime_ns(txtime)
+                if dt > 0:
+                    await asyncio.sleep(dt / NS)
+            elif interval:
+                dt = sock.next_interval(interval) - (perf_counter() - t0)
+                if dt > 0:
+                    await asyncio.sleep(dt)
+            n += 1
+            if count and n >= count:
+                break
+
+    async def __iter_window(
+        self,
+        addr: str,
+        sock: PingSocket,
+        *,
+        size: Optional[int],
+        request_id: int,
+        seq: int,
+        interval: Optional[float],
+        count: Optional[int],
+        window: int,
+        registered: bool,
+    ) -> AsyncIterable[Optional[float]]:
+        """
+        Sliding window part of `iter_rtt`.
+
+        Args:
+            addr: Address to ping.
+            sock: PingSocket instance.
+            size: Packets' size.
+            request_id: Request id.
+            seq: Sequence number of the first request.
+            interval: Interval between requests, in seconds.
+            count: Stop after `count` requests, if set.
+            window: Maximal number of outstanding requests.
+            registered: Stop when the target is unregistered.
+
+        Returns:
+            Yields for each attempt, in order of sending.
+        """
+        loop = asyncio.get_running_loop()
+        pending: Deque[Tuple[int, "asyncio.Future[Optional[float]]"]] = (
+            deque()
+        )
+        next_at = loop.time()
+        base = interval
+        detector = RateLimitDetector() if interval else None
+        n = 0
+        try:
+            while True:
+                active = (not registered or addr in self.__targets) and not (
+                    count and n >= count
+                )
+                if not active and not pending:
+                    break
+                can_send = active and len(pending) < window
+                if can_send and loop.time() >= next_at:
+                    if self.is_link_down(addr):
+                        # Do not probe, resolve as lost
+                        fut = loop.create_future()
+                        fut.set_result(None)
+                    else:
+                        fut = asyncio.ensure_future(
+                            sock.ping(
+                                addr,
+                                size=size,
+                                request_id=request_id,
+                                seq=seq,
+                            )
+                        )
+                    pending.append((seq, fut))
+                    seq = (seq + 1) & 0xFFFF
+                    n += 1
+                    if interval:
+                        # Do not burst when falling behind
+                        next_at = max(
+                            next_at + sock.next_interval(interval),
+                            loop.time(),
+                        )
+                    continue
+                if pending and pending[0][1].done():
+                    # Yield completed requests in order of sending
+                    req_seq, fut = pending.popleft()
+                    rtt = fut.result()
+                    if registered and addr not in self.__targets:
+                        continue  # Unregistered
+                    self.__register(addr, req_seq, rtt)
+                    if detector and interval and base:
+                        if detector.register(rtt is not None):
+                            interval = self.__on_rate_limit(
+                                addr, interval, base
+                            )
+                    yield rtt
+                    continue
+                # Wait for the oldest request or for the next slot
+                timeout = (
+                    max(next_at - loop.time(), 0.0) if can_send else None
+                )
+                if pending:
+                    await asyncio.wait([pending[0][1]], timeout=timeout)
+                else:
+                    await asyncio.sleep(timeout or 0.0)
+        finally:
+            for _, fut in pending:
+                fut.cancel()
+
+    async def iter_burst(
+        self,
+        addr: str,
+        *,
+        size: Optional[int] = None,
+        interval: Optional[float] = 1.0,
+        count: Optional[int] = None,
+        burst: int = 3,
+    ) -> AsyncIterable[BurstResult]:
+        """
+        Send `burst` echo requests back-to-back every `interval`
+        seconds, await and yield the burst's result.
+
+        Burst-level metrics allow to distinguish random loss
+        (`partial`) from outages (`all_lost`), and detect
+        reordering on the path. Bursts are accounted
+        in the tag's statistics along with the requests.
+
+        Stops when the target is unregistered by `unregister_target`.
+
+        Args:
+            addr: Address to ping.
+            size: Packets' size, including IP headers. Use the target's
+                profile or PingSocket intialized defaults, when empty.
+            interval: Interval between bursts, in seconds.
+            count: Stop after `count` bursts, if set. Do not stop
+                otherwise.
+            burst: Number of requests in burst.
+
+        Returns:
+            Yields BurstResult for each burst.
+        """
+        if burst < 1:
+            raise ValueError("burst must be positive")
+        sock = self.__get_socket(addr)
+        size = self.__get_size(addr, size)
+        request_id, seq = self.__get_request_id()
+        registered = addr in self.__targets
+        arrival: List[int] = []
+
+        async def probe(i: int) -> Optional[float]:
+            rtt = await sock.ping(
+                addr,
+                size=size,
+                request_id=request_id,
+                seq=(seq + i) & 0xFFFF,
+            )
+            if rtt is not None:
+                arrival.append(i)
+            return rtt
+
+        n = 0
+        while not registered or addr in self.__targets:
+            t0 = perf_counter()
+            arrival = []
+            rtts = list(
+                await asyncio.gather(*(probe(i) for i in range(burst)))
+            )
+            if registered and addr not in self.__targets:
+                break  # Unregistered
+            for i, rtt in enumerate(rtts):
+                self.__register(addr, (seq + i) & 0xFFFF, rtt)
+            result = BurstResult(rtts, arrival)
+            self.__register_burst(addr, result)
+            yield result
+            seq = (seq + burst) & 0xFFFF
             n += 1
             if count and n >= count:
                 break
+            if interval:
+                dt = sock.next_interval(interval) - (perf_counter() - t0)
+                if dt > 0:
+                    await asyncio.sleep(dt)
+
+    async def replay(
+        self, schedule: Schedule, *, speed: float = 1.0
+    ) -> AsyncIterable[Tuple[ScheduledProbe, Optional[float]]]:
+        """
+        Replay the schedule, recorded by `schedule` option: send
+        the probes with the recorded sizes and sequence numbers
+        at the recorded times since the start of the replay,
+        allowing apples-to-apples comparison of the measurements.
+        Results are accounted as usual.
+
+        Probes are sent while the previous ones are pending,
+        but results are yielded in order of the schedule.
+        Slow consumer delays the sending.
+
+        Args:
+            schedule: Schedule to replay.
+            speed: Replay speed multiplier.
+
+        Returns:
+            Yields tuple of probe and round-trip time in seconds,
+            None if timed out.
+        """
+        if speed <= 0:
+            raise ValueError("speed must be positive")
+        loop = asyncio.get_running_loop()
+        request_id, _ = self.__get_request_id()
+        pending: Deque[
+            Tuple[ScheduledProbe, "asyncio.Future[Optional[float]]"]
+        ] = deque()
+        start = loop.time()
+        # Schedule may be recorded by the replay itself
+        for probe in list(schedule.probes):
+            # Yield the results, completed so far
+            while pending and pending[0][1].done():
+                done, fut = pending.popleft()
+                rtt = fut.result()
+                self.__register(done.addr, done.seq, rtt)
+                yield done, rtt
+            dt = start + probe.t / speed - loop.time()
+            if dt > 0:
+                await asyncio.sleep(dt)
+            sock = self.__get_socket(probe.addr)
+            fut = asyncio.ensure_future(
+                sock.ping(
+                    probe.addr,
+                    size=probe.size,
+                    request_id=request_id,
+                    seq=probe.seq,
+                )
+            )
+            pending.append((probe, fut))
+        while pending:
+            done, fut = pending.popleft()
+            rtt = await fut
+            self.__register(done.addr, done.seq, rtt)
+            yield done, rtt
+
+    def iter_target(self, addr: str) -> AsyncIterable[Optional[float]]:
+        """
+        Send echo requests to the target, using interval and count
+        of the target's profile. Same as `iter_rtt` with the default
+        settings for the targets without profile. Stops when the target
+        is unregistered by `unregister_target`.
+
+        Args:
+            addr: Address to ping.
+
+        Returns:
+            Yields for each attempt:
+
+            * Round-trip time in seconds (as float) if success.
+            * None - if failed or timed out.
+        """
+        profile = self.__profiles.get(addr)
+        if profile is None:
+            return self.iter_rtt(addr)
+        return self.iter_rtt(
+            addr,
+            interval=profile.interval,
+            count=profile.count,
+            window=profile.window,
+        )
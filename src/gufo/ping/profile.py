@@ -0,0 +1,73 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: ProbeProfile implementation
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+from typing import Optional
+
+
+class ProbeProfile(object):
+    """
+    Probing settings, shared by the group of targets.
+    Empty settings fall back to `Ping` defaults.
+
+    Args:
+        size: Packets' size, including IP header.
+        interval: Interval between requests, in seconds.
+            Send next request immediately when empty.
+        count: Stop after `count` requests, if set.
+        timeout: Timeout in seconds.
+        dscp: DSCP field of outgoing packets.
+        ttl: Outgoing packets' TTL.
+        window: Maximal number of outstanding requests per target.
+    """
+
+    __slots__ = (
+        "size",
+        "interval",
+        "count",
+        "timeout",
+        "dscp",
+        "ttl",
+        "window",
+    )
+
+    def __init__(
+        self,
+        size: Optional[int] = None,
+        interval: Optional[float] = 1.0,
+        count: Optional[int] = None,
+        timeout: Optional[float] = None,
+        dscp: Optional[int] = None,
+        ttl: Optional[int] = None,
+        window: int = 1,
+    ) -> None:
+        if dscp is not None and (dscp < 0 or dscp > 63):
+            raise ValueError("dscp must be in 0..63 range")
+        if window < 1:
+            raise ValueError("window must be positive")
+        self.size = size
+        self.interval = interval
+        self.count = count
+        self.timeout = timeout
+        self.dscp = dscp
+        self.ttl = ttl
+        self.window = window
+
+    def __repr__(self) -> str:
+        return (
+            f"<ProbeProfile size={self.size} interval={self.interval}"
+            f" count={self.count} timeout={self.timeout}"
+            f" dscp={self.dscp} ttl={self.ttl} window={self.window}>"
+        )
+
+    @property
+    def tos(self) -> Optional[int]:
+        """
+        TOS byte, derived from `dscp`.
+        """
+        if self.dscp is None:
+            return None
+        return self.dscp << 2
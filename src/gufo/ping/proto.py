@@ -6,7 +6,75 @@
 
 
 # Python modules
-from typing import Optional, List, Dict, Protocol
+from typing import (
+    Any,
+    Callable,
+    Optional,
+    List,
+    Dict,
+    Protocol,
+    Tuple,
+    Type,
+)
+from types import TracebackType
+
+
+class ReplyProto(Protocol):
+    """
+    Received ICMP echo reply.
+
+    Attributes:
+        sid: Session id, <address>-<request_id>-<seq>.
+        rtt: Round-trip time, in nanoseconds. 0 for ICMP errors.
+            Zero or negative in `INVALID_RTT_RAW` mode.
+        status: `RECORD_REPLY` for echo reply. ICMP errors,
+            delivered when `recv_errors` is set, have
+            `RECORD_UNREACHABLE`, `RECORD_PROHIBITED`,
+            or `RECORD_TIME_EXCEEDED` status. Replies with
+            zero or negative RTT have `RECORD_INVALID_RTT` status
+            in `INVALID_RTT_ERROR` mode.
+        tos: DSCP/ECN field of the reply. `None`,
+            unless `recv_tos` is set.
+        dscp: DSCP of the reply, `None` unless `recv_tos` is set.
+        ecn: ECN codepoint of the reply, `None` unless
+            `recv_tos` is set.
+        local_addr: Local address the reply is received on,
+            which is the request's source address.
+            `None` unless `recv_pktinfo` is set.
+        ifindex: Index of the interface the reply is received on.
+            `None` unless `recv_pktinfo` is set.
+        size: Size of the reply, including IPv4 header.
+        size_mismatch: Size of the reply differs from the size
+            of the request. `None` unless `strict_size` is set.
+        icmp_type: Type of ICMP error, `None` for echo reply.
+        icmp_code: Code of ICMP error, `None` for echo reply.
+            Tells apart, i.e. the firewall drop
+            (communication administratively prohibited)
+            from the routing blackhole (network unreachable).
+        reason: Human-readable reason of ICMP error,
+            i.e. `host unreachable`. `None` for echo reply.
+        truncated: Reply is larger than the receive buffer
+            and is truncated. `size` is the original size
+            of the reply (Linux).
+        late: Reply to the expired session, received within
+            grace period. See `set_late_grace`.
+    """
+
+    sid: str
+    rtt: int
+    status: int
+    tos: Optional[int]
+    dscp: Optional[int]
+    ecn: Optional[int]
+    local_addr: Optional[str]
+    ifindex: Optional[int]
+    size: int
+    size_mismatch: Optional[bool]
+    icmp_type: Optional[int]
+    icmp_code: Optional[int]
+    reason: Optional[str]
+    truncated: bool
+    late: bool
 
 
 class SocketProto(Protocol):
@@ -15,9 +83,81 @@ class SocketProto(Protocol):
 
     Socket wrapper is the [PyO3](https://pyo3.rs)-wrapped Rust code,
     implementing low-level details of the PingSocket.
+
+    Socket wrapper is not thread-safe. All state is guarded
+    by PyO3's exclusive borrow, so the concurrent call from the other
+    thread, i.e. while `poll`, `wait`, or `ping_once` are waiting
+    with GIL released, raises `RuntimeError` instead of corrupting
+    the state. Use separate sockets per thread.
     """
 
-    def __init__(self, afi: int) -> None:
+    def __init__(self, afi: int, request_id_policy: int = 0) -> None:
+        ...
+
+    @staticmethod
+    def from_fd(
+        afi: int, fd: int, request_id_policy: int = 0
+    ) -> "SocketProto":
+        """
+        Wrap already opened raw socket, i.e. inherited
+        from the privileged parent process, or received over
+        SCM_RIGHTS by `socket.recv_fds`. The wrapper takes
+        the ownership of the file descriptor.
+
+        Socket type, address family, and protocol are checked
+        by `getsockopt` (family and protocol on Linux only).
+        Rejected file descriptor is left open.
+
+        Args:
+            afi: Address family, either 4 or 6.
+            fd: Raw socket's file descriptor.
+            request_id_policy: ICMP request id allocation policy.
+
+        Returns:
+            Socket wrapper instance.
+
+        Raises:
+            ValueError: If `fd` is not a raw ICMP socket of `afi`
+                address family.
+            OSError: If `fd` is not a socket.
+        """
+        ...
+
+    def export_state(self) -> bytes:
+        """
+        Export socket state: signature, settings, and pending sessions.
+        The state may be imported by the socket in the other process,
+        i.e. after the agent's upgrade, to continue the processing
+        of in-flight requests. Pass the socket's file descriptor
+        along with the state to receive pending replies.
+
+        Returns:
+            Serialized state.
+        """
+        ...
+
+    def import_state(
+        self, state: bytes, grace: Optional[int] = None
+    ) -> None:
+        """
+        Import socket state, exported by `export_state`.
+
+        Adopt the exported signature by default, so the imported
+        sessions are matched as before. When the new socket
+        replaces the old one, i.e. on interface bounce,
+        set `grace` to hand the sessions over: the socket keeps
+        its own signature and matches replies with the exported
+        one, including the accelerated filter, until the grace
+        period is over.
+
+        Args:
+            state: Serialized state.
+            grace: Grace period of the handover, in nanoseconds.
+
+        Raises:
+            ValueError: If `grace` is set while the socket
+                has pending sessions.
+        """
         ...
 
     def set_timeout(self, timeout: int) -> None:
@@ -47,6 +187,22 @@ class SocketProto(Protocol):
         """
         ...
 
+    def set_max_in_flight(self, limit: int, policy: int = 0) -> None:
+        """
+        Limit number of in-flight sessions.
+
+        Args:
+            limit: Maximal number of in-flight sessions.
+                0 - unlimited.
+            policy: Action on reaching the limit:
+
+                * `OVERLOAD_REJECT` - `send` raises `BlockingIOError`.
+                * `OVERLOAD_EVICT` - evict the session with the closest
+                  deadline. Evicted sessions are reported
+                  by `get_expired`.
+        """
+        ...
+
     def set_coarse(self, ct: bool) -> None:
         """
         Switch between the internal timer implemenetation.
@@ -56,13 +212,17 @@ class SocketProto(Protocol):
 
                 * `CLOCK_MONOTONIC_COARSE` if True
                 * `CLOCK_MONOTONIC` if False
+
+                `CLOCK_MONOTONIC` is always used when built
+                without `coarsetime` feature.
         """
         ...
 
     def set_send_buffer_size(self, size: int) -> None:
         """
         Set outgoing socket's buffer size. If the requested
-        size is too big, adjust to proper size.
+        size is too big, adjust to proper size. Exceed `wmem_max`
+        limit on Linux, if the process has `CAP_NET_ADMIN` capability.
 
         Args:
             size: Requested send buffer size, in bytes.
@@ -72,13 +232,36 @@ class SocketProto(Protocol):
     def set_recv_buffer_size(self, size: int) -> None:
         """
         Set incoming socket's buffer size. If the requested
-        size is too big, adjust to proper size.
+        size is too big, adjust to proper size. Exceed `rmem_max`
+        limit on Linux, if the process has `CAP_NET_ADMIN` capability.
 
         Args:
             size: Requested recv buffer size, in bytes.
         """
         ...
 
+    def set_busy_poll(self, usecs: int) -> None:
+        """
+        Set `SO_BUSY_POLL` socket option to trade CPU
+        for reduced receive latency on supported NICs (Linux).
+        Ignored on other platforms.
+
+        Args:
+            usecs: Busy polling timeout, in microseconds.
+        """
+        ...
+
+    def set_incoming_cpu(self, cpu: int) -> None:
+        """
+        Set `SO_INCOMING_CPU` socket option to process
+        socket's packets on the CPU receiving the NIC interrupts
+        (Linux). Ignored on other platforms.
+
+        Args:
+            cpu: CPU number.
+        """
+        ...
+
     def set_accelerated(self, a: bool) -> None:
         """
         Enable platform-dependend raw socket processing.
@@ -89,6 +272,663 @@ class SocketProto(Protocol):
                 * False - disable the acceleration.
         """
 
+    def set_freebind(self, enabled: bool) -> None:
+        """
+        Allow binding to the non-local addresses
+        (`IP_FREEBIND`, Linux).
+
+        Args:
+            enabled: Enable option.
+        """
+        ...
+
+    def set_transparent(self, enabled: bool) -> None:
+        """
+        Allow binding to the non-local addresses and transparent
+        proxying (`IP_TRANSPARENT`, Linux).
+
+        Args:
+            enabled: Enable option.
+
+        Raises:
+            PermissionError: When CAP_NET_ADMIN or CAP_NET_RAW
+                is missing.
+        """
+        ...
+
+    def bind(self, addr: str) -> None:
+        """
+        Bind socket to the local address. Binding to the non-local
+        address requires `freebind` or `transparent` option.
+
+        Args:
+            addr: Local address.
+        """
+        ...
+
+    def set_ecn(self, ecn: int) -> None:
+        """
+        Set ECN codepoint of the outgoing packets,
+        preserving DSCP.
+
+        Args:
+            ecn: One of `ECN_NOT_ECT`, `ECN_ECT1`, `ECN_ECT0`,
+                or `ECN_CE`.
+        """
+        ...
+
+    def set_recv_tos(self, enabled: bool) -> None:
+        """
+        Report DSCP/ECN field of the received replies
+        by `recv_replies`. Uses `IP_RECVTOS` or `IPV6_RECVTCLASS`.
+
+        Args:
+            enabled: Enable reporting.
+
+        Raises:
+            OSError: IPv4 reporting is not supported on illumos
+                and Solaris.
+        """
+        ...
+
+    def set_recv_errors(self, enabled: bool) -> None:
+        """
+        Match ICMP errors, quoting the requests: destination
+        unreachable and time exceeded. The failed session is
+        removed without waiting for the timeout.
+        `recv_replies` delivers it with the error status:
+
+        * `RECORD_PROHIBITED` - communication is administratively
+          prohibited or rejected by policy.
+        * `RECORD_UNREACHABLE` - other destination unreachable codes.
+        * `RECORD_TIME_EXCEEDED` - TTL/hop limit exceeded in transit.
+
+        The exact ICMP type and code, along with the reason,
+        are reported by `icmp_type`, `icmp_code`, and `reason`
+        of the reply.
+
+        `recv` and `recv_into` do not deliver errors,
+        the failed session is reported by `get_expired` instead.
+        Errors are counted as `errors`. The BPF filter
+        accepts errors in accelerated mode.
+
+        Args:
+            enabled: Enable matching.
+        """
+        ...
+
+    def set_strict_size(self, enabled: bool) -> None:
+        """
+        Compare the size of the received replies with the size
+        of the requests and report mismatch by `recv_replies`.
+        Truncated or padded replies indicate the middleboxes
+        on the path. Mismatched replies are accepted and counted
+        as `size_mismatch`.
+
+        Args:
+            enabled: Enable comparison.
+        """
+        ...
+
+    def set_rtt_unit(self, unit: int) -> None:
+        """
+        Set unit of round-trip times, delivered by `recv`,
+        `recv_into`, `flush`, `wait`, `close`, and `ping_once`:
+
+        * `RTT_NS` - nanoseconds, as integer (default).
+        * `RTT_US` - microseconds, as float.
+        * `RTT_MS` - milliseconds, as float.
+        * `RTT_S` - seconds, as float.
+
+        Delivering seconds saves the conversion in Python code
+        on every reply. `ReplyProto.rtt` is always in nanoseconds.
+
+        Args:
+            unit: RTT unit.
+
+        Raises:
+            ValueError: On invalid unit.
+        """
+        ...
+
+    def set_invalid_rtt(self, mode: int) -> None:
+        """
+        Set handling of zero or negative RTT. The request's
+        timestamp, not preceding the reply, usually indicates
+        the coarse clock misuse or the corrupted timestamp:
+
+        * `INVALID_RTT_CLAMP` - RTT is clamped to 1ns (default).
+        * `INVALID_RTT_ERROR` - the session is failed.
+          `recv_replies` delivers it with `RECORD_INVALID_RTT`
+          status, `get_expired` reports it otherwise.
+        * `INVALID_RTT_RAW` - signed RTT is delivered as is.
+
+        Occurrences are counted as `invalid_rtt` in all modes.
+
+        Args:
+            mode: Handling mode.
+
+        Raises:
+            ValueError: On invalid mode.
+        """
+        ...
+
+    def set_late_grace(self, grace: int, report: bool = False) -> None:
+        """
+        Keep expired sessions for the grace period, so replies,
+        arrived after the timeout, are counted by `late` counter
+        instead of being mistaken for the other socket's replies,
+        giving "late vs lost" breakdown.
+
+        Args:
+            grace: Grace period, in nanoseconds. 0 - disable.
+            report: Deliver late replies with `late` flag
+                and actual RTT. Late replies are only counted
+                otherwise.
+        """
+        ...
+
+    def set_txtime(self, clock: Optional[int] = None) -> None:
+        """
+        Release requests, passed to `send` with `at`, by kernel
+        at the exact time (SO_TXTIME, Linux), eliminating event loop
+        jitter from the inter-probe spacing. RTT is measured
+        from the release time.
+
+        Requires time-based qdisc on the outgoing interface:
+        `fq` with `time.CLOCK_MONOTONIC`, or `etf`
+        with `time.CLOCK_TAI`. Other qdiscs send requests
+        immediately, underestimating RTT.
+
+        Args:
+            clock: Clock id of `at` timestamps.
+                Stop scheduling when empty.
+
+        Raises:
+            OSError: When not supported or permitted.
+        """
+        ...
+
+    def set_tx_timestamps(self, enabled: bool) -> None:
+        """
+        Measure RTT from the time the requests actually left the host,
+        rather than from the time of encoding, excluding the local
+        queuing delay (Linux). Uses `SO_TIMESTAMPING` software
+        transmit timestamps, received from the socket's error queue.
+
+        Args:
+            enabled: Enable transmit timestamps.
+
+        Raises:
+            OSError: When not supported.
+        """
+        ...
+
+    def set_recv_pktinfo(self, enabled: bool) -> None:
+        """
+        Report local address and interface of the received replies
+        by `recv_replies`. Uses `IP_PKTINFO` or `IPV6_RECVPKTINFO`.
+        Local address of the reply is the source address
+        of the request, allowing to audit the source address
+        selection on multi-homed hosts.
+
+        Args:
+            enabled: Enable reporting.
+        """
+        ...
+
+    def set_diagnostic(self, duration: int) -> None:
+        """
+        Enter diagnostic mode. BPF filter is detached
+        and every received ICMP packet is recorded, helping
+        to debug why replies aren't matched.
+        Replies are processed as usual.
+
+        Args:
+            duration: Duration of diagnostic mode, in nanoseconds.
+        """
+        ...
+
+    def get_diagnostic(self) -> List[Tuple[str, int, int, int]]:
+        """
+        Get ICMP packets, recorded in diagnostic mode.
+        Up to 1024 packets are recorded between calls.
+
+        Returns:
+            List of (`source address`, `icmp type`, `icmp code`, `size`).
+        """
+        ...
+
+    def set_debug_callback(
+        self, callback: Optional[Callable[[int, bytes, str, int], None]] = None
+    ) -> None:
+        """
+        Set callback, called for every sent and received packet.
+        Exceptions raised by callback are printed and ignored.
+
+        Args:
+            callback: Callable, accepting:
+
+                * Direction: `DEBUG_TX` or `DEBUG_RX`.
+                * Raw packet. Received IPv4 packets include IP header.
+                * Remote address.
+                * Timestamp, in nanoseconds, according to `get_clock`.
+
+                Remove callback when empty.
+        """
+        ...
+
+    def get_counters(self) -> Dict[str, int]:
+        """
+        Get received packets counters. Packets, rejected by
+        the BPF filter in accelerated mode, are not counted.
+
+        Returns:
+            Dict of counter -> value:
+
+            * `received` - all received packets.
+            * `too_short` - packets, too short to be a reply.
+            * `wrong_type` - ICMP packets other than echo reply.
+            * `signature_mismatch` - replies to the other sockets.
+            * `errors` - ICMP errors, matched to the pending sessions,
+              when `recv_errors` is set.
+            * `invalid_rtt` - replies with zero or negative RTT.
+              See `set_invalid_rtt`.
+            * `checksum_error` - corrupted replies (IPv4).
+            * `padding_mismatch` - replies with altered random padding.
+            * `matched` - accepted replies.
+            * `size_mismatch` - accepted replies of unexpected size,
+              when `strict_size` is set.
+            * `truncated` - packets, larger than the receive buffer.
+            * `late` - replies to the expired sessions, received
+              within grace period. See `set_late_grace`.
+            * `stale` - replies to the previous requests with
+              the same address, request id, and sequence number,
+              i.e. after sequence wraparound, rejected to not be
+              mistaken for the pending request's reply. Not detected
+              in mimic modes.
+            * `kernel_drops` - packets, dropped by kernel due to
+              receive queue overflow or filter, as seen on the last
+              received packet. Packets, queued before the drop, do not
+              carry the updated counter. `None`, unless `rxq_ovfl`
+              is set.
+            * `result_drops` - result records, dropped due to slow
+              collector or result log's storage. See `set_result_fd`
+              and `set_result_log`.
+            * `result_log_errors` - failed writes to the result log.
+              Records of the failed write are discarded.
+        """
+        ...
+
+    @property
+    def is_diagnostic(self) -> bool:
+        """
+        Check if diagnostic mode is active.
+
+        Returns:
+            True, if diagnostic mode is active.
+        """
+        ...
+
+    def set_latency_budget(self, enabled: bool) -> None:
+        """
+        Account host-side latency of the pipeline stages,
+        telling whether RTT inflation is caused by the network
+        or by the host. Enabling resets the accounted latencies.
+
+        Args:
+            enabled: Enable accounting.
+        """
+        ...
+
+    def get_latency_budget(self) -> Optional[Dict[str, Dict[str, Any]]]:
+        """
+        Get histograms of the pipeline stages' latency.
+
+        Returns:
+            None, unless enabled by `set_latency_budget`.
+            Dict of stage -> histogram otherwise. Stages:
+
+            * `encode` - from `send` call to `sendto`, including
+              packet encoding.
+            * `send` - `sendto` system call.
+            * `deliver` - from reading the reply to its delivery
+              to Python, including batching delay.
+
+            Histogram is the dict of:
+
+            * `count` - number of accounted latencies.
+            * `sum` - sum of latencies, in nanoseconds.
+            * `min`, `max` - minimal and maximal latency,
+              in nanoseconds. None, when empty.
+            * `p50`, `p99` - estimated median and 99th percentile,
+              in nanoseconds. None, when empty.
+            * `buckets` - list of non-empty power-of-two buckets,
+              as (exclusive upper bound in nanoseconds, count).
+        """
+        ...
+
+    def set_rxq_ovfl(self, enabled: bool) -> None:
+        """
+        Report kernel's socket drops counter by `get_counters`,
+        using `SO_RXQ_OVFL` (Linux). Allows to detect replies,
+        silently lost due to receive queue overflow.
+
+        Args:
+            enabled: Enable reporting.
+        """
+        ...
+
+    def set_mimic(self, mode: int) -> None:
+        """
+        Reproduce the payload of the OS ping utility, making
+        probes indistinguishable from it:
+
+        * `MIMIC_NONE` - native payload with signature and timestamp.
+        * `MIMIC_LINUX` - payload of iputils' ping: wall-clock
+          `struct timeval`, followed by incrementing octets.
+        * `MIMIC_WINDOWS` - payload of Windows ping:
+          repeating `abcdefghijklmnopqrstuvw` pattern.
+
+        Signature is not carried in mimic modes, so replies are
+        matched by address, request id and sequence of pending
+        sessions only, and timestamps are kept locally.
+        `padding` and `ts_mode` are ignored.
+        Use `REQUEST_ID_RANDOM` policy to reduce collisions
+        with the other ping processes.
+
+        Args:
+            mode: Mimic mode.
+
+        Raises:
+            ValueError: On invalid mode or when sessions are pending.
+        """
+        ...
+
+    def set_jitter(self, mode: int, percent: float = 10.0) -> None:
+        """
+        Randomize probe intervals, returned by `next_interval_ns`,
+        so probes of many targets with the same interval
+        do not synchronize into bursts:
+
+        * `JITTER_NONE` - fixed interval.
+        * `JITTER_UNIFORM` - uniformly distributed within
+          `percent` of the interval on either side.
+        * `JITTER_EXPONENTIAL` - `percent` of the interval
+          is replaced by the exponentially distributed delay
+          of the same mean.
+
+        Mean interval is preserved in all modes.
+
+        Args:
+            mode: Jitter mode.
+            percent: Randomized share of the interval, in 0..100 range.
+
+        Raises:
+            ValueError: On invalid mode or percent.
+        """
+        ...
+
+    def next_interval_ns(self, interval: int) -> int:
+        """
+        Get the next probe interval, randomized according
+        to `set_jitter`.
+
+        Args:
+            interval: Nominal interval, in nanoseconds.
+
+        Returns:
+            Interval, in nanoseconds.
+        """
+        ...
+
+    def set_icmp_types(self, request_type: int, reply_type: int) -> None:
+        """
+        Override ICMP types of requests and replies, i.e. for
+        test harnesses or stacks, answering with non-standard types.
+        BPF filter is updated to match the reply type.
+
+        Args:
+            request_type: ICMP type of requests.
+            reply_type: ICMP type of replies.
+
+        Raises:
+            ValueError: When sessions are pending.
+        """
+        ...
+
+    def set_icmp_code(self, code: int) -> None:
+        """
+        Set ICMP code of requests. Echo requests carry code 0
+        by default.
+
+        Args:
+            code: ICMP code.
+        """
+        ...
+
+    def set_verify_checksum(self, enabled: bool) -> None:
+        """
+        Verify checksum of ICMPv4 replies, enabled by default.
+        Replies with broken checksums are counted as
+        `checksum_error` and dropped. ICMPv6 checksum
+        is always verified by kernel.
+
+        Args:
+            enabled: Verify checksum.
+        """
+        ...
+
+    def set_padding(self, mode: int) -> None:
+        """
+        Set padding mode of requests:
+
+        * `PADDING_FIXED` - fill padding with constant `0x30` octets.
+        * `PADDING_RANDOM` - fill padding with pseudo-random
+          octets, making probes less fingerprintable. The seed
+          is carried in the payload, so the padding of the replies
+          is verified.
+
+        Args:
+            mode: Padding mode.
+
+        Raises:
+            ValueError: On invalid mode or when sessions are pending.
+        """
+        ...
+
+    def set_ts_mode(self, mode: int) -> None:
+        """
+        Set the mode of timestamps, embedded into requests.
+        Plain timestamps expose the socket's clock on the wire.
+
+        * `TS_PLAIN` - embed timestamp as is.
+        * `TS_OBFUSCATED` - embed timestamp, XORed with the key,
+          derived from the socket's signature, request id,
+          and sequence number.
+        * `TS_NONCE` - embed random nonce, keeping timestamp locally.
+          Late replies and replies to the sessions, imported by
+          `import_state`, are rejected.
+
+        Args:
+            mode: Timestamp mode.
+
+        Raises:
+            ValueError: On invalid mode or when sessions are pending.
+        """
+        ...
+
+    def set_result_fd(self, fd: Optional[int] = None) -> None:
+        """
+        Stream results to the file descriptor, i.e. pipe or Unix socket,
+        allowing the separate collector process to consume them.
+        Each reply and timeout is written as a length-prefixed binary
+        record:
+
+        ```
+        length(2) version(1) status(1) afi(1) addr(4|16)
+        request_id(2) seq(2) rtt(8) ts(8)
+        ```
+
+        Where:
+
+        * `length` - length of the record, excluding `length` field.
+        * `version` - record format version, 1.
+        * `status` - `RECORD_REPLY`, `RECORD_TIMEOUT`, status
+          of ICMP error, when `recv_errors` is set, or
+          `RECORD_INVALID_RTT`, see `set_invalid_rtt`.
+        * `afi` - 4 or 6, defines the size of `addr`.
+        * `rtt` - round-trip time in nanoseconds, 0 unless reply.
+          Negative RTT of `INVALID_RTT_RAW` mode is written
+          in two's complement.
+        * `ts` - UNIX time of the result, in nanoseconds.
+
+        All integers are big-endian. Timeouts are written by
+        `get_expired`. The descriptor is switched to the non-blocking
+        mode, but not owned by the socket. Records, which cannot be
+        written in time, are buffered up to 1MB, and dropped then.
+
+        Args:
+            fd: File descriptor. Disable streaming when None.
+        """
+        ...
+
+    def set_result_log(
+        self,
+        path: Optional[str] = None,
+        max_size: Optional[int] = None,
+        max_age: Optional[int] = None,
+        keep: int = 0,
+    ) -> None:
+        """
+        Append results to the file as binary records, in the same
+        format as `set_result_fd`. Records are written by the socket
+        on every receive and expiration, so the measurements
+        survive the crash of the process and may be backfilled
+        into the time-series database later. The existing log
+        is appended.
+
+        The log is rotated before exceeding `max_size` bytes or after
+        `max_age` nanoseconds since the opening: the current log
+        is renamed to `<path>.1`, shifting the previous rotated logs
+        up to `<path>.<keep>`. The rotated log is removed when `keep`
+        is 0. Records are never split between logs.
+
+        Args:
+            path: Path to the log. Close the log when None.
+            max_size: Maximal log size, in bytes. None for unlimited.
+            max_age: Maximal log age, in nanoseconds. None for unlimited.
+            keep: Number of rotated logs to keep.
+
+        Raises:
+            ValueError: On zero `max_size` or `max_age`.
+            OSError: When the log cannot be opened.
+        """
+        ...
+
+    def set_batching(self, size: int, delay: int) -> None:
+        """
+        Deliver replies by `recv` in batches, reducing the number
+        of calls. The batch is delivered when either `size` replies
+        are accumulated, or `delay` passed since the first reply
+        of the batch. The pending batch should be collected
+        by `flush` at `next_flush_ns`.
+
+        Args:
+            size: Batch size. Zero disables batching.
+            delay: Maximal delivery delay, in nanoseconds.
+        """
+        ...
+
+    def flush(self) -> Optional[Dict[str, float]]:
+        """
+        Deliver the pending batch and replies, received
+        before the `recv` error.
+
+        Returns:
+            * `None` - when no replies pending.
+            * Dict of `session id` -> `rtt`, same as `recv`.
+        """
+        ...
+
+    def next_flush_ns(self) -> Optional[int]:
+        """
+        Get the time of pending batch delivery, according to
+        CLOCK_MONOTONIC. Suitable to arm event loop's timers.
+
+        Returns:
+            * `None` - when no replies pending.
+            * Time in nanoseconds.
+        """
+        ...
+
+    def get_options(self) -> Dict[str, Any]:
+        """
+        Get effective socket options, as applied by the kernel.
+
+        Returns:
+            Dict of option -> value:
+
+            * `ttl` - outgoing packets' TTL/hop limit.
+            * `tos` - outgoing packets' ToS/traffic class.
+            * `ecn` - outgoing packets' ECN codepoint.
+            * `send_buffer_size` - send buffer size, in bytes.
+            * `recv_buffer_size` - receive buffer size, in bytes.
+            * `local_addr` - bound local address.
+            * `device` - bound device name or `None`.
+            * `freebind` - `IP_FREEBIND` is set, `None` if unsupported.
+            * `transparent` - `IP_TRANSPARENT` is set,
+              `None` if unsupported.
+            * `busy_poll` - `SO_BUSY_POLL` timeout, in microseconds.
+            * `incoming_cpu` - `SO_INCOMING_CPU` value.
+            * `napi_id` - `SO_INCOMING_NAPI_ID` of the last received
+              packet's NAPI context. `None` until the packet is received
+              over the NAPI-capable device, or if unsupported.
+              Match with the NIC's queue to place the receive thread
+              on the same CPU with `incoming_cpu` and `busy_poll`.
+            * `timeout` - default timeout, in nanoseconds.
+            * `coarse` - `CLOCK_MONOTONIC_COARSE` is used.
+            * `accelerated` - BPF filter is attached.
+            * `recv_tos` - DSCP/ECN of replies is reported.
+            * `recv_pktinfo` - local address and interface of replies
+              is reported.
+            * `recv_errors` - ICMP errors are matched.
+            * `invalid_rtt` - handling of zero or negative RTT.
+            * `strict_size` - size of replies is verified.
+            * `txtime` - clock of the scheduled requests or `None`.
+            * `tx_timestamps` - RTT is measured from transmit timestamps.
+            * `rxq_ovfl` - kernel drops counter is reported.
+            * `padding` - padding mode.
+            * `mimic` - mimic mode.
+            * `jitter` - probe interval jitter mode.
+            * `jitter_percent` - randomized share of probe interval.
+            * `rtt_unit` - unit of delivered round-trip times.
+            * `ts_mode` - timestamp embedding mode.
+            * `icmp_request_type` - ICMP type of requests.
+            * `icmp_reply_type` - ICMP type of replies.
+            * `icmp_code` - ICMP code of requests.
+            * `verify_checksum` - checksum of ICMPv4 replies is verified.
+        """
+        ...
+
+    def reconfigure(self, options: Dict[str, Any]) -> Dict[str, Any]:
+        """
+        Apply a batch of options at once. If any option fails,
+        already applied options are restored to previous values
+        and the error is raised.
+
+        Args:
+            options: Dict of option -> value. Supported options are
+                `timeout` (in nanoseconds), `ttl`, `tos`, `busy_poll`,
+                `incoming_cpu`, `coarse`, `accelerated`, `recv_tos`,
+                `recv_pktinfo`, and `ecn`.
+
+        Returns:
+            Dict of applied option -> effective value,
+            as reported by `get_options`.
+        """
+        ...
+
     def get_fd(self) -> int:  # @todo: Shold be FileDescriptorLike
         """
         Get socket's file descriptor.
@@ -98,35 +938,309 @@ class SocketProto(Protocol):
         """
         ...
 
-    def send(self, addr: str, request_id: int, seq: int, size: int) -> None:
+    def fileno(self) -> int:
+        """
+        Get socket's file descriptor. Allows to pass the socket
+        directly to `select` or `trio.lowlevel.wait_readable`.
+
+        Returns:
+            file descriptor for open socket.
+        """
+        ...
+
+    def poll(self, timeout: Optional[int] = None) -> bool:
+        """
+        Wait until socket became readable, releasing GIL.
+
+        Args:
+            timeout: Wait timeout in nanoseconds.
+                Wait indefinitely when empty.
+
+        Returns:
+            True, if socket is readable. False on timeout.
+        """
+        ...
+
+    def set_target(self, addr: str) -> None:
+        """
+        Connect socket to the single target. Route lookup
+        is performed once and only replies from the target
+        are received. Requests to the other addresses are rejected.
+        Socket may be connected to the other target later,
+        but cannot be disconnected.
+
+        Args:
+            addr: Target address.
+        """
+        ...
+
+    def get_target(self) -> Optional[str]:
+        """
+        Get connected target.
+
+        Returns:
+            * `None` - when socket is not connected.
+            * Target address.
+        """
+        ...
+
+    def send(
+        self,
+        addr: str,
+        request_id: int,
+        seq: int,
+        size: int,
+        src_addr: Optional[str] = None,
+        at: Optional[int] = None,
+    ) -> str:
         """
         Generate and send icmp request packet.
 
         Args:
             addr: Destination address.
-            request_id: ICMP request id.
+            request_id: ICMP request id. Ignored unless the socket
+                is created with `REQUEST_ID_CALLER` policy.
             seq: ICMP sequental number.
             size: Outgoing packet's size in bytes, including IP header.
+            src_addr: Send from the local address, passed
+                via `IP_PKTINFO`/`IPV6_PKTINFO`. Use socket's
+                source address when empty.
+            at: Release at the time of `set_txtime` clock,
+                in nanoseconds. Send immediately when empty.
+
+        Returns:
+            Session id, the string of <address>-<request_id>-<seq>
+
+        Raises:
+            ValueError: When `at` is set without `set_txtime`.
+        """
+        ...
+
+    def ping_once(
+        self,
+        addr: str,
+        size: int = 64,
+        timeout: Optional[int] = None,
+        request_id: int = 0,
+        seq: int = 0,
+        src_addr: Optional[str] = None,
+    ) -> float:
+        """
+        Send ICMP echo request and block until reply, releasing GIL.
+        Suitable for scripts and threads without event loop.
+
+        Args:
+            addr: Target address.
+            size: Packet size, including IP header.
+            timeout: Timeout in nanoseconds.
+                Use socket's timeout when empty.
+            request_id: ICMP request id.
+            seq: ICMP sequental number.
+            src_addr: Send from the local address.
+
+        Returns:
+            Round-trip time, in units of `set_rtt_unit`.
+            Replies for the other sessions are retained until
+            next `recv` call.
+
+        Raises:
+            ConnectionError: When request is failed by ICMP error,
+                reported by `set_recv_errors`. The exception carries
+                `icmp_type` and `icmp_code` attributes.
+            TimeoutError: When no reply received in time.
+        """
+        ...
+
+    def get_route(self, addr: str) -> Dict[str, Any]:
+        """
+        Look up the route, the probes to the address take,
+        by netlink RTM_GETROUTE query (Linux). Useful to explain
+        RTT changes by routing changes.
+
+        Args:
+            addr: Target address.
+
+        Returns:
+            Dict of:
+
+            * `gateway` - next-hop address, `None` for directly
+              connected destinations.
+            * `ifindex` - index of the egress interface.
+            * `ifname` - name of the egress interface.
+            * `src` - preferred source address.
+            * `table` - routing table.
+
+        Raises:
+            OSError: When no route or not supported.
+        """
+        ...
+
+    def cancel(self, addr: str) -> List[str]:
+        """
+        Cancel all pending sessions of the address. Cancelled sessions
+        are neither matched by `recv` nor reported by `get_expired`.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            List of cancelled session ids.
         """
         ...
 
     def recv(self) -> Optional[Dict[str, float]]:
         """
-        Receive all awaiting packets.
+        Receive all awaiting packets. Replies are delivered
+        in batches, when enabled by `set_batching`.
 
         Returns:
             * `None` - when no packets received.
             * Dict of `session id` -> `rtt`,
               where `session id` is the string of
               <address>-<request_id>-<seq>,
-              and `rtt` - is the measured round-trip-time in nanoseconds.
+              and `rtt` - is the measured round-trip-time
+              in units of `set_rtt_unit`, nanoseconds by default.
+
+        Raises:
+            OSError: On socket error, i.e. `ENETDOWN` or `EBADF`.
+                Interrupted calls and pending errors of the connected
+                socket, reported by ICMP, are skipped. Replies,
+                received before the error, are delivered by the next
+                call or by `flush`.
+        """
+        ...
+
+    def recv_into(self, out: List[Tuple[str, float]]) -> int:
+        """
+        Receive all awaiting packets, like `recv`, but append
+        (`session id`, `rtt`) tuples to the list. Avoids building
+        the dict on every call, when the list is reused
+        by the caller.
+
+        Args:
+            out: List to append received replies.
+
+        Returns:
+            Number of appended replies.
+
+        Raises:
+            OSError: On socket error, same as `recv`.
+        """
+        ...
+
+    def recv_replies(self) -> Optional[List[ReplyProto]]:
+        """
+        Receive all pending ICMP echo replies,
+        along with reply details. ICMP errors are delivered
+        as replies with the error `status`, when `recv_errors`
+        is set.
+
+        Returns:
+            * `None` - when no packets received.
+            * List of replies.
+
+        Raises:
+            OSError: On socket error, same as `recv`.
+        """
+        ...
+
+    def get_pending(self) -> List[Tuple[str, str, int]]:
+        """
+        Get list of pending sessions, ordered by deadline.
+
+        Returns:
+            List of (`session id`, `target`, `nanoseconds to deadline`).
+            Expired, but not collected by `get_expired` sessions
+            have zero time to deadline.
+        """
+        ...
+
+    def get_pending_count(self) -> int:
+        """
+        Get number of pending sessions.
+
+        Returns:
+            Number of pending sessions.
+        """
+        ...
+
+    def wait(
+        self, timeout: Optional[int] = None
+    ) -> Tuple[Optional[Dict[str, float]], Optional[List[str]]]:
+        """
+        Wait for replies or expiration of the pending sessions,
+        releasing GIL. Allows to drive the socket without event loop.
+        Blocks until socket became readable, the closest deadline
+        or `timeout` passed, whichever comes first.
+
+        Args:
+            timeout: Wait timeout in nanoseconds. Wait until
+                the closest deadline when empty. Do not block when
+                empty and no sessions are pending.
+
+        Returns:
+            Tuple of:
+
+            * `None` or dict of `session id` -> `rtt`, same as `recv`.
+            * `None` or list of expired session ids, same as `get_expired`.
         """
         ...
 
-    def get_expired(self) -> Optional[List[str]]:
+    def get_clock(self) -> int:
+        """
+        Get current timestamp of the socket's clock.
+
+        Returns:
+            Timestamp in nanoseconds. Session deadlines
+            are measured by the same clock.
+        """
+        ...
+
+    def get_clock_resolution(self) -> int:
+        """
+        Get resolution of the socket's clock, either
+        `CLOCK_MONOTONIC_COARSE` or `CLOCK_MONOTONIC`,
+        depending on `set_coarse`.
+
+        Returns:
+            Resolution in nanoseconds. Measured RTTs are
+            quantized by the resolution.
+        """
+        ...
+
+    def get_next_deadline(self) -> Optional[int]:
+        """
+        Get the closest deadline of the pending sessions.
+
+        Returns:
+            * `None` - when no pending sessions.
+            * Deadline in nanoseconds, according to `get_clock`.
+        """
+        ...
+
+    def next_deadline_ns(self) -> Optional[int]:
+        """
+        Get the closest deadline of the pending sessions
+        according to CLOCK_MONOTONIC. Suitable to arm
+        event loop's timers.
+
+        Returns:
+            * `None` - when no pending sessions.
+            * Deadline in nanoseconds.
+        """
+        ...
+
+    def get_expired(self, now: Optional[int] = None) -> Optional[List[str]]:
         """
         Get list of sessions with expired timeouts.
 
+        Args:
+            now: Timestamp in nanoseconds, according to `get_clock`.
+                Use current timestamp when empty.
+
+        Sessions, failed by ICMP errors and not delivered
+        by `recv_replies`, are reported as expired.
+
         Returns:
             * `None` - when no sessions expired.
             * List of expired sessionn ids, where each session id
@@ -134,6 +1248,53 @@ class SocketProto(Protocol):
         """
         ...
 
+    def close(self, drain: bool = False) -> Optional[Dict[str, float]]:
+        """
+        Close the socket. Stop accepting new requests,
+        detach filters and close the file descriptor.
+
+        Args:
+            drain: Block until all pending sessions are
+                either answered or expired.
+
+        Returns:
+            * `None` - when no replies received while draining.
+            * Dict of `session id` -> `rtt`, same as `recv`.
+        """
+        ...
+
+    def detach(self) -> None:
+        """
+        Close the socket, leaving the file descriptor open.
+        Use when the descriptor is already closed elsewhere,
+        as its number may be reused by the other file.
+        """
+        ...
+
+    @property
+    def is_closed(self) -> bool:
+        """
+        Check if the socket is closed.
+
+        Returns:
+            True, if the socket is closed.
+        """
+        ...
+
+    def __enter__(self) -> "SocketProto":
+        ...
+
+    def __exit__(
+        self,
+        exc_type: Optional[Type[BaseException]],
+        exc_val: Optional[BaseException],
+        exc_tb: Optional[TracebackType],
+    ) -> bool:
+        """
+        Close the socket without draining.
+        """
+        ...
+
     def clean_ip(self, addr: str) -> str:
         """
         Normalize IP address to a stable form.
@@ -0,0 +1,55 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: RateLimitDetector implementation
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+from typing import Deque
+from collections import deque
+
+# Minimal number of losses in the window to consider the pattern
+MIN_LOSSES = 3
+
+
+class RateLimitDetector(object):
+    """
+    Detect ICMP rate limiting on the target by the loss pattern.
+
+    Rate limiter on the target passes a fixed number of replies
+    per period, so probes at the constant rate above the limit
+    are lost periodically, with replies between the losses,
+    unlike the random loss or the outage.
+
+    Args:
+        window: Number of the latest results to analyze.
+    """
+
+    def __init__(self, window: int = 20) -> None:
+        self.__window = window
+        self.__results: Deque[bool] = deque(maxlen=window)
+
+    def register(self, success: bool) -> bool:
+        """
+        Account the probe's result.
+
+        Args:
+            success: Reply is received.
+
+        Returns:
+            True, if the rate limiting is detected. Collected
+            results are reset then, so the next detection
+            requires the full window of results.
+        """
+        self.__results.append(success)
+        if len(self.__results) < self.__window:
+            return False
+        losses = [i for i, r in enumerate(self.__results) if not r]
+        if len(losses) < MIN_LOSSES:
+            return False
+        gaps = [b - a for a, b in zip(losses, losses[1:])]
+        # Losses are spaced evenly, with replies between them
+        if min(gaps) < 2 or max(gaps) - min(gaps) > 1:
+            return False
+        self.__results.clear()
+        return True
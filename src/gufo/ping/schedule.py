@@ -0,0 +1,144 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Schedule implementation
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import json
+from time import monotonic
+from typing import Iterable, List, Optional, TextIO
+
+
+class ScheduledProbe(object):
+    """
+    Single probe of the schedule.
+
+    Attributes:
+        addr: Target's address.
+        t: Time of sending, in seconds since the first probe.
+        size: Packet's size, including IP header.
+        seq: ICMP sequence number.
+    """
+
+    __slots__ = ("addr", "t", "size", "seq")
+
+    def __init__(self, addr: str, t: float, size: int, seq: int) -> None:
+        self.addr = addr
+        self.t = t
+        self.size = size
+        self.seq = seq
+
+    def __repr__(self) -> str:
+        return (
+            f"<ScheduledProbe addr={self.addr} t={self.t}"
+            f" size={self.size} seq={self.seq}>"
+        )
+
+    def __eq__(self, other: object) -> bool:
+        if not isinstance(other, ScheduledProbe):
+            return NotImplemented
+        return (
+            self.addr == other.addr
+            and self.t == other.t
+            and self.size == other.size
+            and self.seq == other.seq
+        )
+
+
+class Schedule(object):
+    """
+    Exact schedule of the probes, recorded by `Ping` or `PingSocket`,
+    to be replayed later by `Ping.replay`, making measurement
+    campaigns reproducible.
+
+    Dumped as JSON lines, one probe per line:
+
+    ``` json
+    {"addr": "127.0.0.1", "t": 0.0, "size": 64, "seq": 1}
+    ```
+
+    Args:
+        probes: Recorded probes, ordered by time.
+            Start the recording when empty.
+    """
+
+    __slots__ = ("probes", "_start")
+
+    def __init__(self, probes: Optional[Iterable[ScheduledProbe]] = None):
+        self.probes: List[ScheduledProbe] = list(probes or [])
+        self._start: Optional[float] = None
+
+    def __len__(self) -> int:
+        return len(self.probes)
+
+    def __repr__(self) -> str:
+        return f"<Schedule probes={len(self.probes)}>"
+
+    def record(self, addr: str, size: int, seq: int) -> None:
+        """
+        Record probe, sent just now.
+
+        Args:
+            addr: Target's address.
+            size: Packet's size, including IP header.
+            seq: ICMP sequence number.
+        """
+        now = monotonic()
+        if self._start is None:
+            # Continue after the loaded probes
+            last = self.probes[-1].t if self.probes else 0.0
+            self._start = now - last
+        self.probes.append(ScheduledProbe(addr, now - self._start, size, seq))
+
+    def dump(self, fp: TextIO) -> None:
+        """
+        Write the schedule as JSON lines.
+
+        Args:
+            fp: Text file to write.
+        """
+        for probe in self.probes:
+            json.dump(
+                {
+                    "addr": probe.addr,
+                    "t": probe.t,
+                    "size": probe.size,
+                    "seq": probe.seq,
+                },
+                fp,
+            )
+            fp.write("\n")
+
+    @classmethod
+    def load(cls, fp: TextIO) -> "Schedule":
+        """
+        Read the schedule, written by `dump`.
+
+        Args:
+            fp: Text file to read.
+
+        Returns:
+            Schedule instance.
+
+        Raises:
+            ValueError: On malformed lines.
+        """
+        probes = []
+        for n, line in enumerate(fp, start=1):
+            if not line.strip():
+                continue
+            try:
+                item = json.loads(line)
+                probes.append(
+                    ScheduledProbe(
+                        str(item["addr"]),
+                        float(item["t"]),
+                        int(item["size"]),
+                        int(item["seq"]),
+                    )
+                )
+            except (ValueError, KeyError, TypeError) as e:
+                raise ValueError(f"line {n}: invalid probe: {e}") from e
+        probes.sort(key=lambda x: x.t)
+        return cls(probes)
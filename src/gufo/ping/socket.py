@@ -5,14 +5,41 @@
 # ---------------------------------------------------------------------
 
 # Python modules
-from typing import Optional, Dict, cast
-from asyncio import Future, get_running_loop, sleep
+from typing import (
+    Any,
+    Callable,
+    Optional,
+    Dict,
+    Iterable,
+    List,
+    Tuple,
+    Type,
+    cast,
+)
+from types import TracebackType
+import errno
+from asyncio import Future, TimerHandle, get_running_loop, wait
 
 # Gufo Labs modules
 from .proto import SocketProto
-from ._fast import SocketWrapper
+from .options import ProtoOptions
+from .schedule import Schedule
+from ._fast import (
+    SocketWrapper,
+    INVALID_RTT_CLAMP,
+    INVALID_RTT_ERROR,
+    REQUEST_ID_CALLER,
+    OVERLOAD_REJECT,
+    TS_PLAIN,
+    PADDING_FIXED,
+    MIMIC_NONE,
+    JITTER_NONE,
+    RTT_S,
+)
 
 NS = 1_000_000_000.0
+# Errors, fixed by re-creating the socket
+REBUILD_ERRNO = {errno.ENETDOWN, errno.EBADF}
 
 
 class PingSocket(object):
@@ -36,6 +63,100 @@ class PingSocket(object):
             fall back to CLOCK_MONOTONIC otherwise.
         accelerated: Enable platform-dependend accelerated
             socket processing.
+        busy_poll: Busy polling timeout, in microseconds (Linux).
+            Do not use busy polling when empty.
+        incoming_cpu: Process incoming packets on the given CPU
+            (Linux). Use OS defaults when empty.
+        max_in_flight: Limit number of in-flight requests.
+            Unlimited when empty.
+        overload_policy: Action on reaching `max_in_flight` limit:
+
+            * `OVERLOAD_REJECT` - `ping` raises `BlockingIOError`.
+            * `OVERLOAD_EVICT` - the request with the closest deadline
+              is considered timed out.
+        request_id_policy: ICMP request id allocation policy:
+
+            * `REQUEST_ID_CALLER` - use request id passed to `ping`.
+            * `REQUEST_ID_RANDOM` - random request id,
+              chosen on socket creation.
+            * `REQUEST_ID_SIGNATURE` - request id, derived from
+              socket's signature.
+        src_addr: Bind socket to the source address.
+            Use OS defaults when empty.
+        freebind: Allow binding to the non-local `src_addr` (Linux).
+        transparent: Set `IP_TRANSPARENT`, allowing binding to the
+            non-local `src_addr`. Requires CAP_NET_ADMIN (Linux).
+        batch_size: Deliver replies in batches of up to `batch_size`
+            replies. Deliver immediately when empty.
+        batch_delay: Maximal delay of the batch delivery, in seconds.
+        ts_mode: Mode of timestamps, embedded into requests:
+            `TS_PLAIN`, `TS_OBFUSCATED`, or `TS_NONCE`.
+            See `SocketProto.set_ts_mode` for details.
+        padding: Padding of requests: `PADDING_FIXED` or
+            `PADDING_RANDOM`. See `SocketProto.set_padding` for details.
+        mimic: Reproduce payload of OS ping utility: `MIMIC_NONE`,
+            `MIMIC_LINUX`, or `MIMIC_WINDOWS`.
+            See `SocketProto.set_mimic` for details.
+        txtime: Release requests at exact times by kernel
+            (SO_TXTIME, Linux), according to the clock, i.e.
+            `time.CLOCK_MONOTONIC` for `fq` qdisc, or `time.CLOCK_TAI`
+            for `etf` qdisc. See `SocketProto.set_txtime` for details.
+        tx_timestamps: Measure RTT from the time the requests
+            left the host, excluding local queuing delay (Linux).
+            See `SocketProto.set_tx_timestamps` for details.
+        jitter: Randomize probe intervals: `JITTER_NONE`,
+            `JITTER_UNIFORM`, or `JITTER_EXPONENTIAL`.
+            See `SocketProto.set_jitter` for details.
+        jitter_percent: Randomized share of probe interval, in percents.
+        late_grace: Count replies, arrived up to `late_grace` seconds
+            after the timeout, as late rather than unknown.
+            See `SocketProto.set_late_grace` for details.
+        result_fd: Stream results as binary records to the file
+            descriptor. See `SocketProto.set_result_fd` for details.
+        fd: Wrap already opened raw socket of the `afi` family,
+            i.e. passed by systemd socket activation or by the
+            privileged parent, instead of opening the new one.
+            The socket takes the ownership of the file descriptor.
+            See `SocketProto.from_fd` for details.
+        proto_options: Override ICMP types, code, and checksum
+            verification. See `ProtoOptions` for details.
+        schedule: Record the probes to the schedule, to be replayed
+            later by `Ping.replay`.
+        latency_budget: Account host-side latency of the pipeline
+            stages. See `SocketProto.get_latency_budget` for details.
+        recv_errors: Resolve requests, failed by ICMP errors
+            (unreachable, prohibited, time exceeded), as timed out
+            without waiting for the timeout.
+            See `SocketProto.set_recv_errors` for details.
+        invalid_rtt: Handling of zero or negative RTT:
+            `INVALID_RTT_CLAMP`, `INVALID_RTT_ERROR`, or
+            `INVALID_RTT_RAW`. Failed requests are resolved
+            as timed out in `INVALID_RTT_ERROR` mode.
+            See `SocketProto.set_invalid_rtt` for details.
+        rebuild: Re-create the socket with the same options
+            on `ENETDOWN` and `EBADF` errors of sending and receiving,
+            i.e. after interface bounce, and retry the request.
+            Pending requests are handed over to the new socket.
+            The new socket is always opened by the process, even
+            if `fd` is passed, so rebuild is refused by
+            `Ping.drop_privileges`, `Ping.enable_sandbox`, and
+            `Ping.restrict_sockets`. The old socket is kept,
+            when the new one cannot be opened.
+        on_rebuild: Called with the error after the socket is
+            re-created.
+        result_log: Append results as binary records to the file,
+            surviving the process crash.
+            See `SocketProto.set_result_log` for details.
+        result_log_max_size: Rotate the result log before exceeding
+            `result_log_max_size` bytes.
+        result_log_max_age: Rotate the result log after
+            `result_log_max_age` seconds.
+        result_log_keep: Number of rotated result logs to keep.
+
+    Raises:
+        PermissionError: if the raw socket is denied. The message
+            explains the missed CAP_NET_RAW capability, i.e. suggests
+            to set the file capability of the interpreter.
     """
 
     def __init__(
@@ -49,47 +170,108 @@ class PingSocket(object):
         recv_buffer_size: Optional[int] = None,
         coarse: bool = False,
         accelerated: bool = True,
+        request_id_policy: int = REQUEST_ID_CALLER,
+        busy_poll: Optional[int] = None,
+        incoming_cpu: Optional[int] = None,
+        max_in_flight: Optional[int] = None,
+        overload_policy: int = OVERLOAD_REJECT,
+        src_addr: Optional[str] = None,
+        freebind: bool = False,
+        transparent: bool = False,
+        batch_size: Optional[int] = None,
+        batch_delay: float = 0.05,
+        ts_mode: int = TS_PLAIN,
+        padding: int = PADDING_FIXED,
+        mimic: int = MIMIC_NONE,
+        txtime: Optional[int] = None,
+        tx_timestamps: bool = False,
+        jitter: int = JITTER_NONE,
+        jitter_percent: float = 10.0,
+        late_grace: Optional[float] = None,
+        result_fd: Optional[int] = None,
+        fd: Optional[int] = None,
+        proto_options: Optional[ProtoOptions] = None,
+        schedule: Optional[Schedule] = None,
+        latency_budget: bool = False,
+        recv_errors: bool = False,
+        invalid_rtt: int = INVALID_RTT_CLAMP,
+        rebuild: bool = False,
+        on_rebuild: Optional[Callable[[OSError], None]] = None,
+        result_log: Optional[str] = None,
+        result_log_max_size: Optional[int] = None,
+        result_log_max_age: Optional[float] = None,
+        result_log_keep: int = 0,
     ):
         self.__force_del = False
         if afi != 4 and afi != 6:
             raise ValueError("afi must be 4 or 6")
         # Check settings
-        if ttl is not None and (ttl < 1 or ttl > 255):
-            raise ValueError("ttl must be in 0..255 range")
-        if tos is not None and (tos < 0 or tos > 255):
-            raise ValueError("tos must be in 0..255 range")
+        self.__check_ttl_tos(ttl, tos)
         #
         self.__size = size
-        # Create and initialize wrapped socket
-        self.__sock: SocketProto = cast(SocketProto, SocketWrapper(afi))
-        self.__sock.set_timeout(int(timeout * NS))
-        if ttl is not None:
-            self.__sock.set_ttl(ttl)
-        if tos is not None:
-            self.__sock.set_tos(tos)
-        if send_buffer_size is not None:
-            self.__sock.set_send_buffer_size(send_buffer_size)
-        if recv_buffer_size is not None:
-            self.__sock.set_recv_buffer_size(recv_buffer_size)
-        if coarse:
-            self.__sock.set_coarse(True)
-        if accelerated:
-            self.__sock.set_accelerated(True)
-        self.__timeout = timeout
+        self.__schedule = schedule
+        self.__afi = afi
+        self.__options: Dict[str, Any] = {
+            "request_id_policy": request_id_policy,
+            "timeout": timeout,
+            "ttl": ttl,
+            "tos": tos,
+            "send_buffer_size": send_buffer_size,
+            "recv_buffer_size": recv_buffer_size,
+            "coarse": coarse,
+            "accelerated": accelerated,
+            "busy_poll": busy_poll,
+            "incoming_cpu": incoming_cpu,
+            "max_in_flight": max_in_flight,
+            "overload_policy": overload_policy,
+            "freebind": freebind,
+            "transparent": transparent,
+            "src_addr": src_addr,
+            "batch_size": batch_size,
+            "batch_delay": batch_delay,
+            "result_fd": result_fd,
+            "ts_mode": ts_mode,
+            "padding": padding,
+            "mimic": mimic,
+            "proto_options": proto_options,
+            "txtime": txtime,
+            "tx_timestamps": tx_timestamps,
+            "jitter": jitter,
+            "jitter_percent": jitter_percent,
+            "late_grace": late_grace,
+            "latency_budget": latency_budget,
+            "recv_errors": recv_errors,
+            "invalid_rtt": invalid_rtt,
+            "result_log": result_log,
+            "result_log_max_size": result_log_max_size,
+            "result_log_max_age": result_log_max_age,
+            "result_log_keep": result_log_keep,
+        }
+        self.__rebuild = rebuild
+        self.__on_rebuild = on_rebuild
+        # Sessions may be failed before the deadline
+        self.__may_fail = recv_errors or invalid_rtt == INVALID_RTT_ERROR
+        self.__target: Optional[str] = None
+        self.__sock = self.__open(fd)
         self.__sock_fd = self.__sock.get_fd()
         #  <addr>-<request id>-<seq> -> future
         self.__sessions: Dict[str, Future[Optional[float]]] = {}
+        # Received (sid, rtt), reused between reads
+        self.__seen: List[Tuple[str, float]] = []
         # Install response reader
         self.__force_del = True
         get_running_loop().add_reader(self.__sock_fd, self.__on_read)
-        # Install deadline cleaner
-        self.__cleanup_task = get_running_loop().create_task(self.__cleanup())
+        # Expiration timer, armed for the closest deadline
+        self.__timer: Optional[TimerHandle] = None
+        self.__timer_at = 0.0
+        # Batch delivery timer
+        self.__flush_timer: Optional[TimerHandle] = None
 
     def __del__(self) -> None:
         """
         Perform cleanup on delete:
 
-        * Cancel expiration task.
+        * Cancel expiration timer.
         * Remove socket reader.
         """
         if not self.__force_del:
@@ -98,12 +280,365 @@ class PingSocket(object):
             # Unsubscribe reader
             # get_running_loop() may raise Runtime Error
             get_running_loop().remove_reader(self.__sock_fd)
-            # Stop cleanup task
-            if self.__cleanup_task is not None:
-                self.__cleanup_task.cancel()
+            # Stop expiration timer
+            if self.__timer is not None:
+                self.__timer.cancel()
+            if self.__flush_timer is not None:
+                self.__flush_timer.cancel()
         except RuntimeError:  # pragma: no cover
             pass  # Loop is already closed
 
+    async def __aenter__(self) -> "PingSocket":
+        return self
+
+    async def __aexit__(
+        self,
+        exc_type: Optional[Type[BaseException]],
+        exc_val: Optional[BaseException],
+        exc_tb: Optional[TracebackType],
+    ) -> None:
+        await self.close()
+
+    @property
+    def is_closed(self) -> bool:
+        """
+        Check if the socket is closed.
+
+        Returns:
+            True, if the socket is closed.
+        """
+        return self.__sock.is_closed
+
+    async def close(self, drain: bool = False) -> None:
+        """
+        Close the socket. Pending requests are resolved as timed out.
+
+        Args:
+            drain: Wait until all pending requests are either
+                answered or expired before closing.
+        """
+        if not self.__force_del:
+            return  # Already closed
+        if drain and self.__sessions:
+            await wait(list(self.__sessions.values()))
+        self.__force_del = False
+        get_running_loop().remove_reader(self.__sock_fd)
+        if self.__timer is not None:
+            self.__timer.cancel()
+            self.__timer = None
+        if self.__flush_timer is not None:
+            self.__flush_timer.cancel()
+            self.__flush_timer = None
+        # Deliver pending batch
+        self.__resolve_dict(self.__sock.flush())
+        self.__sock.close()
+        # Resolve pending requests
+        for fut in self.__sessions.values():
+            if not fut.done():
+                fut.set_result(None)
+        self.__sessions = {}
+
+    def get_pending(self) -> List[Tuple[str, str, float]]:
+        """
+        Get list of pending requests, ordered by deadline.
+
+        Returns:
+            List of (`session id`, `target`, `seconds to deadline`).
+        """
+        return [
+            (sid, target, float(left) / NS)
+            for sid, target, left in self.__sock.get_pending()
+        ]
+
+    def get_pending_count(self) -> int:
+        """
+        Get number of pending requests.
+
+        Returns:
+            Number of pending requests.
+        """
+        return self.__sock.get_pending_count()
+
+    def get_latency_budget(self) -> Optional[Dict[str, Dict[str, Any]]]:
+        """
+        Get histograms of the pipeline stages' latency.
+        See `SocketProto.get_latency_budget` for details.
+
+        Returns:
+            Dict of stage -> histogram, None unless `latency_budget`
+            is set.
+        """
+        return self.__sock.get_latency_budget()
+
+    def get_clock_resolution(self) -> float:
+        """
+        Get resolution of the RTT clock. Coarse clock quantizes
+        measured RTTs by its resolution.
+
+        Returns:
+            Resolution, in seconds.
+        """
+        return float(self.__sock.get_clock_resolution()) / NS
+
+    def get_options(self) -> Dict[str, Any]:
+        """
+        Get effective socket options, as applied by the kernel.
+
+        Returns:
+            Dict of option -> value. See `SocketProto.get_options`
+            for details.
+        """
+        return self.__sock.get_options()
+
+    @staticmethod
+    def __check_ttl_tos(ttl: Optional[int], tos: Optional[int]) -> None:
+        """
+        Check TTL and ToS values.
+
+        Args:
+            ttl: TTL value.
+            tos: ToS value.
+
+        Raises:
+            ValueError: if values are out of range.
+        """
+        if ttl is not None and (ttl < 1 or ttl > 255):
+            raise ValueError("ttl must be in 0..255 range")
+        if tos is not None and (tos < 0 or tos > 255):
+            raise ValueError("tos must be in 0..255 range")
+
+    def __open(self, fd: Optional[int]) -> SocketProto:
+        """
+        Create and initialize wrapped socket.
+
+        Args:
+            fd: Wrap already opened socket, open the new one when empty.
+
+        Returns:
+            Initialized socket.
+        """
+        o = self.__options
+        sock = cast(
+            SocketProto,
+            SocketWrapper(self.__afi, o["request_id_policy"])
+            if fd is None
+            else SocketWrapper.from_fd(self.__afi, fd, o["request_id_policy"]),
+        )
+        sock.set_timeout(int(o["timeout"] * NS))
+        # Futures are resolved with seconds
+        sock.set_rtt_unit(RTT_S)
+        if o["ttl"] is not None:
+            sock.set_ttl(o["ttl"])
+        if o["tos"] is not None:
+            sock.set_tos(o["tos"])
+        if o["send_buffer_size"] is not None:
+            sock.set_send_buffer_size(o["send_buffer_size"])
+        if o["recv_buffer_size"] is not None:
+            sock.set_recv_buffer_size(o["recv_buffer_size"])
+        if o["coarse"]:
+            sock.set_coarse(True)
+        if o["accelerated"]:
+            sock.set_accelerated(True)
+        if o["busy_poll"] is not None:
+            sock.set_busy_poll(o["busy_poll"])
+        if o["incoming_cpu"] is not None:
+            sock.set_incoming_cpu(o["incoming_cpu"])
+        if o["max_in_flight"] is not None:
+            sock.set_max_in_flight(o["max_in_flight"], o["overload_policy"])
+        if o["freebind"]:
+            sock.set_freebind(True)
+        if o["transparent"]:
+            sock.set_transparent(True)
+        if o["src_addr"] is not None:
+            sock.bind(o["src_addr"])
+        if o["batch_size"] is not None:
+            sock.set_batching(o["batch_size"], int(o["batch_delay"] * NS))
+        if o["result_fd"] is not None:
+            sock.set_result_fd(o["result_fd"])
+        if o["ts_mode"] != TS_PLAIN:
+            sock.set_ts_mode(o["ts_mode"])
+        if o["padding"] != PADDING_FIXED:
+            sock.set_padding(o["padding"])
+        if o["mimic"] != MIMIC_NONE:
+            sock.set_mimic(o["mimic"])
+        if o["proto_options"] is not None:
+            self.__apply_proto_options(sock, o["proto_options"])
+        if o["txtime"] is not None:
+            sock.set_txtime(o["txtime"])
+        if o["tx_timestamps"]:
+            sock.set_tx_timestamps(True)
+        if o["jitter"] != JITTER_NONE:
+            sock.set_jitter(o["jitter"], o["jitter_percent"])
+        if o["late_grace"]:
+            sock.set_late_grace(int(o["late_grace"] * NS))
+        if o["latency_budget"]:
+            sock.set_latency_budget(True)
+        if o["recv_errors"]:
+            sock.set_recv_errors(True)
+        if o["invalid_rtt"] != INVALID_RTT_CLAMP:
+            sock.set_invalid_rtt(o["invalid_rtt"])
+        if o["result_log"] is not None:
+            max_age = o["result_log_max_age"]
+            sock.set_result_log(
+                o["result_log"],
+                o["result_log_max_size"],
+                None if max_age is None else int(max_age * NS),
+                o["result_log_keep"],
+            )
+        return sock
+
+    def __apply_proto_options(
+        self, sock: SocketProto, options: ProtoOptions
+    ) -> None:
+        """
+        Apply protocol overrides.
+
+        Args:
+            sock: Socket to configure.
+            options: Protocol overrides.
+        """
+        request_type, reply_type = (8, 0) if self.__afi == 4 else (128, 129)
+        if options.request_type is not None:
+            request_type = options.request_type
+        if options.reply_type is not None:
+            reply_type = options.reply_type
+        sock.set_icmp_types(request_type, reply_type)
+        if options.code:
+            sock.set_icmp_code(options.code)
+        if not options.verify_checksum:
+            sock.set_verify_checksum(False)
+
+    def __rebuild_socket(self, e: OSError) -> None:
+        """
+        Re-create the socket with the same options and hand
+        pending requests over to the new one. Replies to the old
+        socket are matched until the requests are expired.
+
+        Args:
+            e: Error, caused the rebuild.
+        """
+        old = self.__sock
+        state = old.export_state()
+        # Old socket is left intact, when the new one cannot be opened
+        sock = self.__open(None)
+        grace = self.__options["timeout"] + (
+            self.__options["late_grace"] or 0.0
+        )
+        sock.import_state(state, int(grace * NS))
+        if self.__target is not None:
+            sock.set_target(self.__target)
+        self.__resolve_dict(old.flush())
+        get_running_loop().remove_reader(self.__sock_fd)
+        if e.errno == errno.EBADF:
+            # Descriptor is already closed, its number may be reused
+            old.detach()
+        else:
+            old.close()
+        self.__sock = sock
+        self.__sock_fd = self.__sock.get_fd()
+        get_running_loop().add_reader(self.__sock_fd, self.__on_read)
+        if self.__on_rebuild:
+            self.__on_rebuild(e)
+
+    def reconfigure(
+        self,
+        timeout: Optional[float] = None,
+        ttl: Optional[int] = None,
+        tos: Optional[int] = None,
+        busy_poll: Optional[int] = None,
+        incoming_cpu: Optional[int] = None,
+        coarse: Optional[bool] = None,
+        accelerated: Optional[bool] = None,
+        ecn: Optional[int] = None,
+        recv_tos: Optional[bool] = None,
+        recv_pktinfo: Optional[bool] = None,
+    ) -> Dict[str, Any]:
+        """
+        Change socket settings without recreating the socket.
+        Settings are applied all at once, leaving unchanged
+        ones intact. If any of setting fails, the previous
+        values are restored.
+
+        Args:
+            timeout: Default timeout in seconds.
+            ttl: Outgoing packets' TTL.
+            tos: Outgoing packets' DSCP/TOS field.
+            busy_poll: Busy polling timeout, in microseconds (Linux).
+            incoming_cpu: Process incoming packets on the given CPU
+                (Linux).
+            coarse: Use CLOCK_MONOTONIC_COARSE.
+            accelerated: Enable platform-dependend accelerated
+                socket processing.
+            ecn: ECN codepoint of the outgoing packets,
+                one of `ECN_*`, preserving DSCP.
+            recv_tos: Report DSCP/ECN field of the received replies.
+            recv_pktinfo: Report local address and interface
+                of the received replies.
+
+        Returns:
+            Dict of changed settings -> effective values,
+            as reported by `get_options`.
+        """
+        self.__check_ttl_tos(ttl, tos)
+        options: Dict[str, Any] = {
+            "ttl": ttl,
+            "tos": tos,
+            "busy_poll": busy_poll,
+            "incoming_cpu": incoming_cpu,
+            "coarse": coarse,
+            "accelerated": accelerated,
+            "ecn": ecn,
+            "recv_tos": recv_tos,
+            "recv_pktinfo": recv_pktinfo,
+        }
+        if timeout is not None:
+            options["timeout"] = int(timeout * NS)
+        r = self.__sock.reconfigure(
+            {k: v for k, v in options.items() if v is not None}
+        )
+        if timeout is not None:
+            # Deadlines may become closer
+            self.__arm_timer()
+        return r
+
+    def set_target(self, addr: str) -> None:
+        """
+        Connect socket to the single target, saving the route
+        lookup on each request. Requests to the other addresses
+        are rejected. Socket may be connected to the other
+        target later, but cannot be disconnected.
+
+        Args:
+            addr: Target address.
+        """
+        self.__sock.set_target(addr)
+        self.__target = addr
+
+    def get_route(self, addr: str) -> Dict[str, Any]:
+        """
+        Look up the route to the address (Linux).
+
+        Args:
+            addr: Target address.
+
+        Returns:
+            Dict of route details. See `SocketProto.get_route`
+            for details.
+        """
+        return self.__sock.get_route(addr)
+
+    def next_interval(self, interval: float) -> float:
+        """
+        Get the next probe interval, randomized according to `jitter`.
+
+        Args:
+            interval: Nominal interval, in seconds.
+
+        Returns:
+            Interval, in seconds.
+        """
+        return float(self.__sock.next_interval_ns(int(interval * NS))) / NS
+
     def clean_ip(self, addr: str) -> str:
         """
         Normalize IP address to a stable form.
@@ -122,6 +657,8 @@ class PingSocket(object):
         size: Optional[int] = None,
         request_id: int = 0,
         seq: int = 0,
+        src_addr: Optional[str] = None,
+        at: Optional[int] = None,
     ) -> Optional[float]:
         """
         Send ICMP echo request and await for result.
@@ -129,52 +666,159 @@ class PingSocket(object):
         Args:
             addr: Socket to ping.
             size: Packet size in bytes, including IP header.
-            request_id: ICMP request id.
+            request_id: ICMP request id. Ignored unless the socket
+                is created with `REQUEST_ID_CALLER` policy.
             seq: ICMP sequental number.
+            src_addr: Send request from the local address.
+                Use socket's source address when empty.
+            at: Release request at the time of `txtime` clock,
+                in nanoseconds. Send immediately when empty.
         """
         if ":" in addr:
             # Convert IPv6 address to compact form
             addr = self.__sock.clean_ip(addr)
-        sid = f"{addr}-{request_id}-{seq}"
         fut: Future[Optional[float]] = get_running_loop().create_future()
         # Build and send the packet
-        self.__sock.send(addr, request_id, seq, size or self.__size)
+        size = size or self.__size
+        try:
+            sid = self.__sock.send(addr, request_id, seq, size, src_addr, at)
+        except OSError as e:
+            if not self.__rebuild or e.errno not in REBUILD_ERRNO:
+                raise
+            self.__rebuild_socket(e)
+            sid = self.__sock.send(addr, request_id, seq, size, src_addr, at)
+        if self.__schedule is not None:
+            self.__schedule.record(addr, size, seq)
         # Install future in the sessions
         self.__sessions[sid] = fut
+        # New sessions expire not earlier than the pending ones
+        if self.__timer is None:
+            self.__arm_timer()
         # Await response or timeout
         return await fut
 
+    def cancel(self, addr: str) -> int:
+        """
+        Cancel all pending requests to the address.
+        Cancelled requests are resolved as timed out.
+
+        Args:
+            addr: IPv4/IPv6 address.
+
+        Returns:
+            Number of cancelled requests.
+        """
+        n = 0
+        for sid in self.__sock.cancel(addr):
+            fut = self.__sessions.pop(sid, None)
+            if fut and not fut.done():
+                fut.set_result(None)
+                n += 1
+        return n
+
     def __on_read(self) -> None:
         """
         Handle socket read event.
         """
         # Get bulk read info from Rust side
-        seen = self.__sock.recv()
-        if seen is None:
+        try:
+            n = self.__sock.recv_into(self.__seen)
+        except OSError as e:
+            if not self.__rebuild or e.errno not in REBUILD_ERRNO:
+                raise
+            self.__rebuild_socket(e)
+            return
+        # Failed requests are expired
+        if self.__may_fail:
+            self.__resolve_expired()
+        if not n:
+            # Replies may be batched
+            if self.__flush_timer is None:
+                self.__arm_flush_timer()
             return
-        # seen is the dict of sid -> rtt
-        for sid, rtt in seen.items():
+        if self.__flush_timer is not None:
+            self.__flush_timer.cancel()
+            self.__flush_timer = None
+        self.__resolve(self.__seen)
+        self.__seen.clear()
+
+    def __arm_flush_timer(self) -> None:
+        """
+        Arm batch delivery timer, if any replies are pending.
+        """
+        at = self.__sock.next_flush_ns()
+        if at is not None:
+            self.__flush_timer = get_running_loop().call_at(
+                float(at) / NS, self.__on_flush
+            )
+
+    def __on_flush(self) -> None:
+        """
+        Deliver the pending batch.
+        """
+        self.__flush_timer = None
+        self.__resolve_dict(self.__sock.flush())
+
+    def __resolve_dict(self, seen: Optional[Dict[str, float]]) -> None:
+        """
+        Pass replies, returned as dict, to the awaiting futures.
+
+        Args:
+            seen: Dict of sid -> rtt, as returned by `flush`.
+        """
+        if seen:
+            self.__resolve(seen.items())
+
+    def __resolve(self, seen: Iterable[Tuple[str, float]]) -> None:
+        """
+        Pass received replies to the awaiting futures.
+
+        Args:
+            seen: Iterable of (sid, rtt), rtt in seconds.
+        """
+        for sid, rtt in seen:
             # Find and pop the future in single call
             fut = self.__sessions.pop(sid, None)
-            if fut:
+            if fut and not fut.done():
                 # Pass rtt to the future, unblock await in `ping`
-                fut.set_result(float(rtt) / NS)
+                fut.set_result(rtt)
+
+    def __arm_timer(self) -> None:
+        """
+        Arm expiration timer for the closest deadline.
+        """
+        deadline = self.__sock.next_deadline_ns()
+        if deadline is None:
+            return
+        # loop.time() is CLOCK_MONOTONIC
+        at = float(deadline) / NS
+        if self.__timer is not None:
+            if self.__timer_at <= at:
+                return  # Already armed
+            self.__timer.cancel()
+        self.__timer = get_running_loop().call_at(at, self.__on_timer)
+        self.__timer_at = at
 
-    async def __cleanup(self) -> None:
+    def __on_timer(self) -> None:
         """
         Check for expired sessions and close them.
         """
-        while True:
-            # Wait for next cycle
-            await sleep(self.__timeout)
-            # Get a list of exired sids
-            expired = self.__sock.get_expired()
-            if not expired:
-                continue
+        self.__timer = None
+        self.__resolve_expired()
+        # Wait for the next deadline
+        self.__arm_timer()
+
+    def __resolve_expired(self) -> None:
+        """
+        Resolve expired sessions as timed out.
+        """
+        # Get a list of exired sids
+        expired = self.__sock.get_expired()
+        if expired:
             # Iterate over expired sids
             for sid in expired:
                 # Find and pop the future by single call
                 fut = self.__sessions.pop(sid, None)
-                if fut:
+                if fut and not fut.done():
                     # Pass None to indicate the timeout
                     fut.set_result(None)
@@ -0,0 +1,212 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: SQLite emitter
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+"""
+SQLite results sink. Requires `sqlite3` module, which may be
+omitted by the minimal Python builds, so the module
+is not imported by `gufo.ping`.
+"""
+
+# Python modules
+import sqlite3
+import time
+from typing import Any, Dict, Hashable, List, Optional, Tuple
+
+# Gufo Labs modules
+from .emitter import (
+    BaseEmitter,
+    ProbeResult,
+    VerdictChange,
+    format_correlation_id,
+)
+from .stats import PingStats
+
+SCHEMA = [
+    "CREATE TABLE IF NOT EXISTS results ("
+    "ts REAL NOT NULL, target TEXT NOT NULL, seq INTEGER NOT NULL,"
+    " rtt REAL, ttl INTEGER, tag TEXT, suppressed INTEGER NOT NULL,"
+    " anomalous INTEGER NOT NULL, correlation_id TEXT)",
+    "CREATE INDEX IF NOT EXISTS results_ts ON results (ts)",
+    "CREATE TABLE IF NOT EXISTS verdicts ("
+    "ts REAL NOT NULL, target TEXT NOT NULL, down INTEGER NOT NULL,"
+    " tag TEXT, suppressed INTEGER NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS aggregates ("
+    "ts REAL NOT NULL, period REAL NOT NULL, target TEXT NOT NULL,"
+    " sent INTEGER NOT NULL, received INTEGER NOT NULL,"
+    " rtt_min REAL, rtt_avg REAL, rtt_max REAL)",
+    "CREATE INDEX IF NOT EXISTS aggregates_ts ON aggregates (ts)",
+]
+
+INSERT = {
+    "results": "INSERT INTO results VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    "verdicts": "INSERT INTO verdicts VALUES (?, ?, ?, ?, ?)",
+    "aggregates": "INSERT INTO aggregates VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+}
+
+
+class SqliteEmitter(BaseEmitter):
+    """
+    Write results, health check verdicts, and periodic per-target
+    aggregates into the local SQLite database, for the edge devices,
+    where running the full time-series database is not feasible.
+
+    Tables are created when missing:
+
+    * `results` - `ts`, `target`, `seq`, `rtt` (seconds, NULL on
+      timeout), `ttl`, `tag`, `suppressed`, `anomalous`,
+      and `correlation_id` (bytes as hex, UUID in canonical form).
+    * `verdicts` - `ts`, `target`, `down`, `tag`, and `suppressed`.
+    * `aggregates` - `ts` (end of the period), `period` (seconds),
+      `target`, `sent`, `received`, `rtt_min`, `rtt_avg`,
+      and `rtt_max`.
+
+    Tags are stored as strings, `ts` is UNIX time. Rows are buffered
+    and inserted in the single transaction when `batch_size` rows
+    are accumulated, on the aggregation, and on `close`.
+    Writes are blocking, so keep the database on the local storage.
+
+    Args:
+        path: Path to the database file.
+        batch_size: Insert after accumulating `batch_size` rows.
+        aggregate_interval: Write the aggregates every
+            `aggregate_interval` seconds. None disables aggregates.
+    """
+
+    def __init__(
+        self,
+        path: str,
+        batch_size: int = 100,
+        aggregate_interval: Optional[float] = 60.0,
+    ) -> None:
+        if batch_size < 1:
+            raise ValueError("batch_size must be positive")
+        if aggregate_interval is not None and aggregate_interval <= 0:
+            raise ValueError("aggregate_interval must be positive")
+        self.__batch_size = batch_size
+        self.__interval = aggregate_interval
+        self.__conn: Optional[sqlite3.Connection] = sqlite3.connect(path)
+        with self.__conn:
+            for sql in SCHEMA:
+                self.__conn.execute(sql)
+        self.__rows: Dict[str, List[Tuple[Any, ...]]] = {
+            table: [] for table in INSERT
+        }
+        self.__pending = 0
+        self.__since = time.time()
+        self.__stats: Dict[str, PingStats] = {}
+
+    @staticmethod
+    def __format_tag(tag: Optional[Hashable]) -> Optional[str]:
+        """
+        Format tag for storage.
+
+        Args:
+            tag: Tag.
+
+        Returns:
+            String tag, or None.
+        """
+        return None if tag is None else str(tag)
+
+    def emit(self, result: ProbeResult) -> None:
+        now = time.time()
+        self.__add(
+            "results",
+            (
+                now,
+                result.target,
+                result.seq,
+                result.rtt,
+                result.ttl,
+                self.__format_tag(result.tag),
+                int(result.suppressed),
+                int(result.anomalous),
+                None
+                if result.correlation_id is None
+                else format_correlation_id(result.correlation_id),
+            ),
+        )
+        if self.__interval is None:
+            return
+        st = self.__stats.get(result.target)
+        if st is None:
+            st = self.__stats[result.target] = PingStats()
+        st.register(result.rtt)
+        if now - self.__since >= self.__interval:
+            self.__aggregate(now)
+
+    def emit_verdict(self, verdict: VerdictChange) -> None:
+        self.__add(
+            "verdicts",
+            (
+                time.time(),
+                verdict.target,
+                int(verdict.down),
+                self.__format_tag(verdict.tag),
+                int(verdict.suppressed),
+            ),
+        )
+
+    def __add(self, table: str, row: Tuple[Any, ...]) -> None:
+        """
+        Buffer the row, insert when batch is full.
+
+        Args:
+            table: Table name.
+            row: Row values.
+        """
+        self.__rows[table].append(row)
+        self.__pending += 1
+        if self.__pending >= self.__batch_size:
+            self.flush()
+
+    def __aggregate(self, now: float) -> None:
+        """
+        Write the aggregates of the period, ended at `now`,
+        and start the next period.
+
+        Args:
+            now: UNIX time.
+        """
+        period = now - self.__since
+        for target, st in self.__stats.items():
+            self.__rows["aggregates"].append(
+                (
+                    now,
+                    period,
+                    target,
+                    st.sent,
+                    st.received,
+                    st.rtt_min,
+                    st.rtt_avg,
+                    st.rtt_max,
+                )
+            )
+        self.__stats = {}
+        self.__since = now
+        self.flush()
+
+    def flush(self) -> None:
+        """
+        Insert buffered rows in the single transaction.
+        """
+        if not self.__conn:
+            return
+        with self.__conn:
+            for table, rows in self.__rows.items():
+                if rows:
+                    self.__conn.executemany(INSERT[table], rows)
+                    rows.clear()
+        self.__pending = 0
+
+    def close(self) -> None:
+        if not self.__conn:
+            return
+        if self.__stats:
+            self.__aggregate(time.time())
+        else:
+            self.flush()
+        self.__conn.close()
+        self.__conn = None
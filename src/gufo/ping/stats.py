@@ -0,0 +1,109 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: PingStats implementation
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+from typing import Optional
+
+# Gufo Labs modules
+from .burst import BurstResult
+
+
+class PingStats(object):
+    """
+    Aggregated probe statistics.
+
+    Attributes:
+        sent: Number of sent requests.
+        received: Number of received replies.
+        rtt_min: Minimal round-trip time, in seconds.
+            None if no replies are received.
+        rtt_max: Maximal round-trip time, in seconds.
+            None if no replies are received.
+        rtt_sum: Sum of round-trip times, in seconds.
+        bursts: Number of sent bursts.
+        bursts_all_lost: Number of bursts with all requests lost.
+        bursts_partial: Number of bursts with some requests lost.
+        bursts_reordered: Number of bursts with reordered replies.
+    """
+
+    __slots__ = (
+        "sent",
+        "received",
+        "rtt_min",
+        "rtt_max",
+        "rtt_sum",
+        "bursts",
+        "bursts_all_lost",
+        "bursts_partial",
+        "bursts_reordered",
+    )
+
+    def __init__(self) -> None:
+        self.sent = 0
+        self.received = 0
+        self.rtt_min: Optional[float] = None
+        self.rtt_max: Optional[float] = None
+        self.rtt_sum = 0.0
+        self.bursts = 0
+        self.bursts_all_lost = 0
+        self.bursts_partial = 0
+        self.bursts_reordered = 0
+
+    def __repr__(self) -> str:
+        return (
+            f"<PingStats sent={self.sent} received={self.received}"
+            f" rtt_min={self.rtt_min} rtt_max={self.rtt_max}>"
+        )
+
+    def register(self, rtt: Optional[float]) -> None:
+        """
+        Account the probe's result.
+
+        Args:
+            rtt: Round-trip time in seconds, None if timed out.
+        """
+        self.sent += 1
+        if rtt is None:
+            return
+        self.received += 1
+        self.rtt_sum += rtt
+        if self.rtt_min is None or rtt < self.rtt_min:
+            self.rtt_min = rtt
+        if self.rtt_max is None or rtt > self.rtt_max:
+            self.rtt_max = rtt
+
+    def register_burst(self, result: BurstResult) -> None:
+        """
+        Account the burst's result. Requests of the burst
+        must be accounted by `register` separately.
+
+        Args:
+            result: Burst result.
+        """
+        self.bursts += 1
+        if result.all_lost:
+            self.bursts_all_lost += 1
+        elif result.partial:
+            self.bursts_partial += 1
+        if result.reordered:
+            self.bursts_reordered += 1
+
+    @property
+    def lost(self) -> int:
+        """
+        Number of lost requests.
+        """
+        return self.sent - self.received
+
+    @property
+    def rtt_avg(self) -> Optional[float]:
+        """
+        Average round-trip time, in seconds.
+        None if no replies are received.
+        """
+        if not self.received:
+            return None
+        return self.rtt_sum / self.received
@@ -0,0 +1,397 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Target health check
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+/// Number of results for RTT conditions, when not set by `lost`
+const DEFAULT_WINDOW: usize = 10;
+
+/// Comparison operator
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cmp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Cmp {
+    fn apply<T: PartialOrd>(&self, a: T, b: T) -> bool {
+        match self {
+            Cmp::Gt => a > b,
+            Cmp::Ge => a >= b,
+            Cmp::Lt => a < b,
+            Cmp::Le => a <= b,
+        }
+    }
+}
+
+/// Aggregate of received RTTs
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    Min,
+    Avg,
+    Max,
+    Percentile(f64),
+}
+
+impl Metric {
+    /// Calculate over sorted non-empty RTTs
+    fn apply(&self, rtts: &[u64]) -> u64 {
+        match self {
+            Metric::Min => rtts[0],
+            Metric::Avg => rtts.iter().sum::<u64>() / rtts.len() as u64,
+            Metric::Max => rtts[rtts.len() - 1],
+            // Nearest rank
+            Metric::Percentile(p) => {
+                let rank = (p / 100.0 * rtts.len() as f64).ceil() as usize;
+                rtts[rank.clamp(1, rtts.len()) - 1]
+            }
+        }
+    }
+}
+
+/// Condition of the policy
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cond {
+    /// Number of lost among `of` latest results
+    Lost { cmp: Cmp, n: usize, of: usize },
+    /// Aggregate of the RTTs, received within the window, in nanoseconds
+    Rtt {
+        metric: Metric,
+        cmp: Cmp,
+        threshold: u64,
+    },
+}
+
+/// Health check policy, marking the target down when the expression
+/// is true. Expression is the `or` of `and`-joined conditions:
+///
+/// * `lost >= 3 of 5`: number of lost requests among the latest ones.
+/// * `p95 > 200ms`, `avg > 100ms`, `min`, `max`: aggregate of the RTTs,
+///   received within the window. Units are `ns`, `us`, `ms`
+///   (default), and `s`.
+///
+/// Window is the largest `of` of the `lost` conditions, or 10.
+/// Operators are `>`, `>=`, `<`, `<=`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    clauses: Vec<Vec<Cond>>,
+    window: usize,
+}
+
+impl Policy {
+    /// Number of latest results, kept for evaluation
+    #[cfg(test)]
+    pub fn get_window(&self) -> usize {
+        self.window
+    }
+
+    /// Evaluate over results, oldest first.
+    /// RTTs are in nanoseconds, None for lost requests
+    pub fn is_down(&self, results: &VecDeque<Option<u64>>) -> bool {
+        let mut rtts: Vec<u64> = results.iter().filter_map(|x| *x).collect();
+        rtts.sort_unstable();
+        self.clauses.iter().any(|clause| {
+            clause.iter().all(|cond| match *cond {
+                Cond::Lost { cmp, n, of } => {
+                    let lost = results
+                        .iter()
+                        .rev()
+                        .take(of)
+                        .filter(|x| x.is_none())
+                        .count();
+                    cmp.apply(lost, n)
+                }
+                Cond::Rtt {
+                    metric,
+                    cmp,
+                    threshold,
+                } => !rtts.is_empty() && cmp.apply(metric.apply(&rtts), threshold),
+            })
+        })
+    }
+}
+
+/// Split expression to tokens
+fn tokenize(expr: &str) -> Vec<String> {
+    let mut r = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '.' {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '.') {
+                    break;
+                }
+                token.push(c.to_ascii_lowercase());
+                chars.next();
+            }
+            r.push(token);
+        } else {
+            chars.next();
+            let token = match c {
+                '≥' => ">=".into(),
+                '≤' => "<=".into(),
+                '>' | '<' if chars.peek() == Some(&'=') => {
+                    chars.next();
+                    format!("{}=", c)
+                }
+                _ => c.to_string(),
+            };
+            r.push(token);
+        }
+    }
+    r
+}
+
+/// Parse comparison operator
+fn parse_cmp(token: Option<&str>) -> Result<Cmp, &'static str> {
+    match token {
+        Some(">") => Ok(Cmp::Gt),
+        Some(">=") => Ok(Cmp::Ge),
+        Some("<") => Ok(Cmp::Lt),
+        Some("<=") => Ok(Cmp::Le),
+        _ => Err("operator expected"),
+    }
+}
+
+/// Parse duration to nanoseconds
+fn parse_duration(token: Option<&str>) -> Result<u64, &'static str> {
+    let token = token.ok_or("duration expected")?;
+    let split = token
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(token.len());
+    let value: f64 = token[..split].parse().map_err(|_| "invalid duration")?;
+    let scale = match &token[split..] {
+        "ns" => 1.0,
+        "us" => 1_000.0,
+        "" | "ms" => 1_000_000.0,
+        "s" => 1_000_000_000.0,
+        _ => return Err("invalid duration unit"),
+    };
+    Ok((value * scale) as u64)
+}
+
+/// Parse positive count
+fn parse_count(token: Option<&str>) -> Result<usize, &'static str> {
+    match token.and_then(|x| x.parse().ok()) {
+        Some(n) if n > 0 => Ok(n),
+        _ => Err("positive number expected"),
+    }
+}
+
+impl FromStr for Policy {
+    type Err = &'static str;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(expr);
+        let mut tokens = tokens.iter().map(|x| x.as_str()).peekable();
+        let mut clauses = Vec::new();
+        let mut clause = Vec::new();
+        let mut window = 0;
+        loop {
+            let cond = match tokens.next() {
+                Some("lost") => {
+                    let cmp = parse_cmp(tokens.next())?;
+                    let n = tokens
+                        .next()
+                        .and_then(|x| x.parse().ok())
+                        .ok_or("number expected")?;
+                    if tokens.next() != Some("of") {
+                        return Err("`of` expected");
+                    }
+                    if tokens.peek() == Some(&"last") {
+                        tokens.next();
+                    }
+                    let of = parse_count(tokens.next())?;
+                    window = window.max(of);
+                    Cond::Lost { cmp, n, of }
+                }
+                Some(name) => {
+                    let metric = match name {
+                        "min" => Metric::Min,
+                        "avg" => Metric::Avg,
+                        "max" => Metric::Max,
+                        _ => match name.strip_prefix('p').and_then(|x| x.parse().ok()) {
+                            Some(p) if (0.0..=100.0).contains(&p) => Metric::Percentile(p),
+                            _ => return Err("unknown condition"),
+                        },
+                    };
+                    Cond::Rtt {
+                        metric,
+                        cmp: parse_cmp(tokens.next())?,
+                        threshold: parse_duration(tokens.next())?,
+                    }
+                }
+                None => return Err("condition expected"),
+            };
+            clause.push(cond);
+            match tokens.next() {
+                Some("and") => {}
+                Some("or") => clauses.push(std::mem::take(&mut clause)),
+                None => break,
+                _ => return Err("`and` or `or` expected"),
+            }
+        }
+        clauses.push(clause);
+        Ok(Policy {
+            clauses,
+            window: if window > 0 { window } else { DEFAULT_WINDOW },
+        })
+    }
+}
+
+/// Health check of the target, tracking the latest results
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+    policy: Policy,
+    results: VecDeque<Option<u64>>,
+    down: Option<bool>,
+}
+
+impl HealthCheck {
+    /// Create health check with the policy
+    pub fn new(policy: Policy) -> Self {
+        HealthCheck {
+            results: VecDeque::with_capacity(policy.window),
+            policy,
+            down: None,
+        }
+    }
+
+    /// Account the result. RTT is in nanoseconds, None for lost request.
+    /// Returns the new verdict, if changed
+    pub fn register(&mut self, rtt: Option<u64>) -> Option<bool> {
+        if self.results.len() == self.policy.window {
+            self.results.pop_front();
+        }
+        self.results.push_back(rtt);
+        let down = self.policy.is_down(&self.results);
+        if self.down == Some(down) {
+            return None;
+        }
+        self.down = Some(down);
+        self.down
+    }
+
+    /// Current verdict, None before the first result
+    pub fn is_down(&self) -> Option<bool> {
+        self.down
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn test_parse() {
+        let policy: Policy = "lost ≥3 of last 5 or p95 > 200ms".parse().unwrap();
+        assert_eq!(policy.get_window(), 5);
+        assert_eq!(
+            policy.clauses,
+            vec![
+                vec![Cond::Lost {
+                    cmp: Cmp::Ge,
+                    n: 3,
+                    of: 5
+                }],
+                vec![Cond::Rtt {
+                    metric: Metric::Percentile(95.0),
+                    cmp: Cmp::Gt,
+                    threshold: 200 * MS
+                }]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_units() {
+        for (expr, threshold) in [
+            ("avg > 500ns", 500),
+            ("avg > 1.5us", 1_500),
+            ("avg > 20", 20 * MS),
+            ("avg > 1s", 1_000 * MS),
+        ] {
+            let policy: Policy = expr.parse().unwrap();
+            assert_eq!(policy.get_window(), DEFAULT_WINDOW);
+            assert_eq!(
+                policy.clauses[0][0],
+                Cond::Rtt {
+                    metric: Metric::Avg,
+                    cmp: Cmp::Gt,
+                    threshold
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        for expr in [
+            "",
+            "lost",
+            "lost > 3",
+            "lost > 3 of 0",
+            "p101 > 1ms",
+            "jitter > 1ms",
+            "avg > 1h",
+            "avg = 1ms",
+            "avg > 1ms lost > 1 of 2",
+            "avg > 1ms or",
+        ] {
+            assert!(expr.parse::<Policy>().is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_metric() {
+        let rtts = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(Metric::Min.apply(&rtts), 1);
+        assert_eq!(Metric::Avg.apply(&rtts), 5);
+        assert_eq!(Metric::Max.apply(&rtts), 10);
+        assert_eq!(Metric::Percentile(50.0).apply(&rtts), 5);
+        assert_eq!(Metric::Percentile(95.0).apply(&rtts), 10);
+        assert_eq!(Metric::Percentile(0.0).apply(&rtts), 1);
+    }
+
+    #[test]
+    fn test_lost() {
+        let mut check = HealthCheck::new("lost >= 3 of 5".parse().unwrap());
+        assert_eq!(check.is_down(), None);
+        assert_eq!(check.register(Some(MS)), Some(false));
+        assert_eq!(check.register(None), None);
+        assert_eq!(check.register(None), None);
+        assert_eq!(check.register(Some(MS)), None);
+        assert_eq!(check.register(None), Some(true));
+        assert_eq!(check.is_down(), Some(true));
+        // Oldest loss is out of window
+        assert_eq!(check.register(Some(MS)), None);
+        assert_eq!(check.register(Some(MS)), Some(false));
+    }
+
+    #[test]
+    fn test_rtt_and() {
+        let mut check = HealthCheck::new("lost >= 1 of 3 and max > 100ms".parse().unwrap());
+        assert_eq!(check.register(Some(200 * MS)), Some(false));
+        assert_eq!(check.register(None), Some(true));
+        assert_eq!(check.register(Some(MS)), None);
+        // Slow reply is out of window
+        assert_eq!(check.register(Some(MS)), Some(false));
+    }
+
+    #[test]
+    fn test_rtt_no_replies() {
+        let mut check = HealthCheck::new("p95 > 200ms".parse().unwrap());
+        assert_eq!(check.register(None), Some(false));
+    }
+}
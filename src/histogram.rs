@@ -0,0 +1,169 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Latency histogram
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+/// Number of buckets, covering the whole u64 range
+const BUCKETS: usize = 65;
+
+/// Histogram of durations in nanoseconds, with power-of-two buckets.
+/// Bucket `i` counts durations within `[2^(i-1), 2^i)`,
+/// bucket 0 counts zero durations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            sum: 0,
+            min: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    /// Get bucket index of the value
+    #[inline]
+    fn get_bucket(value: u64) -> usize {
+        (u64::BITS - value.leading_zeros()) as usize
+    }
+
+    /// Get exclusive upper bound of the bucket,
+    /// saturated on the last bucket
+    fn get_upper(bucket: usize) -> u64 {
+        if bucket >= 64 {
+            u64::MAX
+        } else {
+            1 << bucket
+        }
+    }
+
+    /// Account the duration, in nanoseconds
+    pub fn register(&mut self, value: u64) {
+        self.buckets[Self::get_bucket(value)] += 1;
+        if self.count == 0 || value < self.min {
+            self.min = value;
+        }
+        self.max = self.max.max(value);
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+    }
+
+    /// Number of accounted durations
+    pub fn get_count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of accounted durations
+    pub fn get_sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// Minimal duration, None if empty
+    pub fn get_min(&self) -> Option<u64> {
+        if self.count > 0 {
+            Some(self.min)
+        } else {
+            None
+        }
+    }
+
+    /// Maximal duration, None if empty
+    pub fn get_max(&self) -> Option<u64> {
+        if self.count > 0 {
+            Some(self.max)
+        } else {
+            None
+        }
+    }
+
+    /// Non-empty buckets as (exclusive upper bound, count)
+    pub fn get_buckets(&self) -> Vec<(u64, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n > 0)
+            .map(|(i, n)| (Self::get_upper(i), *n))
+            .collect()
+    }
+
+    /// Estimate the quantile `q` in [0, 1] as the upper bound
+    /// of the bucket, clamped by the maximal duration.
+    /// None if empty
+    pub fn get_quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some((Self::get_upper(i) - 1).min(self.max).max(self.min));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// Forget accounted durations
+    #[cfg(test)]
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty() {
+        let h = Histogram::default();
+        assert_eq!(h.get_count(), 0);
+        assert_eq!(h.get_min(), None);
+        assert_eq!(h.get_max(), None);
+        assert_eq!(h.get_quantile(0.5), None);
+        assert!(h.get_buckets().is_empty());
+    }
+
+    #[test]
+    fn test_register() {
+        let mut h = Histogram::default();
+        for value in [0, 1, 3, 1_000, 1_500, u64::MAX] {
+            h.register(value);
+        }
+        assert_eq!(h.get_count(), 6);
+        assert_eq!(h.get_sum(), u64::MAX);
+        assert_eq!(h.get_min(), Some(0));
+        assert_eq!(h.get_max(), Some(u64::MAX));
+        assert_eq!(
+            h.get_buckets(),
+            vec![(1, 1), (2, 1), (4, 1), (1024, 1), (2048, 1), (u64::MAX, 1)]
+        );
+        h.clear();
+        assert_eq!(h, Histogram::default());
+    }
+
+    #[test]
+    fn test_quantile() {
+        let mut h = Histogram::default();
+        for value in 1..=100 {
+            h.register(value * 1_000);
+        }
+        // 1_000 falls into [512, 1024)
+        assert_eq!(h.get_quantile(0.0), Some(1_023));
+        // 50_000 falls into [32768, 65536)
+        assert_eq!(h.get_quantile(0.5), Some(65_535));
+        assert_eq!(h.get_quantile(1.0), Some(100_000));
+    }
+}
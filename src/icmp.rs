@@ -4,10 +4,12 @@
 // Copyright (C) 2022, Gufo Labs
 // ---------------------------------------------------------------------
 
-use byteorder::{BigEndian, ByteOrder};
+use super::SessionKey;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use internet_checksum::checksum;
 use std::convert::TryFrom;
 use std::mem::MaybeUninit;
+use std::net::IpAddr;
 
 /// ```text
 ///  0                   1                   2                   3
@@ -25,23 +27,45 @@ use std::mem::MaybeUninit;
 /// | Timestamp                                                     |
 /// |                                                               |
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// | Padding ...                                                   |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 /// Where:
 /// * `type`
 ///   * `8`: echo request (ICMPv4)
 ///   * `0`: echo reply (ICMPv4)
 ///   * `128`: echo request (ICMPv6)
 ///   * `129`: echo reply (ICMPv6)
-/// * `code` - 0
+/// * `code` - 0, unless overridden
+/// * `padding` - either filled with `0x30`, or random:
+///   4-octet seed, followed by pseudo-random octets,
+///   generated from the seed.
 /// ```
+///
+/// Mimic layouts replace the whole payload with the one
+/// of the OS ping utility, so the signature and timestamp
+/// are not carried by packet:
+/// * `Linux` - iputils: `struct timeval` of the wall clock
+///   (16 octets, little-endian), followed by octets,
+///   equal to their offset in payload.
+/// * `Windows` - repeating `abcdefghijklmnopqrstuvw` pattern.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Layout {
+    Native,
+    Linux,
+    Windows,
+}
 
 #[derive(Debug, PartialEq)]
-pub(crate) struct IcmpPacket {
+pub struct IcmpPacket {
     icmp_type: u8,
+    code: u8,
     request_id: u16,
     seq: u16,
     signature: u64,
     ts: u64,
-    size: usize, // ip payload size, without IP header
+    size: usize,       // ip payload size, without IP header
+    seed: Option<u32>, // random padding seed
+    layout: Layout,
 }
 
 impl IcmpPacket {
@@ -55,22 +79,60 @@ impl IcmpPacket {
     ) -> Self {
         IcmpPacket {
             icmp_type,
+            code: 0,
             request_id,
             seq,
             signature,
             ts,
             size,
+            seed: None,
+            layout: Layout::Native,
         }
     }
 
+    /// Set ICMP code
+    pub fn with_code(mut self, code: u8) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Fill padding with pseudo-random octets, generated from seed
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Replace payload with the layout of OS ping utility.
+    /// Timestamp is treated as UNIX time in nanoseconds.
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
     pub fn get_sid(&self, addr: String) -> String {
         format!("{}-{}-{}", addr, self.request_id, self.seq)
     }
 
+    pub fn get_key(&self, addr: IpAddr) -> SessionKey {
+        SessionKey::new(addr, self.request_id, self.seq)
+    }
+
     pub fn get_ts(&self) -> u64 {
         self.ts
     }
 
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get_type(&self) -> u8 {
+        self.icmp_type
+    }
+
+    pub fn get_code(&self) -> u8 {
+        self.code
+    }
+
     pub fn is_match(&self, icmp_type: u8, sig: u64) -> bool {
         self.icmp_type == icmp_type && self.signature == sig
     }
@@ -87,19 +149,46 @@ impl IcmpPacket {
         //
         // Assume buffer initialized
         let buf = unsafe { Self::slice_assume_init_mut(&mut buf[..self.size]) };
-        // Write type, fill code and checksum with 0
-        BigEndian::write_u32(buf, (self.icmp_type as u32) << 24);
+        // Write type and code, fill checksum with 0
+        BigEndian::write_u32(
+            buf,
+            (self.icmp_type as u32) << 24 | (self.code as u32) << 16,
+        );
         // Request id, 2 octets
         BigEndian::write_u16(&mut buf[4..], self.request_id);
         // Sequence, 2 octets
         BigEndian::write_u16(&mut buf[6..], self.seq);
-        // Signature, 8 octets
-        BigEndian::write_u64(&mut buf[8..], self.signature);
-        // Timestamp, 8 octets
-        BigEndian::write_u64(&mut buf[16..], self.ts);
-        // Generate padding, Fill rest by "A"
-        if self.size > 24 {
-            buf[24..].fill(48u8);
+        match self.layout {
+            Layout::Native => {
+                // Signature, 8 octets
+                BigEndian::write_u64(&mut buf[8..], self.signature);
+                // Timestamp, 8 octets
+                BigEndian::write_u64(&mut buf[16..], self.ts);
+                // Generate padding
+                if self.size > 24 {
+                    match self.seed {
+                        Some(seed) => fill_padding(&mut buf[24..], seed),
+                        // Fill rest by "0"
+                        None => buf[24..].fill(48u8),
+                    }
+                }
+            }
+            Layout::Linux => {
+                // struct timeval, truncated when payload is short
+                let mut tv = [0u8; 16];
+                LittleEndian::write_u64(&mut tv, self.ts / 1_000_000_000);
+                LittleEndian::write_u64(&mut tv[8..], self.ts % 1_000_000_000 / 1_000);
+                let n = (self.size - 8).min(16);
+                buf[8..8 + n].copy_from_slice(&tv[..n]);
+                for (i, b) in buf[8..].iter_mut().enumerate().skip(16) {
+                    *b = i as u8;
+                }
+            }
+            Layout::Windows => {
+                for (i, b) in buf[8..].iter_mut().enumerate() {
+                    *b = b'a' + (i % 23) as u8;
+                }
+            }
         }
         // Calculate checksum
         // RFC-1071
@@ -108,6 +197,85 @@ impl IcmpPacket {
         buf[3] = cs[1];
         self.size
     }
+
+    /// Rewrite packet, previously written to the buffer by `write`.
+    /// Only request id, sequence and timestamp are replaced,
+    /// so packet's type, code, signature and size must match.
+    /// Checksum is updated incrementally.
+    pub fn rewrite(&self, buf: &mut [u8]) -> usize {
+        let mut cs = BigEndian::read_u16(&buf[2..]);
+        // Request id, 2 octets
+        cs = Self::patch_u16(buf, 4, self.request_id, cs);
+        // Sequence, 2 octets
+        cs = Self::patch_u16(buf, 6, self.seq, cs);
+        // Timestamp, 8 octets
+        cs = Self::patch_u16(buf, 16, (self.ts >> 48) as u16, cs);
+        cs = Self::patch_u16(buf, 18, (self.ts >> 32) as u16, cs);
+        cs = Self::patch_u16(buf, 20, (self.ts >> 16) as u16, cs);
+        cs = Self::patch_u16(buf, 22, self.ts as u16, cs);
+        BigEndian::write_u16(&mut buf[2..], cs);
+        self.size
+    }
+
+    /// Replace 16-bit word at given offset and return adjusted checksum.
+    #[inline(always)]
+    fn patch_u16(buf: &mut [u8], offset: usize, value: u16, cs: u16) -> u16 {
+        let old = BigEndian::read_u16(&buf[offset..]);
+        BigEndian::write_u16(&mut buf[offset..], value);
+        update_checksum(cs, old, value)
+    }
+}
+
+/// Obfuscate or restore timestamp by XOR with the key, derived
+/// from socket's signature, request id and sequence.
+/// The key differs for every packet, so the timestamps
+/// of the consecutive packets cannot be correlated.
+pub fn mask_ts(ts: u64, signature: u64, request_id: u16, seq: u16) -> u64 {
+    // SplitMix64 finalizer
+    let mut z = signature ^ (((request_id as u64) << 16) | seq as u64);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    ts ^ z ^ (z >> 31)
+}
+
+/// Fill random padding: seed, followed by the pseudo-random
+/// octets. Padding, shorter than the seed, is filled with
+/// the seed's octets.
+pub fn fill_padding(buf: &mut [u8], seed: u32) {
+    let mut seed_buf = [0u8; 4];
+    BigEndian::write_u32(&mut seed_buf, seed);
+    let n = buf.len().min(4);
+    buf[..n].copy_from_slice(&seed_buf[..n]);
+    let mut state = seed;
+    for b in buf[n..].iter_mut() {
+        // Numerical Recipes LCG
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        *b = (state >> 24) as u8;
+    }
+}
+
+/// Verify padding, filled by `fill_padding`.
+/// Padding, shorter than the seed, cannot be verified.
+pub fn check_padding(buf: &[u8]) -> bool {
+    if buf.len() <= 4 {
+        return true;
+    }
+    let mut state = BigEndian::read_u32(buf);
+    buf[4..].iter().all(|b| {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        *b == (state >> 24) as u8
+    })
+}
+
+/// Incrementally update RFC-1071 checksum
+/// when 16-bit word `old` is replaced by `new`.
+/// RFC-1624: HC' = ~(~HC + ~m + m')
+#[inline(always)]
+pub fn update_checksum(cs: u16, old: u16, new: u16) -> u16 {
+    let mut sum = (!cs) as u32 + (!old) as u32 + new as u32;
+    sum = (sum & 0xffff) + (sum >> 16);
+    sum = (sum & 0xffff) + (sum >> 16);
+    !(sum as u16)
 }
 
 // Parse IcmpPacket
@@ -121,11 +289,14 @@ impl TryFrom<&[u8]> for IcmpPacket {
         let size = buf.len();
         Ok(Self {
             icmp_type: buf[0],
+            code: buf[1],
             request_id: BigEndian::read_u16(&buf[4..]),
             seq: BigEndian::read_u16(&buf[6..]),
             signature: BigEndian::read_u64(&buf[8..]),
             ts: BigEndian::read_u64(&buf[16..]),
             size,
+            seed: None,
+            layout: Layout::Native,
         })
     }
 }
@@ -151,6 +322,9 @@ mod tests {
         signature: 0xdeadbeefdeadbeef,
         ts: 0x01020304,
         size: 64 - 20,
+        code: 0,
+        seed: None,
+        layout: Layout::Native,
     };
 
     static ICMPV4_REPLY: &[u8] = &[
@@ -169,6 +343,9 @@ mod tests {
         signature: 0xdeadbeefdeadbeef,
         ts: 0x01020304,
         size: 64 - 20,
+        code: 0,
+        seed: None,
+        layout: Layout::Native,
     };
 
     #[test]
@@ -182,6 +359,104 @@ mod tests {
         assert_eq!(result, ICMPV4_REQ);
     }
 
+    #[test]
+    fn test_icmpv4_rewrite() {
+        let mut buf: [MaybeUninit<u8>; 4096] = unsafe { MaybeUninit::uninit().assume_init() };
+        let n = ICMPV4_REQ_PKT.write(&mut buf);
+        let result = unsafe {
+            // slice_assume_init_mut
+            &mut *(&mut buf[..n] as *mut [MaybeUninit<u8>] as *mut [u8])
+        };
+        for (request_id, seq, ts) in [
+            (0x0102, 2, 0x01020305),
+            (0xffff, 0xffff, 0xffffffffffffffff),
+            (0, 0, 0),
+            (0x0102, 1, 0x01020304),
+        ] {
+            let pkt = IcmpPacket::new(8, request_id, seq, 0xdeadbeefdeadbeef, ts, 64 - 20);
+            let mut expected: [MaybeUninit<u8>; 4096] =
+                unsafe { MaybeUninit::uninit().assume_init() };
+            let en = pkt.write(&mut expected);
+            let expected =
+                unsafe { &*(&expected[..en] as *const [MaybeUninit<u8>] as *const [u8]) };
+            assert_eq!(pkt.rewrite(result), en);
+            assert_eq!(result, expected);
+        }
+        assert_eq!(result, ICMPV4_REQ);
+    }
+
+    #[test]
+    fn test_update_checksum() {
+        // RFC-1624, section 4
+        assert_eq!(update_checksum(0xdd2f, 0x5555, 0x3285), 0x0000);
+    }
+
+    #[test]
+    fn test_random_padding() {
+        let pkt = IcmpPacket::new(8, 1, 1, 0xdeadbeef, 1, 64).with_seed(0x01020304);
+        let mut buf: [MaybeUninit<u8>; 4096] = unsafe { MaybeUninit::uninit().assume_init() };
+        let n = pkt.write(&mut buf);
+        let result = unsafe { &mut *(&mut buf[..n] as *mut [MaybeUninit<u8>] as *mut [u8]) };
+        assert_eq!(checksum(result), [0, 0]);
+        assert_eq!(&result[24..28], &[1, 2, 3, 4]);
+        assert!(result[28..].iter().any(|b| *b != result[28]));
+        assert!(check_padding(&result[24..]));
+        result[40] ^= 1;
+        assert!(!check_padding(&result[24..]));
+    }
+
+    #[test]
+    fn test_code() {
+        let pkt = IcmpPacket::new(8, 1, 1, 0xdeadbeef, 1, 64).with_code(7);
+        let mut buf: [MaybeUninit<u8>; 4096] = unsafe { MaybeUninit::uninit().assume_init() };
+        let n = pkt.write(&mut buf);
+        let result = unsafe { &*(&buf[..n] as *const [MaybeUninit<u8>] as *const [u8]) };
+        assert_eq!(&result[..2], &[8, 7]);
+        assert_eq!(checksum(result), [0, 0]);
+        assert_eq!(IcmpPacket::try_from(result).unwrap().get_code(), 7);
+    }
+
+    #[test]
+    fn test_short_padding() {
+        let mut buf = [0u8; 2];
+        fill_padding(&mut buf, 0x01020304);
+        assert_eq!(buf, [1, 2]);
+        assert!(check_padding(&buf));
+    }
+
+    #[test]
+    fn test_linux_layout() {
+        let ts = 1_600_000_000_123_456_789;
+        let pkt = IcmpPacket::new(8, 1, 1, 0xdeadbeef, ts, 64).with_layout(Layout::Linux);
+        let mut buf: [MaybeUninit<u8>; 4096] = unsafe { MaybeUninit::uninit().assume_init() };
+        let n = pkt.write(&mut buf);
+        let result = unsafe { &*(&buf[..n] as *const [MaybeUninit<u8>] as *const [u8]) };
+        assert_eq!(checksum(result), [0, 0]);
+        assert_eq!(LittleEndian::read_u64(&result[8..]), 1_600_000_000);
+        assert_eq!(LittleEndian::read_u64(&result[16..]), 123_456);
+        assert_eq!(&result[24..28], &[0x10, 0x11, 0x12, 0x13]);
+        assert_eq!(result[63], 55);
+    }
+
+    #[test]
+    fn test_windows_layout() {
+        let pkt = IcmpPacket::new(8, 1, 1, 0xdeadbeef, 1, 40).with_layout(Layout::Windows);
+        let mut buf: [MaybeUninit<u8>; 4096] = unsafe { MaybeUninit::uninit().assume_init() };
+        let n = pkt.write(&mut buf);
+        let result = unsafe { &*(&buf[..n] as *const [MaybeUninit<u8>] as *const [u8]) };
+        assert_eq!(checksum(result), [0, 0]);
+        assert_eq!(&result[8..], b"abcdefghijklmnopqrstuvwabcdefghi");
+    }
+
+    #[test]
+    fn test_mask_ts() {
+        let sig = 0xdeadbeefdeadbeef;
+        let masked = mask_ts(0x01020304, sig, 1, 1);
+        assert_ne!(masked, 0x01020304);
+        assert_ne!(masked, mask_ts(0x01020304, sig, 1, 2));
+        assert_eq!(mask_ts(masked, sig, 1, 1), 0x01020304);
+    }
+
     #[test]
     fn test_arr_to_icmpv4() {
         let pkt = IcmpPacket::try_from(ICMPV4_REPLY).unwrap();
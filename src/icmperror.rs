@@ -0,0 +1,312 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: ICMP error parsing
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::ip::ipv4_header_len;
+use super::record::{RECORD_PROHIBITED, RECORD_TIME_EXCEEDED, RECORD_UNREACHABLE};
+use super::SessionKey;
+use byteorder::{BigEndian, ByteOrder};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// ICMPv4 Destination Unreachable
+pub const ICMPV4_UNREACHABLE: u8 = 3;
+/// ICMPv4 Time Exceeded
+pub const ICMPV4_TIME_EXCEEDED: u8 = 11;
+/// ICMPv6 Destination Unreachable
+pub const ICMPV6_UNREACHABLE: u8 = 1;
+/// ICMPv6 Time Exceeded
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+
+// Type, code, checksum and unused/MTU field
+const ERROR_HEADER_SIZE: usize = 8;
+// Type, code, checksum, request id, and sequence
+const QUOTED_ICMP_SIZE: usize = 8;
+// Quoted ICMP header, followed by signature
+const QUOTED_SIGNATURE_SIZE: usize = QUOTED_ICMP_SIZE + 8;
+const IPV6_HEADER_SIZE: usize = 40;
+const PROTO_ICMPV4: u8 = 1;
+const PROTO_ICMPV6: u8 = 58;
+
+/// ICMP error, quoting the echo request.
+/// ```text
+/// error header(8) ip header(20-60|40) request(8+)
+/// ```
+/// Error is related to the request by the destination address,
+/// request id, and sequence of the quoted request.
+/// Routers are obliged to quote 8 octets of the request only,
+/// so the signature is not always present.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IcmpError {
+    icmp_type: u8,
+    code: u8,
+    addr: IpAddr,
+    request_type: u8,
+    request_id: u16,
+    seq: u16,
+    signature: Option<u64>,
+}
+
+impl IcmpError {
+    /// Check if ICMPv4 type is the error, quoting the request
+    pub fn is_v4_error(icmp_type: u8) -> bool {
+        matches!(icmp_type, ICMPV4_UNREACHABLE | ICMPV4_TIME_EXCEEDED)
+    }
+
+    /// Check if ICMPv6 type is the error, quoting the request
+    pub fn is_v6_error(icmp_type: u8) -> bool {
+        matches!(icmp_type, ICMPV6_UNREACHABLE | ICMPV6_TIME_EXCEEDED)
+    }
+
+    /// Parse ICMPv4 error, starting from the ICMP header.
+    /// Returns None, unless the error quotes ICMPv4 packet.
+    pub fn parse_v4(buf: &[u8]) -> Option<Self> {
+        if buf.len() < ERROR_HEADER_SIZE || !Self::is_v4_error(buf[0]) {
+            return None;
+        }
+        let quoted = &buf[ERROR_HEADER_SIZE..];
+        let hlen = ipv4_header_len(quoted)?;
+        if quoted[9] != PROTO_ICMPV4 {
+            return None;
+        }
+        let addr = IpAddr::V4(Ipv4Addr::new(
+            quoted[16], quoted[17], quoted[18], quoted[19],
+        ));
+        Self::parse_request(buf[0], buf[1], addr, &quoted[hlen..])
+    }
+
+    /// Parse ICMPv6 error, starting from the ICMP header.
+    /// Extension headers of the quoted packet are not supported.
+    /// Returns None, unless the error quotes ICMPv6 packet.
+    pub fn parse_v6(buf: &[u8]) -> Option<Self> {
+        if buf.len() < ERROR_HEADER_SIZE + IPV6_HEADER_SIZE || !Self::is_v6_error(buf[0]) {
+            return None;
+        }
+        let quoted = &buf[ERROR_HEADER_SIZE..];
+        if quoted[0] >> 4 != 6 || quoted[6] != PROTO_ICMPV6 {
+            return None;
+        }
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&quoted[24..IPV6_HEADER_SIZE]);
+        let addr = IpAddr::V6(Ipv6Addr::from(octets));
+        Self::parse_request(buf[0], buf[1], addr, &quoted[IPV6_HEADER_SIZE..])
+    }
+
+    fn parse_request(icmp_type: u8, code: u8, addr: IpAddr, request: &[u8]) -> Option<Self> {
+        if request.len() < QUOTED_ICMP_SIZE {
+            return None;
+        }
+        Some(IcmpError {
+            icmp_type,
+            code,
+            addr,
+            request_type: request[0],
+            request_id: BigEndian::read_u16(&request[4..]),
+            seq: BigEndian::read_u16(&request[6..]),
+            signature: if request.len() >= QUOTED_SIGNATURE_SIZE {
+                Some(BigEndian::read_u64(&request[QUOTED_ICMP_SIZE..]))
+            } else {
+                None
+            },
+        })
+    }
+
+    pub fn get_type(&self) -> u8 {
+        self.icmp_type
+    }
+
+    pub fn get_code(&self) -> u8 {
+        self.code
+    }
+
+    /// Get type of the quoted request
+    pub fn get_request_type(&self) -> u8 {
+        self.request_type
+    }
+
+    /// Get signature of the quoted request, if quoted
+    pub fn get_signature(&self) -> Option<u64> {
+        self.signature
+    }
+
+    /// Get key of the session, the request belongs to
+    pub fn get_key(&self) -> SessionKey {
+        SessionKey::new(self.addr, self.request_id, self.seq)
+    }
+
+    /// Get human-readable reason of the error
+    pub fn get_reason(&self) -> &'static str {
+        match (self.addr, self.icmp_type, self.code) {
+            (IpAddr::V4(_), ICMPV4_UNREACHABLE, code) => match code {
+                0 => "network unreachable",
+                1 => "host unreachable",
+                2 => "protocol unreachable",
+                3 => "port unreachable",
+                4 => "fragmentation needed",
+                5 => "source route failed",
+                6 => "destination network unknown",
+                7 => "destination host unknown",
+                8 => "source host isolated",
+                9 => "network administratively prohibited",
+                10 => "host administratively prohibited",
+                11 => "network unreachable for tos",
+                12 => "host unreachable for tos",
+                13 => "communication administratively prohibited",
+                14 => "host precedence violation",
+                15 => "precedence cutoff in effect",
+                _ => "destination unreachable",
+            },
+            (IpAddr::V6(_), ICMPV6_UNREACHABLE, code) => match code {
+                0 => "no route to destination",
+                1 => "communication administratively prohibited",
+                2 => "beyond scope of source address",
+                3 => "address unreachable",
+                4 => "port unreachable",
+                5 => "source address failed ingress/egress policy",
+                6 => "reject route to destination",
+                _ => "destination unreachable",
+            },
+            (_, _, 0) => "hop limit exceeded in transit",
+            (_, _, 1) => "fragment reassembly time exceeded",
+            _ => "time exceeded",
+        }
+    }
+
+    /// Get result status of the error.
+    /// Administratively prohibited destinations are told apart
+    /// from the unreachable ones.
+    pub fn get_status(&self) -> u8 {
+        match (self.addr, self.icmp_type, self.code) {
+            (IpAddr::V4(_), ICMPV4_TIME_EXCEEDED, _) => RECORD_TIME_EXCEEDED,
+            // Network, host, and communication prohibited
+            (IpAddr::V4(_), _, 9 | 10 | 13) => RECORD_PROHIBITED,
+            (IpAddr::V6(_), ICMPV6_TIME_EXCEEDED, _) => RECORD_TIME_EXCEEDED,
+            // Administratively prohibited, policy failure, reject route
+            (IpAddr::V6(_), _, 1 | 5 | 6) => RECORD_PROHIBITED,
+            _ => RECORD_UNREACHABLE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Host unreachable from 192.0.2.254,
+    // quoting the whole echo request to 192.0.2.1
+    const UNREACH_V4: &[u8] = &[
+        0x03, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Error header
+        0x45, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x40, 0x00, 0x40, 0x01, 0x00, 0x00, 0xc0, 0x00, 0x02,
+        0xfe, 0xc0, 0x00, 0x02, 0x01, // IP header
+        0x08, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, // Request header
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // Signature
+    ];
+    // Admin prohibited, quoting request to 2001:db8::1
+    const PROHIBITED_V6: &[u8] = &[
+        0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Error header
+        0x60, 0x00, 0x00, 0x00, 0x00, 0x10, 0x3a, 0x40, // IPv6 header
+        0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x02, // Source
+        0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, // Destination
+        0x80, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x04, // Request header
+    ];
+
+    #[test]
+    fn test_parse_v4() {
+        let err = IcmpError::parse_v4(UNREACH_V4).unwrap();
+        assert_eq!(err.get_type(), ICMPV4_UNREACHABLE);
+        assert_eq!(err.get_code(), 1);
+        assert_eq!(err.get_request_type(), 8);
+        assert_eq!(err.get_signature(), Some(0x0102030405060708));
+        assert_eq!(
+            err.get_key(),
+            SessionKey::new("192.0.2.1".parse().unwrap(), 1, 2)
+        );
+        assert_eq!(err.get_status(), RECORD_UNREACHABLE);
+        assert_eq!(err.get_reason(), "host unreachable");
+    }
+
+    #[test]
+    fn test_parse_v4_short_quote() {
+        // 8 octets of request are quoted
+        let err = IcmpError::parse_v4(&UNREACH_V4[..36]).unwrap();
+        assert_eq!(err.get_signature(), None);
+        assert_eq!(IcmpError::parse_v4(&UNREACH_V4[..35]), None);
+    }
+
+    #[test]
+    fn test_parse_v4_invalid() {
+        // Echo reply
+        let mut buf = UNREACH_V4.to_vec();
+        buf[0] = 0;
+        assert_eq!(IcmpError::parse_v4(&buf), None);
+        // Quoted UDP packet
+        let mut buf = UNREACH_V4.to_vec();
+        buf[17] = 17;
+        assert_eq!(IcmpError::parse_v4(&buf), None);
+    }
+
+    #[test]
+    fn test_parse_v6() {
+        let err = IcmpError::parse_v6(PROHIBITED_V6).unwrap();
+        assert_eq!(err.get_request_type(), 128);
+        assert_eq!(err.get_signature(), None);
+        assert_eq!(
+            err.get_key(),
+            SessionKey::new("2001:db8::1".parse().unwrap(), 3, 4)
+        );
+        assert_eq!(err.get_status(), RECORD_PROHIBITED);
+        assert_eq!(
+            err.get_reason(),
+            "communication administratively prohibited"
+        );
+    }
+
+    #[test]
+    fn test_reason() {
+        let mut buf = UNREACH_V4.to_vec();
+        for (t, code, reason) in [
+            (ICMPV4_UNREACHABLE, 4, "fragmentation needed"),
+            (ICMPV4_UNREACHABLE, 99, "destination unreachable"),
+            (ICMPV4_TIME_EXCEEDED, 0, "hop limit exceeded in transit"),
+            (ICMPV4_TIME_EXCEEDED, 1, "fragment reassembly time exceeded"),
+        ] {
+            buf[0] = t;
+            buf[1] = code;
+            assert_eq!(IcmpError::parse_v4(&buf).unwrap().get_reason(), reason);
+        }
+        let mut buf = PROHIBITED_V6.to_vec();
+        buf[1] = 0;
+        assert_eq!(
+            IcmpError::parse_v6(&buf).unwrap().get_reason(),
+            "no route to destination"
+        );
+    }
+
+    #[test]
+    fn test_status() {
+        let mut buf = UNREACH_V4.to_vec();
+        for (t, code, status) in [
+            (ICMPV4_UNREACHABLE, 0, RECORD_UNREACHABLE),
+            (ICMPV4_UNREACHABLE, 4, RECORD_UNREACHABLE),
+            (ICMPV4_UNREACHABLE, 13, RECORD_PROHIBITED),
+            (ICMPV4_TIME_EXCEEDED, 0, RECORD_TIME_EXCEEDED),
+        ] {
+            buf[0] = t;
+            buf[1] = code;
+            assert_eq!(IcmpError::parse_v4(&buf).unwrap().get_status(), status);
+        }
+        let mut buf = PROHIBITED_V6.to_vec();
+        for (t, code, status) in [
+            (ICMPV6_UNREACHABLE, 3, RECORD_UNREACHABLE),
+            (ICMPV6_UNREACHABLE, 6, RECORD_PROHIBITED),
+            (ICMPV6_TIME_EXCEEDED, 0, RECORD_TIME_EXCEEDED),
+        ] {
+            buf[0] = t;
+            buf[1] = code;
+            assert_eq!(IcmpError::parse_v6(&buf).unwrap().get_status(), status);
+        }
+    }
+}
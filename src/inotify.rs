@@ -0,0 +1,91 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: File change notifications
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use byteorder::{ByteOrder, NativeEndian};
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+
+/// inotify_event header: wd(4) mask(4) cookie(4) len(4)
+const EVENT_HEADER_SIZE: usize = 16;
+
+/// Names of the files, affected by the events in the buffer
+pub fn parse_names(buf: &[u8]) -> Vec<&[u8]> {
+    let mut r = Vec::new();
+    let mut rest = buf;
+    while rest.len() >= EVENT_HEADER_SIZE {
+        let len = NativeEndian::read_u32(&rest[12..16]) as usize;
+        if rest.len() < EVENT_HEADER_SIZE + len {
+            break;
+        }
+        // Name is padded with NULs
+        let name = &rest[EVENT_HEADER_SIZE..EVENT_HEADER_SIZE + len];
+        let end = name.iter().position(|&c| c == 0).unwrap_or(len);
+        if end > 0 {
+            r.push(&name[..end]);
+        }
+        rest = &rest[EVENT_HEADER_SIZE + len..];
+    }
+    r
+}
+
+/// Open non-blocking inotify descriptor, watching for the files
+/// written or moved into the directory (Linux)
+#[cfg(target_os = "linux")]
+pub fn watch_dir(dir: &Path) -> Result<RawFd> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|_| Error::from(ErrorKind::InvalidInput))?;
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    let wd = unsafe {
+        libc::inotify_add_watch(fd, path.as_ptr(), libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO)
+    };
+    if wd < 0 {
+        let err = Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Err(err);
+    }
+    Ok(fd)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch_dir(_dir: &Path) -> Result<RawFd> {
+    Err(ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_event(buf: &mut Vec<u8>, name: &str, len: usize) {
+        let mut header = [0u8; EVENT_HEADER_SIZE];
+        NativeEndian::write_u32(&mut header[12..16], len as u32);
+        buf.extend_from_slice(&header);
+        let mut padded = name.as_bytes().to_vec();
+        padded.resize(len, 0);
+        buf.extend_from_slice(&padded);
+    }
+
+    #[test]
+    fn test_parse_names() {
+        let mut buf = Vec::new();
+        add_event(&mut buf, "ping.toml", 16);
+        // Event of the directory itself
+        add_event(&mut buf, "", 0);
+        add_event(&mut buf, "other.toml", 16);
+        assert_eq!(
+            parse_names(&buf),
+            vec![b"ping.toml".as_slice(), b"other.toml".as_slice()]
+        );
+        // Truncated
+        assert_eq!(parse_names(&buf[..20]), Vec::<&[u8]>::new());
+    }
+}
@@ -0,0 +1,88 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: IP header parsing
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+/// Minimal IPv4 header length, without options
+pub const IPV4_MIN_HEADER_SIZE: usize = 20;
+/// Maximal IPv4 header length, with 40 octets of options
+#[cfg(test)]
+pub const IPV4_MAX_HEADER_SIZE: usize = 60;
+
+/// Get length of the IPv4 header, passed over raw socket,
+/// from the IHL field. Returns None for non-IPv4 or truncated header.
+///
+/// Only the first octet is used, so the parsing is not affected
+/// by the platforms passing `ip_len` and `ip_off` in the host
+/// byte order, or `ip_len` without the header (BSD).
+pub fn ipv4_header_len(buf: &[u8]) -> Option<usize> {
+    let first = *buf.first()?;
+    if first >> 4 != 4 {
+        return None;
+    }
+    let len = ((first & 0x0f) as usize) * 4;
+    if len < IPV4_MIN_HEADER_SIZE || buf.len() < len {
+        return None;
+    }
+    Some(len)
+}
+
+/// Check if the IPv4 header carries options, by the first octet
+pub fn has_ipv4_options(buf: &[u8]) -> bool {
+    matches!(buf.first(), Some(x) if x >> 4 == 4 && x & 0x0f > 5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Echo reply from 127.0.0.1
+    const REPLY: &[u8] = &[
+        0x45, 0x00, 0x00, 0x54, 0x2b, 0x2e, 0x00, 0x00, 0x40, 0x01, 0x51, 0x79, 0x7f, 0x00, 0x00,
+        0x01, 0x7f, 0x00, 0x00, 0x01, 0x00, 0x00, 0x82, 0x9e, 0x00, 0x01, 0x00, 0x01,
+    ];
+    // Same with Record Route option, containing 1 of 9 addresses
+    const REPLY_RR: &[u8] = &[
+        0x4f, 0x00, 0x00, 0x7c, 0x2b, 0x2e, 0x00, 0x00, 0x40, 0x01, 0x51, 0x79, 0x7f, 0x00, 0x00,
+        0x01, 0x7f, 0x00, 0x00, 0x01, 0x07, 0x27, 0x08, 0x7f, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x82, 0x9e, 0x00, 0x01, 0x00, 0x01,
+    ];
+    // BSD: ip_len in host order without header, ip_off in host order
+    const REPLY_BSD: &[u8] = &[
+        0x45, 0x00, 0x40, 0x00, 0x2b, 0x2e, 0x00, 0x40, 0x40, 0x01, 0x51, 0x79, 0x7f, 0x00, 0x00,
+        0x01, 0x7f, 0x00, 0x00, 0x01, 0x00, 0x00, 0x82, 0x9e, 0x00, 0x01, 0x00, 0x01,
+    ];
+
+    #[test]
+    fn test_header_len() {
+        assert_eq!(ipv4_header_len(REPLY), Some(IPV4_MIN_HEADER_SIZE));
+        assert_eq!(ipv4_header_len(REPLY_BSD), Some(IPV4_MIN_HEADER_SIZE));
+        assert_eq!(ipv4_header_len(REPLY_RR), Some(IPV4_MAX_HEADER_SIZE));
+        // ICMP header follows options
+        assert_eq!(REPLY_RR[IPV4_MAX_HEADER_SIZE], 0);
+    }
+
+    #[test]
+    fn test_has_options() {
+        assert!(!has_ipv4_options(REPLY));
+        assert!(!has_ipv4_options(REPLY_BSD));
+        assert!(has_ipv4_options(REPLY_RR));
+        assert!(has_ipv4_options(&REPLY_RR[..1]));
+        assert!(!has_ipv4_options(&[]));
+        assert!(!has_ipv4_options(&[0x6f]));
+    }
+
+    #[test]
+    fn test_header_len_invalid() {
+        assert_eq!(ipv4_header_len(&[]), None);
+        // IPv6
+        assert_eq!(ipv4_header_len(&[0x60, 0, 0, 0]), None);
+        // IHL < 5
+        assert_eq!(ipv4_header_len(&[0x44; 20]), None);
+        // Truncated options
+        assert_eq!(ipv4_header_len(&REPLY_RR[..40]), None);
+    }
+}
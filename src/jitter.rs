@@ -0,0 +1,88 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Probe interval jitter
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+/// Upper bound of the exponential part, in means
+const EXP_CAP: f64 = 10.0;
+
+/// Random deviation of the probe intervals,
+/// preventing the probes of many targets from synchronizing into bursts.
+/// Percentage is the share of the interval to be randomized.
+/// Mean interval is preserved.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Jitter {
+    /// Fixed interval
+    #[default]
+    None,
+    /// Uniformly distributed in `interval * (1 ± pct / 100)`
+    Uniform(f64),
+    /// `interval * (1 - pct / 100)` followed by the exponentially
+    /// distributed delay with the mean of `interval * pct / 100`
+    Exponential(f64),
+}
+
+impl Jitter {
+    /// Get jittered interval for the random sample `u` in `[0, 1)`
+    pub fn apply(&self, interval: u64, u: f64) -> u64 {
+        let interval = interval as f64;
+        let r = match *self {
+            Jitter::None => interval,
+            Jitter::Uniform(pct) => interval * (1.0 + (2.0 * u - 1.0) * pct / 100.0),
+            Jitter::Exponential(pct) => {
+                let mean = interval * pct / 100.0;
+                let exp = (-(1.0 - u).ln()).min(EXP_CAP);
+                interval - mean + exp * mean
+            }
+        };
+        r.max(0.0) as u64
+    }
+
+    /// Get percentage
+    pub fn get_percent(&self) -> f64 {
+        match *self {
+            Jitter::None => 0.0,
+            Jitter::Uniform(pct) | Jitter::Exponential(pct) => pct,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_none() {
+        assert_eq!(Jitter::None.apply(1_000, 0.3), 1_000);
+    }
+
+    #[test]
+    fn test_uniform() {
+        let j = Jitter::Uniform(10.0);
+        assert_eq!(j.apply(1_000, 0.0), 900);
+        assert_eq!(j.apply(1_000, 0.5), 1_000);
+        assert_eq!(j.apply(1_000, 0.75), 1_050);
+    }
+
+    #[test]
+    fn test_exponential() {
+        let j = Jitter::Exponential(20.0);
+        assert_eq!(j.apply(1_000, 0.0), 800);
+        // Median of exponential distribution is mean * ln 2
+        assert_eq!(j.apply(1_000, 0.5), 938);
+        // Capped
+        assert_eq!(j.apply(1_000, 1.0 - 1e-12), 2_800);
+    }
+
+    #[test]
+    fn test_exponential_mean() {
+        let j = Jitter::Exponential(50.0);
+        let n = 100_000;
+        let total: u64 = (0..n)
+            .map(|i| j.apply(1_000_000, (i as f64 + 0.5) / n as f64))
+            .sum();
+        let mean = total / n;
+        assert!((999_000..=1_001_000).contains(&mean), "mean={}", mean);
+    }
+}
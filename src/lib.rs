@@ -4,18 +4,152 @@
 // Copyright (C) 2022, Gufo Labs
 // ---------------------------------------------------------------------
 
-use pyo3::prelude::*;
+// Only the benchmarked core is used without Python bindings
+#![cfg_attr(not(feature = "python"), allow(dead_code, unused_imports))]
+
 pub(crate) mod session;
-pub(crate) use session::Session;
+// Used by benchmarks, not a part of the public API
+#[doc(hidden)]
+pub use session::{Session, SessionKey};
+pub(crate) mod table;
+#[doc(hidden)]
+pub use table::SessionTable;
 pub(crate) mod icmp;
-pub(crate) use icmp::IcmpPacket;
+#[doc(hidden)]
+pub use icmp::IcmpPacket;
+pub(crate) mod icmperror;
+pub(crate) use icmperror::IcmpError;
+pub(crate) mod ip;
+pub(crate) mod template;
+pub(crate) use template::PacketTemplate;
+pub(crate) mod state;
+pub(crate) use state::State;
+pub(crate) mod record;
+pub(crate) use record::Record;
+pub(crate) mod resultlog;
+pub(crate) use resultlog::{ResultLog, Rotation};
+pub(crate) mod jitter;
+pub(crate) use jitter::Jitter;
+pub(crate) mod histogram;
+pub(crate) use histogram::Histogram;
+#[cfg(feature = "python")]
+pub(crate) mod activation;
+pub(crate) mod baseline;
+pub(crate) mod caps;
+// PyO3 0.16 `#[pymethods]` expands into non-local impl blocks
+#[cfg(feature = "python")]
+#[allow(non_local_definitions)]
+pub(crate) mod checker;
+pub(crate) mod config;
+#[cfg(feature = "python")]
+#[allow(non_local_definitions)]
+pub(crate) mod detector;
+pub(crate) mod health;
+pub(crate) mod inotify;
+#[cfg(feature = "python")]
+pub(crate) mod loader;
+pub(crate) mod maintenance;
+#[cfg(feature = "python")]
+#[allow(non_local_definitions)]
+pub(crate) mod monitor;
+pub(crate) mod netlink;
+#[cfg(feature = "python")]
+pub(crate) mod persist;
+pub(crate) mod privilege;
+#[cfg(feature = "python")]
+pub(crate) mod reply;
+#[cfg(feature = "python")]
+pub(crate) mod sandbox;
+#[cfg(target_os = "linux")]
+pub(crate) mod seccomp;
+#[cfg(feature = "python")]
+#[allow(non_local_definitions)]
 pub(crate) mod socket;
+#[cfg(feature = "python")]
+pub(crate) mod sockopt;
+pub(crate) mod stats;
+#[cfg(feature = "python")]
+#[allow(non_local_definitions)]
+pub(crate) mod suppressor;
+pub(crate) mod systemd;
+pub(crate) mod timer;
+pub(crate) use timer::Timer;
+#[cfg(feature = "python")]
+#[allow(non_local_definitions)]
+pub(crate) mod watcher;
+
+#[cfg(feature = "python")]
+pub(crate) use checker::HealthChecker;
+#[cfg(feature = "python")]
+pub(crate) use detector::AnomalyDetector;
+#[cfg(feature = "python")]
+pub(crate) use monitor::LinkMonitor;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+pub(crate) use reply::Reply;
+#[cfg(feature = "python")]
 pub(crate) use socket::SocketWrapper;
+#[cfg(feature = "python")]
+pub(crate) use suppressor::Suppressor;
+#[cfg(feature = "python")]
+pub(crate) use watcher::FileWatcher;
 
 /// Module index
+#[cfg(feature = "python")]
 #[pymodule]
 #[pyo3(name = "_fast")]
 fn gufo_ping(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<SocketWrapper>()?;
+    m.add_class::<Reply>()?;
+    m.add_class::<LinkMonitor>()?;
+    m.add_class::<HealthChecker>()?;
+    m.add_class::<Suppressor>()?;
+    m.add_class::<AnomalyDetector>()?;
+    m.add_class::<FileWatcher>()?;
+    m.add_function(wrap_pyfunction!(persist::encode_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(persist::decode_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(loader::parse_config, m)?)?;
+    m.add_function(wrap_pyfunction!(activation::listen_fds, m)?)?;
+    m.add_function(wrap_pyfunction!(activation::sd_notify, m)?)?;
+    m.add_function(wrap_pyfunction!(activation::get_watchdog_usec, m)?)?;
+    m.add_function(wrap_pyfunction!(sandbox::drop_privileges, m)?)?;
+    m.add_function(wrap_pyfunction!(sandbox::enable_seccomp, m)?)?;
+    m.add_function(wrap_pyfunction!(sandbox::restrict_sockets, m)?)?;
+    m.add("REQUEST_ID_CALLER", socket::REQUEST_ID_CALLER)?;
+    m.add("REQUEST_ID_RANDOM", socket::REQUEST_ID_RANDOM)?;
+    m.add("REQUEST_ID_SIGNATURE", socket::REQUEST_ID_SIGNATURE)?;
+    m.add("OVERLOAD_REJECT", socket::OVERLOAD_REJECT)?;
+    m.add("OVERLOAD_EVICT", socket::OVERLOAD_EVICT)?;
+    m.add("ECN_NOT_ECT", socket::ECN_NOT_ECT)?;
+    m.add("ECN_ECT1", socket::ECN_ECT1)?;
+    m.add("ECN_ECT0", socket::ECN_ECT0)?;
+    m.add("ECN_CE", socket::ECN_CE)?;
+    m.add("DEBUG_TX", socket::DEBUG_TX)?;
+    m.add("DEBUG_RX", socket::DEBUG_RX)?;
+    m.add("PADDING_FIXED", socket::PADDING_FIXED)?;
+    m.add("PADDING_RANDOM", socket::PADDING_RANDOM)?;
+    m.add("TS_PLAIN", socket::TS_PLAIN)?;
+    m.add("TS_OBFUSCATED", socket::TS_OBFUSCATED)?;
+    m.add("TS_NONCE", socket::TS_NONCE)?;
+    m.add("MIMIC_NONE", socket::MIMIC_NONE)?;
+    m.add("MIMIC_LINUX", socket::MIMIC_LINUX)?;
+    m.add("MIMIC_WINDOWS", socket::MIMIC_WINDOWS)?;
+    m.add("RTT_NS", socket::RTT_NS)?;
+    m.add("RTT_US", socket::RTT_US)?;
+    m.add("RTT_MS", socket::RTT_MS)?;
+    m.add("RTT_S", socket::RTT_S)?;
+    m.add("INVALID_RTT_CLAMP", socket::INVALID_RTT_CLAMP)?;
+    m.add("INVALID_RTT_ERROR", socket::INVALID_RTT_ERROR)?;
+    m.add("INVALID_RTT_RAW", socket::INVALID_RTT_RAW)?;
+    m.add("JITTER_NONE", socket::JITTER_NONE)?;
+    m.add("JITTER_UNIFORM", socket::JITTER_UNIFORM)?;
+    m.add("JITTER_EXPONENTIAL", socket::JITTER_EXPONENTIAL)?;
+    m.add("RECORD_REPLY", record::RECORD_REPLY)?;
+    m.add("RECORD_TIMEOUT", record::RECORD_TIMEOUT)?;
+    m.add("RECORD_UNREACHABLE", record::RECORD_UNREACHABLE)?;
+    m.add("RECORD_PROHIBITED", record::RECORD_PROHIBITED)?;
+    m.add("RECORD_TIME_EXCEEDED", record::RECORD_TIME_EXCEEDED)?;
+    m.add("RECORD_INVALID_RTT", record::RECORD_INVALID_RTT)?;
     Ok(())
 }
@@ -0,0 +1,50 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Configuration loader
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::config::{Config, Options, Value};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+
+/// Convert options to dict
+fn to_dict<'a>(py: Python<'a>, options: &Options) -> PyResult<&'a PyDict> {
+    let r = PyDict::new(py);
+    for (key, value) in options.iter() {
+        let value = match value {
+            Value::Str(x) => x.into_py(py),
+            Value::Int(x) => x.into_py(py),
+            Value::Float(x) => x.into_py(py),
+            Value::Bool(x) => x.into_py(py),
+        };
+        r.set_item(key, value)?;
+    }
+    Ok(r)
+}
+
+/// Parse and validate configuration.
+/// Returns dict of:
+/// * `ping` - dict of `Ping` settings.
+/// * `profiles` - dict of profile name -> dict of settings.
+/// * `targets` - list of dicts of targets' settings.
+#[pyfunction]
+pub(crate) fn parse_config(py: Python, text: &str) -> PyResult<PyObject> {
+    let config: Config = text.parse().map_err(PyValueError::new_err)?;
+    let profiles = PyDict::new(py);
+    for (name, options) in config.profiles.iter() {
+        profiles.set_item(name, to_dict(py, options)?)?;
+    }
+    let targets = PyList::empty(py);
+    for options in config.targets.iter() {
+        targets.append(to_dict(py, options)?)?;
+    }
+    let r = PyDict::new(py);
+    r.set_item("ping", to_dict(py, &config.ping)?)?;
+    r.set_item("profiles", profiles)?;
+    r.set_item("targets", targets)?;
+    Ok(r.into())
+}
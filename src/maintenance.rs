@@ -0,0 +1,102 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Maintenance windows
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+/// Maintenance windows of the targets, as `(start, end)`
+/// UNIX timestamps. Results of the target are suppressed
+/// within the window, ended windows are pruned on check.
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    windows: HashMap<String, Vec<(f64, f64)>>,
+}
+
+impl Maintenance {
+    /// Create empty maintenance table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the window to the target
+    pub fn add(&mut self, target: &str, start: f64, end: f64) {
+        self.windows
+            .entry(target.into())
+            .or_default()
+            .push((start, end));
+    }
+
+    /// Remove all windows of the target
+    pub fn clear(&mut self, target: &str) {
+        self.windows.remove(target);
+    }
+
+    /// Get windows of the target
+    pub fn get(&self, target: &str) -> &[(f64, f64)] {
+        self.windows
+            .get(target)
+            .map(|x| x.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Check if the target's results are suppressed at the time `ts`
+    pub fn is_suppressed(&mut self, target: &str, ts: f64) -> bool {
+        let windows = match self.windows.get_mut(target) {
+            Some(x) => x,
+            None => return false,
+        };
+        windows.retain(|&(_, end)| end > ts);
+        if windows.is_empty() {
+            self.windows.remove(target);
+            return false;
+        }
+        windows.iter().any(|&(start, end)| start <= ts && ts < end)
+    }
+
+    /// Number of targets with windows
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// Check if there are no windows
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suppressed() {
+        let mut m = Maintenance::new();
+        m.add("127.0.0.1", 100.0, 200.0);
+        m.add("127.0.0.1", 300.0, 400.0);
+        assert!(!m.is_suppressed("127.0.0.1", 50.0));
+        assert!(m.is_suppressed("127.0.0.1", 100.0));
+        assert!(!m.is_suppressed("127.0.0.1", 200.0));
+        assert!(!m.is_suppressed("127.0.0.2", 150.0));
+        // First window is pruned
+        assert_eq!(m.get("127.0.0.1"), &[(300.0, 400.0)]);
+        assert!(m.is_suppressed("127.0.0.1", 350.0));
+        assert!(!m.is_suppressed("127.0.0.1", 400.0));
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut m = Maintenance::new();
+        m.add("127.0.0.1", 100.0, 200.0);
+        m.add("127.0.0.2", 100.0, 200.0);
+        assert_eq!(m.len(), 2);
+        m.clear("127.0.0.1");
+        assert!(!m.is_suppressed("127.0.0.1", 150.0));
+        assert!(m.is_suppressed("127.0.0.2", 150.0));
+        assert_eq!(m.get("127.0.0.1"), &[]);
+    }
+}
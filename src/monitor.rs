@@ -0,0 +1,79 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: LinkMonitor implementation
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::netlink;
+use pyo3::{exceptions::PyOSError, prelude::*};
+use socket2::Socket;
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+const BUFFER_SIZE: usize = 32768;
+
+/// Netlink link events listener (Linux)
+#[pyclass]
+pub(crate) struct LinkMonitor {
+    io: Option<Socket>,
+    buf: Vec<MaybeUninit<u8>>,
+}
+
+#[pymethods]
+impl LinkMonitor {
+    /// Subscribe to link events
+    #[new]
+    fn new() -> PyResult<Self> {
+        let io = netlink::open_link_events().map_err(|e| PyOSError::new_err(e.to_string()))?;
+        Ok(LinkMonitor {
+            io: Some(io),
+            buf: vec![MaybeUninit::uninit(); BUFFER_SIZE],
+        })
+    }
+
+    /// Get file descriptor, to be polled for events
+    fn get_fd(&self) -> PyResult<RawFd> {
+        Ok(self.get_io()?.as_raw_fd())
+    }
+
+    /// Get current state of all links.
+    /// Returns dict of <ifindex> -> <is up>
+    fn get_links(&self) -> PyResult<HashMap<u32, bool>> {
+        Ok(netlink::get_links()
+            .map_err(|e| PyOSError::new_err(e.to_string()))?
+            .into_iter()
+            .map(|link| (link.ifindex, link.up))
+            .collect())
+    }
+
+    /// Receive all pending link events.
+    /// Returns list of (<ifindex>, <is up>), in order of arrival
+    fn recv(&mut self) -> PyResult<Vec<(u32, bool)>> {
+        let io = self.io.as_ref().ok_or_else(Self::closed)?;
+        let mut r = Vec::new();
+        while let Ok(n) = io.recv(&mut self.buf) {
+            // Buffer is initialized by `recv`
+            let buf = unsafe { &*(&self.buf[..n] as *const [MaybeUninit<u8>] as *const [u8]) };
+            let (links, _) =
+                netlink::parse_links(buf).map_err(|e| PyOSError::new_err(e.to_string()))?;
+            r.extend(links.into_iter().map(|link| (link.ifindex, link.up)));
+        }
+        Ok(r)
+    }
+
+    /// Close netlink socket
+    fn close(&mut self) {
+        self.io = None;
+    }
+}
+
+impl LinkMonitor {
+    fn get_io(&self) -> PyResult<&Socket> {
+        self.io.as_ref().ok_or_else(Self::closed)
+    }
+
+    fn closed() -> PyErr {
+        PyOSError::new_err("I/O operation on closed socket")
+    }
+}
@@ -0,0 +1,421 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Netlink route lookup and link state
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use byteorder::{ByteOrder, NativeEndian};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const NLMSG_HDR_SIZE: usize = 16;
+const RTMSG_SIZE: usize = 12;
+const RTA_HDR_SIZE: usize = 4;
+const IFINFOMSG_SIZE: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 1;
+const NLM_F_DUMP: u16 = 0x300;
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_GETLINK: u16 = 18;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_GETROUTE: u16 = 26;
+const RTM_F_LOOKUP_TABLE: u32 = 0x1000;
+const RTA_DST: u16 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PREFSRC: u16 = 7;
+const RTA_TABLE: u16 = 15;
+const AF_INET: u8 = 2;
+const AF_INET6: u8 = 10;
+const IFF_UP: u32 = 0x1;
+const IFF_LOWER_UP: u32 = 0x10000;
+/// Multicast group of link events
+pub const RTMGRP_LINK: u32 = 1;
+
+/// Route to the destination, as resolved by kernel
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Route {
+    /// Next-hop, None for directly connected destinations
+    pub gateway: Option<IpAddr>,
+    /// Index of the egress interface
+    pub ifindex: Option<u32>,
+    /// Preferred source address
+    pub src: Option<IpAddr>,
+    /// Routing table
+    pub table: Option<u32>,
+}
+
+/// Link state, as reported by kernel
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LinkState {
+    /// Interface index
+    pub ifindex: u32,
+    /// Interface is administratively up and has carrier
+    pub up: bool,
+}
+
+/// Align netlink length
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Build RTM_GETROUTE request for the destination
+pub fn build_request(dst: IpAddr, seq: u32) -> Vec<u8> {
+    let (family, addr): (u8, Vec<u8>) = match dst {
+        IpAddr::V4(addr) => (AF_INET, addr.octets().to_vec()),
+        IpAddr::V6(addr) => (AF_INET6, addr.octets().to_vec()),
+    };
+    let rta_len = RTA_HDR_SIZE + addr.len();
+    let len = NLMSG_HDR_SIZE + RTMSG_SIZE + align(rta_len);
+    let mut buf = vec![0u8; len];
+    // nlmsghdr
+    NativeEndian::write_u32(&mut buf[0..4], len as u32);
+    NativeEndian::write_u16(&mut buf[4..6], RTM_GETROUTE);
+    NativeEndian::write_u16(&mut buf[6..8], NLM_F_REQUEST);
+    NativeEndian::write_u32(&mut buf[8..12], seq);
+    // rtmsg
+    let rtm = &mut buf[NLMSG_HDR_SIZE..];
+    rtm[0] = family;
+    rtm[1] = (addr.len() * 8) as u8; // dst_len
+    NativeEndian::write_u32(&mut rtm[8..12], RTM_F_LOOKUP_TABLE);
+    // RTA_DST
+    let rta = &mut buf[NLMSG_HDR_SIZE + RTMSG_SIZE..];
+    NativeEndian::write_u16(&mut rta[0..2], rta_len as u16);
+    NativeEndian::write_u16(&mut rta[2..4], RTA_DST);
+    rta[RTA_HDR_SIZE..rta_len].copy_from_slice(&addr);
+    buf
+}
+
+/// Build RTM_GETLINK request, dumping all links
+pub fn build_link_dump(seq: u32) -> Vec<u8> {
+    let len = NLMSG_HDR_SIZE + IFINFOMSG_SIZE;
+    let mut buf = vec![0u8; len];
+    NativeEndian::write_u32(&mut buf[0..4], len as u32);
+    NativeEndian::write_u16(&mut buf[4..6], RTM_GETLINK);
+    NativeEndian::write_u16(&mut buf[6..8], NLM_F_REQUEST | NLM_F_DUMP);
+    NativeEndian::write_u32(&mut buf[8..12], seq);
+    buf
+}
+
+/// Parse link messages of the netlink datagram.
+/// Returns link states and the flag of dump completion,
+/// or the error, reported by kernel
+pub fn parse_links(buf: &[u8]) -> Result<(Vec<LinkState>, bool)> {
+    let mut r = Vec::new();
+    let mut msgs = buf;
+    while msgs.len() >= NLMSG_HDR_SIZE {
+        let len = NativeEndian::read_u32(&msgs[0..4]) as usize;
+        if len < NLMSG_HDR_SIZE || len > msgs.len() {
+            break;
+        }
+        let msg = &msgs[NLMSG_HDR_SIZE..len];
+        match NativeEndian::read_u16(&msgs[4..6]) {
+            NLMSG_DONE => return Ok((r, true)),
+            NLMSG_ERROR if msg.len() >= 4 => {
+                let errno = -NativeEndian::read_i32(&msg[0..4]);
+                // Zero errno is acknowledgement
+                if errno != 0 {
+                    return Err(Error::from_raw_os_error(errno));
+                }
+                return Ok((r, true));
+            }
+            NLMSG_ERROR => return Err(Error::new(ErrorKind::InvalidData, "invalid netlink reply")),
+            kind @ (RTM_NEWLINK | RTM_DELLINK) if msg.len() >= IFINFOMSG_SIZE => {
+                let flags = NativeEndian::read_u32(&msg[8..12]);
+                r.push(LinkState {
+                    ifindex: NativeEndian::read_i32(&msg[4..8]) as u32,
+                    up: kind == RTM_NEWLINK && flags & IFF_UP != 0 && flags & IFF_LOWER_UP != 0,
+                });
+            }
+            _ => {}
+        }
+        msgs = &msgs[align(len).min(msgs.len())..];
+    }
+    Ok((r, false))
+}
+
+/// Parse address attribute
+fn parse_addr(family: u8, data: &[u8]) -> Option<IpAddr> {
+    match (family, data.len()) {
+        (AF_INET, 4) => Some(IpAddr::V4(Ipv4Addr::new(
+            data[0], data[1], data[2], data[3],
+        ))),
+        (AF_INET6, 16) => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(data);
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+/// Parse kernel's reply to RTM_GETROUTE
+pub fn parse_reply(buf: &[u8]) -> Result<Route> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid netlink reply");
+    if buf.len() < NLMSG_HDR_SIZE {
+        return Err(invalid());
+    }
+    let len = (NativeEndian::read_u32(&buf[0..4]) as usize).min(buf.len());
+    match NativeEndian::read_u16(&buf[4..6]) {
+        NLMSG_ERROR if len >= NLMSG_HDR_SIZE + 4 => {
+            let errno = -NativeEndian::read_i32(&buf[NLMSG_HDR_SIZE..NLMSG_HDR_SIZE + 4]);
+            return Err(Error::from_raw_os_error(errno));
+        }
+        RTM_NEWROUTE if len >= NLMSG_HDR_SIZE + RTMSG_SIZE => {}
+        _ => return Err(invalid()),
+    }
+    let family = buf[NLMSG_HDR_SIZE];
+    let mut route = Route::default();
+    let mut attrs = &buf[NLMSG_HDR_SIZE + RTMSG_SIZE..len];
+    while attrs.len() >= RTA_HDR_SIZE {
+        let rta_len = NativeEndian::read_u16(&attrs[0..2]) as usize;
+        if rta_len < RTA_HDR_SIZE || rta_len > attrs.len() {
+            return Err(invalid());
+        }
+        let data = &attrs[RTA_HDR_SIZE..rta_len];
+        match NativeEndian::read_u16(&attrs[2..4]) {
+            RTA_GATEWAY => route.gateway = parse_addr(family, data),
+            RTA_PREFSRC => route.src = parse_addr(family, data),
+            RTA_OIF if data.len() == 4 => route.ifindex = Some(NativeEndian::read_u32(data)),
+            RTA_TABLE if data.len() == 4 => route.table = Some(NativeEndian::read_u32(data)),
+            _ => {}
+        }
+        attrs = &attrs[align(rta_len).min(attrs.len())..];
+    }
+    Ok(route)
+}
+
+/// Look up the route to the destination (Linux)
+#[cfg(target_os = "linux")]
+pub fn get_route(dst: IpAddr) -> Result<Route> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::mem::MaybeUninit;
+    use std::time::Duration;
+
+    let io = Socket::new(
+        Domain::from(libc::AF_NETLINK),
+        Type::RAW,
+        Some(Protocol::from(libc::NETLINK_ROUTE)),
+    )?;
+    io.set_read_timeout(Some(Duration::from_secs(1)))?;
+    // Unbound socket talks to kernel
+    io.send(&build_request(dst, 1))?;
+    let mut buf = [MaybeUninit::<u8>::uninit(); 4096];
+    let n = io.recv(&mut buf)?;
+    // Buffer is initialized by `recv`
+    let buf = unsafe { &*(&buf[..n] as *const [MaybeUninit<u8>] as *const [u8]) };
+    parse_reply(buf)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_route(_dst: IpAddr) -> Result<Route> {
+    Err(ErrorKind::Unsupported.into())
+}
+
+/// Open non-blocking netlink socket, subscribed to link events (Linux)
+#[cfg(target_os = "linux")]
+pub fn open_link_events() -> Result<socket2::Socket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::os::unix::io::AsRawFd;
+
+    let io = Socket::new(
+        Domain::from(libc::AF_NETLINK),
+        Type::RAW,
+        Some(Protocol::from(libc::NETLINK_ROUTE)),
+    )?;
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = RTMGRP_LINK;
+    let r = unsafe {
+        libc::bind(
+            io.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if r < 0 {
+        return Err(Error::last_os_error());
+    }
+    io.set_nonblocking(true)?;
+    Ok(io)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_link_events() -> Result<socket2::Socket> {
+    Err(ErrorKind::Unsupported.into())
+}
+
+/// Get state of all links (Linux)
+#[cfg(target_os = "linux")]
+pub fn get_links() -> Result<Vec<LinkState>> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::mem::MaybeUninit;
+    use std::time::Duration;
+
+    let io = Socket::new(
+        Domain::from(libc::AF_NETLINK),
+        Type::RAW,
+        Some(Protocol::from(libc::NETLINK_ROUTE)),
+    )?;
+    io.set_read_timeout(Some(Duration::from_secs(1)))?;
+    io.send(&build_link_dump(1))?;
+    let mut r = Vec::new();
+    let mut buf = [MaybeUninit::<u8>::uninit(); 32768];
+    loop {
+        let n = io.recv(&mut buf)?;
+        // Buffer is initialized by `recv`
+        let buf = unsafe { &*(&buf[..n] as *const [MaybeUninit<u8>] as *const [u8]) };
+        let (links, done) = parse_links(buf)?;
+        r.extend(links);
+        if done || n == 0 {
+            return Ok(r);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_links() -> Result<Vec<LinkState>> {
+    Err(ErrorKind::Unsupported.into())
+}
+
+/// Get name of the interface by index
+pub fn get_ifname(ifindex: u32) -> Option<String> {
+    let mut buf = [0 as libc::c_char; libc::IF_NAMESIZE];
+    let r = unsafe { libc::if_indextoname(ifindex, buf.as_mut_ptr()) };
+    if r.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_request() {
+        let buf = build_request(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 7);
+        assert_eq!(buf.len(), 36);
+        assert_eq!(NativeEndian::read_u32(&buf[0..4]), 36);
+        assert_eq!(NativeEndian::read_u16(&buf[4..6]), RTM_GETROUTE);
+        assert_eq!(NativeEndian::read_u32(&buf[8..12]), 7);
+        assert_eq!(buf[16], AF_INET);
+        assert_eq!(buf[17], 32);
+        assert_eq!(NativeEndian::read_u16(&buf[28..30]), 8);
+        assert_eq!(NativeEndian::read_u16(&buf[30..32]), RTA_DST);
+        assert_eq!(&buf[32..36], &[192, 0, 2, 1]);
+        let buf = build_request("2001:db8::1".parse().unwrap(), 1);
+        assert_eq!(buf.len(), 48);
+        assert_eq!(buf[17], 128);
+    }
+
+    fn add_attr(buf: &mut Vec<u8>, kind: u16, data: &[u8]) {
+        let mut hdr = [0u8; 4];
+        NativeEndian::write_u16(&mut hdr[0..2], (RTA_HDR_SIZE + data.len()) as u16);
+        NativeEndian::write_u16(&mut hdr[2..4], kind);
+        buf.extend_from_slice(&hdr);
+        buf.extend_from_slice(data);
+        buf.resize(align(buf.len()), 0);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let mut buf = vec![0u8; NLMSG_HDR_SIZE + RTMSG_SIZE];
+        NativeEndian::write_u16(&mut buf[4..6], RTM_NEWROUTE);
+        buf[NLMSG_HDR_SIZE] = AF_INET;
+        let mut u32_buf = [0u8; 4];
+        NativeEndian::write_u32(&mut u32_buf, 254);
+        add_attr(&mut buf, RTA_TABLE, &u32_buf);
+        add_attr(&mut buf, RTA_DST, &[192, 0, 2, 1]);
+        NativeEndian::write_u32(&mut u32_buf, 2);
+        add_attr(&mut buf, RTA_OIF, &u32_buf);
+        add_attr(&mut buf, RTA_PREFSRC, &[10, 0, 0, 2]);
+        add_attr(&mut buf, RTA_GATEWAY, &[10, 0, 0, 1]);
+        let len = buf.len() as u32;
+        NativeEndian::write_u32(&mut buf[0..4], len);
+        assert_eq!(
+            parse_reply(&buf).unwrap(),
+            Route {
+                gateway: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))),
+                ifindex: Some(2),
+                src: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))),
+                table: Some(254),
+            }
+        );
+    }
+
+    fn add_link(buf: &mut Vec<u8>, kind: u16, ifindex: i32, flags: u32) {
+        let mut msg = [0u8; NLMSG_HDR_SIZE + IFINFOMSG_SIZE];
+        NativeEndian::write_u32(&mut msg[0..4], (NLMSG_HDR_SIZE + IFINFOMSG_SIZE) as u32);
+        NativeEndian::write_u16(&mut msg[4..6], kind);
+        NativeEndian::write_i32(&mut msg[20..24], ifindex);
+        NativeEndian::write_u32(&mut msg[24..28], flags);
+        buf.extend_from_slice(&msg);
+    }
+
+    #[test]
+    fn test_parse_links() {
+        let mut buf = Vec::new();
+        add_link(&mut buf, RTM_NEWLINK, 1, IFF_UP | IFF_LOWER_UP);
+        // No carrier
+        add_link(&mut buf, RTM_NEWLINK, 2, IFF_UP);
+        add_link(&mut buf, RTM_DELLINK, 3, IFF_UP | IFF_LOWER_UP);
+        assert_eq!(
+            parse_links(&buf).unwrap(),
+            (
+                vec![
+                    LinkState {
+                        ifindex: 1,
+                        up: true
+                    },
+                    LinkState {
+                        ifindex: 2,
+                        up: false
+                    },
+                    LinkState {
+                        ifindex: 3,
+                        up: false
+                    },
+                ],
+                false
+            )
+        );
+        let mut done = [0u8; NLMSG_HDR_SIZE];
+        NativeEndian::write_u32(&mut done[0..4], NLMSG_HDR_SIZE as u32);
+        NativeEndian::write_u16(&mut done[4..6], NLMSG_DONE);
+        buf.extend_from_slice(&done);
+        assert!(parse_links(&buf).unwrap().1);
+    }
+
+    #[test]
+    fn test_parse_links_error() {
+        let mut buf = Vec::new();
+        add_link(&mut buf, RTM_NEWLINK, 1, IFF_UP | IFF_LOWER_UP);
+        let mut err = [0u8; NLMSG_HDR_SIZE + 4];
+        NativeEndian::write_u32(&mut err[0..4], (NLMSG_HDR_SIZE + 4) as u32);
+        NativeEndian::write_u16(&mut err[4..6], NLMSG_ERROR);
+        NativeEndian::write_i32(&mut err[16..20], -libc::EPERM);
+        buf.extend_from_slice(&err);
+        let err = parse_links(&buf).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+        // Truncated error
+        let mut buf = vec![0u8; NLMSG_HDR_SIZE];
+        NativeEndian::write_u32(&mut buf[0..4], NLMSG_HDR_SIZE as u32);
+        NativeEndian::write_u16(&mut buf[4..6], NLMSG_ERROR);
+        let err = parse_links(&buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let mut buf = vec![0u8; NLMSG_HDR_SIZE + 4];
+        NativeEndian::write_u32(&mut buf[0..4], (NLMSG_HDR_SIZE + 4) as u32);
+        NativeEndian::write_u16(&mut buf[4..6], NLMSG_ERROR);
+        NativeEndian::write_i32(&mut buf[16..20], -libc::ENETUNREACH);
+        let err = parse_reply(&buf).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENETUNREACH));
+    }
+}
@@ -0,0 +1,103 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Statistics persistence
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::stats::{Stats, StatsState, Tag};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBool, PyBytes, PyLong, PyString},
+};
+use std::convert::TryFrom;
+
+/// PingStats fields:
+/// (sent, received, rtt_min, rtt_max, rtt_sum,
+///  bursts, bursts_all_lost, bursts_partial, bursts_reordered)
+type StatsTuple = (u64, u64, Option<f64>, Option<f64>, f64, u64, u64, u64, u64);
+
+/// Convert Python tag
+fn to_tag(tag: &PyAny) -> PyResult<Tag> {
+    if tag.is_none() {
+        Ok(Tag::None)
+    } else if tag.is_instance_of::<PyString>()? {
+        Ok(Tag::Str(tag.extract()?))
+    } else if tag.is_instance_of::<PyLong>()? && !tag.is_instance_of::<PyBool>()? {
+        Ok(Tag::Int(tag.extract()?))
+    } else {
+        Err(PyValueError::new_err(
+            "tag must be either str, int, or None",
+        ))
+    }
+}
+
+/// Serialize tags' statistics and optional baselines,
+/// exported by `AnomalyDetector.export_state`
+#[pyfunction]
+pub(crate) fn encode_stats(
+    py: Python,
+    stats: Vec<(&PyAny, StatsTuple)>,
+    baselines: Option<&[u8]>,
+) -> PyResult<PyObject> {
+    let state = StatsState {
+        stats: stats
+            .into_iter()
+            .map(|(tag, x)| {
+                Ok((
+                    to_tag(tag)?,
+                    Stats {
+                        sent: x.0,
+                        received: x.1,
+                        rtt_min: x.2,
+                        rtt_max: x.3,
+                        rtt_sum: x.4,
+                        bursts: x.5,
+                        bursts_all_lost: x.6,
+                        bursts_partial: x.7,
+                        bursts_reordered: x.8,
+                    },
+                ))
+            })
+            .collect::<PyResult<_>>()?,
+        baselines: baselines.map(|x| x.to_vec()),
+    };
+    Ok(PyBytes::new(py, &state.encode()).into())
+}
+
+/// Restore tags' statistics and baselines, serialized by `encode_stats`
+#[pyfunction]
+#[allow(clippy::type_complexity)]
+pub(crate) fn decode_stats(
+    py: Python,
+    state: &[u8],
+) -> PyResult<(Vec<(PyObject, StatsTuple)>, Option<PyObject>)> {
+    let state = StatsState::try_from(state).map_err(PyValueError::new_err)?;
+    let stats = state
+        .stats
+        .into_iter()
+        .map(|(tag, x)| {
+            let tag = match tag {
+                Tag::None => py.None(),
+                Tag::Str(x) => x.into_py(py),
+                Tag::Int(x) => x.into_py(py),
+            };
+            (
+                tag,
+                (
+                    x.sent,
+                    x.received,
+                    x.rtt_min,
+                    x.rtt_max,
+                    x.rtt_sum,
+                    x.bursts,
+                    x.bursts_all_lost,
+                    x.bursts_partial,
+                    x.bursts_reordered,
+                ),
+            )
+        })
+        .collect();
+    let baselines = state.baselines.map(|x| PyBytes::new(py, &x).into());
+    Ok((stats, baselines))
+}
@@ -0,0 +1,91 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Privilege drop
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+#[cfg(target_os = "linux")]
+use super::caps::{CapData, CapHeader, CAPABILITY_VERSION_3};
+use std::io::{Error, Result};
+
+/// Check the libc call result
+fn check(r: libc::c_int) -> Result<()> {
+    if r < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Switch to the group, dropping the supplementary ones
+pub fn set_gid(gid: u32) -> Result<()> {
+    check(unsafe { libc::setgroups(0, std::ptr::null()) })?;
+    #[cfg(not(any(target_os = "illumos", target_os = "solaris")))]
+    check(unsafe { libc::setresgid(gid, gid, gid) })?;
+    // Sets real, effective, and saved ids, when privileged
+    #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+    check(unsafe { libc::setgid(gid) })?;
+    Ok(())
+}
+
+/// Switch real, effective, and saved user ids. Switching from root
+/// clears the capabilities, unless `PR_SET_KEEPCAPS` is set.
+pub fn set_uid(uid: u32) -> Result<()> {
+    #[cfg(not(any(target_os = "illumos", target_os = "solaris")))]
+    check(unsafe { libc::setresuid(uid, uid, uid) })?;
+    #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+    check(unsafe { libc::setuid(uid) })?;
+    // Regaining the privileges must fail
+    if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        return Err(Error::from_raw_os_error(libc::EPERM));
+    }
+    Ok(())
+}
+
+/// Clear effective, permitted, and inheritable capabilities
+/// of the current thread, and forbid gaining the new privileges
+/// on `execve` (Linux)
+#[cfg(target_os = "linux")]
+pub fn clear_capabilities() -> Result<()> {
+    let mut header = CapHeader {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let data = [CapData::default(); 2];
+    check(unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } as libc::c_int)?;
+    check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn clear_capabilities() -> Result<()> {
+    Ok(())
+}
+
+/// Get number of threads of the process (Linux)
+#[cfg(target_os = "linux")]
+pub fn get_thread_count() -> Result<usize> {
+    Ok(std::fs::read_dir("/proc/self/task")?.count())
+}
+
+/// Drop privileges of the process: switch to the group and user,
+/// when set, and clear capabilities. Already opened sockets
+/// remain usable.
+///
+/// Capabilities and `PR_SET_NO_NEW_PRIVS` are per-thread on Linux,
+/// so the other threads would keep them. The drop is refused
+/// when the process has more than one thread.
+pub fn drop_privileges(uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    if get_thread_count()? > 1 {
+        return Err(Error::other(
+            "cannot drop privileges of multi-threaded process",
+        ));
+    }
+    if let Some(gid) = gid {
+        set_gid(gid)?;
+    }
+    if let Some(uid) = uid {
+        set_uid(uid)?;
+    }
+    clear_capabilities()
+}
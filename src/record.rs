@@ -0,0 +1,165 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Result record serialization
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::SessionKey;
+use byteorder::{BigEndian, ByteOrder};
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const VERSION: u8 = 1;
+// Record size, excluding address and length prefix
+const FIXED_SIZE: usize = 1 + 1 + 1 + 2 + 2 + 8 + 8;
+
+/// Reply is received
+pub const RECORD_REPLY: u8 = 0;
+/// Request is timed out
+pub const RECORD_TIMEOUT: u8 = 1;
+/// Destination is reported unreachable by ICMP error
+pub const RECORD_UNREACHABLE: u8 = 2;
+/// Destination is reported administratively prohibited by ICMP error
+pub const RECORD_PROHIBITED: u8 = 3;
+/// Request is reported to exceed hop limit or reassembly time
+pub const RECORD_TIME_EXCEEDED: u8 = 4;
+/// Reply with zero or negative RTT, see `set_invalid_rtt`
+pub const RECORD_INVALID_RTT: u8 = 5;
+
+/// Measurement result, streamed to the external collector.
+/// ```text
+/// length(2) version(1) status(1) afi(1) addr(4|16)
+/// request_id(2) seq(2) rtt(8) ts(8)
+/// ```
+/// Where:
+/// * `length` - length of the record, excluding the `length` field.
+/// * `status` - `RECORD_REPLY`, `RECORD_TIMEOUT`, or one of
+///   ICMP error statuses: `RECORD_UNREACHABLE`, `RECORD_PROHIBITED`,
+///   `RECORD_TIME_EXCEEDED`, `RECORD_INVALID_RTT`.
+/// * `afi` - 4 or 6, defines the size of `addr`.
+/// * `rtt` - round-trip time in nanoseconds, 0 unless `RECORD_REPLY`.
+/// * `ts` - UNIX time of the result, in nanoseconds.
+///
+/// All integers are big-endian.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Record {
+    pub status: u8,
+    pub key: SessionKey,
+    pub rtt: u64,
+    pub ts: u64,
+}
+
+impl Record {
+    /// Append serialized record to the buffer
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        let addr = self.key.get_addr();
+        let (afi, addr_size) = match addr {
+            IpAddr::V4(_) => (4, 4),
+            IpAddr::V6(_) => (6, 16),
+        };
+        let len = FIXED_SIZE + addr_size;
+        let start = buf.len();
+        buf.resize(start + 2 + len, 0);
+        let out = &mut buf[start..];
+        BigEndian::write_u16(out, len as u16);
+        out[2] = VERSION;
+        out[3] = self.status;
+        out[4] = afi;
+        match addr {
+            IpAddr::V4(addr) => out[5..9].copy_from_slice(&addr.octets()),
+            IpAddr::V6(addr) => out[5..21].copy_from_slice(&addr.octets()),
+        }
+        let out = &mut out[5 + addr_size..];
+        BigEndian::write_u16(out, self.key.get_request_id());
+        BigEndian::write_u16(&mut out[2..], self.key.get_seq());
+        BigEndian::write_u64(&mut out[4..], self.rtt);
+        BigEndian::write_u64(&mut out[12..], self.ts);
+    }
+}
+
+// Parse single record, including length prefix
+impl TryFrom<&[u8]> for Record {
+    type Error = &'static str;
+
+    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+        if buf.len() < 2 + FIXED_SIZE {
+            return Err("too short");
+        }
+        let len = BigEndian::read_u16(buf) as usize;
+        if buf.len() < 2 + len {
+            return Err("too short");
+        }
+        if buf[2] != VERSION {
+            return Err("unsupported version");
+        }
+        let (addr, addr_size) = match buf[4] {
+            4 if len == FIXED_SIZE + 4 => {
+                let mut octets = [0u8; 4];
+                octets.copy_from_slice(&buf[5..9]);
+                (IpAddr::V4(Ipv4Addr::from(octets)), 4)
+            }
+            6 if len == FIXED_SIZE + 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&buf[5..21]);
+                (IpAddr::V6(Ipv6Addr::from(octets)), 16)
+            }
+            _ => return Err("invalid address"),
+        };
+        let rest = &buf[5 + addr_size..];
+        Ok(Self {
+            status: buf[3],
+            key: SessionKey::new(
+                addr,
+                BigEndian::read_u16(rest),
+                BigEndian::read_u16(&rest[2..]),
+            ),
+            rtt: BigEndian::read_u64(&rest[4..]),
+            ts: BigEndian::read_u64(&rest[12..]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_record(addr: &str) -> Record {
+        Record {
+            status: RECORD_REPLY,
+            key: SessionKey::new(addr.parse().unwrap(), 1, 2),
+            rtt: 1_000_000,
+            ts: 1_600_000_000_000_000_000,
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for addr in ["127.0.0.1", "::1"] {
+            let record = get_record(addr);
+            let mut buf = Vec::new();
+            record.encode_into(&mut buf);
+            assert_eq!(Record::try_from(buf.as_slice()).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut buf = Vec::new();
+        get_record("127.0.0.1").encode_into(&mut buf);
+        get_record("::1").encode_into(&mut buf);
+        let len = BigEndian::read_u16(&buf) as usize;
+        assert_eq!(len, FIXED_SIZE + 4);
+        assert_eq!(buf.len(), 2 + len + 2 + FIXED_SIZE + 16);
+        assert_eq!(
+            Record::try_from(&buf[2 + len..]).unwrap(),
+            get_record("::1")
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut buf = Vec::new();
+        get_record("::1").encode_into(&mut buf);
+        assert_eq!(Record::try_from(&buf[..buf.len() - 1]), Err("too short"));
+    }
+}
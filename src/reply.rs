@@ -0,0 +1,95 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Reply implementation
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use pyo3::prelude::*;
+
+/// Received ICMP echo reply
+#[pyclass]
+#[derive(Clone, Debug)]
+pub(crate) struct Reply {
+    /// Session id, <address>-<request id>-<seq>
+    #[pyo3(get)]
+    pub(crate) sid: String,
+    /// Round-trip time, in nanoseconds.
+    /// 0, unless `status` is `RECORD_REPLY`.
+    /// Zero or negative in `INVALID_RTT_RAW` mode.
+    #[pyo3(get)]
+    pub(crate) rtt: i64,
+    /// `RECORD_REPLY` for echo reply, or status of ICMP error:
+    /// `RECORD_UNREACHABLE`, `RECORD_PROHIBITED`, `RECORD_TIME_EXCEEDED`.
+    /// See `set_recv_errors`. `RECORD_INVALID_RTT` for the reply
+    /// with zero or negative RTT, see `set_invalid_rtt`.
+    #[pyo3(get)]
+    pub(crate) status: u8,
+    /// DSCP/ECN field of the reply.
+    /// None, unless `recv_tos` is set.
+    #[pyo3(get)]
+    pub(crate) tos: Option<u8>,
+    /// Local address, the reply is received on.
+    /// Same as the request's source address.
+    /// None, unless `recv_pktinfo` is set.
+    #[pyo3(get)]
+    pub(crate) local_addr: Option<String>,
+    /// Index of interface, the reply is received on.
+    /// None, unless `recv_pktinfo` is set.
+    #[pyo3(get)]
+    pub(crate) ifindex: Option<u32>,
+    /// Size of the reply, including IPv4 header.
+    /// Same as `size` of the request, unless altered on path.
+    #[pyo3(get)]
+    pub(crate) size: usize,
+    /// Size of the reply differs from the size of the request.
+    /// None, unless `strict_size` is set.
+    #[pyo3(get)]
+    pub(crate) size_mismatch: Option<bool>,
+    /// Type of ICMP error, None for echo reply
+    #[pyo3(get)]
+    pub(crate) icmp_type: Option<u8>,
+    /// Code of ICMP error, None for echo reply
+    #[pyo3(get)]
+    pub(crate) icmp_code: Option<u8>,
+    /// Human-readable reason of ICMP error
+    pub(crate) reason: Option<&'static str>,
+    /// Reply is larger than the receive buffer and is truncated.
+    /// `size` is the original size of the reply.
+    #[pyo3(get)]
+    pub(crate) truncated: bool,
+    /// Reply to the expired session, received within grace period.
+    /// See `set_late_grace`.
+    #[pyo3(get)]
+    pub(crate) late: bool,
+    /// Time of reading the reply, CLOCK_MONOTONIC.
+    /// 0, unless latency budget is accounted
+    pub(crate) rx_ts: u64,
+}
+
+#[pymethods]
+impl Reply {
+    /// DSCP of the reply
+    #[getter]
+    fn dscp(&self) -> Option<u8> {
+        self.tos.map(|tos| tos >> 2)
+    }
+
+    /// ECN codepoint of the reply
+    #[getter]
+    fn ecn(&self) -> Option<u8> {
+        self.tos.map(|tos| tos & 0x3)
+    }
+
+    /// Human-readable reason of ICMP error, None for echo reply
+    #[getter]
+    fn reason(&self) -> Option<&str> {
+        self.reason
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<Reply sid={} rtt={} status={} tos={:?}>",
+            self.sid, self.rtt, self.status, self.tos
+        )
+    }
+}
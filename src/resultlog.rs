@@ -0,0 +1,263 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Persistent result log
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::Record;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Result, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Maximal size of unwritten records
+const MAX_BUFFER: usize = 1 << 20;
+
+/// Rotation settings of the result log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// Rotate before the log exceeds `max_size` bytes
+    pub max_size: Option<u64>,
+    /// Rotate the log, opened more than `max_age` nanoseconds ago
+    pub max_age: Option<u64>,
+    /// Number of rotated logs to keep, as `<path>.1` .. `<path>.<keep>`,
+    /// the most recent first. Rotated log is removed when 0.
+    pub keep: usize,
+}
+
+/// Append-only log of the result records, in the same format
+/// as streamed by `set_result_fd`. Records are buffered
+/// and appended by `flush`, so they are kept by OS
+/// when the process crashes. Record is never split
+/// between rotated logs.
+pub struct ResultLog {
+    path: PathBuf,
+    file: File,
+    rotation: Rotation,
+    // Current log size
+    size: u64,
+    // UNIX time of log opening, in nanoseconds
+    since: u64,
+    // Records, awaiting write
+    buf: Vec<u8>,
+}
+
+impl ResultLog {
+    /// Open or create the log at `now` UNIX time, in nanoseconds.
+    /// Existing log is appended.
+    pub fn open<P: Into<PathBuf>>(path: P, rotation: Rotation, now: u64) -> Result<Self> {
+        let path = path.into();
+        let file = Self::open_file(&path)?;
+        let size = file.metadata()?.len();
+        Ok(ResultLog {
+            path,
+            file,
+            rotation,
+            size,
+            since: now,
+            buf: Vec::new(),
+        })
+    }
+
+    fn open_file(path: &PathBuf) -> Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    /// Get path of the rotated log, 1-based
+    pub fn get_rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// Get number of bytes, written to the current log
+    #[cfg(test)]
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Append the record to the buffer.
+    /// Returns false, when the record is dropped due to
+    /// the buffer overflow.
+    pub fn add(&mut self, record: &Record) -> bool {
+        if self.buf.len() >= MAX_BUFFER {
+            return false;
+        }
+        record.encode_into(&mut self.buf);
+        true
+    }
+
+    /// Check if the log must be rotated before writing `len` bytes
+    fn is_due(&self, len: usize, now: u64) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        if let Some(max_size) = self.rotation.max_size {
+            if self.size + len as u64 > max_size {
+                return true;
+            }
+        }
+        matches!(self.rotation.max_age, Some(max_age) if now.saturating_sub(self.since) >= max_age)
+    }
+
+    /// Shift rotated logs and start the new one
+    fn rotate(&mut self, now: u64) -> Result<()> {
+        let keep = self.rotation.keep;
+        if keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..keep).rev() {
+                let from = self.get_rotated(n);
+                if from.exists() {
+                    fs::rename(&from, self.get_rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.get_rotated(1))?;
+        }
+        self.file = Self::open_file(&self.path)?;
+        self.size = 0;
+        self.since = now;
+        Ok(())
+    }
+
+    /// Write buffered records at `now` UNIX time, in nanoseconds,
+    /// rotating the log when necessary. Records are discarded
+    /// on write error.
+    pub fn flush(&mut self, now: u64) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        if self.is_due(self.buf.len(), now) {
+            if let Err(e) = self.rotate(now) {
+                self.buf.clear();
+                return Err(e);
+            }
+        }
+        let r = self.file.write_all(&self.buf);
+        if r.is_ok() {
+            self.size += self.buf.len() as u64;
+        }
+        self.buf.clear();
+        r
+    }
+}
+
+impl Drop for ResultLog {
+    fn drop(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let _ = self.flush(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SessionKey;
+    use std::convert::TryFrom;
+
+    const RECORD_SIZE: u64 = 2 + 23 + 4;
+
+    fn get_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gufo-ping-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn get_record(seq: u16) -> Record {
+        Record {
+            status: 0,
+            key: SessionKey::new("127.0.0.1".parse().unwrap(), 1, seq),
+            rtt: 1_000,
+            ts: 1_600_000_000_000_000_000,
+        }
+    }
+
+    fn write(log: &mut ResultLog, seq: u16, now: u64) {
+        assert!(log.add(&get_record(seq)));
+        log.flush(now).unwrap();
+    }
+
+    fn read_seqs(path: &PathBuf) -> Vec<u16> {
+        let data = fs::read(path).unwrap();
+        let mut r = Vec::new();
+        let mut buf = data.as_slice();
+        while !buf.is_empty() {
+            r.push(Record::try_from(buf).unwrap().key.get_seq());
+            buf = &buf[RECORD_SIZE as usize..];
+        }
+        r
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = get_dir("append");
+        let path = dir.join("results.log");
+        let mut log = ResultLog::open(&path, Rotation::default(), 0).unwrap();
+        write(&mut log, 1, 0);
+        write(&mut log, 2, u64::MAX);
+        drop(log);
+        // Reopened log is appended
+        let mut log = ResultLog::open(&path, Rotation::default(), 0).unwrap();
+        assert_eq!(log.get_size(), 2 * RECORD_SIZE);
+        assert!(log.add(&get_record(3)));
+        drop(log);
+        assert_eq!(read_seqs(&path), vec![1, 2, 3]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_size() {
+        let dir = get_dir("size");
+        let path = dir.join("results.log");
+        let rotation = Rotation {
+            max_size: Some(2 * RECORD_SIZE),
+            max_age: None,
+            keep: 2,
+        };
+        let mut log = ResultLog::open(&path, rotation, 0).unwrap();
+        for seq in 1..=7 {
+            write(&mut log, seq, 0);
+        }
+        assert_eq!(read_seqs(&path), vec![7]);
+        assert_eq!(read_seqs(&log.get_rotated(1)), vec![5, 6]);
+        assert_eq!(read_seqs(&log.get_rotated(2)), vec![3, 4]);
+        assert!(!log.get_rotated(3).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_age() {
+        let dir = get_dir("age");
+        let path = dir.join("results.log");
+        let rotation = Rotation {
+            max_size: None,
+            max_age: Some(100),
+            keep: 0,
+        };
+        let mut log = ResultLog::open(&path, rotation, 1_000).unwrap();
+        write(&mut log, 1, 1_050);
+        write(&mut log, 2, 1_099);
+        write(&mut log, 3, 1_100);
+        // Rotated log is removed
+        assert_eq!(read_seqs(&path), vec![3]);
+        assert!(!log.get_rotated(1).exists());
+        write(&mut log, 4, 1_150);
+        assert_eq!(read_seqs(&path), vec![3, 4]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_overflow() {
+        let dir = get_dir("overflow");
+        let mut log = ResultLog::open(dir.join("results.log"), Rotation::default(), 0).unwrap();
+        let n = (0..).take_while(|_| log.add(&get_record(1))).count();
+        assert_eq!(n as u64, (MAX_BUFFER as u64).div_ceil(RECORD_SIZE));
+        log.flush(0).unwrap();
+        assert!(log.add(&get_record(1)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
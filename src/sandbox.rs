@@ -0,0 +1,45 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Process sandboxing
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::privilege;
+use pyo3::{exceptions::PyOSError, prelude::*};
+
+/// Switch to the user and group, when set, and clear capabilities.
+/// Raises OSError on failure.
+#[pyfunction]
+pub(crate) fn drop_privileges(uid: Option<u32>, gid: Option<u32>) -> PyResult<()> {
+    privilege::drop_privileges(uid, gid).map_err(|e| PyOSError::new_err(e.to_string()))
+}
+
+/// Install seccomp filter for the calling thread, denying
+/// the syscalls beyond the measurement with EPERM, or killing
+/// the process when `kill` is set (Linux).
+#[pyfunction]
+pub(crate) fn enable_seccomp(kill: bool) -> PyResult<()> {
+    #[cfg(target_os = "linux")]
+    let r = super::seccomp::install(kill);
+    #[cfg(not(target_os = "linux"))]
+    let r: std::io::Result<()> = {
+        let _ = kill;
+        Err(std::io::ErrorKind::Unsupported.into())
+    };
+    r.map_err(|e| PyOSError::new_err(e.to_string()))
+}
+
+/// Forbid creating the sockets, other than unix, TCP, UDP,
+/// and routing netlink ones, for the calling thread or for
+/// all threads (Linux)
+#[pyfunction]
+pub(crate) fn restrict_sockets(kill: bool, all_threads: bool) -> PyResult<()> {
+    #[cfg(target_os = "linux")]
+    let r = super::seccomp::restrict_sockets(kill, all_threads);
+    #[cfg(not(target_os = "linux"))]
+    let r: std::io::Result<()> = {
+        let _ = (kill, all_threads);
+        Err(std::io::ErrorKind::Unsupported.into())
+    };
+    r.map_err(|e| PyOSError::new_err(e.to_string()))
+}
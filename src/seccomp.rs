@@ -0,0 +1,351 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: seccomp filter (Linux)
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use std::io::{Error, ErrorKind, Result};
+
+// Classic BPF opcodes: BPF_LD | BPF_W | BPF_ABS,
+// BPF_JMP | BPF_JEQ | BPF_K, BPF_ALU | BPF_AND | BPF_K, BPF_RET | BPF_K
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_ALU_AND_K: u16 = 0x54;
+const BPF_RET_K: u16 = 0x06;
+// struct seccomp_data offsets
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+// Lower halves of the 64-bit arguments
+#[cfg(target_endian = "little")]
+const DATA_ARGS: u32 = 16;
+#[cfg(target_endian = "big")]
+const DATA_ARGS: u32 = 20;
+const SECCOMP_SET_MODE_FILTER: libc::c_long = 1;
+// Socket type without SOCK_NONBLOCK and SOCK_CLOEXEC
+const SOCK_TYPE_MASK: u32 = 0xf;
+
+/// Audit architecture of the target
+#[cfg(target_arch = "x86_64")]
+pub const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+pub const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub const AUDIT_ARCH: u32 = 0;
+
+/// Syscalls, allowed within the sandbox: network I/O over already
+/// opened sockets, event polling, clocks, and the bare minimum
+/// for the Python runtime (memory, locks, signals, and reads and
+/// writes to the opened descriptors). Opening the files, creating
+/// the sockets, and spawning the processes are denied.
+pub const ALLOWED: &[libc::c_long] = &[
+    // Network
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_recvmmsg,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockname,
+    // Polling
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_ctl,
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    // Clocks
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    // Runtime
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_close,
+    libc::SYS_lseek,
+    libc::SYS_fcntl,
+    libc::SYS_newfstatat,
+    libc::SYS_fstat,
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_futex,
+    libc::SYS_sched_yield,
+    libc::SYS_getrandom,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_restart_syscall,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_select,
+];
+
+fn stmt(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jeq(k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: BPF_JMP_JEQ_K,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Build filter, returning `action` for the syscalls, missed
+/// in `allowed`, and killing the process on the foreign architecture
+pub fn build_filter(arch: u32, allowed: &[libc::c_long], action: u32) -> Vec<libc::sock_filter> {
+    let mut r = vec![
+        stmt(BPF_LD_W_ABS, DATA_ARCH),
+        jeq(arch, 1, 0),
+        stmt(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(BPF_LD_W_ABS, DATA_NR),
+    ];
+    // Jump to ALLOW on match, skipping the rest checks and the action
+    let n = allowed.len();
+    assert!(n < 256, "too many syscalls");
+    for (i, nr) in allowed.iter().enumerate() {
+        r.push(jeq(*nr as u32, (n - i) as u8, 0));
+    }
+    r.push(stmt(BPF_RET_K, action));
+    r.push(stmt(BPF_RET_K, libc::SECCOMP_RET_ALLOW));
+    r
+}
+
+/// Build filter, returning `action` for the sockets, other than
+/// `AF_UNIX`, TCP and UDP over `AF_INET` and `AF_INET6`,
+/// and `NETLINK_ROUTE`, as well as for `io_uring_setup`,
+/// which bypasses the filter. Kills the process on the foreign
+/// architecture.
+pub fn build_socket_filter(arch: u32, action: u32) -> Vec<libc::sock_filter> {
+    vec![
+        stmt(BPF_LD_W_ABS, DATA_ARCH),
+        jeq(arch, 1, 0),
+        stmt(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(BPF_LD_W_ABS, DATA_NR),
+        jeq(libc::SYS_io_uring_setup as u32, 12, 0),
+        jeq(libc::SYS_socket as u32, 0, 12),
+        // Domain
+        stmt(BPF_LD_W_ABS, DATA_ARGS),
+        jeq(libc::AF_UNIX as u32, 10, 0),
+        jeq(libc::AF_INET as u32, 4, 0),
+        jeq(libc::AF_INET6 as u32, 3, 0),
+        jeq(libc::AF_NETLINK as u32, 0, 6),
+        // Netlink protocol
+        stmt(BPF_LD_W_ABS, DATA_ARGS + 16),
+        jeq(libc::NETLINK_ROUTE as u32, 5, 4),
+        // Inet type
+        stmt(BPF_LD_W_ABS, DATA_ARGS + 8),
+        stmt(BPF_ALU_AND_K, SOCK_TYPE_MASK),
+        jeq(libc::SOCK_STREAM as u32, 2, 0),
+        jeq(libc::SOCK_DGRAM as u32, 1, 0),
+        stmt(BPF_RET_K, action),
+        stmt(BPF_RET_K, libc::SECCOMP_RET_ALLOW),
+    ]
+}
+
+/// Denying action
+fn get_action(kill: bool) -> u32 {
+    if kill {
+        libc::SECCOMP_RET_KILL_PROCESS
+    } else {
+        libc::SECCOMP_RET_ERRNO | libc::EPERM as u32
+    }
+}
+
+/// Set `PR_SET_NO_NEW_PRIVS` and load the filter for the calling
+/// thread, or for all threads of the process
+fn load(filter: &[libc::sock_filter], all_threads: bool) -> Result<()> {
+    if AUDIT_ARCH == 0 {
+        return Err(Error::from(ErrorKind::Unsupported));
+    }
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut libc::sock_filter,
+    };
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    let flags = if all_threads {
+        libc::SECCOMP_FILTER_FLAG_TSYNC
+    } else {
+        0
+    };
+    let r = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            flags,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    match r {
+        0 => Ok(()),
+        // Thread id, failed to synchronize
+        r if r > 0 => Err(Error::from_raw_os_error(libc::ESRCH)),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Install the filter for the calling thread, denying the syscalls
+/// beyond `ALLOWED` with EPERM, or killing the process when `kill`
+/// is set (x86_64 and aarch64). Sets `PR_SET_NO_NEW_PRIVS`.
+/// Filter cannot be removed.
+pub fn install(kill: bool) -> Result<()> {
+    load(&build_filter(AUDIT_ARCH, ALLOWED, get_action(kill)), false)
+}
+
+/// Forbid creating the sockets, beyond ones used by the crate,
+/// for the calling thread or for all threads of the process.
+/// See `build_socket_filter` for details.
+pub fn restrict_sockets(kill: bool, all_threads: bool) -> Result<()> {
+    load(
+        &build_socket_filter(AUDIT_ARCH, get_action(kill)),
+        all_threads,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run the filter over the syscall
+    fn run_args(filter: &[libc::sock_filter], arch: u32, nr: u32, args: [u64; 6]) -> u32 {
+        // struct seccomp_data: nr(4) arch(4) instruction_pointer(8) args(6 * 8)
+        let mut data = [0u8; 64];
+        data[..4].copy_from_slice(&nr.to_ne_bytes());
+        data[4..8].copy_from_slice(&arch.to_ne_bytes());
+        for (i, arg) in args.iter().enumerate() {
+            data[16 + i * 8..24 + i * 8].copy_from_slice(&arg.to_ne_bytes());
+        }
+        let mut acc = 0;
+        let mut pc = 0;
+        loop {
+            let insn = &filter[pc];
+            pc += 1;
+            match insn.code {
+                BPF_LD_W_ABS => {
+                    let k = insn.k as usize;
+                    acc = u32::from_ne_bytes(data[k..k + 4].try_into().unwrap());
+                }
+                BPF_ALU_AND_K => acc &= insn.k,
+                BPF_JMP_JEQ_K => {
+                    pc += if acc == insn.k { insn.jt } else { insn.jf } as usize;
+                }
+                BPF_RET_K => return insn.k,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Run the filter over (arch, nr)
+    fn run(filter: &[libc::sock_filter], arch: u32, nr: u32) -> u32 {
+        run_args(filter, arch, nr, [0; 6])
+    }
+
+    #[test]
+    fn test_filter() {
+        const DENY: u32 = 0x0005_0001;
+        let filter = build_filter(AUDIT_ARCH, &[1, 2, 3], DENY);
+        assert_eq!(filter.len(), 4 + 3 + 2);
+        for nr in [1, 2, 3] {
+            assert_eq!(run(&filter, AUDIT_ARCH, nr), libc::SECCOMP_RET_ALLOW);
+        }
+        assert_eq!(run(&filter, AUDIT_ARCH, 0), DENY);
+        assert_eq!(run(&filter, AUDIT_ARCH, 4), DENY);
+        assert_eq!(
+            run(&filter, AUDIT_ARCH ^ 1, 1),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+    }
+
+    #[test]
+    fn test_allowed() {
+        let filter = build_filter(AUDIT_ARCH, ALLOWED, 0);
+        assert_eq!(
+            run(&filter, AUDIT_ARCH, libc::SYS_sendto as u32),
+            libc::SECCOMP_RET_ALLOW
+        );
+        assert_eq!(run(&filter, AUDIT_ARCH, libc::SYS_openat as u32), 0);
+        assert_eq!(run(&filter, AUDIT_ARCH, libc::SYS_socket as u32), 0);
+    }
+
+    #[test]
+    fn test_socket_filter() {
+        const DENY: u32 = 0x0005_0001;
+        let filter = build_socket_filter(AUDIT_ARCH, DENY);
+        let socket = |domain: i32, type_: i32, protocol: i32| {
+            run_args(
+                &filter,
+                AUDIT_ARCH,
+                libc::SYS_socket as u32,
+                [domain as u64, type_ as u64, protocol as u64, 0, 0, 0],
+            )
+        };
+        let nonblock = libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+        for (domain, type_, protocol) in [
+            (libc::AF_UNIX, libc::SOCK_DGRAM, 0),
+            (libc::AF_INET, libc::SOCK_STREAM, 0),
+            (libc::AF_INET6, libc::SOCK_DGRAM | nonblock, 0),
+            (libc::AF_NETLINK, libc::SOCK_RAW, libc::NETLINK_ROUTE),
+        ] {
+            assert_eq!(
+                socket(domain, type_, protocol),
+                libc::SECCOMP_RET_ALLOW,
+                "{} {}",
+                domain,
+                type_
+            );
+        }
+        for (domain, type_, protocol) in [
+            (libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP),
+            (
+                libc::AF_INET6,
+                libc::SOCK_RAW | nonblock,
+                libc::IPPROTO_ICMPV6,
+            ),
+            (libc::AF_PACKET, libc::SOCK_RAW, 0),
+            (libc::AF_NETLINK, libc::SOCK_RAW, libc::NETLINK_AUDIT),
+        ] {
+            assert_eq!(
+                socket(domain, type_, protocol),
+                DENY,
+                "{} {}",
+                domain,
+                type_
+            );
+        }
+        assert_eq!(
+            run(&filter, AUDIT_ARCH, libc::SYS_io_uring_setup as u32),
+            DENY
+        );
+        assert_eq!(
+            run(&filter, AUDIT_ARCH, libc::SYS_sendto as u32),
+            libc::SECCOMP_RET_ALLOW
+        );
+        assert_eq!(
+            run(&filter, AUDIT_ARCH ^ 1, libc::SYS_sendto as u32),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+    }
+}
@@ -5,24 +5,91 @@
 // ---------------------------------------------------------------------
 
 use std::cmp::Ordering;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Compact session key.
+/// Printable form (sid) is a string of <addr>-<request id>-<seq>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionKey {
+    addr: IpAddr,
+    request_id: u16,
+    seq: u16,
+}
+
+impl SessionKey {
+    /// Create new key
+    pub fn new(addr: IpAddr, request_id: u16, seq: u16) -> Self {
+        SessionKey {
+            addr,
+            request_id,
+            seq,
+        }
+    }
+
+    /// Get target address
+    pub fn get_addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Get ICMP request id
+    pub fn get_request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Get ICMP sequence number
+    pub fn get_seq(&self) -> u16 {
+        self.seq
+    }
+
+    /// Get printable session id
+    pub fn get_sid(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.addr, self.request_id, self.seq)
+    }
+}
+
+// Parse sid
+impl FromStr for SessionKey {
+    type Err = &'static str;
+
+    fn from_str(sid: &str) -> Result<Self, Self::Err> {
+        let mut parts = sid.rsplitn(3, '-');
+        let seq = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or("invalid seq")?;
+        let request_id = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or("invalid request id")?;
+        let addr = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or("invalid address")?;
+        Ok(SessionKey::new(addr, request_id, seq))
+    }
+}
 
 /// Ping probe state
-/// sid is a string of <addr>-<request id>-<seq>
 /// deeadline - is timeout deadline in nanoseconds
 /// according to Socket::get_ts()
-#[derive(PartialEq, Eq, Clone)]
-pub(crate) struct Session {
-    sid: String,
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Session {
+    key: SessionKey,
     deadline: u64,
 }
 
 impl Session {
     /// Create new session
-    pub fn new(sid: &str, deadline: u64) -> Self {
-        Session {
-            sid: sid.to_string(),
-            deadline,
-        }
+    pub fn new(key: SessionKey, deadline: u64) -> Self {
+        Session { key, deadline }
     }
 
     /// Check if session is expired
@@ -30,19 +97,28 @@ impl Session {
         self.deadline < ts
     }
 
-    /// Get owned instance of sid
+    /// Get deadline
+    pub fn get_deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// Get session key
+    pub fn get_key(&self) -> &SessionKey {
+        &self.key
+    }
+
+    /// Get printable session id
     pub fn get_sid(&self) -> String {
-        self.sid.clone()
+        self.key.get_sid()
     }
 }
 
 impl Ord for Session {
-    /// Sorting for BTreeSet.
-    /// Sorting order - (deadline, sid)
+    /// Sorting order - (deadline, key)
     fn cmp(&self, other: &Self) -> Ordering {
         match self.deadline.cmp(&other.deadline) {
             Ordering::Less => Ordering::Less,
-            Ordering::Equal => self.sid.cmp(&other.sid),
+            Ordering::Equal => self.key.cmp(&other.key),
             Ordering::Greater => Ordering::Greater,
         }
     }
@@ -53,3 +129,23 @@ impl PartialOrd for Session {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sid() {
+        for sid in ["127.0.0.1-1-2", "::1-65535-0"] {
+            let key: SessionKey = sid.parse().unwrap();
+            assert_eq!(key.get_sid(), sid);
+        }
+    }
+
+    #[test]
+    fn test_invalid_sid() {
+        for sid in ["", "127.0.0.1", "127.0.0.1-1", "127.0.0.1-1-x", "x-1-1"] {
+            assert!(sid.parse::<SessionKey>().is_err());
+        }
+    }
+}
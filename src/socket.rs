@@ -4,31 +4,57 @@
 // Copyright (C) 2022, Gufo Labs
 // ---------------------------------------------------------------------
 
-use super::{IcmpPacket, Session};
-use coarsetime::Clock;
+use super::caps;
+use super::icmp::{check_padding, mask_ts, Layout};
+use super::icmperror::{
+    ICMPV4_TIME_EXCEEDED, ICMPV4_UNREACHABLE, ICMPV6_TIME_EXCEEDED, ICMPV6_UNREACHABLE,
+};
+use super::ip;
+use super::netlink;
+use super::record::{RECORD_INVALID_RTT, RECORD_REPLY, RECORD_TIMEOUT};
+use super::sockopt::{self, RecvInfo};
+use super::{
+    Histogram, IcmpError, IcmpPacket, Jitter, PacketTemplate, Record, Reply, ResultLog, Rotation,
+    Session, SessionKey, SessionTable, State, Timer,
+};
+use internet_checksum::Checksum;
 use pyo3::{
-    exceptions::{PyOSError, PyValueError},
+    exceptions::{
+        PyBlockingIOError, PyConnectionError, PyOSError, PyPermissionError, PyTimeoutError,
+        PyValueError,
+    },
     prelude::*,
+    types::{PyBytes, PyDict, PyList},
 };
 use rand::Rng;
-use socket2::{Domain, Protocol, SockAddr, Socket, Type};
-use std::collections::{BTreeSet, HashMap};
+use socket2::{Domain, MaybeUninitSlice, Protocol, SockAddr, Socket, Type};
+use std::collections::{hash_map::Entry, HashMap};
 use std::convert::TryFrom;
 use std::mem::MaybeUninit;
-use std::net::{SocketAddrV4, SocketAddrV6};
-use std::os::unix::io::AsRawFd;
-use std::time::Instant;
+use std::net::{IpAddr, SocketAddrV4, SocketAddrV6};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const MAX_SIZE: usize = 4096;
 const ICMP_SIZE: usize = 8;
+// ICMP header along with signature and timestamp
+const ICMP_HEADER_SIZE: usize = 24;
+// Maximal size of IP and ICMP headers
+const HEADER_SIZE: usize = 20 + ICMP_HEADER_SIZE;
+const MAX_TEMPLATES: usize = 16;
+const MAX_DIAGNOSTIC: usize = 1024;
+// Maximal size of unwritten result records
+const MAX_RESULT_BUFFER: usize = 1 << 20;
+// Packet id of the imported transmit timestamps
+const IMPORTED_TX_ID: u32 = u32::MAX;
 
-enum AFI {
+enum Afi {
     IPV4,
     IPV6,
 }
 
 struct Proto {
-    afi: AFI,
+    afi: Afi,
     domain: Domain,
     protocol: Protocol,
     ip_header_size: usize,
@@ -37,7 +63,7 @@ struct Proto {
 }
 
 static IPV4: Proto = Proto {
-    afi: AFI::IPV4,
+    afi: Afi::IPV4,
     domain: Domain::IPV4,
     protocol: Protocol::ICMPV4,
     ip_header_size: 20,
@@ -46,7 +72,7 @@ static IPV4: Proto = Proto {
 };
 
 static IPV6: Proto = Proto {
-    afi: AFI::IPV6,
+    afi: Afi::IPV6,
     domain: Domain::IPV6,
     protocol: Protocol::ICMPV6,
     ip_header_size: 0, // No IPv6 header is passed over socket
@@ -54,46 +80,340 @@ static IPV6: Proto = Proto {
     icmp_reply_type: 129,
 };
 
+/// Options, which may be changed by `reconfigure`
+const RECONFIGURABLE: [&str; 10] = [
+    "timeout",
+    "ttl",
+    "tos",
+    "ecn",
+    "busy_poll",
+    "incoming_cpu",
+    "coarse",
+    "accelerated",
+    "recv_tos",
+    "recv_pktinfo",
+];
+
+/// Request id is passed by caller
+pub(crate) const REQUEST_ID_CALLER: u8 = 0;
+/// Random request id, chosen on socket creation
+pub(crate) const REQUEST_ID_RANDOM: u8 = 1;
+/// Request id, derived from socket's signature
+pub(crate) const REQUEST_ID_SIGNATURE: u8 = 2;
+
+/// Reject new sessions when in-flight limit is reached
+pub(crate) const OVERLOAD_REJECT: u8 = 0;
+/// Evict the oldest session when in-flight limit is reached
+pub(crate) const OVERLOAD_EVICT: u8 = 1;
+
+/// Packet is sent
+pub(crate) const DEBUG_TX: u8 = 0;
+/// Packet is received
+pub(crate) const DEBUG_RX: u8 = 1;
+
+/// Not ECN-Capable Transport
+pub(crate) const ECN_NOT_ECT: u8 = 0;
+/// ECN Capable Transport, ECT(1)
+pub(crate) const ECN_ECT1: u8 = 1;
+/// ECN Capable Transport, ECT(0)
+pub(crate) const ECN_ECT0: u8 = 2;
+/// Congestion Experienced
+pub(crate) const ECN_CE: u8 = 3;
+/// ECN bits of ToS/traffic class
+const ECN_MASK: u32 = 0x3;
+
+/// Timestamp, embedded into packet, as is
+pub(crate) const TS_PLAIN: u8 = 0;
+/// Timestamp is obfuscated with per-packet key
+pub(crate) const TS_OBFUSCATED: u8 = 1;
+/// Random nonce is embedded, timestamp is kept locally
+pub(crate) const TS_NONCE: u8 = 2;
+
+/// Padding is filled with constant pattern
+pub(crate) const PADDING_FIXED: u8 = 0;
+/// Padding is filled with pseudo-random octets
+pub(crate) const PADDING_RANDOM: u8 = 1;
+
+/// Native payload with signature and timestamp
+pub(crate) const MIMIC_NONE: u8 = 0;
+/// Payload of Linux iputils ping
+pub(crate) const MIMIC_LINUX: u8 = 1;
+/// Payload of Windows ping
+pub(crate) const MIMIC_WINDOWS: u8 = 2;
+
+/// RTT in nanoseconds, as integer
+pub(crate) const RTT_NS: u8 = 0;
+/// RTT in microseconds, as float
+pub(crate) const RTT_US: u8 = 1;
+/// RTT in milliseconds, as float
+pub(crate) const RTT_MS: u8 = 2;
+/// RTT in seconds, as float
+pub(crate) const RTT_S: u8 = 3;
+
+/// Zero or negative RTT is clamped to 1ns
+pub(crate) const INVALID_RTT_CLAMP: u8 = 0;
+/// Session with zero or negative RTT is failed
+pub(crate) const INVALID_RTT_ERROR: u8 = 1;
+/// Zero or negative RTT is delivered as is
+pub(crate) const INVALID_RTT_RAW: u8 = 2;
+
+/// Fixed probe interval
+pub(crate) const JITTER_NONE: u8 = 0;
+/// Uniformly distributed probe interval
+pub(crate) const JITTER_UNIFORM: u8 = 1;
+/// Exponentially distributed part of probe interval
+pub(crate) const JITTER_EXPONENTIAL: u8 = 2;
+
+/// Timestamp embedding mode
+#[derive(Clone, Copy, PartialEq)]
+enum TsMode {
+    Plain,
+    Obfuscated,
+    Nonce,
+}
+
+/// Handling of zero or negative RTT
+#[derive(Clone, Copy, PartialEq)]
+enum InvalidRtt {
+    Clamp,
+    Error,
+    Raw,
+}
+
+/// Unit of the delivered RTT
+#[derive(Clone, Copy, PartialEq)]
+enum RttUnit {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+impl RttUnit {
+    /// Convert RTT in nanoseconds to Python object
+    fn to_py(self, py: Python, rtt: i64) -> PyObject {
+        match self {
+            RttUnit::Ns => rtt.into_py(py),
+            RttUnit::Us => (rtt as f64 / 1e3).into_py(py),
+            RttUnit::Ms => (rtt as f64 / 1e6).into_py(py),
+            RttUnit::S => (rtt as f64 / 1e9).into_py(py),
+        }
+    }
+}
+
+/// Overload policy
+#[derive(Clone, Copy)]
+enum OverloadPolicy {
+    Reject,
+    Evict,
+}
+
+/// Request id allocation policy
+#[derive(Clone, Copy)]
+enum RequestIdPolicy {
+    Caller,
+    Random(u16),
+    Signature,
+}
+
+/// Result of `wait`: replies and expired sessions
+type WaitResult = (Option<HashMap<String, PyObject>>, Option<Vec<String>>);
+
+/// Received packets counters
+#[derive(Default)]
+struct Counters {
+    // All received packets
+    received: u64,
+    // Too short to be a request
+    too_short: u64,
+    // Not an echo reply
+    wrong_type: u64,
+    // Replies to the other sockets
+    signature_mismatch: u64,
+    // Accepted ICMP errors, when `recv_errors` is set
+    errors: u64,
+    // Replies with zero or negative RTT
+    invalid_rtt: u64,
+    // Corrupted replies
+    checksum_error: u64,
+    // Replies with altered random padding
+    padding_mismatch: u64,
+    // Accepted replies
+    matched: u64,
+    // Accepted replies of unexpected size
+    size_mismatch: u64,
+    // Packets, larger than receive buffer
+    truncated: u64,
+    // Replies to expired sessions, within grace period
+    late: u64,
+    // Replies to the previous requests with the same sequence number
+    stale: u64,
+    // Socket receive queue drops, reported by kernel
+    kernel_drops: Option<u32>,
+    // Result records, dropped due to slow collector or log's storage
+    result_drops: u64,
+    // Failed writes to the result log
+    result_log_errors: u64,
+}
+
+/// Host-side latency of the pipeline stages, in nanoseconds
+#[derive(Default)]
+struct Budget {
+    // From `send` call to `sendto`, packet encoding
+    encode: Histogram,
+    // `sendto` system call
+    send: Histogram,
+    // From reading the reply to the delivery to Python
+    deliver: Histogram,
+}
+
 /// Python class wrapping socket implementation
 #[pyclass]
 pub(crate) struct SocketWrapper {
     proto: &'static Proto,
-    io: Socket,
+    // ICMP types of requests and replies, and code of requests.
+    // Protocol defaults, unless overridden
+    icmp_request_type: u8,
+    icmp_reply_type: u8,
+    icmp_code: u8,
+    // Verify checksum of ICMPv4 replies
+    verify_checksum: bool,
+    // None, when closed
+    io: Option<Socket>,
     signature: u64,
+    // Signature of the previous socket, matched until the deadline
+    handover: Option<(u64, u64)>,
+    request_id_policy: RequestIdPolicy,
+    ts_mode: TsMode,
+    // Unit of the delivered RTT
+    rtt_unit: RttUnit,
+    // Handling of zero or negative RTT
+    invalid_rtt: InvalidRtt,
+    // Session -> (nonce, timestamp) in nonce and mimic modes
+    nonces: HashMap<SessionKey, (u64, u64)>,
+    // Fill padding with pseudo-random octets
+    random_padding: bool,
+    // Payload layout
+    layout: Layout,
+    // Clock of the scheduled requests' release times
+    txtime: Option<libc::clockid_t>,
+    // Measure RTT from the transmit timestamps
+    tx_timestamps: bool,
+    // Probe interval jitter
+    jitter: Jitter,
+    // Id of the next sent packet, as counted by kernel
+    tx_id: u32,
+    // Packet id -> session
+    tx_ids: HashMap<u32, SessionKey>,
+    // Session -> (packet id, transmit timestamp or 0)
+    tx_sent: HashMap<SessionKey, (u32, u64)>,
+    // Compare size of replies with the size of requests
+    strict_size: bool,
+    // Session -> request size, when `strict_size` is set
+    sizes: HashMap<SessionKey, usize>,
+    // Grace period of expired sessions, 0 - disabled
+    late_grace: u64,
+    // Deliver late replies
+    report_late: bool,
+    // Expired session -> (end of grace period, locally kept nonce and ts)
+    late: HashMap<SessionKey, (u64, Option<(u64, u64)>)>,
     timeout: u64,
-    sessions: BTreeSet<Session>,
-    start: Instant,
-    coarse: bool,
+    // Maximal timeout of sent requests
+    max_timeout: u64,
+    sessions: SessionTable,
+    // In-flight sessions limit, 0 - unlimited
+    max_in_flight: usize,
+    overload_policy: OverloadPolicy,
+    // Evicted sessions, reported by get_expired
+    evicted: Vec<Session>,
+    // Replies for other sessions, received by ping_once
+    retained: Vec<Reply>,
+    // Match ICMP errors, quoting the requests
+    recv_errors: bool,
+    // Sessions, failed by ICMP errors, reported by get_expired
+    failed: Vec<Session>,
+    // ICMP errors, delivered by recv_replies
+    errors: Vec<Reply>,
+    // Deliver replies by batches of `batch_size`, 0 - disabled
+    batch_size: usize,
+    // Maximal delay of the batch delivery
+    batch_delay: u64,
+    // Replies, awaiting delivery
+    batched: Vec<Reply>,
+    // Arrival of the first reply in the batch
+    batch_since: u64,
+    // Report DSCP/ECN of replies
+    recv_tos: bool,
+    // Report local address and interface of replies
+    recv_pktinfo: bool,
+    // Report kernel drops
+    rxq_ovfl: bool,
+    // End of diagnostic mode
+    diag_until: Option<u64>,
+    // Packets, recorded in diagnostic mode
+    diag: Vec<(String, u8, u8, usize)>,
+    // Called for every sent and received packet
+    debug_callback: Option<PyObject>,
+    // Received packets counters
+    counters: Counters,
+    // Latency budget, when accounted
+    budget: Option<Box<Budget>>,
+    // Connected target
+    target: Option<IpAddr>,
+    // Stream result records to the descriptor
+    result_fd: Option<RawFd>,
+    // Result records, awaiting write
+    result_buf: Vec<u8>,
+    // Persistent result log
+    result_log: Option<ResultLog>,
+    timer: Timer,
+    // Added to timestamps to continue imported state
+    ts_offset: u64,
+    accelerated: bool,
+    // Receive buffer for IP and ICMP headers
+    hdr_buf: [MaybeUninit<u8>; HEADER_SIZE],
+    // Receive buffer for payload, past the ICMP header
     buf: [MaybeUninit<u8>; MAX_SIZE],
+    // Pre-encoded requests, indexed by packet size
+    templates: HashMap<usize, PacketTemplate>,
 }
 
 #[pymethods]
 impl SocketWrapper {
     /// Python constructor
     #[new]
-    fn new(afi: u8) -> PyResult<Self> {
-        let proto = match afi {
-            4 => &IPV4,
-            6 => &IPV6,
-            _ => return Err(PyValueError::new_err("invalid afi".to_string())),
-        };
+    #[args(request_id_policy = "REQUEST_ID_CALLER")]
+    fn new(afi: u8, request_id_policy: u8) -> PyResult<Self> {
+        let proto = Self::get_proto(afi)?;
         // Create socket for given address family
-        let io = Socket::new(proto.domain, Type::RAW, Some(proto.protocol))
-            .map_err(|e| PyOSError::new_err(e.to_string()))?;
-        // Mark socket as non-blocking
-        io.set_nonblocking(true)
-            .map_err(|e| PyOSError::new_err(e.to_string()))?;
-        let mut rng = rand::thread_rng();
-        Ok(Self {
-            proto,
-            io,
-            signature: rng.gen(),
-            sessions: BTreeSet::new(),
-            timeout: 1_000_000_000,
-            start: Instant::now(),
-            coarse: false,
-            buf: unsafe { MaybeUninit::uninit().assume_init() },
-        })
+        let io = Socket::new(proto.domain, Type::RAW, Some(proto.protocol)).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                PyPermissionError::new_err((
+                    e.raw_os_error().unwrap_or(libc::EPERM),
+                    caps::raw_denied_hint(),
+                ))
+            } else {
+                PyOSError::new_err(e.to_string())
+            }
+        })?;
+        Self::with_socket(proto, io, request_id_policy)
+    }
+
+    /// Wrap already opened raw socket's file descriptor,
+    /// i.e. inherited from the parent process or received
+    /// over SCM_RIGHTS. Wrapper takes the ownership of the descriptor.
+    /// Descriptors of other sockets are rejected and left open.
+    #[staticmethod]
+    #[args(request_id_policy = "REQUEST_ID_CALLER")]
+    fn from_fd(afi: u8, fd: i32, request_id_policy: u8) -> PyResult<Self> {
+        let proto = Self::get_proto(afi)?;
+        let io = unsafe { Socket::from_raw_fd(fd) };
+        if let Err(e) = Self::check_socket(proto, &io) {
+            // Leave the descriptor to the caller
+            let _ = io.into_raw_fd();
+            return Err(e);
+        }
+        Self::with_socket(proto, io, request_id_policy)
     }
 
     /// Set default timeout, in nanoseconds
@@ -104,22 +424,78 @@ impl SocketWrapper {
 
     /// Set default outgoing packets' TTL
     fn set_ttl(&self, ttl: u32) -> PyResult<()> {
-        self.io.set_ttl(ttl)?;
+        match self.proto.afi {
+            Afi::IPV4 => self.get_io()?.set_ttl(ttl)?,
+            Afi::IPV6 => self.get_io()?.set_unicast_hops_v6(ttl)?,
+        }
         Ok(())
     }
 
     /// Set default outgoing packets' ToS
     fn set_tos(&self, tos: u32) -> PyResult<()> {
-        self.io.set_tos(tos)?;
+        match self.proto.afi {
+            Afi::IPV4 => self.get_io()?.set_tos(tos)?,
+            Afi::IPV6 => self.set_tclass(tos)?,
+        }
+        Ok(())
+    }
+
+    /// Allow binding to the non-local addresses (Linux)
+    fn set_freebind(&self, enabled: bool) -> PyResult<()> {
+        self.apply_freebind(enabled)?;
         Ok(())
     }
 
+    /// Allow binding to the non-local addresses
+    /// and transparent proxying (Linux).
+    /// Requires CAP_NET_ADMIN or CAP_NET_RAW
+    fn set_transparent(&self, enabled: bool) -> PyResult<()> {
+        self.apply_transparent(enabled).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                PyPermissionError::new_err((
+                    libc::EPERM,
+                    "transparent requires CAP_NET_ADMIN or CAP_NET_RAW",
+                ))
+            } else {
+                e.into()
+            }
+        })
+    }
+
+    /// Bind socket to the local address.
+    /// Non-local addresses require `freebind` or `transparent`
+    fn bind(&self, addr: String) -> PyResult<()> {
+        let local_addr = self.parse_addr(&addr)?;
+        self.get_io()?.bind(&local_addr).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::AddrNotAvailable {
+                PyOSError::new_err((
+                    libc::EADDRNOTAVAIL,
+                    "address is not local, set freebind or transparent",
+                ))
+            } else {
+                e.into()
+            }
+        })
+    }
+
+    /// Set ECN codepoint of outgoing packets, preserving DSCP
+    fn set_ecn(&self, ecn: u8) -> PyResult<()> {
+        if ecn > ECN_CE {
+            return Err(PyValueError::new_err("ecn must be in 0..3 range"));
+        }
+        let tos = self.get_tos()?;
+        self.set_tos((tos & !ECN_MASK) | ecn as u32)
+    }
+
     /// Set internal socket's send buffer size
     fn set_send_buffer_size(&self, size: usize) -> PyResult<()> {
-        // @todo: get wmem_max limit on Linux
+        // Exceed wmem_max, when CAP_NET_ADMIN is present
+        if self.force_send_buffer_size(size).is_ok() {
+            return Ok(());
+        }
         let mut effective_size = size;
         while effective_size > 0 {
-            if self.io.set_send_buffer_size(effective_size).is_ok() {
+            if self.get_io()?.set_send_buffer_size(effective_size).is_ok() {
                 return Ok(());
             }
             effective_size >>= 1;
@@ -129,9 +505,13 @@ impl SocketWrapper {
 
     /// Set internal socket's receive buffer size
     fn set_recv_buffer_size(&self, size: usize) -> PyResult<()> {
+        // Exceed rmem_max, when CAP_NET_ADMIN is present
+        if self.force_recv_buffer_size(size).is_ok() {
+            return Ok(());
+        }
         let mut effective_size = size;
         while effective_size > 0 {
-            if self.io.set_recv_buffer_size(effective_size).is_ok() {
+            if self.get_io()?.set_recv_buffer_size(effective_size).is_ok() {
                 return Ok(());
             }
             effective_size >>= 1;
@@ -139,146 +519,2327 @@ impl SocketWrapper {
         Err(PyOSError::new_err("unable to set buffer size"))
     }
 
-    /// Switch to CLOCK_MONOTONIC_COARSE implementation
+    /// Set SO_BUSY_POLL timeout, in microseconds.
+    /// Trade CPU for reduced receive latency on supported NICs
+    fn set_busy_poll(&self, usecs: u32) -> PyResult<()> {
+        self.apply_busy_poll(usecs)?;
+        Ok(())
+    }
+
+    /// Set SO_INCOMING_CPU.
+    /// Prefer processing of the socket's packets on the given CPU
+    fn set_incoming_cpu(&self, cpu: usize) -> PyResult<()> {
+        self.apply_incoming_cpu(cpu)?;
+        Ok(())
+    }
+
+    /// Limit number of in-flight sessions. 0 - unlimited.
+    /// When the limit is reached, `send` either
+    /// * OVERLOAD_REJECT: raises BlockingIOError.
+    /// * OVERLOAD_EVICT: evicts the session with closest deadline,
+    ///   which is reported by `get_expired`.
+    #[args(policy = "OVERLOAD_REJECT")]
+    fn set_max_in_flight(&mut self, limit: usize, policy: u8) -> PyResult<()> {
+        self.overload_policy = match policy {
+            OVERLOAD_REJECT => OverloadPolicy::Reject,
+            OVERLOAD_EVICT => OverloadPolicy::Evict,
+            _ => return Err(PyValueError::new_err("invalid overload policy")),
+        };
+        self.max_in_flight = limit;
+        Ok(())
+    }
+
+    /// Report DSCP/ECN field of the replies by `recv_replies`
+    fn set_recv_tos(&mut self, enabled: bool) -> PyResult<()> {
+        let (level, name) = match self.proto.afi {
+            Afi::IPV4 => (
+                libc::IPPROTO_IP,
+                sockopt::IP_RECVTOS
+                    .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::Unsupported))?,
+            ),
+            Afi::IPV6 => (libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS),
+        };
+        sockopt::set_int(self.get_io()?, level, name, enabled as libc::c_int)?;
+        self.recv_tos = enabled;
+        Ok(())
+    }
+
+    /// Match ICMP errors (unreachable, prohibited, time exceeded),
+    /// quoting the requests. Failed sessions are delivered
+    /// by `recv_replies` with the error status, and are reported
+    /// by `get_expired` otherwise, without waiting for the timeout
+    fn set_recv_errors(&mut self, enabled: bool) -> PyResult<()> {
+        self.recv_errors = enabled;
+        if self.accelerated && self.diag_until.is_none() {
+            self.enable_accelerated()?;
+        }
+        Ok(())
+    }
+
+    /// Compare size of the replies with the size of the requests.
+    /// Mismatch is reported by `recv_replies`
+    fn set_strict_size(&mut self, enabled: bool) {
+        if !enabled {
+            self.sizes.clear();
+        }
+        self.strict_size = enabled;
+    }
+
+    /// Set unit of RTT, delivered by `recv`, `recv_into`, `flush`,
+    /// `wait`, `close`, and `ping_once`. Nanoseconds are delivered
+    /// as integers, other units as floats.
+    /// `Reply.rtt` is always in nanoseconds
+    fn set_rtt_unit(&mut self, unit: u8) -> PyResult<()> {
+        self.rtt_unit = match unit {
+            RTT_NS => RttUnit::Ns,
+            RTT_US => RttUnit::Us,
+            RTT_MS => RttUnit::Ms,
+            RTT_S => RttUnit::S,
+            _ => return Err(PyValueError::new_err("invalid rtt unit")),
+        };
+        Ok(())
+    }
+
+    /// Set handling of zero or negative RTT, usually caused
+    /// by the coarse clock or corrupted timestamps.
+    /// Occurrences are counted as `invalid_rtt`
+    fn set_invalid_rtt(&mut self, mode: u8) -> PyResult<()> {
+        self.invalid_rtt = match mode {
+            INVALID_RTT_CLAMP => InvalidRtt::Clamp,
+            INVALID_RTT_ERROR => InvalidRtt::Error,
+            INVALID_RTT_RAW => InvalidRtt::Raw,
+            _ => return Err(PyValueError::new_err("invalid rtt mode")),
+        };
+        Ok(())
+    }
+
+    /// Keep expired sessions for `grace` nanoseconds to count
+    /// late replies separately from the lost ones.
+    /// Late replies are delivered with `late` flag and actual RTT
+    /// when `report` is set. Zero `grace` disables tracking
+    #[args(report = "false")]
+    fn set_late_grace(&mut self, grace: u64, report: bool) {
+        if grace == 0 {
+            self.late.clear();
+        }
+        self.late_grace = grace;
+        self.report_late = report;
+    }
+
+    /// Release requests, passed to `send` with `at`,
+    /// at the given time of the clock (SO_TXTIME, Linux).
+    /// None stops scheduling
+    #[args(clock = "None")]
+    fn set_txtime(&mut self, clock: Option<libc::clockid_t>) -> PyResult<()> {
+        if let Some(clock) = clock {
+            sockopt::set_txtime(self.get_io()?, clock)?;
+        }
+        self.txtime = clock;
+        Ok(())
+    }
+
+    /// Measure RTT from the time the requests actually left the host,
+    /// as reported by kernel's software transmit timestamps,
+    /// excluding local queuing delay (Linux)
+    fn set_tx_timestamps(&mut self, enabled: bool) -> PyResult<()> {
+        if enabled == self.tx_timestamps {
+            return Ok(());
+        }
+        sockopt::set_tx_timestamps(self.get_io()?, enabled)?;
+        // Kernel restarts counting
+        self.tx_id = 0;
+        self.tx_ids.clear();
+        self.tx_sent.clear();
+        self.tx_timestamps = enabled;
+        Ok(())
+    }
+
+    /// Report local address and interface of the replies
+    /// by `recv_replies`. Local address of the reply is the source
+    /// address of the request
+    fn set_recv_pktinfo(&mut self, enabled: bool) -> PyResult<()> {
+        let (level, name) = match self.proto.afi {
+            Afi::IPV4 => (libc::IPPROTO_IP, libc::IP_PKTINFO),
+            Afi::IPV6 => (libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO),
+        };
+        sockopt::set_int(self.get_io()?, level, name, enabled as libc::c_int)?;
+        self.recv_pktinfo = enabled;
+        Ok(())
+    }
+
+    /// Report kernel's receive queue drops counter
+    /// by `get_counters` (Linux)
+    fn set_rxq_ovfl(&mut self, enabled: bool) -> PyResult<()> {
+        self.apply_rxq_ovfl(enabled)?;
+        self.rxq_ovfl = enabled;
+        // Counter is passed only when non-zero
+        self.counters.kernel_drops = if enabled { Some(0) } else { None };
+        Ok(())
+    }
+
+    /// Account host-side latency of the pipeline stages,
+    /// resetting the accounted ones
+    fn set_latency_budget(&mut self, enabled: bool) -> PyResult<()> {
+        self.budget = if enabled { Some(Box::default()) } else { None };
+        Ok(())
+    }
+
+    /// Get histograms of the pipeline stages, in nanoseconds.
+    /// Returns dict of <stage> -> dict of `count`, `sum`, `min`,
+    /// `max`, `p50`, `p99`, and `buckets`. None, unless accounted
+    fn get_latency_budget(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let budget = match &self.budget {
+            Some(budget) => budget,
+            None => return Ok(None),
+        };
+        let r = PyDict::new(py);
+        for (stage, h) in [
+            ("encode", &budget.encode),
+            ("send", &budget.send),
+            ("deliver", &budget.deliver),
+        ] {
+            let item = PyDict::new(py);
+            item.set_item("count", h.get_count())?;
+            item.set_item("sum", h.get_sum())?;
+            item.set_item("min", h.get_min())?;
+            item.set_item("max", h.get_max())?;
+            item.set_item("p50", h.get_quantile(0.5))?;
+            item.set_item("p99", h.get_quantile(0.99))?;
+            item.set_item("buckets", h.get_buckets())?;
+            r.set_item(stage, item)?;
+        }
+        Ok(Some(r.into()))
+    }
+
+    /// Switch to CLOCK_MONOTONIC_COARSE implementation.
+    /// Timeline is rebased, so deadlines and send timestamps
+    /// of pending sessions remain valid.
     fn set_coarse(&mut self, ct: bool) -> PyResult<()> {
-        self.coarse = ct;
+        let ts = self.get_ts();
+        self.timer.set_coarse(ct);
+        self.ts_offset = ts.wrapping_sub(self.timer.now());
         Ok(())
     }
 
     /// Enable accelerated socket processing
-    fn set_accelerated(&self, a: bool) -> PyResult<()> {
+    fn set_accelerated(&mut self, a: bool) -> PyResult<()> {
         if a {
-            self.enable_accelerated()?
+            // Filter is attached at the end of diagnostic
+            if self.diag_until.is_none() {
+                self.enable_accelerated()?
+            }
         } else {
             self.disable_accelerated()?
         }
+        self.accelerated = a;
+        Ok(())
+    }
+
+    /// Set the mode of timestamps, embedded into requests.
+    /// Cannot be changed while sessions are pending.
+    fn set_ts_mode(&mut self, mode: u8) -> PyResult<()> {
+        let mode = match mode {
+            TS_PLAIN => TsMode::Plain,
+            TS_OBFUSCATED => TsMode::Obfuscated,
+            TS_NONCE => TsMode::Nonce,
+            _ => return Err(PyValueError::new_err("invalid timestamp mode")),
+        };
+        if mode != self.ts_mode && !self.sessions.is_empty() {
+            return Err(PyValueError::new_err(
+                "cannot change timestamp mode with pending sessions",
+            ));
+        }
+        self.ts_mode = mode;
+        Ok(())
+    }
+
+    /// Set padding mode of requests.
+    /// Cannot be changed while sessions are pending.
+    fn set_padding(&mut self, mode: u8) -> PyResult<()> {
+        let random = match mode {
+            PADDING_FIXED => false,
+            PADDING_RANDOM => true,
+            _ => return Err(PyValueError::new_err("invalid padding mode")),
+        };
+        if random != self.random_padding && !self.sessions.is_empty() {
+            return Err(PyValueError::new_err(
+                "cannot change padding mode with pending sessions",
+            ));
+        }
+        self.random_padding = random;
+        Ok(())
+    }
+
+    /// Randomize probe intervals, returned by `next_interval_ns`,
+    /// by `percent` of the interval. Mean interval is preserved.
+    #[args(percent = "10.0")]
+    fn set_jitter(&mut self, mode: u8, percent: f64) -> PyResult<()> {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(PyValueError::new_err("percent must be in 0..100 range"));
+        }
+        self.jitter = match mode {
+            JITTER_NONE => Jitter::None,
+            JITTER_UNIFORM => Jitter::Uniform(percent),
+            JITTER_EXPONENTIAL => Jitter::Exponential(percent),
+            _ => return Err(PyValueError::new_err("invalid jitter mode")),
+        };
+        Ok(())
+    }
+
+    /// Get the next probe interval, randomized according to `set_jitter`
+    fn next_interval_ns(&self, interval: u64) -> u64 {
+        match self.jitter {
+            Jitter::None => interval,
+            jitter => jitter.apply(interval, rand::thread_rng().gen()),
+        }
+    }
+
+    /// Reproduce payload of the OS ping utility.
+    /// Replies are matched by pending sessions instead of signature.
+    /// Cannot be changed while sessions are pending.
+    fn set_mimic(&mut self, mode: u8) -> PyResult<()> {
+        let layout = match mode {
+            MIMIC_NONE => Layout::Native,
+            MIMIC_LINUX => Layout::Linux,
+            MIMIC_WINDOWS => Layout::Windows,
+            _ => return Err(PyValueError::new_err("invalid mimic mode")),
+        };
+        if layout == self.layout {
+            return Ok(());
+        }
+        if !self.sessions.is_empty() {
+            return Err(PyValueError::new_err(
+                "cannot change mimic mode with pending sessions",
+            ));
+        }
+        self.layout = layout;
+        // Signature is no longer filtered
+        if self.accelerated && self.diag_until.is_none() {
+            self.enable_accelerated()
+                .map_err(|e| PyOSError::new_err(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Override ICMP types of requests and replies,
+    /// i.e. for test harnesses or stacks, answering with
+    /// non-standard types. Cannot be changed while sessions are pending.
+    fn set_icmp_types(&mut self, request_type: u8, reply_type: u8) -> PyResult<()> {
+        if request_type == self.icmp_request_type && reply_type == self.icmp_reply_type {
+            return Ok(());
+        }
+        if !self.sessions.is_empty() {
+            return Err(PyValueError::new_err(
+                "cannot change icmp types with pending sessions",
+            ));
+        }
+        self.icmp_request_type = request_type;
+        self.icmp_reply_type = reply_type;
+        self.templates.clear();
+        // Filter matches the reply type
+        if self.accelerated && self.diag_until.is_none() {
+            self.enable_accelerated()
+                .map_err(|e| PyOSError::new_err(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Set ICMP code of requests
+    fn set_icmp_code(&mut self, code: u8) -> PyResult<()> {
+        if code != self.icmp_code {
+            self.icmp_code = code;
+            self.templates.clear();
+        }
+        Ok(())
+    }
+
+    /// Verify checksum of ICMPv4 replies, enabled by default.
+    /// ICMPv6 checksum is always verified by kernel
+    fn set_verify_checksum(&mut self, enabled: bool) -> PyResult<()> {
+        self.verify_checksum = enabled;
+        Ok(())
+    }
+
+    /// Deliver replies by `recv` in batches, when either
+    /// `size` replies are accumulated or `delay` nanoseconds
+    /// passed since the first reply of the batch. Pending batch
+    /// is to be delivered by `flush` at `next_flush_ns`.
+    /// Zero `size` disables batching.
+    fn set_batching(&mut self, size: usize, delay: u64) -> PyResult<()> {
+        self.batch_size = size;
+        self.batch_delay = delay;
+        Ok(())
+    }
+
+    /// Deliver all pending batched replies, and replies,
+    /// received before the `recv` error.
+    /// Returns dict of <session id> -> rtt
+    fn flush(&mut self, py: Python) -> PyResult<Option<HashMap<String, PyObject>>> {
+        if self.batched.is_empty() && self.retained.is_empty() {
+            return Ok(None);
+        }
+        let mut batched = std::mem::take(&mut self.batched);
+        batched.append(&mut self.retained);
+        self.account_delivery(&batched);
+        let unit = self.rtt_unit;
+        Ok(Some(
+            batched
+                .into_iter()
+                .map(|x| (x.sid, unit.to_py(py, x.rtt)))
+                .collect(),
+        ))
+    }
+
+    /// Get the time when pending batch is to be flushed,
+    /// according to CLOCK_MONOTONIC, in nanoseconds.
+    fn next_flush_ns(&self) -> Option<u64> {
+        if self.batched.is_empty() {
+            return None;
+        }
+        let deadline = self.batch_since + self.batch_delay;
+        let left = deadline.wrapping_sub(self.get_ts()) as i64;
+        Some(Self::get_monotonic().wrapping_add(left as u64))
+    }
+
+    /// Stream results to the file descriptor, i.e. pipe or
+    /// Unix socket, as length-prefixed binary records.
+    /// Descriptor is switched to non-blocking mode but not owned
+    /// by the socket. Records, not fitting into the pending
+    /// buffer, are dropped. None disables streaming.
+    #[args(fd = "None")]
+    fn set_result_fd(&mut self, fd: Option<i32>) -> PyResult<()> {
+        if let Some(fd) = fd {
+            sockopt::set_nonblocking(fd).map_err(|e| PyOSError::new_err(e.to_string()))?;
+        }
+        self.result_fd = fd;
+        self.result_buf.clear();
+        Ok(())
+    }
+
+    /// Append results to the file at `path` as length-prefixed binary
+    /// records, same as `set_result_fd`. The log is rotated before
+    /// exceeding `max_size` bytes or after `max_age` nanoseconds,
+    /// keeping up to `keep` rotated logs. None disables the log.
+    #[args(path = "None", max_size = "None", max_age = "None", keep = "0")]
+    fn set_result_log(
+        &mut self,
+        path: Option<String>,
+        max_size: Option<u64>,
+        max_age: Option<u64>,
+        keep: usize,
+    ) -> PyResult<()> {
+        if max_size == Some(0) || max_age == Some(0) {
+            return Err(PyValueError::new_err(
+                "max_size and max_age must be positive",
+            ));
+        }
+        // Flush and close the previous log
+        self.result_log = None;
+        if let Some(path) = path {
+            let rotation = Rotation {
+                max_size,
+                max_age,
+                keep,
+            };
+            self.result_log =
+                Some(ResultLog::open(path, rotation, Self::get_time()).map_err(Self::os_error)?);
+        }
+        Ok(())
+    }
+
+    /// Enter diagnostic mode for `duration` nanoseconds.
+    /// BPF filter is detached and every received ICMP packet
+    /// is recorded, to be collected by `get_diagnostic`.
+    /// Replies are processed as usual.
+    fn set_diagnostic(&mut self, duration: u64) -> PyResult<()> {
+        if self.accelerated && self.diag_until.is_none() {
+            self.disable_accelerated()?;
+        }
+        self.diag_until = Some(self.get_ts() + duration);
         Ok(())
     }
 
+    /// Get ICMP packets, recorded in diagnostic mode.
+    /// Returns list of (<source address>, <icmp type>, <icmp code>, <size>)
+    fn get_diagnostic(&mut self) -> PyResult<Vec<(String, u8, u8, usize)>> {
+        self.check_diagnostic()?;
+        Ok(std::mem::take(&mut self.diag))
+    }
+
+    /// Set callback, called for every sent and received packet
+    /// with (<direction>, <packet>, <address>, <timestamp>).
+    /// Remove callback when not set.
+    #[args(callback = "None")]
+    fn set_debug_callback(&mut self, callback: Option<PyObject>) {
+        self.debug_callback = callback;
+    }
+
+    /// Get received packets counters
+    fn get_counters(&self, py: Python) -> PyResult<PyObject> {
+        let r = PyDict::new(py);
+        r.set_item("received", self.counters.received)?;
+        r.set_item("too_short", self.counters.too_short)?;
+        r.set_item("wrong_type", self.counters.wrong_type)?;
+        r.set_item("errors", self.counters.errors)?;
+        r.set_item("invalid_rtt", self.counters.invalid_rtt)?;
+        r.set_item("signature_mismatch", self.counters.signature_mismatch)?;
+        r.set_item("checksum_error", self.counters.checksum_error)?;
+        r.set_item("padding_mismatch", self.counters.padding_mismatch)?;
+        r.set_item("matched", self.counters.matched)?;
+        r.set_item("size_mismatch", self.counters.size_mismatch)?;
+        r.set_item("truncated", self.counters.truncated)?;
+        r.set_item("late", self.counters.late)?;
+        r.set_item("stale", self.counters.stale)?;
+        r.set_item("kernel_drops", self.counters.kernel_drops)?;
+        r.set_item("result_drops", self.counters.result_drops)?;
+        r.set_item("result_log_errors", self.counters.result_log_errors)?;
+        Ok(r.into())
+    }
+
+    /// Check if diagnostic mode is active
+    #[getter]
+    fn is_diagnostic(&self) -> bool {
+        self.diag_until.is_some()
+    }
+
+    /// Get effective socket options.
+    /// Values are read back from the kernel, when possible,
+    /// so the clamped values are reported.
+    fn get_options(&self, py: Python) -> PyResult<PyObject> {
+        Ok(self.options_dict(py)?.into())
+    }
+
+    /// Apply a batch of options at once:
+    /// timeout, ttl, tos, busy_poll, incoming_cpu, coarse, accelerated,
+    /// recv_tos, ecn, recv_pktinfo.
+    /// On failure, already applied options are restored
+    /// to the previous values and the error is raised.
+    /// Returns dict of applied options' effective values.
+    fn reconfigure(&mut self, py: Python, options: &PyDict) -> PyResult<PyObject> {
+        // Check names before applying anything
+        for name in options.keys() {
+            let name: &str = name.extract()?;
+            if !RECONFIGURABLE.contains(&name) {
+                return Err(PyValueError::new_err(format!("invalid option: {}", name)));
+            }
+        }
+        let prev = self.options_dict(py)?;
+        let mut applied = Vec::<&str>::new();
+        for (name, value) in options.iter() {
+            let name: &str = name.extract()?;
+            if let Err(e) = self.set_option(name, value) {
+                // Rollback
+                for name in applied.iter().rev() {
+                    if let Some(value) = prev.get_item(name) {
+                        if !value.is_none() {
+                            let _ = self.set_option(name, value);
+                        }
+                    }
+                }
+                return Err(e);
+            }
+            applied.push(name);
+        }
+        let effective = self.options_dict(py)?;
+        let r = PyDict::new(py);
+        for name in applied {
+            r.set_item(name, effective.get_item(name))?;
+        }
+        Ok(r.into())
+    }
+
     /// Get socket's file descriptor
     fn get_fd(&self) -> PyResult<i32> {
-        Ok(self.io.as_raw_fd())
+        Ok(self.get_io()?.as_raw_fd())
+    }
+
+    /// Get socket's file descriptor.
+    /// Allows passing socket directly to `select`,
+    /// `trio.lowlevel.wait_readable` and so on.
+    fn fileno(&self) -> PyResult<i32> {
+        self.get_fd()
+    }
+
+    /// Wait until socket became readable, releasing GIL.
+    /// Waits up to `timeout` nanoseconds, or indefinitely when not set.
+    /// Returns true if socket is readable, false on timeout.
+    #[args(timeout = "None")]
+    fn poll(&self, py: Python, timeout: Option<u64>) -> PyResult<bool> {
+        let r = self.wait_readable(py, timeout.unwrap_or(u64::MAX))?;
+        py.check_signals()?;
+        Ok(r)
     }
 
     /// Normalize address
     fn clean_ip(&self, addr: String) -> PyResult<String> {
         Ok(match self.proto.afi {
-            AFI::IPV4 => SocketAddrV4::new(addr.parse()?, 0).ip().to_string(),
-            AFI::IPV6 => SocketAddrV6::new(addr.parse()?, 0, 0, 0).ip().to_string(),
+            Afi::IPV4 => SocketAddrV4::new(addr.parse()?, 0).ip().to_string(),
+            Afi::IPV6 => SocketAddrV6::new(addr.parse()?, 0, 0, 0).ip().to_string(),
         })
     }
 
-    /// Send single ICMP echo request
-    fn send(&mut self, addr: String, request_id: u16, seq: u16, size: usize) -> PyResult<()> {
-        // Parse IP address
-        let to_addr: SockAddr = match self.proto.afi {
-            AFI::IPV4 => SocketAddrV4::new(addr.parse()?, 0).into(),
-            AFI::IPV6 => SocketAddrV6::new(addr.parse()?, 0, 0, 0).into(),
-        };
-        // Get timestamp
-        let ts = self.get_ts();
-        let pkt = IcmpPacket::new(
-            self.proto.icmp_request_type,
+    /// Connect socket to the single target. Route lookup is performed
+    /// once and only replies from the target are received.
+    /// Requests to the other addresses are rejected.
+    /// Socket may be reconnected to the other target, but not
+    /// disconnected: Linux stops delivering packets to
+    /// the disconnected raw socket.
+    fn set_target(&mut self, addr: String) -> PyResult<()> {
+        let to_addr = self.parse_addr(&addr)?;
+        self.get_io()?.connect(&to_addr)?;
+        self.target = Some(Self::get_ip(&to_addr));
+        Ok(())
+    }
+
+    /// Get connected target
+    fn get_target(&self) -> Option<String> {
+        self.target.map(|addr| addr.to_string())
+    }
+
+    /// Send single ICMP echo request.
+    /// Send from `src_addr` local address, when set.
+    /// Release at `at` nanoseconds of the txtime clock, when set.
+    /// Returns session id
+    #[args(src_addr = "None", at = "None")]
+    fn send(
+        &mut self,
+        addr: String,
+        request_id: u16,
+        seq: u16,
+        size: usize,
+        src_addr: Option<String>,
+        at: Option<u64>,
+    ) -> PyResult<String> {
+        let timeout = self.timeout;
+        let key = self.send_request(
+            &addr,
             request_id,
             seq,
-            self.signature,
-            ts,
-            size - self.proto.ip_header_size,
-        );
-        let n = pkt.write(&mut self.buf);
-        let buf = unsafe { Self::slice_assume_init_ref(&self.buf[..n]) };
-        self.io
-            .send_to(buf, &to_addr)
-            .map_err(|e| PyOSError::new_err(e.to_string()))?;
-        self.sessions
-            .insert(Session::new(&pkt.get_sid(addr), ts + self.timeout));
-        Ok(())
+            size,
+            timeout,
+            src_addr.as_deref(),
+            at,
+        )?;
+        Ok(key.get_sid())
     }
 
-    /// Receive all pending icmp echo replies.
-    /// Returns dict of <session id> -> rtt
-    fn recv(&mut self) -> PyResult<Option<HashMap<String, u64>>> {
-        let mut r = HashMap::<String, u64>::new();
-        while let Ok((size, addr)) = self.io.recv_from(&mut self.buf) {
-            // Drop too short packets
-            if size < self.proto.ip_header_size + ICMP_SIZE {
-                continue;
+    /// Send single ICMP echo request and wait for reply, releasing GIL.
+    /// Waits up to `timeout` nanoseconds, or the socket's timeout
+    /// when not set.
+    /// Returns rtt in nanoseconds.
+    /// Replies for other sessions are retained until next `recv`.
+    #[args(
+        size = "64",
+        timeout = "None",
+        request_id = "0",
+        seq = "0",
+        src_addr = "None"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn ping_once(
+        &mut self,
+        py: Python,
+        addr: String,
+        size: usize,
+        timeout: Option<u64>,
+        request_id: u16,
+        seq: u16,
+        src_addr: Option<String>,
+    ) -> PyResult<PyObject> {
+        let timeout = timeout.unwrap_or(self.timeout);
+        let key = self.send_request(
+            &addr,
+            request_id,
+            seq,
+            size,
+            timeout,
+            src_addr.as_deref(),
+            None,
+        )?;
+        let sid = key.get_sid();
+        let deadline = self.get_ts() + timeout;
+        loop {
+            let ts = self.get_ts();
+            if ts > deadline {
+                break;
             }
-            let buf =
-                unsafe { Self::slice_assume_init_ref(&self.buf[self.proto.ip_header_size..size]) };
-            // Parse packet
-            if let Ok(pkt) = IcmpPacket::try_from(buf) {
-                if pkt.is_match(self.proto.icmp_reply_type, self.signature) {
-                    // Measure RTT
-                    let ts = self.get_ts();
-                    let pkt_ts = pkt.get_ts();
-                    let delay = if ts > pkt_ts {
-                        ts - pkt_ts
-                    } else {
-                        1 // Minimal delay
-                    };
-                    // Convert SockAddr to printable form
-                    let paddr = match self.proto.afi {
-                        AFI::IPV4 => addr.as_socket_ipv4().unwrap().ip().to_string(),
-                        AFI::IPV6 => addr.as_socket_ipv6().unwrap().ip().to_string(),
-                    };
-                    r.insert(pkt.get_sid(paddr.clone()), delay);
-                    self.sessions
-                        .remove(&Session::new(&pkt.get_sid(paddr), pkt_ts + self.timeout));
+            if self.wait_readable(py, deadline - ts)? {
+                let mut seen = self.receive()?;
+                let found = seen.iter().position(|x| x.sid == sid);
+                let reply = found.map(|idx| seen.swap_remove(idx));
+                self.retained.extend(seen);
+                if let Some(reply) = reply {
+                    self.account_delivery(std::slice::from_ref(&reply));
+                    return Ok(self.rtt_unit.to_py(py, reply.rtt));
+                }
+                // Failed by ICMP error, expire early
+                if let Some(idx) = self.errors.iter().position(|x| x.sid == sid) {
+                    let err = self.errors.swap_remove(idx);
+                    self.failed.retain(|s| s.get_key() != &key);
+                    return Err(match (err.icmp_type, err.icmp_code, err.reason) {
+                        (Some(icmp_type), Some(icmp_code), Some(reason)) => {
+                            Self::icmp_error(py, reason, icmp_type, icmp_code)?
+                        }
+                        _ => PyTimeoutError::new_err("timed out"),
+                    });
                 }
             }
+            py.check_signals()?;
+        }
+        self.sessions.remove(&key);
+        self.forget(&key);
+        Err(PyTimeoutError::new_err("timed out"))
+    }
+
+    /// Look up the route to the address (Linux).
+    /// Returns dict of `gateway`, `ifindex`, `ifname`, `src`, and `table`
+    fn get_route(&self, py: Python, addr: &str) -> PyResult<PyObject> {
+        let ip = Self::get_ip(&self.parse_addr(addr)?);
+        let route = netlink::get_route(ip).map_err(|e| PyOSError::new_err(e.to_string()))?;
+        let r = PyDict::new(py);
+        r.set_item("gateway", route.gateway.map(|x| x.to_string()))?;
+        r.set_item("ifindex", route.ifindex)?;
+        r.set_item("ifname", route.ifindex.and_then(netlink::get_ifname))?;
+        r.set_item("src", route.src.map(|x| x.to_string()))?;
+        r.set_item("table", route.table)?;
+        Ok(r.into())
+    }
+
+    /// Cancel all pending sessions of the address.
+    /// Returns list of cancelled session ids.
+    fn cancel(&mut self, addr: String) -> PyResult<Vec<String>> {
+        let ip = Self::get_ip(&self.parse_addr(&addr)?);
+        // Evicted sessions are not reported as expired anymore
+        let (mut r, evicted): (Vec<Session>, Vec<Session>) = std::mem::take(&mut self.evicted)
+            .into_iter()
+            .partition(|s| s.get_key().get_addr() == ip);
+        self.evicted = evicted;
+        r.extend(self.sessions.remove_addr(&ip));
+        for session in r.iter() {
+            self.forget(session.get_key());
         }
+        Ok(r.iter().map(|s| s.get_sid()).collect())
+    }
+
+    /// Receive all pending icmp echo replies.
+    /// Replies are delivered by batches, when set by `set_batching`.
+    /// Returns dict of <session id> -> rtt
+    fn recv(&mut self, py: Python) -> PyResult<Option<HashMap<String, PyObject>>> {
+        let r = self.receive_ready()?;
+        self.errors.clear();
         if !r.is_empty() {
-            Ok(Some(r))
+            self.account_delivery(&r);
+            let unit = self.rtt_unit;
+            Ok(Some(
+                r.into_iter()
+                    .map(|x| (x.sid, unit.to_py(py, x.rtt)))
+                    .collect(),
+            ))
         } else {
             Ok(None)
         }
     }
 
-    /// Get list of session ids of expired sessions
-    fn get_expired(&mut self) -> PyResult<Option<Vec<String>>> {
-        let mut r = Vec::<Session>::new();
-        // @todo: Waiting until map_first_last API
-        let ts = self.get_ts();
-        // Extract and cleanup expired sessions
-        // NOTE:
-        // We cannnot remove items while holding the iterator.
-        // Remove them later in separate cycle.
-        // To be replaced with `pop_first` after the `map_first_last`
-        // feeature will be stabilized.
-        for item in self.sessions.iter() {
-            if !item.is_expired(ts) {
-                break;
-            }
-            r.push(item.clone());
+    /// Receive all pending icmp echo replies, like `recv`,
+    /// but append (<session id>, rtt) tuples to the caller's list,
+    /// avoiding the dict construction on every call.
+    /// Returns number of appended replies
+    fn recv_into(&mut self, out: &PyList) -> PyResult<usize> {
+        let r = self.receive_ready()?;
+        self.errors.clear();
+        for reply in r.iter() {
+            out.append((reply.sid.as_str(), self.rtt_unit.to_py(out.py(), reply.rtt)))?;
         }
-        // Cleanup expired sessions sessions
-        for item in r.iter() {
-            self.sessions.remove(item);
+        self.account_delivery(&r);
+        Ok(r.len())
+    }
+
+    /// Receive all pending icmp echo replies,
+    /// along with reply details. ICMP errors are delivered
+    /// with the error `status`, when `recv_errors` is set.
+    /// Returns list of replies
+    fn recv_replies(&mut self) -> PyResult<Option<Vec<Reply>>> {
+        let mut r = self.receive()?;
+        if !self.errors.is_empty() {
+            let errors = std::mem::take(&mut self.errors);
+            // Delivered, not expired
+            self.failed
+                .retain(|s| !errors.iter().any(|e| e.sid == s.get_sid()));
+            r.extend(errors);
         }
-        //  Return result
-        if r.is_empty() {
-            Ok(None)
+        if !r.is_empty() {
+            self.account_delivery(&r);
+            Ok(Some(r))
         } else {
-            Ok(Some(r.iter().map(|x| x.get_sid()).collect()))
+            Ok(None)
         }
     }
-}
 
-impl SocketWrapper {
-    /// Get current timestamp.
+    /// Get list of pending sessions, ordered by deadline.
+    /// Returns list of (<session id>, <target>, <nanoseconds to deadline>)
+    fn get_pending(&self) -> PyResult<Vec<(String, String, u64)>> {
+        let ts = self.get_ts();
+        Ok(self
+            .sessions
+            .to_vec()
+            .iter()
+            .map(|s| {
+                (
+                    s.get_sid(),
+                    s.get_key().get_addr().to_string(),
+                    s.get_deadline().saturating_sub(ts),
+                )
+            })
+            .collect())
+    }
+
+    /// Get number of pending sessions
+    fn get_pending_count(&self) -> PyResult<usize> {
+        Ok(self.sessions.len())
+    }
+
+    /// Close socket. Stop accepting new requests, detach filters
+    /// and close the file descriptor.
+    /// When `drain` is set, block until all pending sessions
+    /// are either answered or expired.
+    /// Returns dict of <session id> -> rtt for replies received
+    /// while draining.
+    #[args(drain = "false")]
+    fn close(&mut self, py: Python, drain: bool) -> PyResult<Option<HashMap<String, PyObject>>> {
+        if self.io.is_none() {
+            return Ok(None);
+        }
+        let mut r = HashMap::<String, PyObject>::new();
+        if drain {
+            // Wait until the latest deadline
+            while let Some(deadline) = self.sessions.last_deadline() {
+                let ts = self.get_ts();
+                if deadline < ts {
+                    break;
+                }
+                if self.wait_readable(py, deadline - ts)? {
+                    if let Some(seen) = self.recv(py)? {
+                        r.extend(seen);
+                    }
+                }
+                py.check_signals()?;
+            }
+            if let Some(seen) = self.flush(py)? {
+                r.extend(seen);
+            }
+        }
+        if self.accelerated {
+            // Socket is to be closed anyway
+            let _ = self.disable_accelerated();
+            self.accelerated = false;
+        }
+        // Close file descriptor
+        self.io = None;
+        self.release();
+        if r.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(r))
+        }
+    }
+
+    /// Close socket, leaving the file descriptor open,
+    /// i.e. when it is already closed elsewhere
+    /// and its number may be reused.
+    fn detach(&mut self) {
+        if let Some(io) = self.io.take() {
+            let _ = io.into_raw_fd();
+        }
+        self.accelerated = false;
+        self.release();
+    }
+
+    /// Export socket state: signature, settings and pending sessions.
+    /// The state may be imported by the socket in other process
+    /// to continue processing of the in-flight requests.
+    fn export_state(&self, py: Python) -> PyResult<PyObject> {
+        let state = State {
+            afi: self.get_afi(),
+            signature: self.signature,
+            ts: self.get_ts(),
+            mono: Self::get_monotonic(),
+            timeout: self.timeout,
+            coarse: self.timer.is_coarse(),
+            accelerated: self.accelerated,
+            sessions: self.sessions.to_vec(),
+            nonces: self.nonces.clone(),
+            sizes: self.sizes.clone(),
+            // Packet ids are not valid for the other socket
+            tx_sent: self
+                .tx_sent
+                .iter()
+                .filter(|(_, (_, ts))| *ts != 0)
+                .map(|(key, (_, ts))| (*key, *ts))
+                .collect(),
+        };
+        Ok(PyBytes::new(py, &state.encode()).into())
+    }
+
+    /// Import socket state, exported by `export_state`.
+    /// Socket must be of the same address family.
+    /// When `grace` is set, keep own signature and hand over
+    /// the imported sessions: replies with the imported signature
+    /// are matched for `grace` nanoseconds. The socket must have
+    /// no pending sessions of its own.
+    #[args(grace = "None")]
+    fn import_state(&mut self, state: &[u8], grace: Option<u64>) -> PyResult<()> {
+        let state = State::try_from(state).map_err(PyValueError::new_err)?;
+        if state.afi != self.get_afi() {
+            return Err(PyValueError::new_err("address family mismatch"));
+        }
+        if grace.is_some() && !self.sessions.is_empty() {
+            return Err(PyValueError::new_err(
+                "cannot hand over sessions with pending sessions",
+            ));
+        }
+        self.timer.set_coarse(state.coarse);
+        // Continue the exported timeline:
+        // new ts = exported ts + time passed since export
+        self.ts_offset = 0;
+        let elapsed = Self::get_monotonic().saturating_sub(state.mono);
+        self.ts_offset = state.ts.wrapping_add(elapsed).wrapping_sub(self.get_ts());
+        match grace {
+            Some(grace) if state.signature != self.signature => {
+                self.handover = Some((state.signature, self.get_ts().saturating_add(grace)))
+            }
+            Some(_) => self.handover = None,
+            None => {
+                self.signature = state.signature;
+                self.handover = None;
+                self.templates.clear();
+            }
+        }
+        self.timeout = state.timeout;
+        self.sessions.extend(state.sessions);
+        self.nonces.extend(state.nonces);
+        self.sizes.extend(state.sizes);
+        // Imported transmit timestamps have no packet id of the socket
+        self.tx_sent.extend(
+            state
+                .tx_sent
+                .into_iter()
+                .map(|(key, ts)| (key, (IMPORTED_TX_ID, ts))),
+        );
+        // Reinstall filter for the new signature
+        if state.accelerated || self.accelerated {
+            self.set_accelerated(state.accelerated)?;
+        }
+        Ok(())
+    }
+
+    /// Check if socket is closed
+    #[getter]
+    fn is_closed(&self) -> bool {
+        self.io.is_none()
+    }
+
+    /// Context manager entry
+    fn __enter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    /// Context manager exit. Close socket without draining
+    fn __exit__(
+        &mut self,
+        py: Python,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        self.close(py, false)?;
+        Ok(false)
+    }
+
+    /// Wait for replies or expiration of pending sessions,
+    /// releasing GIL. Blocks until socket became readable,
+    /// the closest deadline or `timeout` nanoseconds passed,
+    /// whichever comes first. Does not block when no sessions
+    /// are pending and `timeout` is not set.
+    /// Returns tuple of (<session id> -> rtt, list of expired session ids),
+    /// same as `recv` and `get_expired`.
+    #[args(timeout = "None")]
+    fn wait(&mut self, py: Python, timeout: Option<u64>) -> PyResult<WaitResult> {
+        let ts = self.get_ts();
+        let mut left = self
+            .sessions
+            .next_deadline()
+            .map(|deadline| deadline.saturating_sub(ts) + 1);
+        if !self.batched.is_empty() {
+            let flush = (self.batch_since + self.batch_delay).saturating_sub(ts) + 1;
+            left = Some(left.map_or(flush, |left| left.min(flush)));
+        }
+        let delay = match (timeout, left) {
+            (Some(timeout), Some(left)) => timeout.min(left),
+            (Some(timeout), None) => timeout,
+            (None, Some(left)) => left,
+            (None, None) => 0,
+        };
+        // Pending batch may be due
+        let seen = if self.wait_readable(py, delay)? || !self.batched.is_empty() {
+            self.recv(py)?
+        } else {
+            None
+        };
+        py.check_signals()?;
+        Ok((seen, self.get_expired(None)?))
+    }
+
+    /// Get current timestamp, in nanoseconds.
+    /// Deadlines and `get_expired` use the same clock.
+    fn get_clock(&self) -> u64 {
+        self.get_ts()
+    }
+
+    /// Get resolution of the socket's clock, in nanoseconds.
+    /// Timestamps and RTTs are quantized by the resolution.
+    fn get_clock_resolution(&self) -> PyResult<u64> {
+        let clock = self.timer.clock_id();
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        if unsafe { libc::clock_getres(clock, &mut ts) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+    }
+
+    /// Get the closest deadline of pending sessions,
+    /// according to `get_clock`.
+    fn get_next_deadline(&self) -> Option<u64> {
+        self.sessions.next_deadline()
+    }
+
+    /// Get the closest deadline of pending sessions,
+    /// according to CLOCK_MONOTONIC, in nanoseconds.
+    /// Suitable for event loop timers.
+    fn next_deadline_ns(&self) -> Option<u64> {
+        let deadline = self.sessions.next_deadline()?;
+        let left = deadline.wrapping_sub(self.get_ts()) as i64;
+        Some(Self::get_monotonic().wrapping_add(left as u64))
+    }
+
+    /// Get list of session ids of expired sessions.
+    /// Sessions are considered expired at `now`,
+    /// current timestamp is used when not set.
+    #[args(now = "None")]
+    fn get_expired(&mut self, now: Option<u64>) -> PyResult<Option<Vec<String>>> {
+        // Report evicted sessions as expired
+        let mut r = std::mem::take(&mut self.evicted);
+        // Extract and cleanup expired sessions
+        let ts = now.unwrap_or_else(|| self.get_ts());
+        r.extend(self.sessions.expire(ts));
+        // Failed sessions are already recorded and forgotten
+        let failed = std::mem::take(&mut self.failed);
+        if self.late_grace > 0 {
+            // Grace period is over
+            self.late.retain(|_, (until, _)| *until >= ts);
+            for session in r.iter() {
+                let key = *session.get_key();
+                self.late.insert(
+                    key,
+                    (
+                        session.get_deadline() + self.late_grace,
+                        self.nonces.get(&key).copied(),
+                    ),
+                );
+            }
+        }
+        if !self.nonces.is_empty() || !self.sizes.is_empty() || !self.tx_sent.is_empty() {
+            for session in r.iter() {
+                self.forget(session.get_key());
+            }
+        }
+        if self.result_fd.is_some() || self.result_log.is_some() {
+            for session in r.iter() {
+                Self::add_result(
+                    self.result_fd.map(|_| &mut self.result_buf),
+                    self.result_log.as_mut(),
+                    &mut self.counters,
+                    RECORD_TIMEOUT,
+                    *session.get_key(),
+                    0,
+                );
+            }
+            self.write_results();
+        }
+        r.extend(failed);
+        //  Return result
+        if r.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(r.iter().map(|x| x.get_sid()).collect()))
+        }
+    }
+}
+
+impl SocketWrapper {
+    /// Get current timestamp.
     /// Use CLOCK_MONOTONIC by default.
     /// Switch to CLOCK_MONOTONIC_COARSE when .set_coarse(true)
     pub fn get_ts(&self) -> u64 {
-        if self.coarse {
-            // CLOCK_MONOTONIC_COARSE
-            Clock::now_since_epoch().as_nanos()
+        self.timer.now().wrapping_add(self.ts_offset)
+    }
+
+    /// Get protocol definitions for address family
+    fn get_proto(afi: u8) -> PyResult<&'static Proto> {
+        match afi {
+            4 => Ok(&IPV4),
+            6 => Ok(&IPV6),
+            _ => Err(PyValueError::new_err("invalid afi".to_string())),
+        }
+    }
+
+    /// Check the socket is the raw ICMP socket of the address family.
+    /// Family and protocol are checked on Linux only
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn check_socket(proto: &Proto, io: &Socket) -> PyResult<()> {
+        let to_err = |e: std::io::Error| PyOSError::new_err(e.to_string());
+        if io.r#type().map_err(to_err)? != Type::RAW {
+            return Err(PyValueError::new_err("not a raw socket"));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if io.domain().map_err(to_err)? != proto.domain {
+                return Err(PyValueError::new_err("address family mismatch"));
+            }
+            if io.protocol().map_err(to_err)? != Some(proto.protocol) {
+                return Err(PyValueError::new_err("not an ICMP socket"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get address family number
+    fn get_afi(&self) -> u8 {
+        match self.proto.afi {
+            Afi::IPV4 => 4,
+            Afi::IPV6 => 6,
+        }
+    }
+
+    /// Pass packet to the debug callback.
+    /// Errors are printed and ignored
+    fn debug(callback: &PyObject, direction: u8, buf: &[u8], addr: IpAddr, ts: u64) {
+        Python::with_gil(|py| {
+            let args = (direction, PyBytes::new(py, buf), addr.to_string(), ts);
+            if let Err(e) = callback.call1(py, args) {
+                e.print(py);
+            }
+        })
+    }
+
+    /// Leave diagnostic mode when expired
+    fn check_diagnostic(&mut self) -> std::io::Result<()> {
+        if let Some(until) = self.diag_until {
+            if until < self.get_ts() {
+                self.diag_until = None;
+                if self.accelerated {
+                    self.enable_accelerated()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop sessions and per-socket state of the closed socket
+    fn release(&mut self) {
+        self.sessions.clear();
+        self.nonces.clear();
+        self.sizes.clear();
+        self.late.clear();
+        self.tx_ids.clear();
+        self.tx_sent.clear();
+        self.evicted.clear();
+        self.retained.clear();
+        self.failed.clear();
+        self.errors.clear();
+        self.batched.clear();
+        self.target = None;
+        self.result_fd = None;
+        self.result_buf.clear();
+        self.result_log = None;
+        self.diag_until = None;
+        self.diag.clear();
+        self.handover = None;
+        self.templates.clear();
+    }
+
+    /// Stop matching the handed over signature
+    /// at the end of grace period
+    fn check_handover(&mut self) -> std::io::Result<()> {
+        if let Some((_, until)) = self.handover {
+            if until < self.get_ts() {
+                self.handover = None;
+                if self.accelerated && self.diag_until.is_none() {
+                    self.enable_accelerated()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive all pending icmp echo replies
+    fn receive(&mut self) -> PyResult<Vec<Reply>> {
+        self.check_diagnostic()?;
+        self.check_handover()?;
+        // Replies, received by `ping_once`
+        let mut r = std::mem::take(&mut self.retained);
+        if self.tx_timestamps {
+            self.receive_tx_timestamps()?;
+        }
+        let io = self.io.as_ref().ok_or_else(Self::closed)?;
+        // Headers are received into the separate buffer
+        let hdr_size = self.proto.ip_header_size + ICMP_HEADER_SIZE;
+        let capacity = hdr_size + self.buf.len();
+        // ICMP errors and replies with invalid RTT fail the sessions
+        // after draining the socket
+        let mut errors = Vec::new();
+        let mut invalid = Vec::new();
+        // Error, reported after processing of the received packets
+        let mut fatal = None;
+        let target = self.target;
+        loop {
+            let mut bufs = [
+                MaybeUninitSlice::new(&mut self.hdr_buf[..hdr_size]),
+                MaybeUninitSlice::new(&mut self.buf),
+            ];
+            let received = if self.recv_tos || self.recv_pktinfo || self.rxq_ovfl {
+                // Get reply details from ancillary data
+                sockopt::recv_msg(io, &mut bufs)
+                    .map(|(size, info)| (size, target.or(info.src), info))
+            } else {
+                // Connected socket receives replies from target only
+                match target {
+                    Some(target) => io
+                        .recv_vectored_with_flags(&mut bufs, sockopt::RECV_FLAGS)
+                        .map(|(size, _)| (size, Some(target), RecvInfo::default())),
+                    None => io
+                        .recv_from_vectored_with_flags(&mut bufs, sockopt::RECV_FLAGS)
+                        .map(|(size, _, addr)| {
+                            (size, Some(Self::get_ip(&addr)), RecvInfo::default())
+                        }),
+                }
+            };
+            let (dgram_size, addr, info) = match received {
+                Ok((size, Some(addr), info)) => (size, addr, info),
+                Ok(_) => continue,
+                Err(e) if Self::is_soft_error(&e) => continue,
+                Err(e) => {
+                    if e.kind() != std::io::ErrorKind::WouldBlock {
+                        fatal = Some(e);
+                    }
+                    break;
+                }
+            };
+            // Datagram, larger than buffers, is truncated
+            let truncated = dgram_size > capacity;
+            let size = dgram_size.min(capacity);
+            let hdr = unsafe { Self::slice_assume_init_ref(&self.hdr_buf[..size.min(hdr_size)]) };
+            let payload =
+                unsafe { Self::slice_assume_init_ref(&self.buf[..size.saturating_sub(hdr_size)]) };
+            // IPv4 header with options shifts ICMP header into the payload buffer
+            let mut ip_hlen = self.proto.ip_header_size;
+            let joined: Vec<u8>;
+            let (hdr, payload) = match self.proto.afi {
+                Afi::IPV4 if ip::has_ipv4_options(hdr) => {
+                    joined = [hdr, payload].concat();
+                    match ip::ipv4_header_len(&joined) {
+                        Some(n) => {
+                            ip_hlen = n;
+                            let split = joined.len().min(n + ICMP_HEADER_SIZE);
+                            (&joined[..split], &joined[split..])
+                        }
+                        None => (hdr, payload),
+                    }
+                }
+                _ => (hdr, payload),
+            };
+            if let Some(callback) = &self.debug_callback {
+                let mut buf = hdr.to_vec();
+                buf.extend_from_slice(payload);
+                Self::debug(callback, DEBUG_RX, &buf, addr, self.get_ts());
+            }
+            // Record packet in diagnostic mode
+            if self.diag_until.is_some() && size >= ip_hlen + 2 && self.diag.len() < MAX_DIAGNOSTIC
+            {
+                let icmp = &hdr[ip_hlen..];
+                self.diag
+                    .push((addr.to_string(), icmp[0], icmp[1], dgram_size));
+            }
+            self.counters.received += 1;
+            if truncated {
+                self.counters.truncated += 1;
+            }
+            if info.drops.is_some() {
+                self.counters.kernel_drops = info.drops;
+            }
+            // Drop too short packets
+            if size < ip_hlen + ICMP_SIZE {
+                self.counters.too_short += 1;
+                continue;
+            }
+            let icmp = &hdr[ip_hlen..];
+            if self.recv_errors && self.is_error(icmp[0]) {
+                errors.push(([icmp, payload].concat(), info, dgram_size, truncated));
+                continue;
+            }
+            // Parse packet
+            let pkt = match IcmpPacket::try_from(icmp) {
+                Ok(pkt) => pkt,
+                Err(_) => {
+                    self.counters.too_short += 1;
+                    continue;
+                }
+            };
+            let native = matches!(self.layout, Layout::Native);
+            // Reply to the handed over session
+            let signature = match self.handover {
+                Some((prev, _))
+                    if native
+                        && !pkt.is_match(self.icmp_reply_type, self.signature)
+                        && pkt.is_match(self.icmp_reply_type, prev) =>
+                {
+                    prev
+                }
+                _ => self.signature,
+            };
+            let is_match = if native {
+                pkt.is_match(self.icmp_reply_type, signature)
+            } else {
+                pkt.get_type() == self.icmp_reply_type
+            };
+            if !is_match {
+                if pkt.get_type() != self.icmp_reply_type {
+                    self.counters.wrong_type += 1;
+                } else {
+                    self.counters.signature_mismatch += 1;
+                }
+                continue;
+            }
+            // ICMPv6 checksum is verified by kernel.
+            // Checksum of truncated packet cannot be verified
+            if matches!(self.proto.afi, Afi::IPV4)
+                && self.verify_checksum
+                && !truncated
+                && Self::checksum(&[icmp, payload]) != [0, 0]
+            {
+                self.counters.checksum_error += 1;
+                continue;
+            }
+            // Verify padding integrity
+            if native && self.random_padding && !check_padding(payload) {
+                self.counters.padding_mismatch += 1;
+                continue;
+            }
+            let key = pkt.get_key(addr);
+            // Reply to the expired session
+            let late = if self.late.is_empty() || self.sessions.contains(&key) {
+                None
+            } else {
+                let ts = self.get_ts();
+                self.late.remove(&key).filter(|(until, _)| *until >= ts)
+            };
+            // Restore request's timestamp
+            let pkt_ts = match (self.layout, self.ts_mode) {
+                (Layout::Native, TsMode::Plain) => pkt.get_ts(),
+                (Layout::Native, TsMode::Obfuscated) => {
+                    mask_ts(pkt.get_ts(), signature, key.get_request_id(), key.get_seq())
+                }
+                // Nonce is not carried in mimic mode
+                _ => match self.nonces.get(&key).copied().or(late.and_then(|(_, n)| n)) {
+                    Some((nonce, ts)) if !native || nonce == pkt.get_ts() => ts,
+                    // Unknown or late reply
+                    _ => {
+                        self.counters.signature_mismatch += 1;
+                        continue;
+                    }
+                },
+            };
+            // Sequence numbers wrap around, so the reply may belong to
+            // the previous request with the same key. It is sent before
+            // the pending request, which is sent `timeout` before deadline.
+            // Nonces already tell requests apart
+            if native && self.ts_mode != TsMode::Nonce {
+                if let Some(deadline) = self.sessions.get_deadline(&key) {
+                    if deadline.saturating_sub(pkt_ts) > self.max_timeout.max(self.timeout) {
+                        self.counters.stale += 1;
+                        continue;
+                    }
+                }
+            }
+            self.nonces.remove(&key);
+            // Request left the host later
+            let pkt_ts = match self.tx_sent.remove(&key) {
+                Some((id, tx_ts)) => {
+                    Self::forget_tx_id(&mut self.tx_ids, id, &key);
+                    pkt_ts.max(tx_ts)
+                }
+                None => pkt_ts,
+            };
+            if late.is_some() {
+                self.counters.late += 1;
+                if !self.report_late {
+                    continue;
+                }
+            } else {
+                self.counters.matched += 1;
+            }
+            let size_mismatch = self.sizes.remove(&key).map(|sent| sent != dgram_size);
+            if size_mismatch == Some(true) {
+                self.counters.size_mismatch += 1;
+            }
+            // Measure RTT
+            let delay = self.get_ts().wrapping_sub(pkt_ts) as i64;
+            let delay = if delay > 0 {
+                delay
+            } else {
+                self.counters.invalid_rtt += 1;
+                match self.invalid_rtt {
+                    InvalidRtt::Clamp => 1, // Minimal delay
+                    InvalidRtt::Raw => delay,
+                    InvalidRtt::Error => {
+                        if late.is_none() {
+                            invalid.push((key, info, dgram_size, truncated));
+                        }
+                        continue;
+                    }
+                }
+            };
+            self.sessions.remove(&key);
+            // Timeout is already recorded for late replies
+            if late.is_none() {
+                Self::add_result(
+                    self.result_fd.map(|_| &mut self.result_buf),
+                    self.result_log.as_mut(),
+                    &mut self.counters,
+                    RECORD_REPLY,
+                    key,
+                    delay as u64,
+                );
+            }
+            r.push(Reply {
+                sid: key.get_sid(),
+                rtt: delay,
+                status: RECORD_REPLY,
+                tos: info.tos,
+                local_addr: info.local_addr.map(|addr| addr.to_string()),
+                ifindex: info.ifindex,
+                size: dgram_size,
+                size_mismatch,
+                icmp_type: None,
+                icmp_code: None,
+                reason: None,
+                truncated,
+                late: late.is_some(),
+                rx_ts: if self.budget.is_some() {
+                    Self::get_monotonic()
+                } else {
+                    0
+                },
+            });
+        }
+        for (buf, info, size, truncated) in errors {
+            match self.match_error(&buf) {
+                Some(err) => {
+                    self.counters.errors += 1;
+                    self.fail(
+                        err.get_key(),
+                        err.get_status(),
+                        Some(&err),
+                        info,
+                        size,
+                        truncated,
+                    );
+                }
+                None => self.counters.signature_mismatch += 1,
+            }
+        }
+        for (key, info, size, truncated) in invalid {
+            self.fail(key, RECORD_INVALID_RTT, None, info, size, truncated);
+        }
+        self.write_results();
+        if let Some(e) = fatal {
+            // Received replies are delivered by the next `recv` or `flush`
+            self.retained = r;
+            return Err(Self::os_error(e));
+        }
+        Ok(r)
+    }
+
+    /// Check if the receive may be continued after the error:
+    /// interrupted call, or pending error of the connected socket,
+    /// reported by ICMP once
+    fn is_soft_error(e: &std::io::Error) -> bool {
+        matches!(
+            e.raw_os_error(),
+            Some(
+                libc::EINTR
+                    | libc::ECONNREFUSED
+                    | libc::EHOSTUNREACH
+                    | libc::ENETUNREACH
+                    | libc::EHOSTDOWN
+                    | libc::EPROTO
+                    | libc::EMSGSIZE
+            )
+        )
+    }
+
+    /// Check if ICMP type is the error, quoting the request
+    fn is_error(&self, icmp_type: u8) -> bool {
+        match self.proto.afi {
+            Afi::IPV4 => IcmpError::is_v4_error(icmp_type),
+            Afi::IPV6 => IcmpError::is_v6_error(icmp_type),
+        }
+    }
+
+    /// Match ICMP error to the pending session.
+    /// Returns None for errors, related to the other sockets
+    fn match_error(&self, buf: &[u8]) -> Option<IcmpError> {
+        let err = match self.proto.afi {
+            Afi::IPV4 => IcmpError::parse_v4(buf),
+            Afi::IPV6 => IcmpError::parse_v6(buf),
+        }?;
+        if err.get_request_type() != self.icmp_request_type {
+            return None;
+        }
+        // Signature is not quoted by some routers
+        if let (Layout::Native, Some(sig)) = (self.layout, err.get_signature()) {
+            if sig != self.signature && !matches!(self.handover, Some((prev, _)) if prev == sig) {
+                return None;
+            }
+        }
+        if !self.sessions.contains(&err.get_key()) {
+            return None;
+        }
+        Some(err)
+    }
+
+    /// Build ConnectionError for the session, failed by ICMP error
+    fn icmp_error(py: Python, reason: &str, icmp_type: u8, icmp_code: u8) -> PyResult<PyErr> {
+        let err = PyConnectionError::new_err(reason.to_string());
+        let value = err.value(py);
+        value.setattr("icmp_type", icmp_type)?;
+        value.setattr("icmp_code", icmp_code)?;
+        Ok(err)
+    }
+
+    /// Fail pending session with the status. The session is delivered
+    /// by `recv_replies`, or reported by `get_expired`
+    fn fail(
+        &mut self,
+        key: SessionKey,
+        status: u8,
+        err: Option<&IcmpError>,
+        info: RecvInfo,
+        size: usize,
+        truncated: bool,
+    ) {
+        let session = match self.sessions.remove(&key) {
+            Some(session) => session,
+            None => return,
+        };
+        self.forget(&key);
+        self.failed.push(session);
+        Self::add_result(
+            self.result_fd.map(|_| &mut self.result_buf),
+            self.result_log.as_mut(),
+            &mut self.counters,
+            status,
+            key,
+            0,
+        );
+        self.errors.push(Reply {
+            sid: key.get_sid(),
+            rtt: 0,
+            status,
+            tos: info.tos,
+            local_addr: info.local_addr.map(|addr| addr.to_string()),
+            ifindex: info.ifindex,
+            size,
+            size_mismatch: None,
+            icmp_type: err.map(|e| e.get_type()),
+            icmp_code: err.map(|e| e.get_code()),
+            reason: err.map(|e| e.get_reason()),
+            truncated,
+            late: false,
+            rx_ts: if self.budget.is_some() {
+                Self::get_monotonic()
+            } else {
+                0
+            },
+        });
+    }
+
+    /// Apply pending transmit timestamps from the error queue
+    fn receive_tx_timestamps(&mut self) -> PyResult<()> {
+        let io = self.io.as_ref().ok_or_else(Self::closed)?;
+        let mut offset = None;
+        loop {
+            let (id, tx_ts) = match sockopt::recv_tx_timestamp(io) {
+                Ok(r) => r,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+                Err(_) => break,
+            };
+            // Timestamps are reported by CLOCK_REALTIME
+            let offset = *offset.get_or_insert_with(|| {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or(0);
+                now.wrapping_sub(self.get_ts())
+            });
+            if let Some(key) = self.tx_ids.get(&id) {
+                if let Some(sent) = self.tx_sent.get_mut(key) {
+                    // Both scheduled and sent are reported, keep the latest
+                    sent.1 = sent.1.max(tx_ts.wrapping_sub(offset));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// RFC-1071 checksum of the packet, split into parts
+    fn checksum(parts: &[&[u8]]) -> [u8; 2] {
+        let mut cs = Checksum::new();
+        for part in parts {
+            cs.add_bytes(part);
+        }
+        cs.checksum()
+    }
+
+    /// Account the delivery of the replies to Python
+    fn account_delivery(&mut self, replies: &[Reply]) {
+        if let Some(budget) = self.budget.as_mut() {
+            let ts = Self::get_monotonic();
+            for reply in replies {
+                budget.deliver.register(ts.saturating_sub(reply.rx_ts));
+            }
+        }
+    }
+
+    /// Receive replies, ready to be delivered by `recv`,
+    /// holding them back when batching is enabled
+    fn receive_ready(&mut self) -> PyResult<Vec<Reply>> {
+        let r = self.receive()?;
+        if self.batch_size == 0 {
+            return Ok(r);
+        }
+        let ts = self.get_ts();
+        if self.batched.is_empty() {
+            self.batch_since = ts;
+        }
+        self.batched.extend(r);
+        if self.batched.len() >= self.batch_size || ts >= self.batch_since + self.batch_delay {
+            return Ok(std::mem::take(&mut self.batched));
+        }
+        Ok(Vec::new())
+    }
+
+    /// Get UNIX time, in nanoseconds
+    fn get_time() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+
+    /// Append result record to the pending buffer of the stream
+    /// and to the result log, when enabled
+    fn add_result(
+        buf: Option<&mut Vec<u8>>,
+        log: Option<&mut ResultLog>,
+        counters: &mut Counters,
+        status: u8,
+        key: SessionKey,
+        rtt: u64,
+    ) {
+        if buf.is_none() && log.is_none() {
+            return;
+        }
+        let record = Record {
+            status,
+            key,
+            rtt,
+            ts: Self::get_time(),
+        };
+        if let Some(buf) = buf {
+            if buf.len() >= MAX_RESULT_BUFFER {
+                counters.result_drops += 1;
+            } else {
+                record.encode_into(buf);
+            }
+        }
+        if let Some(log) = log {
+            if !log.add(&record) {
+                counters.result_drops += 1;
+            }
+        }
+    }
+
+    /// Write pending result records, as much as the descriptor accepts,
+    /// and append them to the result log
+    fn write_results(&mut self) {
+        if let Some(log) = self.result_log.as_mut() {
+            if log.flush(Self::get_time()).is_err() {
+                self.counters.result_log_errors += 1;
+            }
+        }
+        let fd = match self.result_fd {
+            Some(fd) if !self.result_buf.is_empty() => fd,
+            _ => return,
+        };
+        match sockopt::write_fd(fd, &self.result_buf) {
+            Ok(n) => {
+                self.result_buf.drain(..n);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            // Collector is gone
+            Err(_) => self.result_buf.clear(),
+        }
+    }
+
+    /// Send single ICMP echo request and register the session.
+    /// Returns session key
+    #[allow(clippy::too_many_arguments)]
+    fn send_request(
+        &mut self,
+        addr: &str,
+        request_id: u16,
+        seq: u16,
+        size: usize,
+        timeout: u64,
+        src_addr: Option<&str>,
+        at: Option<u64>,
+    ) -> PyResult<SessionKey> {
+        let started = self.budget.as_ref().map(|_| Self::get_monotonic());
+        // Parse IP address
+        let to_addr = self.parse_addr(addr)?;
+        let src_addr = match src_addr {
+            Some(src_addr) => Some(Self::get_ip(&self.parse_addr(src_addr)?)),
+            None => None,
+        };
+        let ip_addr = Self::get_ip(&to_addr);
+        // Check in-flight limit
+        let overload = self.max_in_flight > 0 && self.sessions.len() >= self.max_in_flight;
+        if overload && matches!(self.overload_policy, OverloadPolicy::Reject) {
+            return Err(PyBlockingIOError::new_err("too many in-flight sessions"));
+        }
+        // Delay of the scheduled release
+        let delay = match (at, self.txtime) {
+            (None, _) => 0,
+            (Some(_), None) => return Err(PyValueError::new_err("txtime is not enabled")),
+            (Some(at), Some(clock)) => at.saturating_sub(
+                sockopt::clock_ns(clock).map_err(|e| PyOSError::new_err(e.to_string()))?,
+            ),
+        };
+        // Get timestamp of the packet's departure
+        let ts = self.get_ts() + delay;
+        let pkt_size = size - self.proto.ip_header_size;
+        let request_id = match self.request_id_policy {
+            RequestIdPolicy::Caller => request_id,
+            RequestIdPolicy::Random(request_id) => request_id,
+            RequestIdPolicy::Signature => {
+                let sig = self.signature;
+                (sig ^ (sig >> 16) ^ (sig >> 32) ^ (sig >> 48)) as u16
+            }
+        };
+        let pkt_ts = match (self.layout, self.ts_mode) {
+            (Layout::Native, TsMode::Plain) => ts,
+            (Layout::Native, TsMode::Obfuscated) => mask_ts(ts, self.signature, request_id, seq),
+            (Layout::Native, TsMode::Nonce) => rand::thread_rng().gen(),
+            (Layout::Linux, _) => {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or(0)
+                    + delay
+            }
+            (Layout::Windows, _) => 0,
+        };
+        let mut pkt = IcmpPacket::new(
+            self.icmp_request_type,
+            request_id,
+            seq,
+            self.signature,
+            pkt_ts,
+            pkt_size,
+        )
+        .with_code(self.icmp_code);
+        let random_tpl;
+        // Patch pre-encoded packet of the same size, when possible
+        if self.templates.len() >= MAX_TEMPLATES && !self.templates.contains_key(&pkt_size) {
+            self.templates.clear();
+        }
+        let io = self.io.as_ref().ok_or_else(Self::closed)?;
+        let buf = if !matches!(self.layout, Layout::Native) {
+            // Timestamp layout is not patchable
+            pkt = pkt.with_layout(self.layout);
+            random_tpl = PacketTemplate::new(&pkt);
+            random_tpl.as_slice()
+        } else if self.random_padding {
+            // Padding differs for every packet
+            pkt = pkt.with_seed(rand::thread_rng().gen());
+            random_tpl = PacketTemplate::new(&pkt);
+            random_tpl.as_slice()
         } else {
-            // CLOCK_MONOTONIC
-            self.start.elapsed().as_nanos() as u64
+            match self.templates.entry(pkt_size) {
+                Entry::Occupied(e) => e.into_mut().render(&pkt),
+                Entry::Vacant(e) => e.insert(PacketTemplate::new(&pkt)).as_slice(),
+            }
+        };
+        let encoded = started.map(|_| Self::get_monotonic());
+        match (self.target, src_addr, at) {
+            (Some(target), _, _) if target != ip_addr => {
+                return Err(PyValueError::new_err("socket is connected to other target"))
+            }
+            // Connected socket, route is already resolved
+            (Some(_), None, None) => io.send(buf),
+            (None, None, None) => io.send_to(buf, &to_addr),
+            // Ancillary data is required
+            (target, src_addr, at) => sockopt::send_msg(
+                io,
+                buf,
+                target.map_or(Some(&to_addr), |_| None),
+                src_addr,
+                at,
+            ),
+        }
+        .map_err(Self::os_error)?;
+        if let (Some(budget), Some(started), Some(encoded)) =
+            (self.budget.as_mut(), started, encoded)
+        {
+            budget.encode.register(encoded - started);
+            budget.send.register(Self::get_monotonic() - encoded);
+        }
+        if let Some(callback) = &self.debug_callback {
+            Self::debug(callback, DEBUG_TX, buf, ip_addr, ts);
+        }
+        if overload {
+            // Evict the oldest session
+            if let Some(oldest) = self.sessions.pop_first() {
+                self.evicted.push(oldest);
+            }
+        }
+        let key = pkt.get_key(ip_addr);
+        self.sessions.insert(Session::new(key, ts + timeout));
+        self.max_timeout = self.max_timeout.max(timeout);
+        if matches!(self.ts_mode, TsMode::Nonce) || !matches!(self.layout, Layout::Native) {
+            self.nonces.insert(key, (pkt_ts, ts));
+        }
+        if self.strict_size {
+            self.sizes.insert(key, size);
+        }
+        if self.tx_timestamps {
+            self.tx_ids.insert(self.tx_id, key);
+            self.tx_sent.insert(key, (self.tx_id, 0));
+            self.tx_id = self.tx_id.wrapping_add(1);
+        }
+        Ok(key)
+    }
+
+    /// Drop session's per-request state
+    fn forget(&mut self, key: &SessionKey) {
+        self.nonces.remove(key);
+        self.sizes.remove(key);
+        if let Some((id, _)) = self.tx_sent.remove(key) {
+            Self::forget_tx_id(&mut self.tx_ids, id, key);
+        }
+    }
+
+    /// Forget the session's packet id. Id of the imported session
+    /// may be in use by the socket's own session
+    fn forget_tx_id(tx_ids: &mut HashMap<u32, SessionKey>, id: u32, key: &SessionKey) {
+        if tx_ids.get(&id) == Some(key) {
+            tx_ids.remove(&id);
+        }
+    }
+
+    /// Parse IP address of the socket's address family
+    fn parse_addr(&self, addr: &str) -> PyResult<SockAddr> {
+        Ok(match self.proto.afi {
+            Afi::IPV4 => SocketAddrV4::new(addr.parse()?, 0).into(),
+            Afi::IPV6 => SocketAddrV6::new(addr.parse()?, 0, 0, 0).into(),
+        })
+    }
+
+    /// Get IP address of the socket address
+    fn get_ip(addr: &SockAddr) -> IpAddr {
+        addr.as_socket().unwrap().ip()
+    }
+
+    /// Initialize wrapper with opened socket
+    fn with_socket(proto: &'static Proto, io: Socket, request_id_policy: u8) -> PyResult<Self> {
+        let mut rng = rand::thread_rng();
+        let request_id_policy = match request_id_policy {
+            REQUEST_ID_CALLER => RequestIdPolicy::Caller,
+            REQUEST_ID_RANDOM => RequestIdPolicy::Random(rng.gen()),
+            REQUEST_ID_SIGNATURE => RequestIdPolicy::Signature,
+            _ => {
+                return Err(PyValueError::new_err(
+                    "invalid request id policy".to_string(),
+                ))
+            }
+        };
+        // Mark socket as non-blocking
+        io.set_nonblocking(true)
+            .map_err(|e| PyOSError::new_err(e.to_string()))?;
+        Ok(Self {
+            proto,
+            icmp_request_type: proto.icmp_request_type,
+            icmp_reply_type: proto.icmp_reply_type,
+            icmp_code: 0,
+            verify_checksum: true,
+            io: Some(io),
+            signature: rng.gen(),
+            handover: None,
+            request_id_policy,
+            ts_mode: TsMode::Plain,
+            rtt_unit: RttUnit::Ns,
+            invalid_rtt: InvalidRtt::Clamp,
+            nonces: HashMap::new(),
+            random_padding: false,
+            layout: Layout::Native,
+            txtime: None,
+            tx_timestamps: false,
+            jitter: Jitter::None,
+            tx_id: 0,
+            tx_ids: HashMap::new(),
+            tx_sent: HashMap::new(),
+            strict_size: false,
+            sizes: HashMap::new(),
+            late_grace: 0,
+            report_late: false,
+            late: HashMap::new(),
+            sessions: SessionTable::new(),
+            max_in_flight: 0,
+            overload_policy: OverloadPolicy::Reject,
+            evicted: Vec::new(),
+            retained: Vec::new(),
+            recv_errors: false,
+            failed: Vec::new(),
+            errors: Vec::new(),
+            batch_size: 0,
+            batch_delay: 0,
+            batched: Vec::new(),
+            batch_since: 0,
+            recv_tos: false,
+            recv_pktinfo: false,
+            rxq_ovfl: false,
+            diag_until: None,
+            diag: Vec::new(),
+            debug_callback: None,
+            counters: Counters::default(),
+            budget: None,
+            target: None,
+            result_fd: None,
+            result_buf: Vec::new(),
+            result_log: None,
+            timeout: 1_000_000_000,
+            max_timeout: 0,
+            timer: Timer::default(),
+            ts_offset: 0,
+            accelerated: false,
+            hdr_buf: unsafe { MaybeUninit::uninit().assume_init() },
+            buf: unsafe { MaybeUninit::uninit().assume_init() },
+            templates: HashMap::new(),
+        })
+    }
+
+    /// Get system-wide CLOCK_MONOTONIC, in nanoseconds
+    fn get_monotonic() -> u64 {
+        let mut tp = MaybeUninit::<libc::timespec>::uninit();
+        let tp = unsafe {
+            libc::clock_gettime(libc::CLOCK_MONOTONIC, tp.as_mut_ptr());
+            tp.assume_init()
+        };
+        tp.tv_sec as u64 * 1_000_000_000 + tp.tv_nsec as u64
+    }
+
+    /// Get underlying socket.
+    /// Fails when socket is closed.
+    fn get_io(&self) -> std::io::Result<&Socket> {
+        self.io.as_ref().ok_or_else(Self::closed)
+    }
+
+    /// Convert I/O error to OSError, preserving errno
+    fn os_error(e: std::io::Error) -> PyErr {
+        match e.raw_os_error() {
+            Some(errno) => PyOSError::new_err((errno, e.to_string())),
+            None => PyOSError::new_err(e.to_string()),
+        }
+    }
+
+    /// Error for operations on closed socket
+    fn closed() -> std::io::Error {
+        std::io::Error::other("I/O operation on closed socket")
+    }
+
+    /// Wait until socket became readable, releasing GIL.
+    /// Returns true if socket is readable,
+    /// false on timeout or interrupted system call.
+    fn wait_readable(&self, py: Python, timeout: u64) -> std::io::Result<bool> {
+        let mut pfd = libc::pollfd {
+            fd: self.get_io()?.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // Round up to milliseconds
+        let timeout_ms =
+            libc::c_int::try_from(timeout.div_ceil(1_000_000)).unwrap_or(libc::c_int::MAX);
+        let r = py.allow_threads(|| unsafe { libc::poll(&mut pfd, 1, timeout_ms) });
+        if r < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(e);
+        }
+        Ok(r > 0)
+    }
+
+    /// Get outgoing packets' ToS/traffic class
+    fn get_tos(&self) -> std::io::Result<u32> {
+        match self.proto.afi {
+            Afi::IPV4 => self.get_io()?.tos(),
+            Afi::IPV6 => self.get_tclass(),
+        }
+    }
+
+    /// Get IPv6 traffic class
+    fn get_tclass(&self) -> std::io::Result<u32> {
+        Ok(sockopt::get_int(self.get_io()?, libc::IPPROTO_IPV6, libc::IPV6_TCLASS)? as u32)
+    }
+
+    /// Set IPv6 traffic class
+    fn set_tclass(&self, tclass: u32) -> std::io::Result<()> {
+        let tclass = libc::c_int::try_from(tclass).map_err(|_| std::io::ErrorKind::InvalidInput)?;
+        sockopt::set_int(
+            self.get_io()?,
+            libc::IPPROTO_IPV6,
+            libc::IPV6_TCLASS,
+            tclass,
+        )
+    }
+
+    /// Build dict of effective socket options
+    fn options_dict<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let r = PyDict::new(py);
+        match self.proto.afi {
+            Afi::IPV4 => r.set_item("ttl", self.get_io()?.ttl()?)?,
+            Afi::IPV6 => r.set_item("ttl", self.get_io()?.unicast_hops_v6()?)?,
+        }
+        let tos = self.get_tos()?;
+        r.set_item("tos", tos)?;
+        r.set_item("ecn", tos & ECN_MASK)?;
+        r.set_item("send_buffer_size", self.get_io()?.send_buffer_size()?)?;
+        r.set_item("recv_buffer_size", self.get_io()?.recv_buffer_size()?)?;
+        r.set_item(
+            "local_addr",
+            self.get_io()?
+                .local_addr()?
+                .as_socket()
+                .map(|addr| addr.ip().to_string()),
+        )?;
+        r.set_item("device", self.get_device()?)?;
+        r.set_item("freebind", self.get_freebind()?)?;
+        r.set_item("transparent", self.get_transparent()?)?;
+        r.set_item("busy_poll", self.get_busy_poll()?)?;
+        r.set_item("incoming_cpu", self.get_incoming_cpu()?)?;
+        r.set_item("napi_id", self.get_napi_id()?)?;
+        r.set_item("timeout", self.timeout)?;
+        r.set_item("coarse", self.timer.is_coarse())?;
+        r.set_item("accelerated", self.accelerated)?;
+        r.set_item("recv_tos", self.recv_tos)?;
+        r.set_item("recv_pktinfo", self.recv_pktinfo)?;
+        r.set_item("recv_errors", self.recv_errors)?;
+        r.set_item(
+            "invalid_rtt",
+            match self.invalid_rtt {
+                InvalidRtt::Clamp => INVALID_RTT_CLAMP,
+                InvalidRtt::Error => INVALID_RTT_ERROR,
+                InvalidRtt::Raw => INVALID_RTT_RAW,
+            },
+        )?;
+        r.set_item("strict_size", self.strict_size)?;
+        r.set_item("txtime", self.txtime)?;
+        r.set_item("tx_timestamps", self.tx_timestamps)?;
+        r.set_item(
+            "jitter",
+            match self.jitter {
+                Jitter::None => JITTER_NONE,
+                Jitter::Uniform(_) => JITTER_UNIFORM,
+                Jitter::Exponential(_) => JITTER_EXPONENTIAL,
+            },
+        )?;
+        r.set_item("jitter_percent", self.jitter.get_percent())?;
+        r.set_item(
+            "rtt_unit",
+            match self.rtt_unit {
+                RttUnit::Ns => RTT_NS,
+                RttUnit::Us => RTT_US,
+                RttUnit::Ms => RTT_MS,
+                RttUnit::S => RTT_S,
+            },
+        )?;
+        r.set_item("rxq_ovfl", self.rxq_ovfl)?;
+        r.set_item(
+            "padding",
+            if self.random_padding {
+                PADDING_RANDOM
+            } else {
+                PADDING_FIXED
+            },
+        )?;
+        r.set_item(
+            "ts_mode",
+            match self.ts_mode {
+                TsMode::Plain => TS_PLAIN,
+                TsMode::Obfuscated => TS_OBFUSCATED,
+                TsMode::Nonce => TS_NONCE,
+            },
+        )?;
+        r.set_item(
+            "mimic",
+            match self.layout {
+                Layout::Native => MIMIC_NONE,
+                Layout::Linux => MIMIC_LINUX,
+                Layout::Windows => MIMIC_WINDOWS,
+            },
+        )?;
+        r.set_item("icmp_request_type", self.icmp_request_type)?;
+        r.set_item("icmp_reply_type", self.icmp_reply_type)?;
+        r.set_item("icmp_code", self.icmp_code)?;
+        r.set_item("verify_checksum", self.verify_checksum)?;
+        Ok(r)
+    }
+
+    /// Apply single option by name
+    fn set_option(&mut self, name: &str, value: &PyAny) -> PyResult<()> {
+        match name {
+            "timeout" => self.set_timeout(value.extract()?),
+            "ttl" => self.set_ttl(value.extract()?),
+            "tos" => self.set_tos(value.extract()?),
+            "ecn" => self.set_ecn(value.extract()?),
+            "busy_poll" => self.set_busy_poll(value.extract()?),
+            "incoming_cpu" => self.set_incoming_cpu(value.extract()?),
+            "coarse" => self.set_coarse(value.extract()?),
+            "accelerated" => self.set_accelerated(value.extract()?),
+            "recv_tos" => self.set_recv_tos(value.extract()?),
+            "recv_pktinfo" => self.set_recv_pktinfo(value.extract()?),
+            _ => Err(PyValueError::new_err(format!("invalid option: {}", name))),
+        }
+    }
+
+    /// Set send buffer size, ignoring wmem_max.
+    /// Requires CAP_NET_ADMIN
+    #[cfg(target_os = "linux")]
+    fn force_send_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        sockopt::set_int(
+            self.get_io()?,
+            libc::SOL_SOCKET,
+            libc::SO_SNDBUFFORCE,
+            Self::buffer_size_opt(size)?,
+        )
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn force_send_buffer_size(&self, _size: usize) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// Set receive buffer size, ignoring rmem_max.
+    /// Requires CAP_NET_ADMIN
+    #[cfg(target_os = "linux")]
+    fn force_recv_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        sockopt::set_int(
+            self.get_io()?,
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUFFORCE,
+            Self::buffer_size_opt(size)?,
+        )
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn force_recv_buffer_size(&self, _size: usize) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// Convert buffer size to socket option value
+    #[cfg(target_os = "linux")]
+    fn buffer_size_opt(size: usize) -> std::io::Result<libc::c_int> {
+        libc::c_int::try_from(size).map_err(|_| std::io::ErrorKind::InvalidInput.into())
+    }
+
+    /// Set SO_BUSY_POLL
+    #[cfg(target_os = "linux")]
+    fn apply_busy_poll(&self, usecs: u32) -> std::io::Result<()> {
+        let usecs = libc::c_int::try_from(usecs).map_err(|_| std::io::ErrorKind::InvalidInput)?;
+        sockopt::set_int(self.get_io()?, libc::SOL_SOCKET, libc::SO_BUSY_POLL, usecs)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply_busy_poll(&self, _usecs: u32) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Get SO_BUSY_POLL
+    #[cfg(target_os = "linux")]
+    fn get_busy_poll(&self) -> std::io::Result<Option<u32>> {
+        Ok(Some(
+            sockopt::get_int(self.get_io()?, libc::SOL_SOCKET, libc::SO_BUSY_POLL)? as u32,
+        ))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_busy_poll(&self) -> std::io::Result<Option<u32>> {
+        Ok(None)
+    }
+
+    /// Set SO_INCOMING_CPU
+    #[cfg(target_os = "linux")]
+    fn apply_incoming_cpu(&self, cpu: usize) -> std::io::Result<()> {
+        self.get_io()?.set_cpu_affinity(cpu)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply_incoming_cpu(&self, _cpu: usize) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Get SO_INCOMING_CPU
+    #[cfg(target_os = "linux")]
+    fn get_incoming_cpu(&self) -> std::io::Result<Option<usize>> {
+        Ok(Some(self.get_io()?.cpu_affinity()?))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_incoming_cpu(&self) -> std::io::Result<Option<usize>> {
+        Ok(None)
+    }
+
+    /// Get SO_INCOMING_NAPI_ID, None until the first packet
+    /// is received over the NAPI-capable device
+    #[cfg(target_os = "linux")]
+    fn get_napi_id(&self) -> std::io::Result<Option<u32>> {
+        let id = sockopt::get_int(
+            self.get_io()?,
+            libc::SOL_SOCKET,
+            sockopt::SO_INCOMING_NAPI_ID,
+        )?;
+        Ok(if id > 0 { Some(id as u32) } else { None })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_napi_id(&self) -> std::io::Result<Option<u32>> {
+        Ok(None)
+    }
+
+    /// Set SO_RXQ_OVFL
+    #[cfg(target_os = "linux")]
+    fn apply_rxq_ovfl(&self, v: bool) -> std::io::Result<()> {
+        sockopt::set_int(
+            self.get_io()?,
+            libc::SOL_SOCKET,
+            libc::SO_RXQ_OVFL,
+            v as libc::c_int,
+        )
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply_rxq_ovfl(&self, _v: bool) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// Get level and name of IP_FREEBIND/IPV6_FREEBIND
+    #[cfg(target_os = "linux")]
+    fn freebind_opt(&self) -> (libc::c_int, libc::c_int) {
+        match self.proto.afi {
+            Afi::IPV4 => (libc::IPPROTO_IP, libc::IP_FREEBIND),
+            Afi::IPV6 => (libc::IPPROTO_IPV6, libc::IPV6_FREEBIND),
         }
     }
 
+    /// Get level and name of IP_TRANSPARENT/IPV6_TRANSPARENT
+    #[cfg(target_os = "linux")]
+    fn transparent_opt(&self) -> (libc::c_int, libc::c_int) {
+        match self.proto.afi {
+            Afi::IPV4 => (libc::IPPROTO_IP, libc::IP_TRANSPARENT),
+            Afi::IPV6 => (libc::IPPROTO_IPV6, libc::IPV6_TRANSPARENT),
+        }
+    }
+
+    /// Set IP_FREEBIND or IP_TRANSPARENT
+    #[cfg(target_os = "linux")]
+    fn apply_bool_opt(&self, opt: (libc::c_int, libc::c_int), v: bool) -> std::io::Result<()> {
+        sockopt::set_int(self.get_io()?, opt.0, opt.1, v as libc::c_int)
+    }
+
+    /// Get IP_FREEBIND or IP_TRANSPARENT
+    #[cfg(target_os = "linux")]
+    fn get_bool_opt(&self, opt: (libc::c_int, libc::c_int)) -> std::io::Result<Option<bool>> {
+        Ok(Some(sockopt::get_int(self.get_io()?, opt.0, opt.1)? != 0))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn apply_freebind(&self, v: bool) -> std::io::Result<()> {
+        self.apply_bool_opt(self.freebind_opt(), v)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply_freebind(&self, _v: bool) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_freebind(&self) -> std::io::Result<Option<bool>> {
+        self.get_bool_opt(self.freebind_opt())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_freebind(&self) -> std::io::Result<Option<bool>> {
+        Ok(None)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn apply_transparent(&self, v: bool) -> std::io::Result<()> {
+        self.apply_bool_opt(self.transparent_opt(), v)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply_transparent(&self, _v: bool) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_transparent(&self) -> std::io::Result<Option<bool>> {
+        self.get_bool_opt(self.transparent_opt())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_transparent(&self) -> std::io::Result<Option<bool>> {
+        Ok(None)
+    }
+
+    /// Get name of the device socket is bound to
+    #[cfg(target_os = "linux")]
+    fn get_device(&self) -> std::io::Result<Option<String>> {
+        Ok(self
+            .get_io()?
+            .device()?
+            .map(|d| String::from_utf8_lossy(&d).into_owned()))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_device(&self) -> std::io::Result<Option<String>> {
+        Ok(None)
+    }
+
     /// Attach cBPF filter to socket to reduce context switches
     #[cfg(target_os = "linux")]
     fn enable_accelerated(&self) -> std::io::Result<()> {
@@ -289,35 +2850,77 @@ impl SocketWrapper {
 
         use libc::sock_filter;
 
-        match self.proto.afi {
-            AFI::IPV4 => {
-                let filters = [
-                    op(0x30, 0, 0, 0x00000014),                           // ldb [20]
-                    op(0x15, 0, 5, self.proto.icmp_reply_type as u32),    // jne #0x0, drop
-                    op(0x20, 0, 0, 0x0000001c),                           // ld [28]
-                    op(0x15, 0, 3, (self.signature >> 32) as u32),        // jne #sig1, drop
-                    op(0x20, 0, 0, 0x00000020),                           // ld [32]
-                    op(0x15, 0, 1, (self.signature & 0xFFFFFFFF) as u32), // jne #sig2, drop
-                    op(0x06, 0, 0, 0xffffffff),                           // ret #-1
-                    op(0x06, 0, 0, 0000000000),                           // drop: ret #0
-                ];
-                self.io.attach_filter(&filters)?;
-            }
-            AFI::IPV6 => {
-                let filters = [
-                    op(0x30, 0, 0, 0x00000000),                           // ldb [0]
-                    op(0x15, 0, 5, self.proto.icmp_reply_type as u32),    // jne #0x81, drop
-                    op(0x20, 0, 0, 0x00000008),                           // ld [8]
-                    op(0x15, 0, 3, (self.signature >> 32) as u32),        // jne #sig1, drop
-                    op(0x20, 0, 0, 0x0000000c),                           // ld [12]
-                    op(0x15, 0, 1, (self.signature & 0xFFFFFFFF) as u32), // jne #sig2, drop
-                    op(0x06, 0, 0, 0xffffffff),                           // ret #-1
-                    op(0x06, 0, 0, 0000000000),                           // drop: ret #0
-                ];
-
-                self.io.attach_filter(&filters)?;
+        // ICMP errors are accepted by type, quoted requests
+        // are matched by `receive`
+        let error_types: &[u8] = match self.proto.afi {
+            _ if !self.recv_errors => &[],
+            Afi::IPV4 => &[ICMPV4_UNREACHABLE, ICMPV4_TIME_EXCEEDED],
+            Afi::IPV6 => &[ICMPV6_UNREACHABLE, ICMPV6_TIME_EXCEEDED],
+        };
+        // Jump over the rest of error types and `tail` instructions to accept
+        let accept_errors = |filters: &mut Vec<sock_filter>, tail: u8| {
+            for (i, t) in error_types.iter().enumerate() {
+                let left = (error_types.len() - 1 - i) as u8;
+                filters.push(op(0x15, left + tail, 0, *t as u32)); // jeq #error, accept
             }
+        };
+        // No signature in mimic mode, filter by type only.
+        // IPv4 header length varies with options
+        if !matches!(self.layout, Layout::Native) {
+            let mut filters = match self.proto.afi {
+                Afi::IPV4 => vec![
+                    op(0xb1, 0, 0, 0x00000000), // ldx 4*([0]&0xf)
+                    op(0x50, 0, 0, 0x00000000), // ldb [x+0]
+                ],
+                Afi::IPV6 => vec![
+                    op(0x30, 0, 0, 0x00000000), // ldb [0]
+                ],
+            };
+            accept_errors(&mut filters, 1);
+            filters.extend([
+                op(0x15, 0, 1, self.icmp_reply_type as u32), // jne #reply, drop
+                op(0x06, 0, 0, 0xffffffff),                  // ret #-1
+                op(0x06, 0, 0, 0000000000),                  // drop: ret #0
+            ]);
+            self.get_io()?.attach_filter(&filters)?;
+            return Ok(());
+        }
+        // Match own signature and the handed over one, if any
+        let signatures: Vec<u64> = std::iter::once(self.signature)
+            .chain(self.handover.map(|(prev, _)| prev))
+            .collect();
+        let n = signatures.len() as u8;
+        let (mut filters, ld) = match self.proto.afi {
+            Afi::IPV4 => (
+                vec![
+                    op(0xb1, 0, 0, 0x00000000), // ldx 4*([0]&0xf)
+                    op(0x50, 0, 0, 0x00000000), // ldb [x+0]
+                ],
+                0x40, // ld [x+k]
+            ),
+            Afi::IPV6 => (
+                vec![
+                    op(0x30, 0, 0, 0x00000000), // ldb [0]
+                ],
+                0x20, // ld [k]
+            ),
+        };
+        accept_errors(&mut filters, 4 * n + 1);
+        filters.push(op(0x15, 0, 4 * n + 1, self.icmp_reply_type as u32)); // jne #reply, drop
+        for (i, sig) in signatures.iter().enumerate() {
+            // Signatures left to check, jump to the next one on mismatch
+            let left = n - 1 - i as u8;
+            let last = (left == 0) as u8;
+            filters.extend([
+                op(ld, 0, 0, 0x00000008),                            // ld [8]
+                op(0x15, 0, 2 + last, (sig >> 32) as u32),           // jne #sig1, next
+                op(ld, 0, 0, 0x0000000c),                            // ld [12]
+                op(0x15, 4 * left, last, (sig & 0xFFFFFFFF) as u32), // jeq #sig2, accept
+            ]);
         }
+        filters.push(op(0x06, 0, 0, 0xffffffff)); // ret #-1
+        filters.push(op(0x06, 0, 0, 0000000000)); // drop: ret #0
+        self.get_io()?.attach_filter(&filters)?;
         Ok(())
     }
 
@@ -329,7 +2932,7 @@ impl SocketWrapper {
     /// Remove BPF filter from socket
     #[cfg(target_os = "linux")]
     fn disable_accelerated(&self) -> std::io::Result<()> {
-        self.io.detach_filter()?;
+        self.get_io()?.detach_filter()?;
         Ok(())
     }
 
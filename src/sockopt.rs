@@ -0,0 +1,374 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Socket options and operations, not covered by socket2
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use socket2::{MaybeUninitSlice, SockAddr, Socket};
+use std::io::{Error, Result};
+use std::mem::{size_of, size_of_val, MaybeUninit};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Get integer socket option
+pub(crate) fn get_int(io: &Socket, level: libc::c_int, name: libc::c_int) -> Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+    let r = unsafe {
+        libc::getsockopt(
+            io.as_raw_fd(),
+            level,
+            name,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if r < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(value)
+}
+
+/// Set integer socket option
+pub(crate) fn set_int(
+    io: &Socket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> Result<()> {
+    let r = unsafe {
+        libc::setsockopt(
+            io.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if r < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Receive flags. Linux reports the original size of the datagram
+/// with MSG_TRUNC, even if it does not fit the buffer
+#[cfg(target_os = "linux")]
+pub(crate) const RECV_FLAGS: libc::c_int = libc::MSG_TRUNC;
+#[cfg(not(target_os = "linux"))]
+pub(crate) const RECV_FLAGS: libc::c_int = 0;
+
+/// Option to receive DSCP/ECN field of IPv4 datagrams.
+/// Not available on illumos and Solaris
+#[cfg(not(any(target_os = "illumos", target_os = "solaris")))]
+pub(crate) const IP_RECVTOS: Option<libc::c_int> = Some(libc::IP_RECVTOS);
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+pub(crate) const IP_RECVTOS: Option<libc::c_int> = None;
+
+/// SO_INCOMING_NAPI_ID, not exposed by libc
+#[cfg(all(target_os = "linux", not(target_arch = "sparc64")))]
+pub(crate) const SO_INCOMING_NAPI_ID: libc::c_int = 56;
+#[cfg(all(target_os = "linux", target_arch = "sparc64"))]
+pub(crate) const SO_INCOMING_NAPI_ID: libc::c_int = 0x3a;
+
+/// Datagram details, passed as ancillary data
+#[derive(Default)]
+pub(crate) struct RecvInfo {
+    /// Source address
+    pub(crate) src: Option<IpAddr>,
+    /// DSCP/ECN field, by IP_RECVTOS/IPV6_RECVTCLASS
+    pub(crate) tos: Option<u8>,
+    /// Local destination address, by IP_PKTINFO/IPV6_RECVPKTINFO
+    pub(crate) local_addr: Option<IpAddr>,
+    /// Incoming interface index, by IP_PKTINFO/IPV6_RECVPKTINFO
+    pub(crate) ifindex: Option<u32>,
+    /// Socket drops counter, by SO_RXQ_OVFL
+    pub(crate) drops: Option<u32>,
+}
+
+/// Receive datagram into the buffers along with the ancillary data.
+/// Returns (size, details), where size is the original
+/// size of the datagram, which may exceed buffers' size
+pub(crate) fn recv_msg(
+    io: &Socket,
+    bufs: &mut [MaybeUninitSlice<'_>],
+) -> Result<(usize, RecvInfo)> {
+    let mut addr = MaybeUninit::<libc::sockaddr_storage>::zeroed();
+    // Aligned control buffer
+    let mut control = [0u64; 16];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = addr.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    // MaybeUninitSlice is ABI-compatible with iovec
+    msg.msg_iov = bufs.as_mut_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = bufs.len() as _;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = size_of_val(&control) as _;
+    let n = unsafe { libc::recvmsg(io.as_raw_fd(), &mut msg, RECV_FLAGS) };
+    if n < 0 {
+        return Err(Error::last_os_error());
+    }
+    let mut info = RecvInfo::default();
+    // Parse ancillary data
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let data = libc::CMSG_DATA(cmsg);
+            match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                #[cfg(target_os = "linux")]
+                (libc::SOL_SOCKET, libc::SO_RXQ_OVFL) => {
+                    info.drops = Some((data as *const u32).read_unaligned())
+                }
+                (libc::IPPROTO_IP, libc::IP_TOS) => info.tos = Some(*data),
+                (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => {
+                    info.tos = Some((data as *const libc::c_int).read_unaligned() as u8)
+                }
+                (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                    let pi = (data as *const libc::in_pktinfo).read_unaligned();
+                    info.local_addr =
+                        Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(pi.ipi_addr.s_addr))));
+                    info.ifindex = Some(pi.ipi_ifindex as u32);
+                }
+                (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                    let pi = (data as *const libc::in6_pktinfo).read_unaligned();
+                    info.local_addr = Some(IpAddr::V6(Ipv6Addr::from(pi.ipi6_addr.s6_addr)));
+                    info.ifindex = Some(pi.ipi6_ifindex);
+                }
+                _ => {}
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    // Parse source address
+    let addr = unsafe { addr.assume_init() };
+    info.src = match addr.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let sin = unsafe { &*(&addr as *const _ as *const libc::sockaddr_in) };
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                sin.sin_addr.s_addr,
+            ))))
+        }
+        libc::AF_INET6 => {
+            let sin6 = unsafe { &*(&addr as *const _ as *const libc::sockaddr_in6) };
+            Some(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)))
+        }
+        _ => None,
+    };
+    Ok((n as usize, info))
+}
+
+/// Send datagram with ancillary data:
+/// * `src` - local address, passed as IP_PKTINFO/IPV6_PKTINFO.
+/// * `txtime` - release time in nanoseconds, passed as SCM_TXTIME
+///   (Linux). Requires `set_txtime`.
+pub(crate) fn send_msg(
+    io: &Socket,
+    buf: &[u8],
+    to: Option<&SockAddr>,
+    src: Option<IpAddr>,
+    txtime: Option<u64>,
+) -> Result<usize> {
+    // Aligned control buffer
+    let mut control = [0u64; 16];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    if let Some(to) = to {
+        msg.msg_name = to.as_ptr() as *mut libc::c_void;
+        msg.msg_namelen = to.len();
+    }
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    unsafe {
+        match src {
+            Some(IpAddr::V4(src)) => {
+                let info = libc::in_pktinfo {
+                    ipi_ifindex: 0,
+                    ipi_spec_dst: libc::in_addr {
+                        s_addr: u32::from(src).to_be(),
+                    },
+                    ipi_addr: libc::in_addr { s_addr: 0 },
+                };
+                put_cmsg(&mut msg, libc::IPPROTO_IP, libc::IP_PKTINFO, info);
+            }
+            Some(IpAddr::V6(src)) => {
+                let info = libc::in6_pktinfo {
+                    ipi6_addr: libc::in6_addr {
+                        s6_addr: src.octets(),
+                    },
+                    ipi6_ifindex: 0,
+                };
+                put_cmsg(&mut msg, libc::IPPROTO_IPV6, libc::IPV6_PKTINFO, info);
+            }
+            None => {}
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(txtime) = txtime {
+            put_cmsg(&mut msg, libc::SOL_SOCKET, libc::SCM_TXTIME, txtime);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    if txtime.is_some() {
+        return Err(Error::from(std::io::ErrorKind::Unsupported));
+    }
+    let n = unsafe { libc::sendmsg(io.as_raw_fd(), &msg, 0) };
+    if n < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// Switch file descriptor to non-blocking mode
+pub(crate) fn set_nonblocking(fd: RawFd) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(Error::last_os_error());
+    }
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Write buffer to the file descriptor.
+/// Returns number of written bytes.
+pub(crate) fn write_fd(fd: RawFd, buf: &[u8]) -> Result<usize> {
+    let n = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+    if n < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// Append control message to the message's control buffer.
+/// Control buffer must be large enough to hold all the messages.
+unsafe fn put_cmsg<T>(msg: &mut libc::msghdr, level: libc::c_int, name: libc::c_int, data: T) {
+    let offset: usize = msg.msg_controllen as _;
+    msg.msg_controllen = (offset + libc::CMSG_SPACE(size_of::<T>() as u32) as usize) as _;
+    let cmsg = (msg.msg_control as *mut u8).add(offset) as *mut libc::cmsghdr;
+    (*cmsg).cmsg_level = level;
+    (*cmsg).cmsg_type = name;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<T>() as u32) as _;
+    (libc::CMSG_DATA(cmsg) as *mut T).write_unaligned(data);
+}
+
+/// Release outgoing packets at the time, passed along
+/// with `send_msg`, according to the clock (Linux)
+#[cfg(target_os = "linux")]
+pub(crate) fn set_txtime(io: &Socket, clock: libc::clockid_t) -> Result<()> {
+    let txtime = libc::sock_txtime {
+        clockid: clock,
+        flags: 0,
+    };
+    let r = unsafe {
+        libc::setsockopt(
+            io.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TXTIME,
+            &txtime as *const libc::sock_txtime as *const libc::c_void,
+            size_of::<libc::sock_txtime>() as libc::socklen_t,
+        )
+    };
+    if r < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_txtime(_io: &Socket, _clock: libc::clockid_t) -> Result<()> {
+    Err(Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// Get current time of the clock, in nanoseconds
+pub(crate) fn clock_ns(clock: libc::clockid_t) -> Result<u64> {
+    let mut tp = MaybeUninit::<libc::timespec>::uninit();
+    if unsafe { libc::clock_gettime(clock, tp.as_mut_ptr()) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    let tp = unsafe { tp.assume_init() };
+    Ok(tp.tv_sec as u64 * 1_000_000_000 + tp.tv_nsec as u64)
+}
+
+/// Report software transmit timestamps of the outgoing packets
+/// to the error queue (Linux)
+#[cfg(target_os = "linux")]
+pub(crate) fn set_tx_timestamps(io: &Socket, enabled: bool) -> Result<()> {
+    let flags = if enabled {
+        libc::SOF_TIMESTAMPING_TX_SCHED
+            | libc::SOF_TIMESTAMPING_TX_SOFTWARE
+            | libc::SOF_TIMESTAMPING_SOFTWARE
+            | libc::SOF_TIMESTAMPING_OPT_ID
+            | libc::SOF_TIMESTAMPING_OPT_TSONLY
+    } else {
+        0
+    };
+    set_int(
+        io,
+        libc::SOL_SOCKET,
+        libc::SO_TIMESTAMPING,
+        flags as libc::c_int,
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_tx_timestamps(_io: &Socket, _enabled: bool) -> Result<()> {
+    Err(Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// Receive single transmit timestamp from the error queue.
+/// Returns (packet id, UNIX time in nanoseconds),
+/// where packet id is the counter of sent packets
+#[cfg(target_os = "linux")]
+pub(crate) fn recv_tx_timestamp(io: &Socket) -> Result<(u32, u64)> {
+    // Aligned control buffer
+    let mut control = [0u64; 32];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = size_of_val(&control) as _;
+    let n = unsafe {
+        libc::recvmsg(
+            io.as_raw_fd(),
+            &mut msg,
+            libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+        )
+    };
+    if n < 0 {
+        return Err(Error::last_os_error());
+    }
+    let mut id = None;
+    let mut ts = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let data = libc::CMSG_DATA(cmsg);
+            match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                (libc::SOL_SOCKET, libc::SCM_TIMESTAMPING) => {
+                    // Software timestamp is the first of three
+                    let tp = (data as *const libc::timespec).read_unaligned();
+                    ts = Some(tp.tv_sec as u64 * 1_000_000_000 + tp.tv_nsec as u64);
+                }
+                (libc::IPPROTO_IP, libc::IP_RECVERR) | (libc::IPPROTO_IPV6, libc::IPV6_RECVERR) => {
+                    let err = (data as *const libc::sock_extended_err).read_unaligned();
+                    if err.ee_origin == libc::SO_EE_ORIGIN_TIMESTAMPING {
+                        id = Some(err.ee_data);
+                    }
+                }
+                _ => {}
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    match (id, ts) {
+        (Some(id), Some(ts)) => Ok((id, ts)),
+        _ => Err(Error::from(std::io::ErrorKind::InvalidData)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn recv_tx_timestamp(_io: &Socket) -> Result<(u32, u64)> {
+    Err(Error::from(std::io::ErrorKind::Unsupported))
+}
@@ -0,0 +1,246 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Socket state serialization
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::{Session, SessionKey};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+const VERSION: u8 = 2;
+// Sessions carry no details
+const VERSION_1: u8 = 1;
+const HEADER_SIZE: usize = 1 + 1 + 8 + 8 + 8 + 8 + 1 + 1 + 4;
+// Session's details flags
+const HAS_NONCE: u8 = 1;
+const HAS_SIZE: u8 = 2;
+const HAS_TX_TS: u8 = 4;
+
+/// Exported socket state, allowing to continue
+/// processing of in-flight sessions in the other process.
+/// ```text
+/// version(1) afi(1) signature(8) ts(8) mono(8) timeout(8)
+/// coarse(1) accelerated(1) sessions(4)
+/// [deadline(8) flags(1) [nonce(8) nonce_ts(8)] [size(4)] [tx_ts(8)]
+///  sid_len(2) sid(sid_len)] * sessions
+/// ```
+/// Where:
+/// * `ts` - socket's timestamp at the moment of export.
+/// * `mono` - system-wide CLOCK_MONOTONIC at the moment of export,
+///   used to adjust timestamps after import.
+/// * `flags` - optional fields of the session, present when bit is set:
+///   1 - nonce and its timestamp, 2 - request size,
+///   4 - transmit timestamp.
+///
+/// Version 1 sessions have no `flags` and optional fields.
+#[derive(Debug, PartialEq, Eq)]
+pub struct State {
+    pub afi: u8,
+    pub signature: u64,
+    pub ts: u64,
+    pub mono: u64,
+    pub timeout: u64,
+    pub coarse: bool,
+    pub accelerated: bool,
+    pub sessions: Vec<Session>,
+    /// Session -> (nonce, timestamp) in nonce and mimic modes
+    pub nonces: HashMap<SessionKey, (u64, u64)>,
+    /// Session -> request size, when strict size is set
+    pub sizes: HashMap<SessionKey, usize>,
+    /// Session -> transmit timestamp, when reported
+    pub tx_sent: HashMap<SessionKey, u64>,
+}
+
+impl State {
+    /// Serialize state
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_SIZE];
+        buf[0] = VERSION;
+        buf[1] = self.afi;
+        BigEndian::write_u64(&mut buf[2..], self.signature);
+        BigEndian::write_u64(&mut buf[10..], self.ts);
+        BigEndian::write_u64(&mut buf[18..], self.mono);
+        BigEndian::write_u64(&mut buf[26..], self.timeout);
+        buf[34] = self.coarse as u8;
+        buf[35] = self.accelerated as u8;
+        BigEndian::write_u32(&mut buf[36..], self.sessions.len() as u32);
+        let mut item = [0u8; 8];
+        for session in self.sessions.iter() {
+            let key = session.get_key();
+            let nonce = self.nonces.get(key);
+            let size = self.sizes.get(key);
+            let tx_ts = self.tx_sent.get(key);
+            BigEndian::write_u64(&mut item, session.get_deadline());
+            buf.extend_from_slice(&item);
+            buf.push(
+                nonce.map_or(0, |_| HAS_NONCE)
+                    | size.map_or(0, |_| HAS_SIZE)
+                    | tx_ts.map_or(0, |_| HAS_TX_TS),
+            );
+            if let Some((nonce, ts)) = nonce {
+                BigEndian::write_u64(&mut item, *nonce);
+                buf.extend_from_slice(&item);
+                BigEndian::write_u64(&mut item, *ts);
+                buf.extend_from_slice(&item);
+            }
+            if let Some(size) = size {
+                BigEndian::write_u32(&mut item, *size as u32);
+                buf.extend_from_slice(&item[..4]);
+            }
+            if let Some(tx_ts) = tx_ts {
+                BigEndian::write_u64(&mut item, *tx_ts);
+                buf.extend_from_slice(&item);
+            }
+            let sid = session.get_sid();
+            BigEndian::write_u16(&mut item, sid.len() as u16);
+            buf.extend_from_slice(&item[..2]);
+            buf.extend_from_slice(sid.as_bytes());
+        }
+        buf
+    }
+}
+
+// Parse State
+impl TryFrom<&[u8]> for State {
+    type Error = &'static str;
+
+    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+        if buf.len() < HEADER_SIZE {
+            return Err("too short");
+        }
+        let version = buf[0];
+        if version != VERSION && version != VERSION_1 {
+            return Err("unsupported version");
+        }
+        let count = BigEndian::read_u32(&buf[36..]) as usize;
+        let mut sessions = Vec::with_capacity(count.min(buf.len() / 10));
+        let mut nonces = HashMap::new();
+        let mut sizes = HashMap::new();
+        let mut tx_sent = HashMap::new();
+        let mut rest = &buf[HEADER_SIZE..];
+        for _ in 0..count {
+            let flags = match version {
+                VERSION_1 => 0,
+                _ => *rest.get(8).ok_or("too short")?,
+            };
+            // deadline and flags
+            let head_len = 8 + (version != VERSION_1) as usize;
+            let fields_len = head_len
+                + if flags & HAS_NONCE != 0 { 16 } else { 0 }
+                + if flags & HAS_SIZE != 0 { 4 } else { 0 }
+                + if flags & HAS_TX_TS != 0 { 8 } else { 0 };
+            if rest.len() < fields_len + 2 {
+                return Err("too short");
+            }
+            let deadline = BigEndian::read_u64(rest);
+            let mut fields = &rest[head_len..fields_len];
+            let sid_len = BigEndian::read_u16(&rest[fields_len..]) as usize;
+            rest = &rest[fields_len + 2..];
+            if rest.len() < sid_len {
+                return Err("too short");
+            }
+            let sid = std::str::from_utf8(&rest[..sid_len]).map_err(|_| "invalid sid")?;
+            let key: SessionKey = sid.parse()?;
+            if flags & HAS_NONCE != 0 {
+                nonces.insert(
+                    key,
+                    (
+                        BigEndian::read_u64(fields),
+                        BigEndian::read_u64(&fields[8..]),
+                    ),
+                );
+                fields = &fields[16..];
+            }
+            if flags & HAS_SIZE != 0 {
+                sizes.insert(key, BigEndian::read_u32(fields) as usize);
+                fields = &fields[4..];
+            }
+            if flags & HAS_TX_TS != 0 {
+                tx_sent.insert(key, BigEndian::read_u64(fields));
+            }
+            sessions.push(Session::new(key, deadline));
+            rest = &rest[sid_len..];
+        }
+        Ok(Self {
+            afi: buf[1],
+            signature: BigEndian::read_u64(&buf[2..]),
+            ts: BigEndian::read_u64(&buf[10..]),
+            mono: BigEndian::read_u64(&buf[18..]),
+            timeout: BigEndian::read_u64(&buf[26..]),
+            coarse: buf[34] != 0,
+            accelerated: buf[35] != 0,
+            sessions,
+            nonces,
+            sizes,
+            tx_sent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_state() -> State {
+        let key = "127.0.0.1-1-2".parse().unwrap();
+        State {
+            afi: 4,
+            signature: 0xdeadbeefdeadbeef,
+            ts: 1_000,
+            mono: 2_000,
+            timeout: 1_000_000_000,
+            coarse: false,
+            accelerated: true,
+            sessions: vec![
+                Session::new("127.0.0.1-1-1".parse().unwrap(), 1_000_001_000),
+                Session::new(key, 1_000_002_000),
+            ],
+            nonces: HashMap::from([(key, (0x1234, 1_500))]),
+            sizes: HashMap::from([(key, 64)]),
+            tx_sent: HashMap::from([(key, 1_600)]),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let state = get_state();
+        let buf = state.encode();
+        assert_eq!(State::try_from(buf.as_slice()).unwrap(), state);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let buf = get_state().encode();
+        assert!(State::try_from(&buf[..buf.len() - 1]).is_err());
+        assert!(State::try_from(&buf[..HEADER_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn test_version_1() {
+        let mut state = get_state();
+        state.nonces.clear();
+        state.sizes.clear();
+        state.tx_sent.clear();
+        let buf = state.encode();
+        // Drop flags of sessions
+        let mut v1 = buf[..HEADER_SIZE].to_vec();
+        v1[0] = VERSION_1;
+        let mut rest = &buf[HEADER_SIZE..];
+        while !rest.is_empty() {
+            let sid_len = BigEndian::read_u16(&rest[9..]) as usize;
+            v1.extend_from_slice(&rest[..8]);
+            v1.extend_from_slice(&rest[9..11 + sid_len]);
+            rest = &rest[11 + sid_len..];
+        }
+        assert_eq!(State::try_from(v1.as_slice()).unwrap(), state);
+    }
+
+    #[test]
+    fn test_version() {
+        let mut buf = get_state().encode();
+        buf[0] = VERSION + 1;
+        assert_eq!(State::try_from(buf.as_slice()), Err("unsupported version"));
+    }
+}
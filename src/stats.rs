@@ -0,0 +1,241 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Statistics serialization
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use byteorder::{BigEndian, ByteOrder};
+use std::convert::TryFrom;
+
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 1 + 4;
+const TAG_HEADER_SIZE: usize = 1 + 2;
+const STATS_SIZE: usize = 9 * 8;
+const TAG_NONE: u8 = 0;
+const TAG_STR: u8 = 1;
+const TAG_INT: u8 = 2;
+
+/// Statistics tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tag {
+    None,
+    Str(String),
+    Int(i64),
+}
+
+/// Accumulated statistics of the tag. RTTs are in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Stats {
+    pub sent: u64,
+    pub received: u64,
+    pub rtt_min: Option<f64>,
+    pub rtt_max: Option<f64>,
+    pub rtt_sum: f64,
+    pub bursts: u64,
+    pub bursts_all_lost: u64,
+    pub bursts_partial: u64,
+    pub bursts_reordered: u64,
+}
+
+/// Exported statistics and RTT baselines, allowing to continue
+/// the accumulation after restart.
+/// ```text
+/// version(1) tags(4)
+/// [kind(1) tag_len(2) tag(tag_len) sent(8) received(8) rtt_min(8)
+///  rtt_max(8) rtt_sum(8) bursts(8) bursts_all_lost(8)
+///  bursts_partial(8) bursts_reordered(8)] * tags
+/// baselines_len(4) baselines(baselines_len)
+/// ```
+/// Where:
+/// * `kind` - 0 for untagged, 1 for string, and 2 for integer tags,
+///   integer tags are stored as decimal strings.
+/// * `rtt_min`, `rtt_max` - NaN when no replies are received.
+/// * `baselines` - serialized `Baselines`, empty when missed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StatsState {
+    pub stats: Vec<(Tag, Stats)>,
+    pub baselines: Option<Vec<u8>>,
+}
+
+impl StatsState {
+    /// Serialize state
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_SIZE];
+        buf[0] = VERSION;
+        BigEndian::write_u32(&mut buf[1..], self.stats.len() as u32);
+        let mut item = [0u8; STATS_SIZE];
+        for (tag, stats) in self.stats.iter() {
+            let (kind, value) = match tag {
+                Tag::None => (TAG_NONE, String::new()),
+                Tag::Str(x) => (TAG_STR, x.clone()),
+                Tag::Int(x) => (TAG_INT, x.to_string()),
+            };
+            let mut header = [kind, 0, 0];
+            BigEndian::write_u16(&mut header[1..], value.len() as u16);
+            buf.extend_from_slice(&header);
+            buf.extend_from_slice(value.as_bytes());
+            BigEndian::write_u64(&mut item, stats.sent);
+            BigEndian::write_u64(&mut item[8..], stats.received);
+            BigEndian::write_f64(&mut item[16..], stats.rtt_min.unwrap_or(f64::NAN));
+            BigEndian::write_f64(&mut item[24..], stats.rtt_max.unwrap_or(f64::NAN));
+            BigEndian::write_f64(&mut item[32..], stats.rtt_sum);
+            BigEndian::write_u64(&mut item[40..], stats.bursts);
+            BigEndian::write_u64(&mut item[48..], stats.bursts_all_lost);
+            BigEndian::write_u64(&mut item[56..], stats.bursts_partial);
+            BigEndian::write_u64(&mut item[64..], stats.bursts_reordered);
+            buf.extend_from_slice(&item);
+        }
+        let baselines = self.baselines.as_deref().unwrap_or(&[]);
+        let mut len = [0u8; 4];
+        BigEndian::write_u32(&mut len, baselines.len() as u32);
+        buf.extend_from_slice(&len);
+        buf.extend_from_slice(baselines);
+        buf
+    }
+}
+
+/// Read optional RTT
+fn read_rtt(buf: &[u8]) -> Option<f64> {
+    let x = BigEndian::read_f64(buf);
+    if x.is_nan() {
+        None
+    } else {
+        Some(x)
+    }
+}
+
+// Parse StatsState
+impl TryFrom<&[u8]> for StatsState {
+    type Error = &'static str;
+
+    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+        if buf.len() < HEADER_SIZE {
+            return Err("too short");
+        }
+        if buf[0] != VERSION {
+            return Err("unsupported version");
+        }
+        let count = BigEndian::read_u32(&buf[1..]) as usize;
+        let mut stats = Vec::with_capacity(count.min(buf.len() / STATS_SIZE));
+        let mut rest = &buf[HEADER_SIZE..];
+        for _ in 0..count {
+            if rest.len() < TAG_HEADER_SIZE {
+                return Err("too short");
+            }
+            let kind = rest[0];
+            let tag_len = BigEndian::read_u16(&rest[1..]) as usize;
+            rest = &rest[TAG_HEADER_SIZE..];
+            if rest.len() < tag_len + STATS_SIZE {
+                return Err("too short");
+            }
+            let value = std::str::from_utf8(&rest[..tag_len]).map_err(|_| "invalid tag")?;
+            let tag = match kind {
+                TAG_NONE => Tag::None,
+                TAG_STR => Tag::Str(value.into()),
+                TAG_INT => Tag::Int(value.parse().map_err(|_| "invalid tag")?),
+                _ => return Err("invalid tag"),
+            };
+            rest = &rest[tag_len..];
+            stats.push((
+                tag,
+                Stats {
+                    sent: BigEndian::read_u64(rest),
+                    received: BigEndian::read_u64(&rest[8..]),
+                    rtt_min: read_rtt(&rest[16..]),
+                    rtt_max: read_rtt(&rest[24..]),
+                    rtt_sum: BigEndian::read_f64(&rest[32..]),
+                    bursts: BigEndian::read_u64(&rest[40..]),
+                    bursts_all_lost: BigEndian::read_u64(&rest[48..]),
+                    bursts_partial: BigEndian::read_u64(&rest[56..]),
+                    bursts_reordered: BigEndian::read_u64(&rest[64..]),
+                },
+            ));
+            rest = &rest[STATS_SIZE..];
+        }
+        if rest.len() < 4 {
+            return Err("too short");
+        }
+        let baselines_len = BigEndian::read_u32(rest) as usize;
+        rest = &rest[4..];
+        if rest.len() < baselines_len {
+            return Err("too short");
+        }
+        Ok(Self {
+            stats,
+            baselines: if baselines_len > 0 {
+                Some(rest[..baselines_len].to_vec())
+            } else {
+                None
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_state() -> StatsState {
+        StatsState {
+            stats: vec![
+                (
+                    Tag::None,
+                    Stats {
+                        sent: 2,
+                        ..Default::default()
+                    },
+                ),
+                (
+                    Tag::Str("core".into()),
+                    Stats {
+                        sent: 10,
+                        received: 9,
+                        rtt_min: Some(0.001),
+                        rtt_max: Some(0.005),
+                        rtt_sum: 0.027,
+                        bursts: 3,
+                        bursts_all_lost: 1,
+                        bursts_partial: 1,
+                        bursts_reordered: 1,
+                    },
+                ),
+                (Tag::Int(-5), Stats::default()),
+            ],
+            baselines: Some(vec![1, 0, 0, 0, 0]),
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let state = get_state();
+        let buf = state.encode();
+        assert_eq!(StatsState::try_from(buf.as_slice()), Ok(state));
+    }
+
+    #[test]
+    fn test_no_baselines() {
+        let state = StatsState {
+            baselines: None,
+            ..get_state()
+        };
+        let buf = state.encode();
+        assert_eq!(StatsState::try_from(buf.as_slice()), Ok(state));
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let buf = get_state().encode();
+        for n in [0, 3, 10, 100, buf.len() - 1] {
+            assert_eq!(StatsState::try_from(&buf[..n]), Err("too short"), "{}", n);
+        }
+        let mut bad = buf.clone();
+        bad[0] = 0;
+        assert_eq!(
+            StatsState::try_from(bad.as_slice()),
+            Err("unsupported version")
+        );
+        let mut bad = buf;
+        bad[HEADER_SIZE] = 3;
+        assert_eq!(StatsState::try_from(bad.as_slice()), Err("invalid tag"));
+    }
+}
@@ -0,0 +1,48 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Suppressor implementation
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::maintenance::Maintenance;
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+/// Python wrapper for the targets' maintenance windows
+#[pyclass]
+pub(crate) struct Suppressor {
+    maintenance: Maintenance,
+}
+
+#[pymethods]
+impl Suppressor {
+    #[new]
+    fn new() -> Self {
+        Suppressor {
+            maintenance: Maintenance::new(),
+        }
+    }
+
+    /// Add the window to the target
+    fn add(&mut self, target: &str, start: f64, end: f64) -> PyResult<()> {
+        if end <= start {
+            return Err(PyValueError::new_err("end must be after start"));
+        }
+        self.maintenance.add(target, start, end);
+        Ok(())
+    }
+
+    /// Remove all windows of the target
+    fn clear(&mut self, target: &str) {
+        self.maintenance.clear(target);
+    }
+
+    /// Get windows of the target
+    fn get(&self, target: &str) -> Vec<(f64, f64)> {
+        self.maintenance.get(target).to_vec()
+    }
+
+    /// Check if the target's results are suppressed at the time `ts`
+    fn is_suppressed(&mut self, target: &str, ts: f64) -> bool {
+        self.maintenance.is_suppressed(target, ts)
+    }
+}
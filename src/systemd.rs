@@ -0,0 +1,154 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: systemd socket activation and notifications
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::RawFd;
+
+/// First file descriptor, passed by systemd
+pub const LISTEN_FDS_START: RawFd = 3;
+
+/// Check the variable holds pid of the current process
+fn is_own(pid: Option<&str>, own_pid: u32) -> bool {
+    pid.and_then(|x| x.parse::<u32>().ok()) == Some(own_pid)
+}
+
+/// File descriptors, passed by systemd socket activation,
+/// from `LISTEN_PID` and `LISTEN_FDS` variables.
+/// Empty, when the sockets are passed to the other process.
+pub fn parse_listen_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> Vec<RawFd> {
+    if !is_own(listen_pid, pid) {
+        return Vec::new();
+    }
+    match listen_fds.and_then(|x| x.parse::<RawFd>().ok()) {
+        Some(n) if n > 0 => (LISTEN_FDS_START..LISTEN_FDS_START + n).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Watchdog timeout in microseconds,
+/// from `WATCHDOG_USEC` and `WATCHDOG_PID` variables.
+/// Missed `WATCHDOG_PID` means the current process.
+pub fn parse_watchdog(usec: Option<&str>, watchdog_pid: Option<&str>, pid: u32) -> Option<u64> {
+    if watchdog_pid.is_some() && !is_own(watchdog_pid, pid) {
+        return None;
+    }
+    match usec.and_then(|x| x.parse::<u64>().ok()) {
+        Some(n) if n > 0 => Some(n),
+        _ => None,
+    }
+}
+
+/// Get address family of the socket (Linux)
+#[cfg(target_os = "linux")]
+pub fn get_domain(fd: RawFd) -> Result<i32> {
+    let mut domain: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let r = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_DOMAIN,
+            &mut domain as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if r < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(domain)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_domain(_fd: RawFd) -> Result<i32> {
+    Err(Error::from(ErrorKind::Unsupported))
+}
+
+/// Set close-on-exec flag, preventing the leaking of the socket
+/// to the child processes
+pub fn set_cloexec(fd: RawFd) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Send state to the service manager's notification socket.
+/// Paths, starting with `@`, are in the abstract namespace (Linux).
+pub fn notify(path: &str, state: &str) -> Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let sock = UnixDatagram::unbound()?;
+    let n = match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+            sock.send_to_addr(state.as_bytes(), &addr)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err(Error::from(ErrorKind::Unsupported)),
+        None => sock.send_to(state.as_bytes(), path)?,
+    };
+    if n != state.len() {
+        return Err(Error::from(ErrorKind::WriteZero));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_parse_listen_fds() {
+        assert_eq!(parse_listen_fds(Some("10"), Some("2"), 10), vec![3, 4]);
+        assert_eq!(parse_listen_fds(Some("11"), Some("2"), 10), vec![]);
+        assert_eq!(parse_listen_fds(None, Some("2"), 10), vec![]);
+        assert_eq!(parse_listen_fds(Some("10"), None, 10), vec![]);
+        assert_eq!(parse_listen_fds(Some("10"), Some("x"), 10), vec![]);
+        assert_eq!(parse_listen_fds(Some("10"), Some("-1"), 10), vec![]);
+    }
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(parse_watchdog(Some("30000000"), None, 10), Some(30_000_000));
+        assert_eq!(
+            parse_watchdog(Some("30000000"), Some("10"), 10),
+            Some(30_000_000)
+        );
+        assert_eq!(parse_watchdog(Some("30000000"), Some("11"), 10), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 10), None);
+        assert_eq!(parse_watchdog(None, None, 10), None);
+    }
+
+    #[test]
+    fn test_get_domain() {
+        let (a, _b) = UnixDatagram::pair().unwrap();
+        assert_eq!(get_domain(a.as_raw_fd()).unwrap(), libc::AF_UNIX);
+        assert!(get_domain(-1).is_err());
+    }
+
+    #[test]
+    fn test_notify() {
+        let dir = std::env::temp_dir().join(format!("gufo-ping-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&dir);
+        let sock = UnixDatagram::bind(&dir).unwrap();
+        notify(dir.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = sock.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+        std::fs::remove_file(&dir).unwrap();
+    }
+}
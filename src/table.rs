@@ -0,0 +1,339 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Pending sessions table
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::{Session, SessionKey};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+// Wheel tick is 2^TICK_SHIFT nanoseconds (~1ms)
+const TICK_SHIFT: u32 = 20;
+// Number of wheel slots, must be power of 2. Covers ~1s of deadlines,
+// later deadlines wait for the next turns of the wheel.
+const SLOTS: usize = 1024;
+const SLOT_MASK: u64 = (SLOTS as u64) - 1;
+
+/// Pending sessions, indexed by key and placed on the timing wheel
+/// by deadline. Insertion is O(1), expiration is amortized
+/// over the wheel ticks.
+/// Removed sessions are left on the wheel and
+/// dropped when their slot is visited.
+pub struct SessionTable {
+    // key -> deadline
+    sessions: HashMap<SessionKey, u64>,
+    // Timing wheel, may contain removed sessions
+    slots: Vec<Vec<Session>>,
+    // Number of sessions on the wheel, including removed
+    queued: usize,
+    // Current wheel tick
+    tick: u64,
+}
+
+impl Default for SessionTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionTable {
+    /// Create empty table
+    pub fn new() -> Self {
+        SessionTable {
+            sessions: HashMap::new(),
+            slots: vec![Vec::new(); SLOTS],
+            queued: 0,
+            tick: 0,
+        }
+    }
+
+    /// Number of pending sessions
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Check if table is empty
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Check if session is pending
+    pub fn contains(&self, key: &SessionKey) -> bool {
+        self.sessions.contains_key(key)
+    }
+
+    /// Get deadline of the pending session
+    pub fn get_deadline(&self, key: &SessionKey) -> Option<u64> {
+        self.sessions.get(key).copied()
+    }
+
+    /// Add session. Replace deadline of existing one.
+    pub fn insert(&mut self, session: Session) {
+        let tick = Self::get_tick(session.get_deadline());
+        if self.queued == 0 {
+            self.tick = tick;
+        }
+        self.sessions
+            .insert(*session.get_key(), session.get_deadline());
+        // Overdue sessions are placed to the current slot
+        self.slots[(tick.max(self.tick) & SLOT_MASK) as usize].push(session);
+        self.queued += 1;
+        self.compact();
+    }
+
+    /// Remove session. Returns removed session, if any.
+    pub fn remove(&mut self, key: &SessionKey) -> Option<Session> {
+        self.sessions
+            .remove(key)
+            .map(|deadline| Session::new(*key, deadline))
+    }
+
+    /// Remove all sessions of the address.
+    /// Returns removed sessions, ordered by deadline.
+    pub fn remove_addr(&mut self, addr: &IpAddr) -> Vec<Session> {
+        let mut r = Vec::new();
+        self.sessions.retain(|key, deadline| {
+            if key.get_addr() == *addr {
+                r.push(Session::new(*key, *deadline));
+                return false;
+            }
+            true
+        });
+        r.sort();
+        r
+    }
+
+    /// Remove all sessions
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+        self.slots.iter_mut().for_each(|slot| slot.clear());
+        self.queued = 0;
+    }
+
+    /// Get the session with the closest deadline
+    pub fn first(&self) -> Option<Session> {
+        // Look for the first slot holding the sessions
+        // of the current turn of the wheel
+        let mut first: Option<Session> = None;
+        for tick in self.tick..self.tick + SLOTS as u64 {
+            for session in self.slots[(tick & SLOT_MASK) as usize].iter() {
+                if Self::get_tick(session.get_deadline()) <= tick
+                    && self.is_live(session)
+                    && first.filter(|f| *f <= *session).is_none()
+                {
+                    first = Some(*session);
+                }
+            }
+            if first.is_some() {
+                return first;
+            }
+        }
+        // Fallback to the full scan for the distant deadlines
+        self.sessions
+            .iter()
+            .map(|(key, deadline)| Session::new(*key, *deadline))
+            .min()
+    }
+
+    /// Remove and return the session with the closest deadline
+    pub fn pop_first(&mut self) -> Option<Session> {
+        let first = self.first()?;
+        self.sessions.remove(first.get_key());
+        Some(first)
+    }
+
+    /// Get the closest deadline
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.first().map(|s| s.get_deadline())
+    }
+
+    /// Remove and return all sessions, expired at `ts`.
+    /// Intended to be called on the timer tick.
+    pub fn expire(&mut self, ts: u64) -> Vec<Session> {
+        let mut r = Vec::new();
+        if self.queued == 0 {
+            return r;
+        }
+        let now = Self::get_tick(ts).max(self.tick);
+        // Visit every slot at most once
+        let last = now.min(self.tick + SLOT_MASK);
+        for tick in self.tick..=last {
+            let slot = &mut self.slots[(tick & SLOT_MASK) as usize];
+            let before = slot.len();
+            let sessions = &mut self.sessions;
+            slot.retain(|session| {
+                if sessions.get(session.get_key()) != Some(&session.get_deadline()) {
+                    return false; // Removed or replaced
+                }
+                if session.is_expired(ts) {
+                    sessions.remove(session.get_key());
+                    r.push(*session);
+                    return false;
+                }
+                true
+            });
+            self.queued -= before - slot.len();
+        }
+        self.tick = now;
+        r.sort();
+        r
+    }
+
+    /// Get the latest deadline
+    pub fn last_deadline(&self) -> Option<u64> {
+        self.sessions.values().max().copied()
+    }
+
+    /// Get all pending sessions, ordered by deadline
+    pub fn to_vec(&self) -> Vec<Session> {
+        let mut r: Vec<Session> = self
+            .sessions
+            .iter()
+            .map(|(key, deadline)| Session::new(*key, *deadline))
+            .collect();
+        r.sort();
+        r
+    }
+
+    /// Get wheel tick for timestamp
+    fn get_tick(ts: u64) -> u64 {
+        ts >> TICK_SHIFT
+    }
+
+    /// Check if queued session is not removed or replaced
+    fn is_live(&self, session: &Session) -> bool {
+        self.sessions.get(session.get_key()) == Some(&session.get_deadline())
+    }
+
+    /// Drop removed sessions from the wheel when they prevail
+    fn compact(&mut self) {
+        if self.queued > 1024 && self.queued > 2 * self.sessions.len() {
+            let sessions = &self.sessions;
+            for slot in self.slots.iter_mut() {
+                slot.retain(|s| sessions.get(s.get_key()) == Some(&s.get_deadline()));
+            }
+            self.queued = self.sessions.len();
+        }
+    }
+}
+
+impl Extend<Session> for SessionTable {
+    fn extend<T: IntoIterator<Item = Session>>(&mut self, iter: T) {
+        for session in iter {
+            self.insert(session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const TICK: u64 = 1 << TICK_SHIFT;
+
+    fn key(seq: u16) -> SessionKey {
+        SessionKey::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1, seq)
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut table = SessionTable::new();
+        table.insert(Session::new(key(1), 30));
+        table.insert(Session::new(key(2), 10));
+        table.insert(Session::new(key(3), 20));
+        assert_eq!(table.remove(&key(3)), Some(Session::new(key(3), 20)));
+        assert!(!table.contains(&key(3)));
+        assert!(table.contains(&key(1)));
+        assert_eq!(table.get_deadline(&key(1)), Some(30));
+        assert_eq!(table.get_deadline(&key(3)), None);
+        assert_eq!(table.expire(5), vec![]);
+        assert_eq!(table.expire(25), vec![Session::new(key(2), 10)]);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.next_deadline(), Some(30));
+        assert_eq!(table.last_deadline(), Some(30));
+        assert_eq!(table.expire(35), vec![Session::new(key(1), 30)]);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_expire_ticks() {
+        let mut table = SessionTable::new();
+        // Next turns of the wheel
+        let deadlines = [
+            TICK,
+            3 * TICK,
+            2 * SLOTS as u64 * TICK,
+            5 * SLOTS as u64 * TICK,
+        ];
+        for (seq, deadline) in deadlines.iter().enumerate() {
+            table.insert(Session::new(key(seq as u16), *deadline));
+        }
+        for (seq, deadline) in deadlines.iter().enumerate() {
+            assert_eq!(table.expire(*deadline), vec![]);
+            assert_eq!(
+                table.expire(*deadline + 1),
+                vec![Session::new(key(seq as u16), *deadline)]
+            );
+        }
+        // Overdue session
+        table.insert(Session::new(key(10), 10 * SLOTS as u64 * TICK));
+        table.insert(Session::new(key(11), TICK));
+        assert_eq!(table.expire(TICK + 1), vec![Session::new(key(11), TICK)]);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut table = SessionTable::new();
+        table.insert(Session::new(key(1), 10));
+        table.insert(Session::new(key(1), 20));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.expire(15), vec![]);
+        assert_eq!(table.pop_first(), Some(Session::new(key(1), 20)));
+        assert_eq!(table.pop_first(), None);
+    }
+
+    #[test]
+    fn test_pop_first() {
+        let mut table = SessionTable::new();
+        table.insert(Session::new(key(1), 3 * SLOTS as u64 * TICK));
+        table.insert(Session::new(key(2), 2 * SLOTS as u64 * TICK));
+        table.insert(Session::new(key(3), 5 * TICK));
+        table.insert(Session::new(key(4), 4 * TICK + 1));
+        assert_eq!(table.pop_first(), Some(Session::new(key(4), 4 * TICK + 1)));
+        assert_eq!(table.pop_first(), Some(Session::new(key(3), 5 * TICK)));
+        assert_eq!(
+            table.pop_first(),
+            Some(Session::new(key(2), 2 * SLOTS as u64 * TICK))
+        );
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_addr() {
+        let mut table = SessionTable::new();
+        let other = SessionKey::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)), 1, 1);
+        table.insert(Session::new(key(1), 20));
+        table.insert(Session::new(other, 15));
+        table.insert(Session::new(key(2), 10));
+        assert_eq!(
+            table.remove_addr(&IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            vec![Session::new(key(2), 10), Session::new(key(1), 20)]
+        );
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.next_deadline(), Some(15));
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut table = SessionTable::new();
+        for seq in 0..4096 {
+            table.insert(Session::new(key(seq), seq as u64));
+            table.remove(&key(seq));
+        }
+        assert!(table.queued <= 2048);
+        assert!(table.is_empty());
+    }
+}
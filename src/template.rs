@@ -0,0 +1,50 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Pre-encoded packet templates
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::IcmpPacket;
+
+/// Pre-encoded ICMP echo request.
+/// The packet is encoded once and only request id, sequence,
+/// timestamp and checksum are patched on each render.
+pub struct PacketTemplate {
+    buf: Vec<u8>,
+}
+
+impl PacketTemplate {
+    /// Encode packet into the new template
+    pub fn new(pkt: &IcmpPacket) -> Self {
+        let mut buf = Vec::with_capacity(pkt.get_size());
+        let n = pkt.write(buf.spare_capacity_mut());
+        // Buffer is initialized by `write`
+        unsafe { buf.set_len(n) };
+        PacketTemplate { buf }
+    }
+
+    /// Patch template with packet's variable fields.
+    /// Packet's type, signature and size must match the template.
+    pub fn render(&mut self, pkt: &IcmpPacket) -> &[u8] {
+        pkt.rewrite(&mut self.buf);
+        &self.buf
+    }
+
+    /// Get encoded packet
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut tpl = PacketTemplate::new(&IcmpPacket::new(8, 1, 1, 0xdeadbeef, 1, 44));
+        let pkt = IcmpPacket::new(8, 2, 3, 0xdeadbeef, 4, 44);
+        let expected = PacketTemplate::new(&pkt);
+        assert_eq!(tpl.render(&pkt), expected.as_slice());
+    }
+}
@@ -0,0 +1,78 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: Timer
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use std::time::Instant;
+
+/// Coarse clock is available, i.e. the crate is built
+/// with `coarsetime` feature
+pub const HAS_COARSE: bool = cfg!(feature = "coarsetime");
+
+/// Monotonic clock, in nanoseconds.
+/// Uses CLOCK_MONOTONIC by default and CLOCK_MONOTONIC_COARSE
+/// when switched to the coarse mode. Falls back to CLOCK_MONOTONIC
+/// when built without `coarsetime` feature.
+#[derive(Debug, Clone, Copy)]
+pub struct Timer {
+    start: Instant,
+    coarse: bool,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer {
+            start: Instant::now(),
+            coarse: false,
+        }
+    }
+}
+
+impl Timer {
+    /// Switch to the coarse clock.
+    /// Ignored when built without `coarsetime` feature.
+    pub fn set_coarse(&mut self, coarse: bool) {
+        self.coarse = coarse && HAS_COARSE;
+    }
+
+    /// Check if coarse clock is used
+    pub fn is_coarse(&self) -> bool {
+        self.coarse
+    }
+
+    /// Get current timestamp
+    pub fn now(&self) -> u64 {
+        #[cfg(feature = "coarsetime")]
+        if self.coarse {
+            return coarsetime::Clock::now_since_epoch().as_nanos();
+        }
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    /// Get clock id of the underlying clock
+    pub fn clock_id(&self) -> libc::clockid_t {
+        #[cfg(target_os = "linux")]
+        if self.coarse {
+            return libc::CLOCK_MONOTONIC_COARSE;
+        }
+        libc::CLOCK_MONOTONIC
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_now() {
+        for coarse in [false, true] {
+            let mut timer = Timer::default();
+            timer.set_coarse(coarse);
+            assert_eq!(timer.is_coarse(), coarse && HAS_COARSE);
+            let t0 = timer.now();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert!(timer.now() > t0);
+        }
+    }
+}
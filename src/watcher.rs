@@ -0,0 +1,83 @@
+// ---------------------------------------------------------------------
+// Gufo Ping: FileWatcher implementation
+// ---------------------------------------------------------------------
+// Copyright (C) 2022, Gufo Labs
+// ---------------------------------------------------------------------
+
+use super::inotify;
+use pyo3::{exceptions::PyOSError, prelude::*};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+
+const BUFFER_SIZE: usize = 4096;
+
+/// Watcher of the file changes (Linux)
+#[pyclass]
+pub(crate) struct FileWatcher {
+    fd: Option<RawFd>,
+    name: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+#[pymethods]
+impl FileWatcher {
+    /// Watch the file for being written or replaced
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        let path = Path::new(path);
+        let name = path
+            .file_name()
+            .ok_or_else(|| PyOSError::new_err("invalid path"))?;
+        let dir = match path.parent() {
+            Some(x) if !x.as_os_str().is_empty() => x,
+            _ => Path::new("."),
+        };
+        let fd = inotify::watch_dir(dir).map_err(|e| PyOSError::new_err(e.to_string()))?;
+        Ok(FileWatcher {
+            fd: Some(fd),
+            name: name.as_bytes().to_vec(),
+            buf: vec![0u8; BUFFER_SIZE],
+        })
+    }
+
+    /// Get file descriptor, to be polled for events
+    fn get_fd(&self) -> PyResult<RawFd> {
+        self.fd
+            .ok_or_else(|| PyOSError::new_err("I/O operation on closed watcher"))
+    }
+
+    /// Read all pending events.
+    /// Returns true, if the file is changed
+    fn recv(&mut self) -> PyResult<bool> {
+        let fd = self.get_fd()?;
+        let mut changed = false;
+        loop {
+            let n = unsafe {
+                libc::read(
+                    fd,
+                    self.buf.as_mut_ptr() as *mut libc::c_void,
+                    self.buf.len(),
+                )
+            };
+            if n <= 0 {
+                return Ok(changed);
+            }
+            changed |=
+                inotify::parse_names(&self.buf[..n as usize]).contains(&self.name.as_slice());
+        }
+    }
+
+    /// Stop watching
+    fn close(&mut self) {
+        if let Some(fd) = self.fd.take() {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,99 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Test resolve and ping combined check
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import asyncio
+import socket
+
+# Third-party modules
+import pytest
+
+# Gufo Labs modules
+from gufo.ping import HostCheckResult, check_host
+from .util import is_denied
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_check_host():
+    result = asyncio.run(check_host("localhost", afi=4, timeout=0.5))
+    assert result.error is None
+    assert result.resolved
+    assert result.resolve_time >= 0
+    assert result.addresses == ["127.0.0.1"]
+    assert result.reachable
+    assert result.addr == "127.0.0.1"
+    assert result.rtt is not None and result.rtt > 0
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_check_host_address():
+    result = asyncio.run(check_host("127.0.0.1", timeout=0.5))
+    assert result.addresses == ["127.0.0.1"]
+    assert result.reachable
+
+
+def test_check_host_unresolved():
+    result = asyncio.run(check_host("nonexistent.invalid", timeout=0.5))
+    assert result.error is not None
+    assert not result.resolved
+    assert not result.reachable
+    assert result.addr is None
+    assert result.rtt is None
+
+
+class ScopedLoop(asyncio.SelectorEventLoop):
+    """
+    Resolve any name to the scoped link-local address.
+    """
+
+    async def getaddrinfo(self, host, port, **kwargs):
+        return [
+            (
+                socket.AF_INET6,
+                socket.SOCK_DGRAM,
+                socket.IPPROTO_UDP,
+                "",
+                ("fe80::1%eth0", 0, 0, 2),
+            )
+        ]
+
+
+def test_check_host_link_local():
+    loop = ScopedLoop()
+    try:
+        result = loop.run_until_complete(
+            check_host("router.example", timeout=0.5)
+        )
+    finally:
+        loop.close()
+    # Reported, rather than raised
+    assert result.addresses == ["fe80::1%eth0"]
+    assert result.rtts == {"fe80::1%eth0": None}
+    assert result.error is not None
+    assert not result.reachable
+
+
+@pytest.mark.parametrize("kwargs", [{"afi": 5}, {"max_addresses": 0}])
+def test_check_host_invalid(kwargs):
+    with pytest.raises(ValueError):
+        asyncio.run(check_host("localhost", **kwargs))
+
+
+def test_result():
+    result = HostCheckResult(
+        "example.com",
+        0.01,
+        ["2001:db8::1", "192.0.2.1", "192.0.2.2"],
+        {"2001:db8::1": None, "192.0.2.1": 0.02, "192.0.2.2": 0.01},
+    )
+    # First answered in resolver's order, not the fastest
+    assert result.addr == "192.0.2.1"
+    assert result.rtt == 0.02
+    assert result.reachable
+    result = HostCheckResult("example.com", 0.01, ["192.0.2.1"], {})
+    assert result.resolved
+    assert not result.reachable
+    assert result.rtt is None
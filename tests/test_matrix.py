@@ -0,0 +1,63 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Test matrix probe
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import asyncio
+
+# Third-party modules
+import pytest
+
+# Gufo Labs modules
+from gufo.ping import MatrixResult, probe_matrix
+from .util import is_denied
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_probe_matrix():
+    result = asyncio.run(
+        probe_matrix(
+            "127.0.0.1",
+            sizes=[64, 1500],
+            dscps=[0, 46],
+            ttls=[None, 8],
+            count=2,
+            timeout=0.5,
+        )
+    )
+    assert len(result.rtts) == 8
+    assert all(len(rtts) == 2 for rtts in result.rtts.values())
+    # Loopback answers everything
+    assert result.reachable
+    assert result.lost == []
+    assert result.max_size == 1500
+    assert result.get_received(1500, 46, 8) == 2
+
+
+@pytest.mark.parametrize(
+    "kwargs",
+    [{"count": 0}, {"sizes": []}, {"dscps": [64]}, {"ttls": []}],
+)
+def test_probe_matrix_invalid(kwargs):
+    with pytest.raises(ValueError):
+        asyncio.run(probe_matrix("127.0.0.1", **kwargs))
+
+
+def test_result():
+    result = MatrixResult(
+        {
+            (64, 0, None): [0.01, None],
+            (1500, 0, None): [None, None],
+            (64, 46, None): [None, None],
+            (1500, 46, None): [None, None],
+        }
+    )
+    assert result.reachable
+    assert result.lost == [(1500, 0, None), (64, 46, None), (1500, 46, None)]
+    assert result.max_size == 64
+    assert result.get_received(64, 0, None) == 1
+    result = MatrixResult({(64, 0, None): [None]})
+    assert not result.reachable
+    assert result.max_size is None
@@ -0,0 +1,40 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Test middlebox fingerprinting
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import asyncio
+
+# Third-party modules
+import pytest
+
+# Gufo Labs modules
+from gufo.ping import MiddleboxResult, probe_middlebox
+from gufo.ping.middlebox import PROBES
+from .util import is_denied
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_probe_middlebox():
+    result = asyncio.run(probe_middlebox("127.0.0.1", count=2, timeout=0.5))
+    assert result.sent == 2
+    assert set(result.received) == {name for name, _, _ in PROBES}
+    # Loopback answers everything
+    assert result.reachable
+    assert result.dropped == []
+
+
+def test_probe_middlebox_invalid():
+    with pytest.raises(ValueError):
+        asyncio.run(probe_middlebox("127.0.0.1", count=0))
+
+
+def test_result():
+    result = MiddleboxResult(3, {"baseline": 3, "code": 0, "ecn_ce": 1})
+    assert result.reachable
+    assert result.dropped == ["code"]
+    result = MiddleboxResult(3, {"baseline": 0, "code": 0})
+    assert not result.reachable
+    assert result.dropped == []
@@ -0,0 +1,42 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Test OpenTelemetry emitter
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Third-party modules
+import pytest
+
+pytest.importorskip("opentelemetry.sdk")
+
+from opentelemetry.sdk.metrics import MeterProvider  # noqa: E402
+from opentelemetry.sdk.metrics.export import (  # noqa: E402
+    InMemoryMetricReader,
+)
+
+# Gufo Labs modules
+from gufo.ping import ProbeResult  # noqa: E402
+from gufo.ping.otel import OtelEmitter  # noqa: E402
+
+
+def test_otel_emitter():
+    reader = InMemoryMetricReader()
+    emitter = OtelEmitter(MeterProvider(metric_readers=[reader]))
+    emitter.emit(ProbeResult("127.0.0.1", 1, 0.5, tag="t1"))
+    emitter.emit(ProbeResult("127.0.0.1", 2, None, tag="t1"))
+    data = reader.get_metrics_data()
+    metrics = {
+        m.name: m
+        for rm in data.resource_metrics
+        for sm in rm.scope_metrics
+        for m in sm.metrics
+    }
+    attrs = {"target": "127.0.0.1", "tag": "t1"}
+    (sent,) = metrics["ping.sent"].data.data_points
+    assert sent.value == 2
+    assert dict(sent.attributes) == attrs
+    (lost,) = metrics["ping.lost"].data.data_points
+    assert lost.value == 1
+    (rtt,) = metrics["ping.rtt"].data.data_points
+    assert rtt.count == 1
+    assert rtt.sum == 0.5
@@ -6,12 +6,39 @@
 
 # Python modules
 import asyncio
+import io
+import json
+import os
+import signal
+import socket
+import struct
+import subprocess
+import sys
+import tempfile
+import time
+import uuid
+from time import perf_counter
 
 # Third-party modules
 import pytest
 
 # Gufo Labs modules
-from gufo.ping import Ping
+from gufo.ping import (
+    BaseEmitter,
+    BurstResult,
+    Calibration,
+    Ping,
+    PingStats,
+    ProbeProfile,
+    ProbeResult,
+    Schedule,
+    ScheduledProbe,
+    VerdictChange,
+    JsonLinesEmitter,
+    InfluxEmitter,
+)
+from gufo.ping import _fast
+from gufo.ping.ratelimit import RateLimitDetector
 from .util import is_denied
 
 
@@ -104,3 +131,1174 @@ def test_valid_ping_settings(cfg, expected):
     else:
         with pytest.raises(ValueError):
             asyncio.run(Ping(**cfg).ping("127.0.0.1"))
+
+
+def test_tag():
+    ping = Ping()
+    assert ping.get_tag("127.0.0.1") is None
+    ping.set_tag("127.0.0.1", "t1")
+    assert ping.get_tag("127.0.0.1") == "t1"
+    ping.set_tag("127.0.0.1", None)
+    assert ping.get_tag("127.0.0.1") is None
+
+
+def test_correlation_id():
+    ping = Ping()
+    assert ping.get_correlation_id("127.0.0.1") is None
+    ping.set_correlation_id("127.0.0.1", b"\x01\x02")
+    assert ping.get_correlation_id("127.0.0.1") == b"\x01\x02"
+    ping.set_correlation_id("127.0.0.1", None)
+    assert ping.get_correlation_id("127.0.0.1") is None
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_tag_stats():
+    async def inner():
+        async with Ping(timeout=0.1) as ping:
+            ping.set_tag("127.0.0.1", "t1")
+            ping.set_tag("127.0.0.2", "t1")
+            await ping.ping("127.0.0.1")
+            await ping.ping("127.0.0.2")
+            await ping.ping("10.255.255.1")
+            return ping.get_stats()
+
+    stats = asyncio.run(inner())
+    assert set(stats) == {"t1", None}
+    assert stats["t1"].sent == 2
+    assert stats["t1"].received == 2
+    assert stats["t1"].lost == 0
+    assert stats["t1"].rtt_min <= stats["t1"].rtt_avg <= stats["t1"].rtt_max
+    assert stats[None].sent == 1
+    assert stats[None].lost == 1
+    assert stats[None].rtt_avg is None
+
+
+@pytest.mark.parametrize(
+    ["rtts", "arrival", "all_lost", "partial", "reordered"],
+    [
+        ([0.1, 0.1, 0.1], [0, 1, 2], False, False, False),
+        ([None, None, None], [], True, False, False),
+        ([0.1, None, 0.1], [0, 2], False, True, False),
+        ([0.1, 0.1, 0.1], [1, 0, 2], False, False, True),
+    ],
+)
+def test_burst_result(rtts, arrival, all_lost, partial, reordered):
+    r = BurstResult(rtts, arrival)
+    assert r.sent == 3
+    assert r.received == len(arrival)
+    assert r.all_lost is all_lost
+    assert r.partial is partial
+    assert r.reordered is reordered
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_iter_burst():
+    async def inner():
+        async with Ping(timeout=0.2) as ping:
+            res = [
+                r
+                async for r in ping.iter_burst(
+                    "127.0.0.1", interval=0.01, count=2, burst=4
+                )
+            ]
+            res += [
+                r
+                async for r in ping.iter_burst(
+                    "10.255.255.1", interval=None, count=1, burst=2
+                )
+            ]
+            return res, ping.get_stats()[None]
+
+    res, stats = asyncio.run(inner())
+    assert len(res) == 3
+    assert res[0].received == 4
+    assert not res[0].all_lost
+    assert res[2].all_lost
+    assert stats.sent == 10
+    assert stats.received == 8
+    assert stats.bursts == 3
+    assert stats.bursts_all_lost == 1
+    assert stats.bursts_partial == 0
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_schedule_replay():
+    async def inner():
+        recorded = Schedule()
+        async with Ping(timeout=0.2, schedule=recorded) as ping:
+            for size in (64, 128, 96):
+                await ping.ping("127.0.0.1", size=size)
+                await asyncio.sleep(0.02)
+        assert len(recorded) == 3
+        fp = io.StringIO()
+        recorded.dump(fp)
+        fp.seek(0)
+        schedule = Schedule.load(fp)
+        assert schedule.probes == recorded.probes
+        async with Ping(timeout=0.2) as ping:
+            t0 = perf_counter()
+            res = [r async for r in ping.replay(schedule, speed=2.0)]
+            dt = perf_counter() - t0
+            stats = ping.get_stats()[None]
+        return schedule, res, dt, stats
+
+    schedule, res, dt, stats = asyncio.run(inner())
+    assert [probe for probe, _ in res] == schedule.probes
+    assert all(rtt is not None for _, rtt in res)
+    assert [probe.size for probe in schedule.probes] == [64, 128, 96]
+    assert schedule.probes[0].t == 0.0
+    assert dt >= schedule.probes[-1].t / 2.0
+    assert stats.sent == 3
+    assert stats.received == 3
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_latency_budget():
+    async def inner():
+        async with Ping(latency_budget=True) as ping:
+            await ping.ping("127.0.0.1")
+            await ping.ping("::1")
+            await ping.ping("127.0.0.1")
+            return ping.get_latency_budget()
+
+    budget = asyncio.run(inner())
+    for h in budget.values():
+        assert h["count"] == 3
+        assert sum(n for _, n in h["buckets"]) == 3
+        assert h["min"] <= h["p50"] <= h["p99"] <= h["max"]
+
+
+def test_latency_budget_disabled():
+    async def inner():
+        async with Ping() as ping:
+            return ping.get_latency_budget()
+
+    assert asyncio.run(inner()) is None
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("coarse", [False, True])
+def test_calibrate(coarse: bool):
+    async def inner():
+        async with Ping(coarse=coarse) as ping:
+            calibration = await ping.calibrate(count=3, subtract=True)
+            rtt = await ping.ping("127.0.0.1")
+            return calibration, ping.get_calibration(), rtt, ping.get_stats()
+
+    calibration, last, rtt, stats = asyncio.run(inner())
+    assert last is calibration
+    assert calibration.count == 3
+    assert calibration.resolution > 0
+    floor = calibration.get_floor("127.0.0.1")
+    assert floor is not None
+    assert floor <= calibration.median[4]
+    assert stats[None].sent == 1
+    assert stats[None].rtt_min == max(rtt - floor, 0.0)
+
+
+def test_calibration():
+    calibration = Calibration.from_rtts(
+        3, {4: [0.003, None, 0.001, 0.002], 6: [None]}, 0.004
+    )
+    assert calibration.floor == {4: 0.001, 6: None}
+    assert calibration.median == {4: 0.002, 6: None}
+    assert calibration.subtract("127.0.0.1", 0.0015) == pytest.approx(0.0005)
+    assert calibration.subtract("127.0.0.1", 0.0005) == 0.0
+    assert calibration.subtract("::1", 0.0015) == 0.0015
+
+
+def test_calibrate_invalid():
+    async def inner(**kwargs):
+        async with Ping() as ping:
+            await ping.calibrate(**kwargs)
+
+    with pytest.raises(ValueError):
+        asyncio.run(inner(count=0))
+    with pytest.raises(ValueError):
+        asyncio.run(inner(afi=(5,)))
+
+
+def test_schedule_load():
+    fp = io.StringIO(
+        '{"addr": "::1", "t": 0.5, "size": 64, "seq": 2}\n'
+        "\n"
+        '{"addr": "127.0.0.1", "t": 0.0, "size": 64, "seq": 1}\n'
+    )
+    schedule = Schedule.load(fp)
+    assert schedule.probes == [
+        ScheduledProbe("127.0.0.1", 0.0, 64, 1),
+        ScheduledProbe("::1", 0.5, 64, 2),
+    ]
+    # Recording continues after the loaded probes
+    schedule.record("127.0.0.1", 64, 3)
+    assert schedule.probes[-1].t >= 0.5
+    with pytest.raises(ValueError):
+        Schedule.load(io.StringIO('{"addr": "::1"}\n'))
+    with pytest.raises(ValueError):
+        Schedule.load(io.StringIO("garbage\n"))
+
+
+def test_replay_invalid_speed():
+    async def inner():
+        async with Ping() as ping:
+            async for _ in ping.replay(Schedule(), speed=0):
+                pass
+
+    with pytest.raises(ValueError):
+        asyncio.run(inner())
+
+
+@pytest.mark.parametrize(
+    ["pattern", "expected"],
+    [
+        # Every other is lost
+        ("01" * 10, True),
+        # Every third is lost, with jitter of the limiter
+        ("110" * 4 + "1110" + "110" + "1", True),
+        ("1" * 20, False),
+        ("0" * 20, False),
+        # Outage
+        ("1" * 15 + "0" * 5, False),
+        # Random loss
+        ("1101111011111110111" + "1", False),
+    ],
+)
+def test_rate_limit_detector(pattern, expected):
+    d = RateLimitDetector(window=20)
+    r = [d.register(x == "1") for x in pattern]
+    assert any(r) is expected
+    if expected:
+        assert r[-1] is True
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_rate_limit_not_detected():
+    async def inner():
+        async with Ping(rate_limit_backoff=True) as ping:
+            async for _ in ping.iter_rtt(
+                "127.0.0.1", interval=0.001, count=30
+            ):
+                pass
+            return ping.get_rate_limit("127.0.0.1")
+
+    assert asyncio.run(inner()) is None
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("addr", ["127.0.0.1", "::1"])
+def test_get_route(addr):
+    async def inner():
+        async with Ping() as ping:
+            return ping.get_route(addr)
+
+    route = asyncio.run(inner())
+    assert route["gateway"] is None
+    assert route["ifname"] == "lo"
+    assert route["src"] == addr
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_link_monitor():
+    async def inner():
+        async with Ping(link_monitor=True) as ping:
+            down = ping.is_link_down("127.0.0.1")
+            rtt = await ping.ping("127.0.0.1")
+            return down, rtt
+
+    down, rtt = asyncio.run(inner())
+    assert down is False
+    assert rtt is not None
+
+
+def test_link_monitor_disabled():
+    assert Ping().is_link_down("127.0.0.1") is False
+
+
+def test_profile():
+    ping = Ping()
+    profile = ProbeProfile(size=128, dscp=46)
+    assert profile.tos == 184
+    ping.set_profile(["127.0.0.1", "127.0.0.2"], profile)
+    assert ping.get_profile("127.0.0.1") is profile
+    assert ping.get_profile("127.0.0.2") is profile
+    assert ping.get_profile("127.0.0.3") is None
+    ping.set_profile(["127.0.0.2"], None)
+    assert ping.get_profile("127.0.0.2") is None
+
+
+def test_profile_invalid_dscp():
+    with pytest.raises(ValueError):
+        ProbeProfile(dscp=64)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_iter_target():
+    async def inner():
+        async with Ping() as ping:
+            ping.set_profile(
+                ["127.0.0.1"], ProbeProfile(interval=0.01, count=3, ttl=5)
+            )
+            return [rtt async for rtt in ping.iter_target("127.0.0.1")]
+
+    res = asyncio.run(inner())
+    assert len(res) == 3
+    assert all(rtt is not None for rtt in res)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_iter_rtt_window():
+    async def inner():
+        async with Ping() as ping:
+            return [
+                rtt
+                async for rtt in ping.iter_rtt(
+                    "127.0.0.1", interval=0.01, count=10, window=3
+                )
+            ]
+
+    res = asyncio.run(inner())
+    assert len(res) == 10
+    assert all(rtt is not None for rtt in res)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_iter_rtt_window_not_bound_by_timeout():
+    async def inner():
+        async with Ping(timeout=0.5) as ping:
+            t0 = perf_counter()
+            res = [
+                rtt
+                async for rtt in ping.iter_rtt(
+                    "10.255.255.1", interval=0.05, count=4, window=4
+                )
+            ]
+            return res, perf_counter() - t0
+
+    res, dt = asyncio.run(inner())
+    assert res == [None] * 4
+    # Sequential probing takes 4 timeouts
+    assert dt < 1.0
+
+
+def test_profile_invalid_window():
+    with pytest.raises(ValueError):
+        ProbeProfile(window=0)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_replace_profile():
+    async def inner():
+        async with Ping() as ping:
+            p1 = ProbeProfile(timeout=0.1)
+            p2 = ProbeProfile(timeout=0.2)
+            ping.set_profile(["127.0.0.1", "10.255.255.1"], p1)
+            t0 = perf_counter()
+            assert await ping.ping("10.255.255.1") is None
+            t1 = perf_counter()
+            assert t1 - t0 < 0.2
+            with pytest.raises(ValueError):
+                ping.set_profile(["127.0.0.2"], p2)
+                ping.replace_profile(p1, p2)
+            ping.set_profile(["127.0.0.2"], None)
+            ping.replace_profile(p1, p2)
+            assert ping.get_profile("127.0.0.1") is p2
+            assert ping.get_profile("10.255.255.1") is p2
+            assert await ping.ping("10.255.255.1") is None
+            assert perf_counter() - t1 >= 0.2
+            assert await ping.ping("127.0.0.1") is not None
+
+    asyncio.run(inner())
+
+
+def test_register_target():
+    ping = Ping()
+    profile = ProbeProfile()
+    ping.register_target("127.0.0.1", profile=profile, tag="t1")
+    assert ping.is_registered("127.0.0.1")
+    assert ping.get_profile("127.0.0.1") is profile
+    assert ping.get_tag("127.0.0.1") == "t1"
+    assert ping.unregister_target("127.0.0.1") == 0
+    assert not ping.is_registered("127.0.0.1")
+    assert ping.get_profile("127.0.0.1") is None
+    assert ping.get_tag("127.0.0.1") is None
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_unregister_target():
+    async def inner():
+        async with Ping(timeout=5.0) as ping:
+            ping.register_target("10.255.255.1", tag="t1")
+            ping.register_target("127.0.0.1", tag="t1")
+            task = asyncio.create_task(ping.ping("10.255.255.1"))
+            await asyncio.sleep(0.1)
+            t0 = perf_counter()
+            assert ping.unregister_target("10.255.255.1") == 1
+            assert await task is None
+            assert perf_counter() - t0 < 1.0
+            assert await ping.ping("127.0.0.1") is not None
+            return ping.get_stats()
+
+    stats = asyncio.run(inner())
+    assert stats["t1"].sent == 1
+    assert None not in stats
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_unregister_iter_target():
+    async def inner():
+        async with Ping(timeout=5.0) as ping:
+            ping.register_target(
+                "10.255.255.1", profile=ProbeProfile(interval=0.1)
+            )
+            loop = asyncio.get_running_loop()
+            loop.call_later(0.2, ping.unregister_target, "10.255.255.1")
+            return [rtt async for rtt in ping.iter_target("10.255.255.1")]
+
+    assert asyncio.run(inner()) == []
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_batching():
+    async def inner():
+        async with Ping(batch_size=3, batch_delay=0.05) as ping:
+            t0 = perf_counter()
+            rtt = await ping.ping("127.0.0.1")
+            dt = perf_counter() - t0
+            assert rtt is not None
+            assert dt >= 0.05
+            res = await asyncio.gather(
+                *(ping.ping("127.0.0.1") for _ in range(6))
+            )
+            assert all(rtt is not None for rtt in res)
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_json_lines_emitter():
+    async def inner():
+        async with Ping(ttl=10, timeout=0.1, emitter=emitter) as ping:
+            ping.set_tag("127.0.0.1", "t1")
+            await ping.ping("127.0.0.1")
+            await ping.ping("10.255.255.1")
+
+    rx, tx = os.pipe()
+    emitter = JsonLinesEmitter(tx)
+    asyncio.run(inner())
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r1, r2 = [json.loads(line) for line in f]
+    assert r1["target"] == "127.0.0.1"
+    assert r1["status"] == "ok"
+    assert r1["rtt_ns"] > 0
+    assert r1["ttl"] == 10
+    assert r1["tag"] == "t1"
+    assert 0 <= r1["seq"] <= 0xFFFF
+    assert r2["target"] == "10.255.255.1"
+    assert r2["status"] == "timeout"
+    assert r2["rtt_ns"] is None
+    assert "tag" not in r2
+    assert r1["ts"] <= r2["ts"]
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_json_lines_emitter_correlation_id():
+    cid = uuid.UUID("12345678-1234-5678-1234-567812345678")
+
+    async def inner():
+        async with Ping(timeout=0.1, emitter=emitter) as ping:
+            ping.set_correlation_id("127.0.0.1", cid)
+            await ping.ping("127.0.0.1")
+            await ping.ping("127.0.0.1", correlation_id=b"\xde\xad")
+            ping.unregister_target("127.0.0.1")
+            await ping.ping("127.0.0.1")
+
+    rx, tx = os.pipe()
+    emitter = JsonLinesEmitter(tx)
+    asyncio.run(inner())
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r1, r2, r3 = [json.loads(line) for line in f]
+    assert r1["correlation_id"] == str(cid)
+    assert r2["correlation_id"] == "dead"
+    assert "correlation_id" not in r3
+
+
+class RecordEmitter(BaseEmitter):
+    def __init__(self):
+        self.results = []
+        self.anomalous = []
+        self.verdicts = []
+
+    def emit(self, result):
+        self.results.append(
+            (result.target, result.rtt is not None, result.suppressed)
+        )
+        self.anomalous.append(result.anomalous)
+
+    def emit_verdict(self, verdict):
+        self.verdicts.append((verdict.target, verdict.down, verdict.tag))
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_health_check():
+    async def inner():
+        async with Ping(timeout=0.1, emitter=emitter) as ping:
+            ping.set_tag("10.255.255.1", "t1")
+            ping.set_health_check(["10.255.255.1"], "lost >= 2 of 3")
+            assert ping.is_down("10.255.255.1") is None
+            for _ in range(3):
+                await ping.ping("10.255.255.1")
+            r = ping.is_down("10.255.255.1")
+            ping.set_health_check(["10.255.255.1"], None)
+            assert ping.is_down("10.255.255.1") is None
+            return r
+
+    emitter = RecordEmitter()
+    assert asyncio.run(inner()) is True
+    assert emitter.verdicts == [
+        ("10.255.255.1", False, "t1"),
+        ("10.255.255.1", True, "t1"),
+    ]
+
+
+@pytest.mark.parametrize(
+    "policy", ["", "lost > 1", "p95 > 1h", "avg > 1ms or", "jitter > 1ms"]
+)
+def test_health_check_invalid(policy):
+    with pytest.raises(ValueError):
+        Ping().set_health_check(["127.0.0.1"], policy)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_maintenance():
+    async def inner():
+        async with Ping(emitter=emitter) as ping:
+            now = time.time()
+            ping.add_maintenance(["127.0.0.1"], now - 1, now + 60)
+            ping.add_maintenance(["127.0.0.1"], now - 60, now - 30)
+            assert ping.get_maintenance("127.0.0.1") == [
+                (now - 1, now + 60),
+                (now - 60, now - 30),
+            ]
+            assert ping.is_suppressed("127.0.0.1")
+            # Ended window is removed
+            assert ping.get_maintenance("127.0.0.1") == [(now - 1, now + 60)]
+            await ping.ping("127.0.0.1")
+            ping.clear_maintenance(["127.0.0.1"])
+            assert not ping.is_suppressed("127.0.0.1")
+            assert ping.get_maintenance("127.0.0.1") == []
+            await ping.ping("127.0.0.1")
+
+    emitter = RecordEmitter()
+    asyncio.run(inner())
+    assert emitter.results == [
+        ("127.0.0.1", True, True),
+        ("127.0.0.1", True, False),
+    ]
+
+
+def test_maintenance_invalid():
+    with pytest.raises(ValueError):
+        Ping().add_maintenance(["127.0.0.1"], 100.0, 100.0)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_anomaly():
+    async def inner():
+        async with Ping(anomaly_threshold=3.0, emitter=emitter) as ping:
+            # Learned baseline of 10s ± 1ms
+            ping.import_baselines(
+                struct.pack("!BIddQH", 1, 1, 1e10, 1e6, 100, 9)
+                + b"127.0.0.1"
+            )
+            assert ping.get_baseline("127.0.0.1") == (10.0, 0.001)
+            assert not ping.is_anomalous("127.0.0.1")
+            await ping.ping("127.0.0.1")
+            assert ping.is_anomalous("127.0.0.1")
+            avg, mad = ping.get_baseline("127.0.0.1")
+            assert avg < 10.0
+            assert mad > 0.001
+            return ping.export_baselines()
+
+    emitter = RecordEmitter()
+    state = asyncio.run(inner())
+    assert emitter.anomalous == [True]
+    ping = Ping(anomaly_threshold=3.0)
+    ping.import_baselines(state)
+    assert ping.get_baseline("127.0.0.1") is not None
+    assert ping.get_baseline("127.0.0.2") is None
+
+
+def test_anomaly_disabled():
+    ping = Ping()
+    assert ping.get_baseline("127.0.0.1") is None
+    assert not ping.is_anomalous("127.0.0.1")
+    with pytest.raises(RuntimeError):
+        ping.export_baselines()
+
+
+@pytest.mark.parametrize(
+    ("threshold", "alpha"), [(0.0, 0.1), (3.0, 0.0), (3.0, 1.5)]
+)
+def test_anomaly_invalid(threshold, alpha):
+    with pytest.raises(ValueError):
+        Ping(anomaly_threshold=threshold, anomaly_alpha=alpha)
+
+
+def test_import_baselines_invalid():
+    with pytest.raises(ValueError):
+        Ping(anomaly_threshold=3.0).import_baselines(b"\x00")
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_save_stats():
+    async def inner():
+        async with Ping(anomaly_threshold=3.0, timeout=0.1) as ping:
+            ping.set_tag("127.0.0.1", "t1")
+            ping.set_tag("10.255.255.1", 5)
+            await ping.ping("127.0.0.1")
+            await ping.ping("10.255.255.1")
+            await ping.ping("127.0.0.2")
+            return (
+                ping.save_stats(),
+                ping.get_stats(),
+                ping.get_baseline("127.0.0.1"),
+            )
+
+    state, stats, baseline = asyncio.run(inner())
+    ping = Ping(anomaly_threshold=3.0)
+    ping.load_stats(state)
+    restored = ping.get_stats()
+    assert set(restored) == {"t1", 5, None}
+    for tag, st in stats.items():
+        r = restored[tag]
+        for name in PingStats.__slots__:
+            assert getattr(r, name) == getattr(st, name)
+    assert restored[5].rtt_min is None
+    assert ping.get_baseline("127.0.0.1") == baseline
+    # Baselines are ignored
+    Ping().load_stats(state)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_save_stats_invalid_tag():
+    async def inner():
+        async with Ping() as ping:
+            ping.set_tag("127.0.0.1", ("a", 1))
+            await ping.ping("127.0.0.1")
+            with pytest.raises(ValueError):
+                ping.save_stats()
+
+    asyncio.run(inner())
+
+
+def test_load_stats_invalid():
+    with pytest.raises(ValueError):
+        Ping().load_stats(b"\x01\x00")
+
+
+CONFIG = """
+[ping]
+timeout = 2
+anomaly_threshold = 3
+
+[profile.core]
+interval = 0.5
+dscp = 46
+health_check = "lost >= 3 of 5"
+
+[[target]]
+address = "127.0.0.1"
+profile = "core"
+tag = "router"
+
+[[target]]
+address = "127.0.0.2"
+profile = "core"
+health_check = "lost >= 1 of 1"
+
+[[target]]
+address = "::1"
+"""
+
+
+def _from_config(text, **kwargs):
+    with tempfile.NamedTemporaryFile("w", suffix=".toml") as f:
+        f.write(text)
+        f.flush()
+        return Ping.from_config(f.name, **kwargs)
+
+
+def test_from_config():
+    emitter = RecordEmitter()
+    ping = _from_config(CONFIG, emitter=emitter)
+    for addr in ("127.0.0.1", "127.0.0.2", "::1"):
+        assert ping.is_registered(addr)
+    profile = ping.get_profile("127.0.0.1")
+    assert profile is ping.get_profile("127.0.0.2")
+    assert profile.interval == 0.5
+    assert profile.dscp == 46
+    assert ping.get_profile("::1") is None
+    assert ping.get_tag("127.0.0.1") == "router"
+    assert ping.get_tag("::1") is None
+    # Anomaly detection is enabled
+    assert ping.get_baseline("127.0.0.1") is None
+    ping.export_baselines()
+
+
+@pytest.mark.parametrize(
+    ("text", "msg"),
+    [
+        ("[ping]\nfoo = 1", "line 2: unknown option `foo`"),
+        ("[[target]]\naddress = \"x\"", "target #1: invalid address `x`"),
+        ("[profile.x]\ndscp = 64", "line 2: `dscp` must be in 0..63 range"),
+    ],
+)
+def test_from_config_invalid(text, msg):
+    with pytest.raises(ValueError) as e:
+        _from_config(text)
+    assert str(e.value) == msg
+
+
+RELOADED_CONFIG = """
+[profile.core]
+interval = 0.25
+dscp = 46
+health_check = "lost >= 3 of 5"
+
+[[target]]
+address = "127.0.0.1"
+profile = "core"
+tag = "switch"
+
+[[target]]
+address = "127.0.0.2"
+profile = "core"
+health_check = "lost >= 1 of 1"
+
+[[target]]
+address = "127.0.0.3"
+"""
+
+
+def _write(path, text):
+    # Replace atomically, as editors do
+    with open(path + ".tmp", "w") as f:
+        f.write(text)
+    os.replace(path + ".tmp", path)
+
+
+def test_reload_config():
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "ping.toml")
+        _write(path, CONFIG)
+        ping = Ping.from_config(path)
+        assert ping.reload_config(path) == ([], [], [])
+        profile = ping.get_profile("127.0.0.1")
+        _write(path, RELOADED_CONFIG)
+        assert ping.reload_config(path) == (
+            ["127.0.0.3"],
+            ["::1"],
+            ["127.0.0.1", "127.0.0.2"],
+        )
+        assert not ping.is_registered("::1")
+        assert ping.is_registered("127.0.0.3")
+        assert ping.get_tag("127.0.0.1") == "switch"
+        new_profile = ping.get_profile("127.0.0.1")
+        assert new_profile is not profile
+        assert new_profile is ping.get_profile("127.0.0.2")
+        assert new_profile.interval == 0.25
+        # Invalid configuration is not applied
+        _write(path, "[[target]]\naddress = \"x\"")
+        with pytest.raises(ValueError):
+            ping.reload_config(path)
+        assert ping.is_registered("127.0.0.3")
+
+
+TWO_PROFILES_CONFIG = """
+[profile.a]
+interval = 0.5
+
+[profile.b]
+interval = 0.5
+dscp = 10
+
+[[target]]
+address = "127.0.0.1"
+profile = "a"
+
+[[target]]
+address = "127.0.0.2"
+profile = "b"
+"""
+
+
+def test_reload_config_atomic():
+    ping = _from_config(TWO_PROFILES_CONFIG)
+    profile = ping.get_profile("127.0.0.1")
+    config = _fast.parse_config(
+        TWO_PROFILES_CONFIG.replace("interval = 0.5", "interval = 0.25")
+    )
+    # Passed Rust validation, rejected by ProbeProfile
+    config["profiles"]["b"]["dscp"] = 99
+    with pytest.raises(ValueError):
+        ping._Ping__apply_config(config)
+    # Preceding profile is not replaced
+    assert ping.get_profile("127.0.0.1") is profile
+    assert profile.interval == 0.5
+
+
+def test_watch_config():
+    async def inner():
+        ping = Ping.from_config(path)
+        ping.watch_config(path)
+        _write(path, RELOADED_CONFIG)
+        await asyncio.sleep(0.1)
+        assert ping.is_registered("127.0.0.3")
+        assert not ping.is_registered("::1")
+        # Invalid configuration is logged and ignored
+        _write(path, "[ping]\nfoo = 1")
+        await asyncio.sleep(0.1)
+        assert ping.is_registered("127.0.0.3")
+        _write(path, CONFIG)
+        os.kill(os.getpid(), signal.SIGHUP)
+        await asyncio.sleep(0.1)
+        assert ping.is_registered("::1")
+        await ping.close()
+
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "ping.toml")
+        _write(path, CONFIG)
+        asyncio.run(inner())
+
+
+def test_json_lines_emitter_verdict():
+    rx, tx = os.pipe()
+    emitter = JsonLinesEmitter(tx)
+    emitter.emit_verdict(VerdictChange("127.0.0.1", True, tag="t1"))
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r = json.loads(f.read())
+    assert r["target"] == "127.0.0.1"
+    assert r["verdict"] == "down"
+    assert r["tag"] == "t1"
+
+
+def test_json_lines_emitter_suppressed():
+    rx, tx = os.pipe()
+    emitter = JsonLinesEmitter(tx)
+    emitter.emit_verdict(VerdictChange("127.0.0.1", True, suppressed=True))
+    emitter.emit(ProbeResult("127.0.0.1", 1, None, suppressed=True))
+    emitter.emit(ProbeResult("127.0.0.1", 2, None))
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r1, r2, r3 = [json.loads(line) for line in f]
+    assert r1["verdict"] == "down"
+    assert r1["suppressed"] is True
+    assert r2["suppressed"] is True
+    assert "suppressed" not in r3
+
+
+def test_json_lines_emitter_anomalous():
+    rx, tx = os.pipe()
+    emitter = JsonLinesEmitter(tx)
+    emitter.emit(ProbeResult("127.0.0.1", 1, 0.5))
+    emitter.emit(ProbeResult("127.0.0.1", 2, 0.5, anomalous=True))
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r1, r2 = [json.loads(line) for line in f]
+    assert "anomalous" not in r1
+    assert r2["anomalous"] is True
+
+
+def test_influx_emitter_verdict():
+    rx, tx = os.pipe()
+    emitter = InfluxEmitter(fd=tx)
+    emitter.emit_verdict(VerdictChange("127.0.0.1", True, tag="t1"))
+    emitter.emit_verdict(VerdictChange("::1", False))
+    emitter.close()
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r1, r2 = [line.rsplit(" ", 1)[0] for line in f.read().splitlines()]
+    assert r1 == 'ping_verdict,target=127.0.0.1,tag=t1 verdict="down"'
+    assert r2 == 'ping_verdict,target=::1 verdict="up"'
+
+
+def test_influx_emitter_suppressed():
+    rx, tx = os.pipe()
+    emitter = InfluxEmitter(fd=tx)
+    emitter.emit_verdict(VerdictChange("::1", False, suppressed=True))
+    emitter.emit(ProbeResult("::1", 1, None, suppressed=True))
+    emitter.close()
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r1, r2 = [line.rsplit(" ", 1)[0] for line in f.read().splitlines()]
+    assert r1 == 'ping_verdict,target=::1 verdict="up",suppressed=true'
+    assert r2 == 'ping,target=::1 seq=1i,status="timeout",suppressed=true'
+
+
+def test_influx_emitter_args():
+    with pytest.raises(ValueError):
+        InfluxEmitter()
+
+
+def test_influx_emitter():
+    rx, tx = os.pipe()
+    emitter = InfluxEmitter(fd=tx)
+    emitter.emit(ProbeResult("127.0.0.1", 1, 0.5, ttl=10, tag="a b"))
+    emitter.emit(ProbeResult("::1", 2, None))
+    emitter.emit_stats({"t1": _stats()})
+    emitter.close()
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        r1, r2, r3 = [line.rsplit(" ", 1) for line in f.read().splitlines()]
+    assert r1[0] == (
+        'ping,target=127.0.0.1,tag=a\\ b seq=1i,status="ok",rtt=0.5,ttl=10i'
+    )
+    assert r2[0] == 'ping,target=::1 seq=2i,status="timeout"'
+    assert r3[0] == (
+        "ping_stats,tag=t1 sent=2i,received=1i,lost=1i,"
+        "rtt_min=0.5,rtt_avg=0.5,rtt_max=0.5"
+    )
+    assert int(r1[1]) <= int(r2[1])
+
+
+def test_influx_emitter_correlation_id():
+    rx, tx = os.pipe()
+    emitter = InfluxEmitter(fd=tx)
+    emitter.emit(ProbeResult("127.0.0.1", 1, None, correlation_id=b"\xbe\xef"))
+    emitter.close()
+    os.close(tx)
+    with os.fdopen(rx) as f:
+        line = f.read().rsplit(" ", 1)[0]
+    assert line == (
+        'ping,target=127.0.0.1 seq=1i,status="timeout",'
+        'correlation_id="beef"'
+    )
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_iter_rtt_txtime():
+    async def inner():
+        async with Ping(txtime=time.CLOCK_MONOTONIC) as ping:
+            return [
+                rtt
+                async for rtt in ping.iter_rtt(
+                    "127.0.0.1", interval=0.1, count=3
+                )
+            ]
+
+    t0 = perf_counter()
+    res = asyncio.run(inner())
+    dt = perf_counter() - t0
+    assert len(res) == 3
+    assert all(rtt is not None for rtt in res)
+    assert 0.2 <= dt < 0.5
+
+
+def _stats():
+    stats = PingStats()
+    stats.register(0.5)
+    stats.register(None)
+    return stats
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_socket_activation():
+    script = (
+        "import asyncio\n"
+        "from gufo.ping import Ping\n"
+        "from gufo.ping._fast import listen_fds\n"
+        "assert listen_fds() == {4: 3}\n"
+        "async def main():\n"
+        "    async with Ping(socket_activation=True) as p:\n"
+        "        assert await p.ping('127.0.0.1') is not None\n"
+        "asyncio.run(main())\n"
+    )
+    sock = socket.socket(socket.AF_INET, socket.SOCK_RAW, socket.IPPROTO_ICMP)
+    fd = sock.fileno()
+    # Pass the socket as fd 3 and set LISTEN_PID to the process' pid
+    subprocess.run(
+        [
+            "sh",
+            "-c",
+            f'LISTEN_PID=$$ LISTEN_FDS=1 exec "$0" -c "$1" 3<&{fd}',
+            sys.executable,
+            script,
+        ],
+        pass_fds=(fd,),
+        check=True,
+    )
+    sock.close()
+
+
+
+@pytest.mark.skipif(os.getuid() != 0, reason="Requires root")
+def test_drop_privileges():
+    script = (
+        "import asyncio, os, pwd, socket\n"
+        "from gufo.ping import Ping\n"
+        "async def main():\n"
+        "    async with Ping() as p:\n"
+        "        p.drop_privileges(user='nobody', afi=(4,))\n"
+        "        assert os.getuid() == pwd.getpwnam('nobody').pw_uid\n"
+        "        assert await p.ping('127.0.0.1') is not None\n"
+        "        try:\n"
+        "            socket.socket(socket.AF_INET, socket.SOCK_RAW, 1)\n"
+        "        except PermissionError:\n"
+        "            pass\n"
+        "        else:\n"
+        "            raise AssertionError('raw socket opened')\n"
+        "asyncio.run(main())\n"
+    )
+    subprocess.run([sys.executable, "-c", script], check=True)
+
+
+def test_drop_privileges_threads():
+    # Separate process, so capabilities are not dropped on failure
+    script = (
+        "import asyncio, threading\n"
+        "from gufo.ping import Ping\n"
+        "async def main():\n"
+        "    done = threading.Event()\n"
+        "    t = threading.Thread(target=done.wait)\n"
+        "    t.start()\n"
+        "    try:\n"
+        "        async with Ping() as p:\n"
+        "            p.drop_privileges(afi=())\n"
+        "    except OSError as e:\n"
+        "        assert 'multi-threaded' in str(e)\n"
+        "    else:\n"
+        "        raise AssertionError('privileges dropped')\n"
+        "    finally:\n"
+        "        done.set()\n"
+        "        t.join()\n"
+        "asyncio.run(main())\n"
+    )
+    subprocess.run([sys.executable, "-c", script], check=True)
+
+
+def test_drop_privileges_unknown_user():
+    async def inner():
+        async with Ping() as p:
+            with pytest.raises(KeyError):
+                p.drop_privileges(user="gufo-ping-no-such-user", afi=())
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_enable_sandbox():
+    script = (
+        "import asyncio, socket\n"
+        "from gufo.ping import Ping\n"
+        "async def main():\n"
+        "    async with Ping() as p:\n"
+        "        p.enable_sandbox(afi=(4,))\n"
+        "        for _ in range(3):\n"
+        "            assert await p.ping('127.0.0.1') is not None\n"
+        "        for f in (\n"
+        "            lambda: open('/etc/hostname'),\n"
+        "            lambda: socket.socket(),\n"
+        "        ):\n"
+        "            try:\n"
+        "                f()\n"
+        "            except PermissionError:\n"
+        "                pass\n"
+        "            else:\n"
+        "                raise AssertionError('not denied')\n"
+        "asyncio.run(main())\n"
+    )
+    subprocess.run([sys.executable, "-c", script], check=True)
+
+
+@pytest.mark.parametrize(
+    "method", ["drop_privileges", "enable_sandbox", "restrict_sockets"]
+)
+def test_restrict_rebuild(method):
+    with pytest.raises(ValueError):
+        getattr(Ping(rebuild=True), method)()
+
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_restrict_sockets():
+    script = (
+        "import asyncio, socket, threading\n"
+        "from gufo.ping import Ping\n"
+        "def check():\n"
+        "    socket.socket(socket.AF_INET, socket.SOCK_STREAM).close()\n"
+        "    for args in (\n"
+        "        (socket.AF_INET, socket.SOCK_RAW, socket.IPPROTO_ICMP),\n"
+        "        (socket.AF_PACKET, socket.SOCK_RAW, 0),\n"
+        "    ):\n"
+        "        try:\n"
+        "            socket.socket(*args)\n"
+        "        except PermissionError:\n"
+        "            pass\n"
+        "        else:\n"
+        "            raise AssertionError('not denied')\n"
+        "async def main():\n"
+        "    async with Ping() as p:\n"
+        "        p.restrict_sockets(afi=(4,))\n"
+        "        assert await p.ping('127.0.0.1') is not None\n"
+        "        assert p.get_route('127.0.0.1') is not None\n"
+        "        check()\n"
+        "        await asyncio.get_running_loop().run_in_executor(\n"
+        "            None, check\n"
+        "        )\n"
+        "asyncio.run(main())\n"
+    )
+    subprocess.run([sys.executable, "-c", script], check=True)
+
+
+def test_socket_activation_foreign():
+    os.environ["LISTEN_PID"] = str(os.getpid() + 1)
+    os.environ["LISTEN_FDS"] = "1"
+    try:
+        assert _fast.listen_fds() == {}
+    finally:
+        del os.environ["LISTEN_PID"]
+        del os.environ["LISTEN_FDS"]
+
+
+def test_notify_ready():
+    async def inner():
+        ping = Ping()
+        assert ping.notify_ready(status="probing") is True
+        await asyncio.sleep(0.12)
+        await ping.close()
+
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "notify")
+        sock = socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM)
+        sock.bind(path)
+        os.environ["NOTIFY_SOCKET"] = path
+        os.environ["WATCHDOG_USEC"] = "100000"
+        try:
+            asyncio.run(inner())
+        finally:
+            del os.environ["NOTIFY_SOCKET"]
+            del os.environ["WATCHDOG_USEC"]
+        sock.setblocking(False)
+        states = []
+        while True:
+            try:
+                states.append(sock.recv(1024).decode())
+            except BlockingIOError:
+                break
+        sock.close()
+    assert states[0] == "READY=1\nSTATUS=probing"
+    assert states[-1] == "STOPPING=1"
+    assert states[1:-1].count("WATCHDOG=1") >= 2
+
+
+def test_notify_ready_disabled():
+    async def inner():
+        ping = Ping()
+        assert ping.notify_ready() is False
+        await ping.close()
+
+    assert "NOTIFY_SOCKET" not in os.environ
+    asyncio.run(inner())
@@ -6,12 +6,51 @@
 
 # Python modules
 import asyncio
+import errno
+import os
+import socket
+import struct
+import subprocess
+import sys
+import tempfile
+import threading
+import time
 
 # Third-party modules
 import pytest
 
 # Gufo Labs modules
+from gufo.ping import (
+    DEBUG_RX,
+    DEBUG_TX,
+    ECN_ECT0,
+    INVALID_RTT_CLAMP,
+    INVALID_RTT_ERROR,
+    INVALID_RTT_RAW,
+    JITTER_EXPONENTIAL,
+    JITTER_UNIFORM,
+    MIMIC_LINUX,
+    MIMIC_WINDOWS,
+    OVERLOAD_EVICT,
+    OVERLOAD_REJECT,
+    PADDING_RANDOM,
+    RECORD_INVALID_RTT,
+    RECORD_PROHIBITED,
+    RECORD_REPLY,
+    RECORD_TIMEOUT,
+    REQUEST_ID_CALLER,
+    REQUEST_ID_RANDOM,
+    REQUEST_ID_SIGNATURE,
+    RTT_MS,
+    RTT_NS,
+    RTT_S,
+    TS_NONCE,
+    TS_OBFUSCATED,
+    TS_PLAIN,
+)
 from gufo.ping.socket import PingSocket
+from gufo.ping.options import ProtoOptions
+from gufo.ping._fast import SocketWrapper
 from .util import is_denied
 
 
@@ -64,3 +103,1457 @@ def test_clean_ip(afi, addr, expected):
             s.clean_ip(addr)
 
     asyncio.run(inner_ok() if expected else inner_fail())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(
+    ["policy", "expected"],
+    [
+        (REQUEST_ID_CALLER, True),
+        (REQUEST_ID_RANDOM, True),
+        (REQUEST_ID_SIGNATURE, True),
+        (100, False),
+    ],
+)
+def test_request_id_policy(policy, expected):
+    async def inner_ok():
+        s = PingSocket(afi=4, request_id_policy=policy)
+        rtt = await s.ping("127.0.0.1", request_id=1, seq=1)
+        assert rtt is not None
+
+    async def inner_fail():
+        with pytest.raises(ValueError):
+            PingSocket(afi=4, request_id_policy=policy)
+
+    asyncio.run(inner_ok() if expected else inner_fail())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("afi", [4, 6])
+def test_get_options(afi):
+    async def inner():
+        s = PingSocket(
+            afi=afi, timeout=2.0, coarse=True, busy_poll=50, incoming_cpu=0
+        )
+        opts = s.get_options()
+        assert opts["busy_poll"] in (50, None)
+        assert opts["napi_id"] is None
+        assert opts["incoming_cpu"] in (0, None)
+        assert opts["timeout"] == 2_000_000_000
+        assert opts["coarse"] is True
+        assert opts["accelerated"] is True
+        assert opts["recv_buffer_size"] > 0
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("drain", [False, True])
+def test_close(drain):
+    async def inner():
+        s = PingSocket(afi=4)
+        task = asyncio.create_task(s.ping("127.0.0.1"))
+        await asyncio.sleep(0)
+        await s.close(drain=drain)
+        rtt = await task
+        if drain:
+            assert rtt is not None
+        # Second close is no-op
+        await s.close()
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_context_manager():
+    async def inner():
+        async with PingSocket(afi=4) as s:
+            assert not s.is_closed
+        assert s.is_closed
+        with pytest.raises(OSError):
+            await s.ping("127.0.0.1")
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_wrapper_context_manager():
+    with SocketWrapper(4) as s:
+        assert not s.is_closed
+    assert s.is_closed
+    with pytest.raises(OSError):
+        s.recv()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("afi", [4, 6])
+def test_reconfigure(afi):
+    async def inner():
+        s = PingSocket(afi=afi)
+        r = s.reconfigure(timeout=2.0, ttl=32, tos=0x20)
+        assert r == {"timeout": 2_000_000_000, "ttl": 32, "tos": 0x20}
+        opts = s.get_options()
+        assert opts["ttl"] == 32
+        # Failed batch is rolled back
+        with pytest.raises(OverflowError):
+            s.reconfigure(ttl=16, incoming_cpu=-1)
+        assert s.get_options()["ttl"] == 32
+        r = s.reconfigure(ecn=ECN_ECT0, recv_tos=True, recv_pktinfo=True)
+        assert r == {"ecn": ECN_ECT0, "recv_tos": True, "recv_pktinfo": True}
+        assert s.get_options()["tos"] == 0x20 | ECN_ECT0
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_reconfigure_coarse():
+    s = SocketWrapper(4)
+    s.set_timeout(1_000_000_000)
+    s.send("192.0.2.77", 1, 1, 64)
+    for coarse in (True, False, True):
+        t0 = s.get_clock()
+        s.reconfigure({"coarse": coarse})
+        # Timeline is continued, pending session is not expired
+        assert 0 <= s.get_clock() - t0 < 100_000_000
+        assert s.get_expired() is None
+        assert 0 < s.get_next_deadline() - s.get_clock() <= 1_000_000_000
+    assert s.get_pending_count() == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("afi", [4, 6])
+def test_export_import_state(afi):
+    addr = "127.0.0.1" if afi == 4 else "::1"
+    s1 = SocketWrapper(afi)
+    s1.set_accelerated(True)
+    sid = s1.send(addr, 1, 1, 64)
+    state = s1.export_state()
+    # Pass the descriptor to the new wrapper
+    s2 = SocketWrapper.from_fd(afi, os.dup(s1.get_fd()))
+    s1.close()
+    s2.import_state(state)
+    assert s2.export_state()[2:10] == state[2:10]  # Signature
+    time.sleep(0.1)
+    r = s2.recv()
+    assert r and sid in r
+    assert r[sid] < 1_000_000_000
+    s2.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("afi", [4, 6])
+def test_handover(afi):
+    addr = "127.0.0.1" if afi == 4 else "::1"
+    # Replacement socket, receiving replies to the old one
+    s2 = SocketWrapper(afi)
+    s1 = SocketWrapper(afi)
+    old_sid = s1.send(addr, 1, 1, 64)
+    state = s1.export_state()
+    s1.close()
+    s2.import_state(state, 1_000_000_000)
+    assert s2.export_state()[2:10] != state[2:10]  # Own signature
+    sid = s2.send(addr, 2, 2, 64)
+    time.sleep(0.1)
+    r = s2.recv()
+    assert r and old_sid in r and sid in r
+    s2.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("afi", [4, 6])
+def test_handover_filter(afi):
+    addr = "127.0.0.1" if afi == 4 else "::1"
+    s1 = SocketWrapper(afi)
+    s1.set_accelerated(True)
+    s2 = SocketWrapper(afi)
+    s2.import_state(s1.export_state(), 1_000_000_000)
+    # Reply to the old socket passes the new socket's filter
+    s1.send(addr, 1, 1, 64)
+    time.sleep(0.1)
+    s2.recv()
+    assert s2.get_counters()["received"] == 1
+    s1.close()
+    s2.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(
+    ("ts_mode", "mimic"), [(TS_NONCE, None), (TS_PLAIN, MIMIC_LINUX)]
+)
+def test_handover_nonce(ts_mode, mimic):
+    def get_socket():
+        s = SocketWrapper(4)
+        s.set_ts_mode(ts_mode)
+        if mimic is not None:
+            s.set_mimic(mimic)
+        s.set_strict_size(True)
+        return s
+
+    s2 = get_socket()
+    s1 = get_socket()
+    old_sid = s1.send("127.0.0.1", 1, 1, 64)
+    state = s1.export_state()
+    s1.close()
+    s2.import_state(state, 1_000_000_000)
+    sid = s2.send("127.0.0.1", 2, 2, 64)
+    time.sleep(0.1)
+    r = s2.recv()
+    # Nonce and size of the old request are handed over
+    assert r and old_sid in r and sid in r
+    counters = s2.get_counters()
+    assert counters["signature_mismatch"] == 0
+    assert counters["size_mismatch"] == 0
+    s2.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_handover_expired():
+    s2 = SocketWrapper(4)
+    s1 = SocketWrapper(4)
+    s1.send("127.0.0.1", 1, 1, 64)
+    state = s1.export_state()
+    s1.close()
+    s2.import_state(state, 0)
+    time.sleep(0.1)
+    assert not s2.recv()
+    assert s2.get_counters()["signature_mismatch"] == 1
+    s2.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_handover_pending():
+    s1 = SocketWrapper(4)
+    s1.send("192.0.2.1", 1, 1, 64)
+    s2 = SocketWrapper(4)
+    s2.send("192.0.2.2", 1, 1, 64)
+    with pytest.raises(ValueError):
+        s2.import_state(s1.export_state(), 1_000_000_000)
+    s1.close()
+    s2.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_get_pending():
+    s = SocketWrapper(4)
+    assert s.get_pending_count() == 0
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    assert s.get_pending_count() == 1
+    (p_sid, target, left), *_ = s.get_pending()
+    assert p_sid == sid
+    assert target == "127.0.0.1"
+    assert 0 < left <= 1_000_000_000
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_cancel():
+    s = SocketWrapper(4)
+    sid = s.send("192.0.2.1", 1, 1, 64)
+    s.send("192.0.2.2", 1, 1, 64)
+    assert s.cancel("192.0.2.1") == [sid]
+    assert s.cancel("192.0.2.1") == []
+    assert s.get_pending_count() == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_batching():
+    s = SocketWrapper(4)
+    s.set_batching(2, 1_000_000_000)
+    sid1 = s.send("127.0.0.1", 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    assert s.recv() is None
+    assert s.next_flush_ns() > time.monotonic_ns()
+    sid2 = s.send("127.0.0.1", 1, 2, 64)
+    assert s.poll(1_000_000_000)
+    assert set(s.recv()) == {sid1, sid2}
+    assert s.next_flush_ns() is None
+    assert s.flush() is None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_recv_into():
+    s = SocketWrapper(4)
+    seen = []
+    assert s.recv_into(seen) == 0
+    sid1 = s.send("127.0.0.1", 1, 1, 64)
+    sid2 = s.send("127.0.0.1", 1, 2, 64)
+    time.sleep(0.05)
+    assert s.recv_into(seen) == 2
+    assert {sid for sid, _ in seen} == {sid1, sid2}
+    assert all(rtt > 0 for _, rtt in seen)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_batching_delay():
+    s = SocketWrapper(4)
+    s.set_batching(100, 50_000_000)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    assert s.recv() is None
+    seen, _ = s.wait()
+    assert list(seen) == [sid]
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_result_fd():
+    def read_record():
+        (length,) = struct.unpack("!H", rx.recv(2))
+        data = rx.recv(length)
+        version, status, afi = struct.unpack("!BBB", data[:3])
+        assert version == 1
+        assert afi == 4
+        addr = socket.inet_ntoa(data[3:7])
+        request_id, seq, rtt, ts = struct.unpack("!HHQQ", data[7:])
+        return status, addr, request_id, seq, rtt, ts
+
+    rx, tx = socket.socketpair()
+    s = SocketWrapper(4)
+    s.set_result_fd(tx.fileno())
+    s.set_timeout(100_000_000)
+    t0 = time.time_ns()
+    s.send("127.0.0.1", 1, 2, 64)
+    s.send("192.0.2.1", 1, 3, 64)
+    assert s.poll(1_000_000_000)
+    s.recv()
+    status, addr, request_id, seq, rtt, ts = read_record()
+    assert status == RECORD_REPLY
+    assert (addr, request_id, seq) == ("127.0.0.1", 1, 2)
+    assert rtt > 0
+    assert t0 <= ts <= time.time_ns()
+    time.sleep(0.2)
+    s.get_expired()
+    status, addr, request_id, seq, rtt, _ = read_record()
+    assert status == RECORD_TIMEOUT
+    assert (addr, request_id, seq, rtt) == ("192.0.2.1", 1, 3, 0)
+    assert s.get_counters()["result_drops"] == 0
+    s.close()
+    rx.close()
+    tx.close()
+
+
+def read_log(path):
+    with open(path, "rb") as f:
+        data = f.read()
+    r = []
+    while data:
+        (length,) = struct.unpack("!H", data[:2])
+        _, status, _ = struct.unpack("!BBB", data[2:5])
+        seq, rtt = struct.unpack("!HQ", data[11:21])
+        r.append((status, seq, rtt > 0))
+        data = data[2 + length :]
+    return r
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_result_log():
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "results.log")
+        s = SocketWrapper(4)
+        s.set_result_log(path)
+        s.set_timeout(100_000_000)
+        s.send("127.0.0.1", 1, 2, 64)
+        s.send("192.0.2.77", 1, 3, 64)
+        assert s.poll(1_000_000_000)
+        s.recv()
+        # Written without closing the socket
+        assert read_log(path) == [(RECORD_REPLY, 2, True)]
+        time.sleep(0.2)
+        s.get_expired()
+        s.close()
+        assert read_log(path) == [
+            (RECORD_REPLY, 2, True),
+            (RECORD_TIMEOUT, 3, False),
+        ]
+        assert s.get_counters()["result_log_errors"] == 0
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_result_log_rotate():
+    async def inner():
+        async with PingSocket(
+            timeout=0.1,
+            result_log=path,
+            result_log_max_size=40,
+            result_log_keep=1,
+        ) as sock:
+            for seq in range(3):
+                await sock.ping("127.0.0.1", seq=seq)
+
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "results.log")
+        asyncio.run(inner())
+        assert read_log(path) == [(RECORD_REPLY, 2, True)]
+        assert read_log(path + ".1") == [(RECORD_REPLY, 1, True)]
+        assert sorted(os.listdir(d)) == ["results.log", "results.log.1"]
+
+
+@pytest.mark.parametrize("kwargs", [{"max_size": 0}, {"max_age": 0}])
+def test_result_log_invalid(kwargs):
+    s = SocketWrapper(4)
+    with pytest.raises(ValueError):
+        s.set_result_log("/dev/null", **kwargs)
+    with pytest.raises(OSError):
+        s.set_result_log("/nonexistent/results.log")
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_max_in_flight_reject():
+    s = SocketWrapper(4)
+    s.set_max_in_flight(1, OVERLOAD_REJECT)
+    s.send("127.0.0.1", 1, 1, 64)
+    with pytest.raises(BlockingIOError):
+        s.send("127.0.0.1", 1, 2, 64)
+    assert s.get_pending_count() == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_max_in_flight_evict():
+    s = SocketWrapper(4)
+    s.set_max_in_flight(1, OVERLOAD_EVICT)
+    sid1 = s.send("127.0.0.1", 1, 1, 64)
+    sid2 = s.send("127.0.0.1", 1, 2, 64)
+    assert [x[0] for x in s.get_pending()] == [sid2]
+    assert s.get_expired() == [sid1]
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_get_expired_now():
+    s = SocketWrapper(4)
+    s.set_timeout(1_000_000_000)
+    assert s.get_next_deadline() is None
+    ts = s.get_clock()
+    sid = s.send("192.0.2.1", 1, 1, 64)
+    deadline = s.get_next_deadline()
+    assert ts + 1_000_000_000 <= deadline <= s.get_clock() + 1_000_000_000
+    assert s.get_expired(deadline) is None
+    assert s.get_expired(deadline + 1) == [sid]
+    assert s.get_next_deadline() is None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_next_deadline_ns():
+    s = SocketWrapper(4)
+    s.set_timeout(1_000_000_000)
+    assert s.next_deadline_ns() is None
+    t0 = time.monotonic_ns()
+    s.send("192.0.2.1", 1, 1, 64)
+    deadline = s.next_deadline_ns()
+    t1 = time.monotonic_ns()
+    assert t0 + 1_000_000_000 <= deadline <= t1 + 1_000_000_000
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_wait():
+    s = SocketWrapper(4)
+    s.set_timeout(100_000_000)
+    assert s.wait() == (None, None)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    seen, expired = s.wait()
+    assert seen is not None and sid in seen
+    assert expired is None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_wait_expired():
+    s = SocketWrapper(4)
+    s.set_timeout(100_000_000)
+    sid = s.send("192.0.2.1", 1, 1, 64)
+    t0 = time.monotonic()
+    while True:
+        seen, expired = s.wait()
+        if seen or expired:
+            break
+    assert time.monotonic() - t0 < 1.0
+    assert seen or expired == [sid]
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_poll():
+    s = SocketWrapper(4)
+    assert s.fileno() == s.get_fd()
+    assert s.poll(0) is False
+    s.send("127.0.0.1", 1, 1, 64)
+    assert s.poll(1_000_000_000) is True
+    assert s.recv() is not None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_ping_once():
+    s = SocketWrapper(4)
+    rtt = s.ping_once("127.0.0.1")
+    assert rtt > 0
+    assert s.get_pending_count() == 0
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_ping_once_retained():
+    s = SocketWrapper(4)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    time.sleep(0.1)
+    s.ping_once("127.0.0.1", seq=2)
+    seen = s.recv()
+    assert seen is not None and sid in seen
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_set_target():
+    s = SocketWrapper(4)
+    assert s.get_target() is None
+    s.set_target("127.0.0.1")
+    assert s.get_target() == "127.0.0.1"
+    with pytest.raises(ValueError):
+        s.send("127.0.0.2", 1, 1, 64)
+    assert s.ping_once("127.0.0.1") > 0
+    s.set_target("127.0.0.2")
+    assert s.get_target() == "127.0.0.2"
+    assert s.ping_once("127.0.0.2") > 0
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(("afi", "addr"), [(4, "127.0.0.1"), (6, "::1")])
+def test_recv_tos(afi, addr):
+    s = SocketWrapper(afi)
+    s.set_tos(0x2E << 2)
+    s.set_recv_tos(True)
+    assert s.get_options()["recv_tos"] is True
+    sid = s.send(addr, 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.sid == sid
+    assert reply.rtt > 0
+    assert reply.tos == 0x2E << 2
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_recv_replies_no_tos():
+    s = SocketWrapper(4)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.sid == sid
+    assert reply.tos is None
+    assert reply.icmp_type is None
+    assert reply.icmp_code is None
+    assert reply.reason is None
+    s.close()
+
+
+def inject_prohibited(req, addr):
+    """
+    Inject ICMP communication prohibited error, quoting the request
+    to `addr`, over loopback.
+    """
+    ip = struct.pack(
+        "!BBHHHBBH4s4s",
+        0x45,
+        0,
+        20 + len(req),
+        0,
+        0,
+        64,
+        socket.IPPROTO_ICMP,
+        0,
+        socket.inet_aton("127.0.0.1"),
+        socket.inet_aton(addr),
+    )
+    # Type 3, code 13
+    err = b"\x03\x0d\x00\x00\x00\x00\x00\x00" + ip + req
+    with socket.socket(
+        socket.AF_INET, socket.SOCK_RAW, socket.IPPROTO_ICMP
+    ) as tx:
+        tx.sendto(err, ("127.0.0.1", 0))
+
+
+def send_prohibited(s, addr, request_id, seq, signature=None):
+    """
+    Send the request and inject the error, quoting it.
+    """
+    sent = []
+    s.set_debug_callback(
+        lambda d, pkt, *_: sent.append(pkt) if d == DEBUG_TX else None
+    )
+    sid = s.send(addr, request_id, seq, 64)
+    s.set_debug_callback()
+    req = sent[0]
+    if signature is not None:
+        req = req[:8] + signature + req[16:]
+    inject_prohibited(req, addr)
+    return sid
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("accelerated", [False, True])
+def test_recv_errors(accelerated):
+    s = SocketWrapper(4)
+    s.set_recv_errors(True)
+    if accelerated:
+        s.set_accelerated(True)
+    assert s.get_options()["recv_errors"] is True
+    sid = send_prohibited(s, "192.0.2.77", 1, 1)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.sid == sid
+    assert reply.status == RECORD_PROHIBITED
+    assert reply.rtt == 0
+    assert (reply.icmp_type, reply.icmp_code) == (3, 13)
+    assert reply.reason == "communication administratively prohibited"
+    assert s.get_pending_count() == 0
+    assert s.get_expired() is None
+    assert s.get_counters()["errors"] == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_recv_errors_expired():
+    s = SocketWrapper(4)
+    s.set_recv_errors(True)
+    sid = send_prohibited(s, "192.0.2.77", 1, 1)
+    assert s.poll(1_000_000_000)
+    # Not delivered by `recv`, expired early
+    assert s.recv() is None
+    assert s.get_expired() == [sid]
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_recv_errors_ping():
+    async def inner():
+        async with PingSocket(afi=4, timeout=5.0, recv_errors=True) as s:
+            sent = []
+            s._PingSocket__sock.set_debug_callback(
+                lambda d, pkt, *_: sent.append(pkt) if d == DEBUG_TX else None
+            )
+            t0 = time.monotonic()
+            pending = asyncio.create_task(s.ping("192.0.2.77"))
+            await asyncio.sleep(0.05)
+            inject_prohibited(sent[0], "192.0.2.77")
+            # Resolved as timed out without waiting for timeout
+            rtt = await pending
+            return rtt, time.monotonic() - t0
+
+    rtt, elapsed = asyncio.run(inner())
+    assert rtt is None
+    assert elapsed < 1.0
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_ping_once_error():
+    s = SocketWrapper(4)
+    s.set_recv_errors(True)
+
+    def on_debug(d, pkt, *_):
+        if d == DEBUG_TX:
+            threading.Timer(
+                0.05, inject_prohibited, (pkt, "192.0.2.77")
+            ).start()
+
+    s.set_debug_callback(on_debug)
+    t0 = time.monotonic()
+    with pytest.raises(ConnectionError) as e:
+        s.ping_once("192.0.2.77", timeout=5_000_000_000)
+    assert time.monotonic() - t0 < 1.0
+    assert not isinstance(e.value, TimeoutError)
+    assert (e.value.icmp_type, e.value.icmp_code) == (3, 13)
+    assert str(e.value) == "communication administratively prohibited"
+    assert s.get_pending_count() == 0
+    assert s.get_expired() is None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_recv_errors_disabled():
+    s = SocketWrapper(4)
+    send_prohibited(s, "192.0.2.77", 1, 1)
+    assert s.poll(1_000_000_000)
+    assert s.recv_replies() is None
+    assert s.get_pending_count() == 1
+    assert s.get_counters()["wrong_type"] == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_recv_errors_signature_mismatch():
+    s = SocketWrapper(4)
+    s.set_recv_errors(True)
+    send_prohibited(s, "192.0.2.77", 1, 1, signature=b"\x00" * 8)
+    assert s.poll(1_000_000_000)
+    assert s.recv_replies() is None
+    assert s.get_pending_count() == 1
+    assert s.get_counters()["signature_mismatch"] == 1
+    s.close()
+
+
+def rewind_clock(s, ns):
+    """
+    Turn the socket's clock back by re-importing its own state.
+    """
+    state = bytearray(s.export_state())
+    (ts,) = struct.unpack("!Q", state[10:18])
+    state[10:18] = struct.pack("!Q", (ts - ns) % 2**64)
+    s.import_state(bytes(state))
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_invalid_rtt_clamp():
+    s = SocketWrapper(4)
+    assert s.get_options()["invalid_rtt"] == INVALID_RTT_CLAMP
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    rewind_clock(s, 100_000_000)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.sid == sid
+    assert reply.rtt == 1
+    assert s.get_counters()["invalid_rtt"] == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_invalid_rtt_raw():
+    s = SocketWrapper(4)
+    s.set_invalid_rtt(INVALID_RTT_RAW)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    rewind_clock(s, 100_000_000)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.sid == sid
+    assert -100_000_000 < reply.rtt < 0
+    assert s.get_counters()["invalid_rtt"] == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("deliver", [False, True])
+def test_invalid_rtt_error(deliver):
+    s = SocketWrapper(4)
+    s.set_invalid_rtt(INVALID_RTT_ERROR)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    rewind_clock(s, 100_000_000)
+    assert s.poll(1_000_000_000)
+    if deliver:
+        (reply,) = s.recv_replies()
+        assert reply.sid == sid
+        assert reply.status == RECORD_INVALID_RTT
+        assert s.get_expired() is None
+    else:
+        assert s.recv() is None
+        assert s.get_expired() == [sid]
+    assert s.get_pending_count() == 0
+    assert s.get_counters()["invalid_rtt"] == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_invalid_rtt_invalid():
+    s = SocketWrapper(4)
+    with pytest.raises(ValueError):
+        s.set_invalid_rtt(3)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(("afi", "addr"), [(4, "127.0.0.1"), (6, "::1")])
+def test_strict_size(afi, addr):
+    s = SocketWrapper(afi)
+    sid = s.send(addr, 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.size == 64
+    assert reply.size_mismatch is None
+    s.set_strict_size(True)
+    assert s.get_options()["strict_size"] is True
+    sid = s.send(addr, 1, 2, 100)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.sid == sid
+    assert reply.size == 100
+    assert reply.size_mismatch is False
+    assert s.get_counters()["size_mismatch"] == 0
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_truncated():
+    s = SocketWrapper(4)
+    s.set_strict_size(True)
+    sid = s.send("127.0.0.1", 1, 1, 5000)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.sid == sid
+    assert reply.size == 5000
+    assert reply.truncated is True
+    assert reply.size_mismatch is False
+    assert s.get_counters()["truncated"] >= 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("ts_mode", [TS_PLAIN, TS_NONCE])
+@pytest.mark.parametrize("report", [False, True])
+def test_late_grace(ts_mode, report):
+    s = SocketWrapper(4)
+    s.set_ts_mode(ts_mode)
+    s.set_late_grace(5_000_000_000, report)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    # Expire session before the reply is received
+    assert s.get_expired(s.get_clock() + 2_000_000_000) == [sid]
+    assert s.poll(1_000_000_000)
+    replies = s.recv_replies()
+    if report:
+        (reply,) = replies
+        assert reply.sid == sid
+        assert reply.late is True
+        assert reply.rtt > 0
+    else:
+        assert replies is None
+    counters = s.get_counters()
+    assert counters["late"] == 1
+    assert counters["matched"] == 0
+    assert counters["signature_mismatch"] == 0
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(("afi", "addr"), [(4, "127.0.0.1"), (6, "::1")])
+def test_ecn(afi, addr):
+    s = SocketWrapper(afi)
+    s.set_tos(0x2E << 2)
+    s.set_ecn(ECN_ECT0)
+    opts = s.get_options()
+    assert opts["tos"] == (0x2E << 2) | ECN_ECT0
+    assert opts["ecn"] == ECN_ECT0
+    s.set_recv_tos(True)
+    s.send(addr, 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.dscp == 0x2E
+    assert reply.ecn == ECN_ECT0
+    with pytest.raises(ValueError):
+        s.set_ecn(4)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_bind_non_local():
+    s = SocketWrapper(4)
+    with pytest.raises(OSError):
+        s.bind("192.0.2.55")
+    s.set_freebind(True)
+    assert s.get_options()["freebind"] is True
+    s.bind("192.0.2.55")
+    assert s.get_options()["local_addr"] == "192.0.2.55"
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_bind_transparent():
+    s = SocketWrapper(4)
+    try:
+        s.set_transparent(True)
+    except PermissionError:
+        return
+    assert s.get_options()["transparent"] is True
+    s.bind("192.0.2.56")
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_send_src_addr():
+    s = SocketWrapper(4)
+    sid = s.send("127.0.0.1", 1, 1, 64, "127.0.0.2")
+    assert s.poll(1_000_000_000)
+    seen = s.recv()
+    assert seen is not None and sid in seen
+    with pytest.raises(ValueError):
+        s.send("127.0.0.1", 1, 2, 64, "::1")
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(
+    ("afi", "addr", "src_addr"),
+    [(4, "127.0.0.1", "127.0.0.2"), (6, "::1", "::1")],
+)
+def test_recv_pktinfo(afi, addr, src_addr):
+    s = SocketWrapper(afi)
+    s.set_recv_pktinfo(True)
+    assert s.get_options()["recv_pktinfo"] is True
+    s.send(addr, 1, 1, 64, src_addr)
+    assert s.poll(1_000_000_000)
+    (reply,) = s.recv_replies()
+    assert reply.local_addr == src_addr
+    assert reply.ifindex == socket.if_nametoindex("lo")
+    assert reply.tos is None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_diagnostic():
+    s = SocketWrapper(4)
+    s.set_accelerated(True)
+    s.set_diagnostic(100_000_000)
+    assert s.is_diagnostic is True
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    seen = s.recv()
+    assert seen is not None and sid in seen
+    diag = s.get_diagnostic()
+    # Echo request and echo reply
+    assert ("127.0.0.1", 8, 0, 64) in diag
+    assert ("127.0.0.1", 0, 0, 64) in diag
+    time.sleep(0.1)
+    assert s.get_diagnostic() == []
+    assert s.is_diagnostic is False
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_debug_callback():
+    seen = []
+
+    def callback(direction, pkt, addr, ts):
+        seen.append((direction, len(pkt), addr))
+
+    s = SocketWrapper(4)
+    s.set_debug_callback(callback)
+    s.send("127.0.0.1", 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    s.recv()
+    assert (DEBUG_TX, 44, "127.0.0.1") in seen
+    assert (DEBUG_RX, 64, "127.0.0.1") in seen
+    s.set_debug_callback()
+    seen.clear()
+    s.send("127.0.0.1", 1, 2, 64)
+    assert seen == []
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("mode", [TS_PLAIN, TS_OBFUSCATED, TS_NONCE])
+def test_ts_mode(mode):
+    sent = []
+
+    def callback(direction, pkt, addr, ts):
+        if direction == DEBUG_TX:
+            sent.append((struct.unpack("!Q", pkt[16:24])[0], ts))
+
+    s = SocketWrapper(4)
+    s.set_ts_mode(mode)
+    assert s.get_options()["ts_mode"] == mode
+    s.set_debug_callback(callback)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    assert s.poll(1_000_000_000)
+    rtt = s.recv()[sid]
+    assert 0 < rtt < 1_000_000_000
+    ((pkt_ts, ts),) = sent
+    assert (pkt_ts == ts) == (mode == TS_PLAIN)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_ts_mode_pending():
+    s = SocketWrapper(4)
+    with pytest.raises(ValueError):
+        s.set_ts_mode(3)
+    s.send("192.0.2.1", 1, 1, 64)
+    with pytest.raises(ValueError):
+        s.set_ts_mode(TS_NONCE)
+    s.set_ts_mode(TS_PLAIN)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_random_padding():
+    sent = []
+
+    def callback(direction, pkt, addr, ts):
+        if direction == DEBUG_TX:
+            sent.append(pkt[24:])
+
+    s = SocketWrapper(4)
+    s.set_padding(PADDING_RANDOM)
+    assert s.get_options()["padding"] == PADDING_RANDOM
+    s.set_debug_callback(callback)
+    sid1 = s.send("127.0.0.1", 1, 1, 64)
+    sid2 = s.send("127.0.0.1", 1, 2, 64)
+    assert s.poll(1_000_000_000)
+    time.sleep(0.05)
+    assert set(s.recv()) == {sid1, sid2}
+    p1, p2 = sent
+    assert p1 != p2
+    assert p1 != b"0" * len(p1)
+    assert s.get_counters()["padding_mismatch"] == 0
+    with pytest.raises(ValueError):
+        s.set_padding(2)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(
+    ("mode", "expected"),
+    [
+        (MIMIC_LINUX, bytes(range(16, 56))),
+        (MIMIC_WINDOWS, b"abcdefghijklmnopqrstuvwabcdefghi"),
+    ],
+)
+def test_mimic(mode, expected):
+    sent = []
+
+    def callback(direction, pkt, addr, ts):
+        if direction == DEBUG_TX:
+            sent.append(pkt)
+
+    s = SocketWrapper(4)
+    s.set_mimic(mode)
+    s.set_accelerated(True)
+    assert s.get_options()["mimic"] == mode
+    s.set_debug_callback(callback)
+    size = 20 + 8 + len(expected) + (16 if mode == MIMIC_LINUX else 0)
+    sid = s.send("127.0.0.1", 1, 1, size)
+    assert s.poll(1_000_000_000)
+    time.sleep(0.05)
+    assert set(s.recv()) == {sid}
+    (pkt,) = sent
+    assert pkt.endswith(expected)
+    if mode == MIMIC_LINUX:
+        assert abs(int.from_bytes(pkt[8:16], "little") - time.time()) < 5
+    with pytest.raises(ValueError):
+        s.set_mimic(3)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_txtime():
+    s = SocketWrapper(4)
+    with pytest.raises(ValueError):
+        s.send("127.0.0.1", 1, 1, 64, None, time.monotonic_ns())
+    s.set_txtime(time.CLOCK_MONOTONIC)
+    assert s.get_options()["txtime"] == time.CLOCK_MONOTONIC
+    at = time.monotonic_ns() + 10_000_000
+    sid = s.send("127.0.0.1", 1, 1, 64, None, at)
+    assert s.poll(1_000_000_000)
+    time.sleep(0.05)
+    assert set(s.recv()) == {sid}
+    s.set_txtime(None)
+    assert s.get_options()["txtime"] is None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(("afi", "addr"), [(4, "127.0.0.1"), (6, "::1")])
+def test_tx_timestamps(afi, addr):
+    s = SocketWrapper(afi)
+    s.set_tx_timestamps(True)
+    assert s.get_options()["tx_timestamps"] is True
+    sids = {s.send(addr, 1, seq, 64) for seq in range(3)}
+    seen = {}
+    deadline = time.monotonic() + 1.0
+    while len(seen) < 3 and time.monotonic() < deadline:
+        if s.poll(100_000_000):
+            seen.update(s.recv() or {})
+    assert set(seen) == sids
+    assert all(rtt > 0 for rtt in seen.values())
+    s.set_tx_timestamps(False)
+    assert s.get_options()["tx_timestamps"] is False
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_concurrent_call():
+    s = SocketWrapper(4)
+    t = threading.Thread(target=lambda: s.poll(500_000_000))
+    t.start()
+    time.sleep(0.1)
+    with pytest.raises(RuntimeError):
+        s.send("127.0.0.1", 1, 1, 64)
+    t.join()
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_get_counters():
+    s1 = SocketWrapper(4)
+    s2 = SocketWrapper(4)
+    s1.send("127.0.0.1", 1, 1, 64)
+    assert s1.poll(1_000_000_000)
+    time.sleep(0.05)
+    s1.recv()
+    s2.recv()
+    c1 = s1.get_counters()
+    assert c1["matched"] == 1
+    assert c1["wrong_type"] == 1  # Own echo request
+    assert c1["received"] == 2
+    c2 = s2.get_counters()
+    assert c2["matched"] == 0
+    assert c2["signature_mismatch"] == 1
+    assert c2["too_short"] == 0
+    assert c2["checksum_error"] == 0
+    s1.close()
+    s2.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_rxq_ovfl():
+    s = SocketWrapper(4)
+    assert s.get_counters()["kernel_drops"] is None
+    s.set_rxq_ovfl(True)
+    assert s.get_counters()["kernel_drops"] == 0
+    assert s.get_options()["rxq_ovfl"] is True
+    s.set_recv_buffer_size(1)
+    # Overflow receive queue
+    for seq in range(200):
+        s.send("127.0.0.1", 1, seq, 64)
+    time.sleep(0.1)
+    s.recv()
+    assert s.get_counters()["kernel_drops"] == 0
+    # Next packet carries the counter
+    s.send("127.0.0.1", 1, 1000, 64)
+    assert s.poll(1_000_000_000)
+    s.recv()
+    assert s.get_counters()["kernel_drops"] > 0
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_jitter():
+    s = SocketWrapper(4)
+    assert s.next_interval_ns(1_000_000) == 1_000_000
+    s.set_jitter(JITTER_UNIFORM, 20.0)
+    opts = s.get_options()
+    assert opts["jitter"] == JITTER_UNIFORM
+    assert opts["jitter_percent"] == 20.0
+    r = [s.next_interval_ns(1_000_000) for _ in range(100)]
+    assert all(800_000 <= x <= 1_200_000 for x in r)
+    assert len(set(r)) > 1
+    s.set_jitter(JITTER_EXPONENTIAL, 50.0)
+    r = [s.next_interval_ns(1_000_000) for _ in range(100)]
+    assert all(x >= 500_000 for x in r)
+    with pytest.raises(ValueError):
+        s.set_jitter(JITTER_UNIFORM, 150.0)
+    with pytest.raises(ValueError):
+        s.set_jitter(10)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_ping_socket_jitter():
+    async def inner():
+        async with PingSocket(jitter=JITTER_UNIFORM, jitter_percent=50.0) as s:
+            return [s.next_interval(1.0) for _ in range(100)]
+
+    r = asyncio.run(inner())
+    assert all(0.5 <= x <= 1.5 for x in r)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_rtt_unit():
+    s = SocketWrapper(4)
+    assert s.get_options()["rtt_unit"] == RTT_NS
+    assert isinstance(s.ping_once("127.0.0.1", seq=1), int)
+    s.set_rtt_unit(RTT_S)
+    assert s.get_options()["rtt_unit"] == RTT_S
+    sid = s.send("127.0.0.1", 1, 2, 64)
+    assert s.poll(1_000_000_000)
+    seen = s.recv()
+    assert isinstance(seen[sid], float)
+    assert 0.0 < seen[sid] < 1.0
+    s.set_rtt_unit(RTT_MS)
+    rtt = s.ping_once("127.0.0.1", seq=3)
+    assert isinstance(rtt, float)
+    assert 0.0 < rtt < 1000.0
+    with pytest.raises(ValueError):
+        s.set_rtt_unit(10)
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("ts_mode", [TS_PLAIN, TS_OBFUSCATED])
+def test_stale_reply(ts_mode):
+    s = SocketWrapper(4)
+    s.set_ts_mode(ts_mode)
+    s.set_timeout(10_000_000)
+    sid = s.send("127.0.0.1", 1, 1, 64)
+    # Keep the reply queued until the session expires
+    time.sleep(0.05)
+    assert s.get_expired() == [sid]
+    # Same key after sequence wraparound
+    assert s.send("127.0.0.1", 1, 1, 64) == sid
+    time.sleep(0.001)
+    seen = s.recv()
+    assert set(seen) == {sid}
+    assert seen[sid] < 50_000_000
+    counters = s.get_counters()
+    assert counters["stale"] == 1
+    assert counters["matched"] == 1
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_from_fd():
+    async def inner():
+        sock = socket.socket(
+            socket.AF_INET, socket.SOCK_RAW, socket.IPPROTO_ICMP
+        )
+        async with PingSocket(afi=4, fd=sock.detach()) as s:
+            return await s.ping("127.0.0.1")
+
+    rtt = asyncio.run(inner())
+    assert rtt is not None
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize(
+    ["afi", "family", "type", "proto"],
+    [
+        (4, socket.AF_INET, socket.SOCK_DGRAM, 0),
+        (4, socket.AF_INET6, socket.SOCK_RAW, socket.IPPROTO_ICMPV6),
+        (6, socket.AF_INET6, socket.SOCK_RAW, socket.IPPROTO_UDP),
+    ],
+)
+def test_from_fd_invalid(afi, family, type, proto):
+    async def inner():
+        with socket.socket(family, type, proto) as sock:
+            with pytest.raises(ValueError):
+                PingSocket(afi=afi, fd=sock.fileno())
+            # Left open
+            assert sock.getsockopt(socket.SOL_SOCKET, socket.SO_TYPE) == type
+
+    asyncio.run(inner())
+
+
+def test_from_fd_not_socket():
+    async def inner():
+        rx, tx = os.pipe()
+        with pytest.raises(OSError):
+            PingSocket(afi=4, fd=rx)
+        os.close(rx)
+        os.close(tx)
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(
+    os.getuid() != 0 and not is_denied(), reason="Raw sockets are allowed"
+)
+def test_permission_hint():
+    script = (
+        "import os\n"
+        "from gufo.ping.socket import PingSocket\n"
+        "if os.getuid() == 0:\n"
+        "    os.setgid(65534)\n"
+        "    os.setuid(65534)\n"
+        "try:\n"
+        "    PingSocket()\n"
+        "except PermissionError as e:\n"
+        "    print(e)\n"
+    )
+    r = subprocess.run(
+        [sys.executable, "-c", script],
+        check=True,
+        capture_output=True,
+        text=True,
+    )
+    assert "setcap cap_net_raw+ep" in r.stdout
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_proto_options():
+    sent = []
+
+    def callback(direction, pkt, addr, ts):
+        if direction == DEBUG_TX:
+            sent.append(pkt)
+
+    async def inner():
+        options = ProtoOptions(code=5, verify_checksum=False)
+        async with PingSocket(afi=4, proto_options=options) as s:
+            opts = s.get_options()
+            assert opts["icmp_request_type"] == 8
+            assert opts["icmp_reply_type"] == 0
+            assert opts["icmp_code"] == 5
+            assert opts["verify_checksum"] is False
+            s._PingSocket__sock.set_debug_callback(callback)
+            assert await s.ping("127.0.0.1") is not None
+        assert sent[0][:2] == b"\x08\x05"
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("accelerated", [True, False])
+def test_icmp_types(accelerated):
+    async def inner():
+        # Echo reply is not answered, but looped back to raw socket
+        options = ProtoOptions(request_type=0)
+        async with PingSocket(
+            afi=4, accelerated=accelerated, proto_options=options
+        ) as s:
+            assert s.get_options()["icmp_request_type"] == 0
+            assert await s.ping("127.0.0.1") is not None
+
+    asyncio.run(inner())
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("batch_size", [None, 2])
+def test_latency_budget(batch_size):
+    async def inner():
+        async with PingSocket(
+            afi=4, latency_budget=True, batch_size=batch_size
+        ) as s:
+            for seq in range(4):
+                assert await s.ping("127.0.0.1", seq=seq) is not None
+            return s.get_latency_budget()
+
+    budget = asyncio.run(inner())
+    assert set(budget) == {"encode", "send", "deliver"}
+    for h in budget.values():
+        assert h["count"] == 4
+        assert sum(n for _, n in h["buckets"]) == 4
+        assert h["min"] <= h["p50"] <= h["p99"] <= h["max"]
+        assert h["sum"] >= h["max"]
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_latency_budget_disabled():
+    s = SocketWrapper(4)
+    assert s.get_latency_budget() is None
+    s.set_latency_budget(True)
+    assert s.get_latency_budget()["send"]["count"] == 0
+    assert s.get_latency_budget()["send"]["p50"] is None
+    s.set_latency_budget(False)
+    assert s.get_latency_budget() is None
+    s.close()
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_icmp_types_pending():
+    s = SocketWrapper(4)
+    s.send("127.0.0.1", 1, 1, 64)
+    with pytest.raises(ValueError):
+        s.set_icmp_types(0, 0)
+    s.set_icmp_types(8, 0)
+    s.close()
+
+
+@pytest.mark.parametrize(
+    "kwargs", [{"request_type": 256}, {"reply_type": -1}, {"code": 300}]
+)
+def test_proto_options_invalid(kwargs):
+    with pytest.raises(ValueError):
+        ProtoOptions(**kwargs)
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_rebuild():
+    rebuilt = []
+
+    async def inner():
+        async with PingSocket(
+            afi=4, timeout=2.0, rebuild=True, on_rebuild=rebuilt.append
+        ) as s:
+            pending = asyncio.create_task(s.ping("192.0.2.1"))
+            await asyncio.sleep(0.1)
+            # Descriptor is closed behind the socket's back
+            os.close(s._PingSocket__sock.get_fd())
+            rtt = await s.ping("127.0.0.1")
+            # Pending request is handed over, not lost
+            await pending
+            return rtt
+
+    rtt = asyncio.run(inner())
+    assert rtt is not None
+    assert len(rebuilt) == 1
+    assert rebuilt[0].errno == errno.EBADF
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_rebuild_recv():
+    rebuilt = []
+
+    async def inner():
+        async with PingSocket(
+            afi=4, timeout=0.5, rebuild=True, on_rebuild=rebuilt.append
+        ) as s:
+            pending = asyncio.create_task(s.ping("192.0.2.77"))
+            await asyncio.sleep(0.1)
+            os.close(s._PingSocket__sock.get_fd())
+            # Closed descriptor is removed from epoll, emulate read event
+            s._PingSocket__on_read()
+            assert len(rebuilt) == 1
+            rtt = await s.ping("127.0.0.1")
+            # Pending request is handed over and expired
+            assert await pending is None
+            return rtt
+
+    assert asyncio.run(inner()) is not None
+    assert rebuilt[0].errno == errno.EBADF
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_rebuild_failed():
+    def fail_open(fd):
+        raise PermissionError(errno.EPERM, "Operation not permitted")
+
+    async def inner():
+        async with PingSocket(afi=4, rebuild=True) as s:
+            s._PingSocket__open = fail_open
+            with pytest.raises(PermissionError):
+                s._PingSocket__rebuild_socket(
+                    OSError(errno.ENETDOWN, "Network is down")
+                )
+            # Old socket is left intact
+            return await s.ping("127.0.0.1")
+
+    assert asyncio.run(inner()) is not None
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+def test_rebuild_disabled():
+    async def inner():
+        async with PingSocket(afi=4) as s:
+            os.close(s._PingSocket__sock.get_fd())
+            try:
+                await s.ping("127.0.0.1")
+            finally:
+                s._PingSocket__sock.detach()
+
+    with pytest.raises(OSError) as e:
+        asyncio.run(inner())
+    assert e.value.errno == errno.EBADF
+
+
+@pytest.mark.skipif(is_denied(), reason="Permission denied")
+@pytest.mark.parametrize("accelerated", [True, False])
+def test_ip_options(accelerated):
+    async def inner():
+        async with PingSocket(afi=4, accelerated=accelerated) as s:
+            # Record Route option, echoed back in reply,
+            # shifts ICMP header by 40 octets
+            fd = os.dup(s._PingSocket__sock.get_fd())
+            with socket.socket(fileno=fd) as sock:
+                sock.setsockopt(
+                    socket.IPPROTO_IP,
+                    socket.IP_OPTIONS,
+                    bytes([7, 39, 4]) + bytes(37),
+                )
+            return await s.ping("127.0.0.1")
+
+    rtt = asyncio.run(inner())
+    assert rtt is not None
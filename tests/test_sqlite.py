@@ -0,0 +1,103 @@
+# ---------------------------------------------------------------------
+# Gufo Ping: Test SQLite emitter
+# ---------------------------------------------------------------------
+# Copyright (C) 2022, Gufo Labs
+# ---------------------------------------------------------------------
+
+# Python modules
+import os
+import tempfile
+import time
+import uuid
+
+# Third-party modules
+import pytest
+
+sqlite3 = pytest.importorskip("sqlite3")
+
+# Gufo Labs modules
+from gufo.ping import ProbeResult, VerdictChange  # noqa: E402
+from gufo.ping.sqlite import SqliteEmitter  # noqa: E402
+
+
+def _query(path, sql):
+    conn = sqlite3.connect(path)
+    try:
+        return conn.execute(sql).fetchall()
+    finally:
+        conn.close()
+
+
+def test_sqlite_emitter():
+    cid = uuid.UUID("12345678-1234-5678-1234-567812345678")
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "ping.db")
+        emitter = SqliteEmitter(path, batch_size=2, aggregate_interval=None)
+        emitter.emit(
+            ProbeResult("127.0.0.1", 1, 0.5, ttl=10, tag=1, correlation_id=cid)
+        )
+        # Inserted by the full batch
+        emitter.emit(
+            ProbeResult("127.0.0.1", 2, None, suppressed=True, anomalous=True)
+        )
+        assert len(_query(path, "SELECT * FROM results")) == 2
+        emitter.emit_verdict(VerdictChange("127.0.0.1", True, tag="t1"))
+        assert _query(path, "SELECT * FROM verdicts") == []
+        emitter.close()
+        emitter.close()
+        r1, r2 = _query(
+            path,
+            "SELECT target, seq, rtt, ttl, tag, suppressed, anomalous,"
+            " correlation_id FROM results ORDER BY seq",
+        )
+        assert r1 == ("127.0.0.1", 1, 0.5, 10, "1", 0, 0, str(cid))
+        assert r2 == ("127.0.0.1", 2, None, None, None, 1, 1, None)
+        assert _query(
+            path, "SELECT target, down, tag, suppressed FROM verdicts"
+        ) == [("127.0.0.1", 1, "t1", 0)]
+        assert _query(path, "SELECT * FROM aggregates") == []
+        # Existing database is appended
+        emitter = SqliteEmitter(path)
+        emitter.emit(ProbeResult("127.0.0.1", 3, 0.1))
+        emitter.close()
+        assert len(_query(path, "SELECT * FROM results")) == 3
+
+
+def test_sqlite_emitter_aggregates():
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "ping.db")
+        emitter = SqliteEmitter(path, aggregate_interval=0.1)
+        emitter.emit(ProbeResult("127.0.0.1", 1, 0.5))
+        emitter.emit(ProbeResult("127.0.0.1", 2, None))
+        emitter.emit(ProbeResult("127.0.0.2", 1, 0.1))
+        time.sleep(0.1)
+        # Ends the period, including the result
+        emitter.emit(ProbeResult("127.0.0.1", 3, 0.3))
+        rows = _query(
+            path,
+            "SELECT target, sent, received, rtt_min, rtt_avg, rtt_max"
+            " FROM aggregates ORDER BY target",
+        )
+        assert rows == [
+            ("127.0.0.1", 3, 2, 0.3, 0.4, 0.5),
+            ("127.0.0.2", 1, 1, 0.1, 0.1, 0.1),
+        ]
+        (period,) = _query(path, "SELECT DISTINCT period FROM aggregates")
+        assert period[0] >= 0.1
+        # Period is written on close
+        emitter.emit(ProbeResult("127.0.0.2", 2, None))
+        emitter.close()
+        assert _query(
+            path,
+            "SELECT target, sent, received, rtt_avg FROM aggregates"
+            " ORDER BY ts DESC LIMIT 1",
+        ) == [("127.0.0.2", 1, 0, None)]
+        assert len(_query(path, "SELECT * FROM results")) == 5
+
+
+@pytest.mark.parametrize(
+    "kwargs", [{"batch_size": 0}, {"aggregate_interval": 0.0}]
+)
+def test_sqlite_emitter_invalid(kwargs):
+    with pytest.raises(ValueError):
+        SqliteEmitter(":memory:", **kwargs)